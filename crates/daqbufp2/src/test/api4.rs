@@ -1,4 +1,5 @@
 pub mod binnedjson;
 pub mod common;
 pub mod eventsjson;
+pub mod latest;
 pub mod pulseiddiff;
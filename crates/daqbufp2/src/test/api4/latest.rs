@@ -0,0 +1,77 @@
+use crate::err::ErrConv;
+use crate::nodes::require_test_hosts_running;
+use crate::test::api4::common::fetch_events_json;
+use err::Error;
+use http::StatusCode;
+use hyper::Body;
+use items_2::eventsdim0::EventsDim0CollectorOutput;
+use netpod::log::*;
+use netpod::range::evrange::NanoRange;
+use netpod::AppendToUrl;
+use netpod::Cluster;
+use netpod::HostPort;
+use netpod::SfDbChannel;
+use netpod::APP_JSON;
+use query::api4::events::PlainEventsQuery;
+use query::api4::latest::LatestQuery;
+use serde_json::Value as JsonValue;
+use url::Url;
+
+const TEST_BACKEND: &str = "testbackend-00";
+
+async fn fetch_latest_json(query: LatestQuery, cluster: &Cluster) -> Result<JsonValue, Error> {
+    let node0 = &cluster.nodes[0];
+    let hp = HostPort::from_node(node0);
+    let mut url = Url::parse(&format!("http://{}:{}/api/4/latest", hp.host, hp.port))?;
+    query.append_to_url(&mut url);
+    debug!("fetch_latest_json  url {}", url);
+    let req = hyper::Request::builder()
+        .method(http::Method::GET)
+        .uri(url.to_string())
+        .header(http::header::ACCEPT, APP_JSON)
+        .body(Body::empty())
+        .ec()?;
+    let client = hyper::Client::new();
+    let res = client.request(req).await.ec()?;
+    if res.status() != StatusCode::OK {
+        error!("client response {:?}", res);
+        return Err(Error::with_msg_no_trace(format!("bad result {res:?}")));
+    }
+    let buf = hyper::body::to_bytes(res.into_body()).await.ec()?;
+    let res: JsonValue = serde_json::from_slice(&buf)?;
+    Ok(res)
+}
+
+fn last_ts_ms(res: &EventsDim0CollectorOutput<i32>) -> Option<u64> {
+    res.ts_off_ms().back().map(|x| res.ts_anchor_sec() * 1000 + x)
+}
+
+#[test]
+fn latest_before_at_00() -> Result<(), Error> {
+    let fut = async {
+        let rh = require_test_hosts_running()?;
+        let cluster = &rh.cluster;
+        let name = "test-gen-i32-dim0-v01";
+        // Not aligned to the event grid, the latest event lies strictly before `at`.
+        let at: u64 = 1000_000_000 * (60 * 20 + 4) + 250_000_000;
+        let mut query = LatestQuery::new(TEST_BACKEND.into(), vec![name.into()]);
+        query.set_at(at);
+        let jsv = fetch_latest_json(query, cluster).await?;
+        let ch = &jsv["channels"][0];
+        assert_eq!(ch["name"], name);
+        let latest: EventsDim0CollectorOutput<i32> = serde_json::from_value(ch["event"].clone())?;
+        let latest_ms = last_ts_ms(&latest).ok_or_else(|| Error::with_msg_no_trace("no latest event"))?;
+        assert!(latest_ms * 1000_000 <= at);
+        // The plain events of the seconds before `at` must end with the same event.
+        let range = NanoRange {
+            beg: at - 1000_000_000 * 5,
+            end: at + 1,
+        };
+        let query = PlainEventsQuery::new(SfDbChannel::from_name(TEST_BACKEND, name), range);
+        let jsv = fetch_events_json(query, cluster).await?;
+        let events: EventsDim0CollectorOutput<i32> = serde_json::from_value(jsv)?;
+        assert_eq!(Some(latest_ms), last_ts_ms(&events));
+        Ok(())
+    };
+    taskrun::run(fut)
+}
@@ -0,0 +1,120 @@
+use crate::search::index::SearchIndex;
+use crate::shared_connection;
+use crate::ErrConv;
+use err::Error;
+use netpod::log::*;
+use netpod::ChannelAlias;
+use netpod::ChannelAliasSegment;
+use netpod::ChannelSearchQuery;
+use netpod::ChannelSearchResult;
+use netpod::ChannelSearchSingleResult;
+use netpod::Database;
+use netpod::NodeConfigCached;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+/// How long the content of the alias table is used before it is read again.
+const TABLE_TTL: Duration = Duration::from_secs(60);
+
+static TABLE: Mutex<Option<(Instant, Arc<Vec<ChannelAlias>>)>> = Mutex::new(None);
+
+/// The table is created by `scan::make_tables`.
+async fn read_alias_table(db: &Database) -> Result<Vec<ChannelAlias>, Error> {
+    let cl = shared_connection(db).await?;
+    let sql = concat!(
+        "select backend, alias, channel, ts_beg, ts_end",
+        " from channel_aliases",
+        " order by backend, alias, ts_beg",
+    );
+    let rows = cl.query(sql, &[]).await.err_conv()?;
+    let mut ret: Vec<ChannelAlias> = Vec::new();
+    for row in rows {
+        let backend: String = row.get(0);
+        let name: String = row.get(1);
+        let seg = ChannelAliasSegment {
+            channel: row.get(2),
+            beg: row.get::<_, i64>(3) as u64,
+            end: row.get::<_, Option<i64>>(4).map(|x| x as u64),
+        };
+        match ret.last_mut() {
+            Some(last) if last.backend == backend && last.name == name => last.segments.push(seg),
+            _ => ret.push(ChannelAlias {
+                backend,
+                name,
+                segments: vec![seg],
+            }),
+        }
+    }
+    Ok(ret)
+}
+
+/// The aliases of the `channel_aliases` table, read again after a while.
+/// If the table can not be read, there are no aliases from the database until the next attempt.
+async fn alias_table(db: &Database) -> Arc<Vec<ChannelAlias>> {
+    if let Some((ts, table)) = TABLE.lock().unwrap().as_ref() {
+        if ts.elapsed() < TABLE_TTL {
+            return table.clone();
+        }
+    }
+    let table = match read_alias_table(db).await {
+        Ok(x) => Arc::new(x),
+        Err(e) => {
+            warn!("can not read channel aliases: {e}");
+            Arc::new(Vec::new())
+        }
+    };
+    *TABLE.lock().unwrap() = Some((Instant::now(), table.clone()));
+    table
+}
+
+/// All aliases of the backend. Aliases from the cluster config take precedence over the database.
+/// The database is only asked for the backend of the cluster itself.
+pub async fn channel_aliases(backend: &str, ncc: &NodeConfigCached) -> Vec<ChannelAlias> {
+    let cluster = &ncc.node_config.cluster;
+    let mut ret: Vec<_> = cluster
+        .channel_aliases
+        .iter()
+        .filter(|x| x.backend == backend)
+        .cloned()
+        .collect();
+    if backend == cluster.backend {
+        for alias in alias_table(&cluster.database).await.iter() {
+            if alias.backend == backend && ret.iter().all(|x| x.name != alias.name) {
+                ret.push(alias.clone());
+            }
+        }
+    }
+    ret
+}
+
+pub async fn channel_alias(backend: &str, name: &str, ncc: &NodeConfigCached) -> Option<ChannelAlias> {
+    channel_aliases(backend, ncc).await.into_iter().find(|x| x.name == name)
+}
+
+/// The aliases of the backend which match the search, each listing the channels it stands for.
+pub async fn search_aliases(query: &ChannelSearchQuery, ncc: &NodeConfigCached) -> Result<ChannelSearchResult, Error> {
+    let backend = &ncc.node_config.cluster.backend;
+    let channels = channel_aliases(backend, ncc)
+        .await
+        .into_iter()
+        .map(|alias| {
+            let mut segs = alias.segments;
+            segs.sort_by_key(|x| x.beg);
+            ChannelSearchSingleResult {
+                backend: alias.backend,
+                name: alias.name,
+                series: 0,
+                source: String::new(),
+                ty: String::new(),
+                shape: Vec::new(),
+                unit: String::new(),
+                description: String::new(),
+                is_api_0: None,
+                alias_of: segs.into_iter().map(|x| x.channel).collect(),
+            }
+        })
+        .collect();
+    SearchIndex::new(channels).search(query)
+}
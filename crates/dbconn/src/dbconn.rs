@@ -1,3 +1,4 @@
+pub mod alias;
 pub mod channelconfig;
 pub mod query;
 pub mod scan;
@@ -24,7 +25,9 @@ use netpod::TableSizes;
 use pg::Client as PgClient;
 use pg::NoTls;
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
 use taskrun::tokio;
 
@@ -81,6 +84,33 @@ pub async fn create_connection(db_config: &Database) -> Result<PgClient, Error>
     Ok(cl)
 }
 
+static SHARED_CONNECTIONS: Mutex<BTreeMap<String, Arc<PgClient>>> = Mutex::new(BTreeMap::new());
+
+/// A connection of this process to the database, for the small queries which are made on each request.
+/// Created on first use and again when it was closed.
+pub async fn shared_connection(db_config: &Database) -> Result<Arc<PgClient>, Error> {
+    let key = format!(
+        "{}:{}/{}/{}",
+        db_config.host, db_config.port, db_config.name, db_config.user
+    );
+    if let Some(x) = SHARED_CONNECTIONS.lock().unwrap().get(&key) {
+        if !x.is_closed() {
+            return Ok(x.clone());
+        }
+    }
+    let cl = Arc::new(create_connection(db_config).await?);
+    let mut g = SHARED_CONNECTIONS.lock().unwrap();
+    // Another query may have connected meanwhile, keep the first connection.
+    let ret = match g.get(&key) {
+        Some(x) if !x.is_closed() => x.clone(),
+        _ => {
+            g.insert(key, cl.clone());
+            cl
+        }
+    };
+    Ok(ret)
+}
+
 pub async fn channel_exists(channel_name: &str, node_config: &NodeConfigCached) -> Result<bool, Error> {
     let cl = create_connection(&node_config.node_config.cluster.database).await?;
     let rows = cl
@@ -196,6 +196,18 @@ pub struct UpdatedDbWithChannelNames {
     count: u32,
 }
 
+/// Creates the tables which are not made by the channel scan itself.
+pub async fn make_tables(dbc: &Client) -> Result<(), Error> {
+    let sql = concat!(
+        "create table if not exists channel_aliases (",
+        "backend text not null, alias text not null, channel text not null,",
+        " ts_beg bigint not null default 0, ts_end bigint,",
+        " primary key (backend, alias, ts_beg))",
+    );
+    dbc.execute(sql, &[]).await.err_conv()?;
+    Ok(())
+}
+
 async fn update_db_with_channel_names_inner(
     tx: async_channel::Sender<Result<UpdatedDbWithChannelNames, Error>>,
     node_config: NodeConfigCached,
@@ -203,6 +215,7 @@ async fn update_db_with_channel_names_inner(
 ) -> Result<(), Error> {
     let dbc = create_connection(&db_config).await?;
     info!("update_db_with_channel_names connection done");
+    make_tables(&dbc).await?;
     let node_disk_ident = get_node_disk_ident(&node_config, &dbc).await?;
     info!("update_db_with_channel_names get_node_disk_ident done");
     let insert_sql = concat!(
@@ -265,6 +278,7 @@ async fn update_db_with_channel_names_inner(
         }
     }
     dbc.query("commit", &[]).await.err_conv()?;
+    crate::search::index::refresh(&node_config, false);
     let ret = UpdatedDbWithChannelNames {
         msg: format!("all done"),
         count: c1,
@@ -442,6 +456,7 @@ pub async fn update_search_cache(node_config: &NodeConfigCached) -> Result<bool,
         .await
         .err_conv()
         .map_err(|e| format!("error update_search_cache: {e}"))?;
+    crate::search::index::refresh(node_config, true);
     Ok(true)
 }
 
@@ -549,6 +564,30 @@ async fn update_db_with_channel_config(
     Ok(UpdateChannelConfigResult::Done)
 }
 
+/// The configs which this node had for the channel before its config file was rewritten, oldest first.
+pub async fn channel_configs_history(
+    node_config: &NodeConfigCached,
+    channel: &str,
+) -> Result<Vec<parse::channelconfig::ChannelConfigs>, Error> {
+    let dbc = create_connection(&node_config.node_config.cluster.database).await?;
+    let node_disk_ident = get_node_disk_ident(node_config, &dbc).await?;
+    let sql = concat!(
+        "select h.config from configs_history h, channels c",
+        " where h.node = $1 and h.channel = c.rowid and c.facility = $2 and c.name = $3",
+        " order by h.tsinsert",
+    );
+    let rows = dbc
+        .query(sql, &[&node_disk_ident.rowid(), &node_disk_ident.facility(), &channel])
+        .await
+        .err_conv()?;
+    let mut ret = Vec::new();
+    for row in rows {
+        let config: serde_json::Value = row.get(0);
+        ret.push(serde_json::from_value(config)?);
+    }
+    Ok(ret)
+}
+
 pub async fn update_db_with_all_channel_datafiles(
     node_config: &NodeConfigCached,
     node_disk_ident: &NodeDiskIdent,
@@ -1,3 +1,5 @@
+pub mod index;
+
 use crate::create_connection;
 use crate::ErrConv;
 use err::Error;
@@ -11,6 +13,76 @@ use netpod::ScalarType;
 use netpod::ScyllaConfig;
 use netpod::Shape;
 use serde_json::Value as JsVal;
+use tokio_postgres::Row;
+
+/// At most this many matches are read from the database for a search.
+const SEARCH_ROWS_MAX: i64 = 400000;
+
+/// A row of `searchext`: channel_id, channel_name, source_name, dtype, shape, unit, description, channel_backend.
+pub(crate) fn databuffer_search_row(row: &Row) -> Result<ChannelSearchSingleResult, Error> {
+    let shapedb: Option<serde_json::Value> = row.get(4);
+    let shape = match &shapedb {
+        Some(top) => match top {
+            serde_json::Value::Null => Vec::new(),
+            serde_json::Value::Array(items) => {
+                let mut a = Vec::new();
+                for item in items {
+                    match item {
+                        serde_json::Value::Number(n) => match n.as_i64() {
+                            Some(n) => {
+                                a.push(n as u32);
+                            }
+                            None => return Err(Error::with_msg(format!("can not understand shape {:?}", shapedb))),
+                        },
+                        _ => return Err(Error::with_msg(format!("can not understand shape {:?}", shapedb))),
+                    }
+                }
+                a
+            }
+            _ => return Err(Error::with_msg(format!("can not understand shape {:?}", shapedb))),
+        },
+        None => Vec::new(),
+    };
+    let ty: String = row.get(3);
+    let ret = ChannelSearchSingleResult {
+        backend: row.get(7),
+        name: row.get(1),
+        series: row.get::<_, i64>(0) as u64,
+        source: row.get(2),
+        ty,
+        shape: shape,
+        unit: row.get(5),
+        description: row.get(6),
+        is_api_0: None,
+        alias_of: Vec::new(),
+    };
+    Ok(ret)
+}
+
+/// A row of `series_by_channel`: series, facility, channel, scalar_type, shape_dims.
+pub(crate) fn scylla_search_row(row: &Row) -> Result<ChannelSearchSingleResult, Error> {
+    let series: i64 = row.get(0);
+    let series = series as u64;
+    let backend: String = row.get(1);
+    let channel: String = row.get(2);
+    let a: i32 = row.get(3);
+    let scalar_type = ScalarType::from_scylla_i32(a)?;
+    let a: Vec<i32> = row.get(4);
+    let shape = Shape::from_scylla_shape_dims(&a)?;
+    let ret = ChannelSearchSingleResult {
+        backend,
+        name: channel,
+        series,
+        source: "".into(),
+        ty: scalar_type.to_variant_str().into(),
+        shape: shape.to_scylla_vec().into_iter().map(|x| x as u32).collect(),
+        unit: "".into(),
+        description: "".into(),
+        is_api_0: None,
+        alias_of: Vec::new(),
+    };
+    Ok(ret)
+}
 
 pub async fn search_channel_databuffer(
     query: ChannelSearchQuery,
@@ -26,62 +98,37 @@ pub async fn search_channel_databuffer(
         true
     };
     if empty {
-        let ret = ChannelSearchResult { channels: Vec::new() };
+        let ret = ChannelSearchResult {
+            channels: Vec::new(),
+            total: Some(0),
+        };
         return Ok(ret);
     }
     let sql = format!(concat!(
         "select ",
         "channel_id, channel_name, source_name, dtype, shape, unit, description, channel_backend",
         " from searchext($1, $2, $3, $4)",
+        " limit $5",
     ));
     let cl = create_connection(&node_config.node_config.cluster.database).await?;
     let rows = cl
         .query(
             sql.as_str(),
-            &[&query.name_regex, &query.source_regex, &query.description_regex, &"asc"],
+            &[
+                &query.name_regex,
+                &query.source_regex,
+                &query.description_regex,
+                &"asc",
+                &SEARCH_ROWS_MAX,
+            ],
         )
         .await
         .err_conv()?;
     let mut res = Vec::new();
     for row in rows {
-        let shapedb: Option<serde_json::Value> = row.get(4);
-        let shape = match &shapedb {
-            Some(top) => match top {
-                serde_json::Value::Null => Vec::new(),
-                serde_json::Value::Array(items) => {
-                    let mut a = Vec::new();
-                    for item in items {
-                        match item {
-                            serde_json::Value::Number(n) => match n.as_i64() {
-                                Some(n) => {
-                                    a.push(n as u32);
-                                }
-                                None => return Err(Error::with_msg(format!("can not understand shape {:?}", shapedb))),
-                            },
-                            _ => return Err(Error::with_msg(format!("can not understand shape {:?}", shapedb))),
-                        }
-                    }
-                    a
-                }
-                _ => return Err(Error::with_msg(format!("can not understand shape {:?}", shapedb))),
-            },
-            None => Vec::new(),
-        };
-        let ty: String = row.get(3);
-        let k = ChannelSearchSingleResult {
-            backend: row.get(7),
-            name: row.get(1),
-            series: row.get::<_, i64>(0) as u64,
-            source: row.get(2),
-            ty,
-            shape: shape,
-            unit: row.get(5),
-            description: row.get(6),
-            is_api_0: None,
-        };
-        res.push(k);
+        res.push(databuffer_search_row(&row)?);
     }
-    let ret = ChannelSearchResult { channels: res };
+    let ret = query.rank_and_page(res);
     Ok(ret)
 }
 
@@ -92,43 +139,90 @@ pub async fn search_channel_scylla(
 ) -> Result<ChannelSearchResult, Error> {
     let empty = if !query.name_regex.is_empty() { false } else { true };
     if empty {
-        let ret = ChannelSearchResult { channels: Vec::new() };
+        let ret = ChannelSearchResult {
+            channels: Vec::new(),
+            total: Some(0),
+        };
         return Ok(ret);
     }
-    let sql = format!(concat!(
-        "select",
-        " series, facility, channel, scalar_type, shape_dims",
+    // Series have no unit.
+    if query.unit.as_ref().is_some_and(|x| !x.is_empty()) {
+        let ret = ChannelSearchResult {
+            channels: Vec::new(),
+            total: Some(0),
+        };
+        return Ok(ret);
+    }
+    // Filtered, ranked and paged in the database, in the same order as `ChannelSearchQuery::rank_and_page`.
+    let filter = concat!(
         " from series_by_channel",
         " where channel ~* $1",
         " and scalar_type != -2147483647",
-        " limit 400000",
-    ));
+        " and ($2::int is null or scalar_type = $2)",
+        " and ($3::int is null or cardinality(shape_dims) = $3)",
+        " and ($4::text is null or facility = $4)",
+    );
+    let sql = format!(
+        concat!(
+            "select",
+            " series, facility, channel, scalar_type, shape_dims, count(*) over ()",
+            "{}",
+            " order by",
+            " case when $5 = '' then 3",
+            " when lower(channel) = $5 then 0",
+            " when starts_with(lower(channel), $5) then 1",
+            " when strpos(lower(channel), $5) > 0 then 2",
+            " else 3 end,",
+            " channel collate \"C\", facility collate \"C\"",
+            " offset $6 limit $7",
+        ),
+        filter
+    );
+    let scalar_type = query.scalar_type.as_ref().map(|x| x.to_scylla_i32());
+    let shape_rank = query.shape_rank.map(|x| x as i32);
+    let needle = query.rank_needle();
+    let offset = query.offset as i64;
+    let limit = query.limit.map_or(SEARCH_ROWS_MAX, |x| (x as i64).min(SEARCH_ROWS_MAX));
     let pgclient = crate::create_connection(pgconf).await?;
-    let rows = pgclient.query(sql.as_str(), &[&query.name_regex]).await.err_conv()?;
-    let mut res = Vec::new();
+    let rows = pgclient
+        .query(
+            sql.as_str(),
+            &[
+                &query.name_regex,
+                &scalar_type,
+                &shape_rank,
+                &query.backend,
+                &needle,
+                &offset,
+                &limit,
+            ],
+        )
+        .await
+        .err_conv()?;
+    let total = match rows.first() {
+        Some(row) => row.get::<_, i64>(5),
+        None if offset == 0 => 0,
+        None => {
+            // The page is beyond the end, ask only for the count.
+            let sql = format!("select count(*){}", filter);
+            let row = pgclient
+                .query_one(
+                    sql.as_str(),
+                    &[&query.name_regex, &scalar_type, &shape_rank, &query.backend],
+                )
+                .await
+                .err_conv()?;
+            row.get::<_, i64>(0)
+        }
+    };
+    let mut channels = Vec::new();
     for row in rows {
-        let series: i64 = row.get(0);
-        let series = series as u64;
-        let backend: String = row.get(1);
-        let channel: String = row.get(2);
-        let a: i32 = row.get(3);
-        let scalar_type = ScalarType::from_scylla_i32(a)?;
-        let a: Vec<i32> = row.get(4);
-        let shape = Shape::from_scylla_shape_dims(&a)?;
-        let k = ChannelSearchSingleResult {
-            backend,
-            name: channel,
-            series,
-            source: "".into(),
-            ty: scalar_type.to_variant_str().into(),
-            shape: shape.to_scylla_vec().into_iter().map(|x| x as u32).collect(),
-            unit: "".into(),
-            description: "".into(),
-            is_api_0: None,
-        };
-        res.push(k);
+        channels.push(scylla_search_row(&row)?);
     }
-    let ret = ChannelSearchResult { channels: res };
+    let ret = ChannelSearchResult {
+        channels,
+        total: Some(total as u64),
+    };
     Ok(ret)
 }
 
@@ -150,7 +244,10 @@ pub async fn search_channel_archeng(
         false
     };
     if empty {
-        let ret = ChannelSearchResult { channels: Vec::new() };
+        let ret = ChannelSearchResult {
+            channels: Vec::new(),
+            total: Some(0),
+        };
         return Ok(ret);
     }
     let sql = format!(concat!(
@@ -237,18 +334,33 @@ pub async fn search_channel_archeng(
             unit: String::new(),
             description: String::new(),
             is_api_0: None,
+            alias_of: Vec::new(),
         };
         res.push(k);
     }
-    let ret = ChannelSearchResult { channels: res };
+    let ret = query.rank_and_page(res);
     Ok(ret)
 }
 
 pub async fn search_channel(
     query: ChannelSearchQuery,
     node_config: &NodeConfigCached,
+) -> Result<ChannelSearchResult, Error> {
+    // Both parts are asked for everything up to the end of the requested page, and paged together.
+    let sub = query.for_sub_backend();
+    let aliases = crate::alias::search_aliases(&sub, node_config).await?;
+    let channels = search_channel_backend(sub, node_config).await?;
+    Ok(query.merge_ranked(vec![channels, aliases]))
+}
+
+async fn search_channel_backend(
+    query: ChannelSearchQuery,
+    node_config: &NodeConfigCached,
 ) -> Result<ChannelSearchResult, Error> {
     let pgconf = &node_config.node_config.cluster.database;
+    if let Some(ret) = index::search(&query, node_config)? {
+        return Ok(ret);
+    }
     if let Some(scyconf) = node_config.node_config.cluster.scylla.as_ref() {
         search_channel_scylla(query, scyconf, pgconf).await
     } else if let Some(conf) = node_config.node.channel_archiver.as_ref() {
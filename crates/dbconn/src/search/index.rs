@@ -0,0 +1,438 @@
+use super::databuffer_search_row;
+use super::scylla_search_row;
+use crate::create_connection;
+use crate::ErrConv;
+use err::Error;
+use netpod::log::*;
+use netpod::ChannelSearchQuery;
+use netpod::ChannelSearchResult;
+use netpod::ChannelSearchSingleResult;
+use netpod::Database;
+use netpod::NodeConfigCached;
+use regex::Regex;
+use regex::RegexBuilder;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use taskrun::tokio;
+
+/// How often the database is asked for channels which are not yet in the index.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How often the index is built again from scratch, which also picks up changed channel metadata.
+const RELOAD_INTERVAL: Duration = Duration::from_secs(600);
+
+/// Characters which make a name regex more than a plain substring.
+const REGEX_META: &str = "\\.+*?()|[]{}^$";
+
+type Trigram = [char; 3];
+
+/// The channels added by one update of the index, with the trigrams of the lowercased channel names
+/// pointing to the channels which contain them.
+#[derive(Default)]
+struct IndexPart {
+    channels: Vec<ChannelSearchSingleResult>,
+    by_name: BTreeMap<(String, String), u32>,
+    trigrams: BTreeMap<Trigram, Vec<u32>>,
+}
+
+impl IndexPart {
+    fn new(channels: Vec<ChannelSearchSingleResult>) -> Self {
+        let mut ret = Self::default();
+        for ch in channels {
+            let key = (ch.backend.clone(), ch.name.clone());
+            if let Some(&ix) = ret.by_name.get(&key) {
+                ret.channels[ix as usize] = ch;
+            } else {
+                let ix = ret.channels.len() as u32;
+                for tg in trigrams(&ch.name) {
+                    let ixs = ret.trigrams.entry(tg).or_default();
+                    // A trigram can occur more than once in the same name.
+                    if ixs.last() != Some(&ix) {
+                        ixs.push(ix);
+                    }
+                }
+                ret.by_name.insert(key, ix);
+                ret.channels.push(ch);
+            }
+        }
+        ret
+    }
+
+    fn contains(&self, ch: &ChannelSearchSingleResult) -> bool {
+        self.by_name.contains_key(&(ch.backend.clone(), ch.name.clone()))
+    }
+
+    /// The channels whose name contains every trigram of the name regex, if the regex is a plain
+    /// substring of at least three characters. Otherwise `None`, and every channel needs to be checked.
+    fn candidates(&self, name_regex: &str) -> Option<Vec<u32>> {
+        let needle = name_regex.trim_start_matches('^').trim_end_matches('$');
+        if needle.chars().any(|c| REGEX_META.contains(c)) {
+            return None;
+        }
+        let mut lists = Vec::new();
+        for tg in trigrams(needle) {
+            match self.trigrams.get(&tg) {
+                Some(x) => lists.push(x),
+                None => return Some(Vec::new()),
+            }
+        }
+        lists.sort_by_key(|x| x.len());
+        let mut lists = lists.into_iter();
+        let mut ret = lists.next()?.clone();
+        for list in lists {
+            ret = intersect(&ret, list);
+        }
+        Some(ret)
+    }
+}
+
+/// The channels of a backend in memory. Each update adds a part, so that a copy of the index
+/// shares the parts which are already there.
+#[derive(Clone, Default)]
+pub struct SearchIndex {
+    parts: Vec<Arc<IndexPart>>,
+    len: usize,
+    max_series: u64,
+}
+
+impl SearchIndex {
+    pub fn new(channels: Vec<ChannelSearchSingleResult>) -> Self {
+        let mut ret = Self::default();
+        ret.extend(channels);
+        ret
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Adds the channels, a channel already known under the same backend and name is replaced.
+    pub fn extend(&mut self, channels: Vec<ChannelSearchSingleResult>) {
+        if channels.is_empty() {
+            return;
+        }
+        let part = IndexPart::new(channels);
+        for ch in &part.channels {
+            self.max_series = self.max_series.max(ch.series);
+            if !self.parts.iter().any(|x| x.contains(ch)) {
+                self.len += 1;
+            }
+        }
+        self.parts.push(Arc::new(part));
+    }
+
+    /// Answers the query like the database would: the regexes match case-insensitive
+    /// and a query without any regex finds nothing.
+    pub fn search(&self, query: &ChannelSearchQuery) -> Result<ChannelSearchResult, Error> {
+        if query.name_regex.is_empty() && query.source_regex.is_empty() && query.description_regex.is_empty() {
+            let ret = ChannelSearchResult {
+                channels: Vec::new(),
+                total: Some(0),
+            };
+            return Ok(ret);
+        }
+        let name_re = regex_ci(&query.name_regex)?;
+        let source_re = regex_ci(&query.source_regex)?;
+        let description_re = regex_ci(&query.description_regex)?;
+        let is_match = |re: &Option<Regex>, s: &str| re.as_ref().is_none_or(|re| re.is_match(s));
+        let matches = |ch: &&ChannelSearchSingleResult| {
+            is_match(&name_re, &ch.name)
+                && is_match(&source_re, &ch.source)
+                && is_match(&description_re, &ch.description)
+        };
+        let mut res = Vec::new();
+        for (i, part) in self.parts.iter().enumerate() {
+            // A channel which a later part replaces is only found there.
+            let newer = &self.parts[i + 1..];
+            let current = |ch: &&ChannelSearchSingleResult| !newer.iter().any(|x| x.contains(ch));
+            match part.candidates(&query.name_regex) {
+                Some(ixs) => res.extend(
+                    ixs.into_iter()
+                        .map(|ix| &part.channels[ix as usize])
+                        .filter(matches)
+                        .filter(current)
+                        .cloned(),
+                ),
+                None => res.extend(part.channels.iter().filter(matches).filter(current).cloned()),
+            }
+        }
+        Ok(query.rank_and_page(res))
+    }
+}
+
+fn trigrams(s: &str) -> Vec<Trigram> {
+    let chars: Vec<_> = s.to_lowercase().chars().collect();
+    chars.windows(3).map(|x| [x[0], x[1], x[2]]).collect()
+}
+
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut ret = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] < b[j] {
+            i += 1;
+        } else if a[i] > b[j] {
+            j += 1;
+        } else {
+            ret.push(a[i]);
+            i += 1;
+            j += 1;
+        }
+    }
+    ret
+}
+
+fn regex_ci(re: &str) -> Result<Option<Regex>, Error> {
+    if re.is_empty() {
+        Ok(None)
+    } else {
+        let ret = RegexBuilder::new(re)
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| Error::with_public_msg_no_trace(format!("can not parse search regex {re:?}: {e}")))?;
+        Ok(Some(ret))
+    }
+}
+
+#[derive(Clone, Copy)]
+enum IndexSource {
+    Databuffer,
+    Scylla,
+}
+
+impl IndexSource {
+    /// Channel archiver and archiver appliance searches still go to the database every time.
+    fn for_node(ncc: &NodeConfigCached) -> Option<Self> {
+        if ncc.node_config.cluster.scylla.is_some() {
+            Some(Self::Scylla)
+        } else if ncc.node.channel_archiver.is_some() || ncc.node.archiver_appliance.is_some() {
+            None
+        } else {
+            Some(Self::Databuffer)
+        }
+    }
+
+    /// Reads the channels with an id higher than `after` from the database. Scylla series ids
+    /// are not ordered by age, a new series with a lower id is found by the next full reload.
+    async fn load(&self, db: &Database, after: Option<u64>) -> Result<Vec<ChannelSearchSingleResult>, Error> {
+        let cl = create_connection(db).await?;
+        let mut ret = Vec::new();
+        match self {
+            Self::Databuffer => {
+                let sql = concat!(
+                    "select ",
+                    "channel_id, channel_name, source_name, dtype, shape, unit, description, channel_backend",
+                    " from searchext($1, $2, $3, $4)",
+                    " where channel_id > $5",
+                );
+                let after = after.map_or(-1, |x| x as i64);
+                let rows = cl.query(sql, &[&"", &"", &"", &"asc", &after]).await.err_conv()?;
+                for row in rows {
+                    ret.push(databuffer_search_row(&row)?);
+                }
+            }
+            Self::Scylla => {
+                let sql = concat!(
+                    "select",
+                    " series, facility, channel, scalar_type, shape_dims",
+                    " from series_by_channel",
+                    " where scalar_type != -2147483647",
+                    " and series > $1",
+                );
+                let after = after.map_or(-1, |x| x as i64);
+                let rows = cl.query(sql, &[&after]).await.err_conv()?;
+                for row in rows {
+                    ret.push(scylla_search_row(&row)?);
+                }
+            }
+        }
+        Ok(ret)
+    }
+}
+
+struct IndexState {
+    source: IndexSource,
+    db: Database,
+    index: Option<Arc<SearchIndex>>,
+    refreshing: bool,
+    refreshed: Option<Instant>,
+    reloaded: Option<Instant>,
+    retry_after: Option<Instant>,
+}
+
+impl IndexState {
+    /// Whether the index should be updated now, and if so whether it should be built from scratch.
+    fn due(&self) -> Option<bool> {
+        let now = Instant::now();
+        if self.refreshing || self.retry_after.is_some_and(|x| now < x) {
+            None
+        } else if self.index.is_none() || self.reloaded.is_none_or(|x| x + RELOAD_INTERVAL <= now) {
+            Some(true)
+        } else if self.refreshed.is_none_or(|x| x + REFRESH_INTERVAL <= now) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+}
+
+// Keyed by the backend name of the cluster.
+static INDEXES: Mutex<BTreeMap<String, IndexState>> = Mutex::new(BTreeMap::new());
+
+/// Answers the search from the index of the backend.
+/// Returns `None` while there is no index yet, the caller then asks the database.
+/// The index is created on the first search and updated in the background when it is due.
+pub fn search(query: &ChannelSearchQuery, ncc: &NodeConfigCached) -> Result<Option<ChannelSearchResult>, Error> {
+    let source = match IndexSource::for_node(ncc) {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    let backend = &ncc.node_config.cluster.backend;
+    let index = {
+        let mut indexes = INDEXES.lock().unwrap();
+        let st = indexes.entry(backend.clone()).or_insert_with(|| IndexState {
+            source,
+            db: ncc.node_config.cluster.database.clone(),
+            index: None,
+            refreshing: false,
+            refreshed: None,
+            reloaded: None,
+            retry_after: None,
+        });
+        if let Some(full) = st.due() {
+            spawn_update(backend.clone(), st, full);
+        }
+        st.index.clone()
+    };
+    match index {
+        Some(index) => Ok(Some(index.search(query)?)),
+        None => Ok(None),
+    }
+}
+
+/// Brings the index of the backend up to date with the database, if this node holds one.
+/// `full` builds it again from scratch, otherwise only new channels are added.
+pub fn refresh(ncc: &NodeConfigCached, full: bool) {
+    let backend = &ncc.node_config.cluster.backend;
+    let mut indexes = INDEXES.lock().unwrap();
+    if let Some(st) = indexes.get_mut(backend) {
+        st.refreshed = None;
+        st.retry_after = None;
+        if full {
+            st.reloaded = None;
+        }
+        if let Some(full) = st.due() {
+            spawn_update(backend.clone(), st, full);
+        }
+    }
+}
+
+fn spawn_update(backend: String, st: &mut IndexState, full: bool) {
+    st.refreshing = true;
+    let source = st.source;
+    let db = st.db.clone();
+    let base = if full { None } else { st.index.clone() };
+    let fut = async move {
+        let ts1 = Instant::now();
+        let res = source.load(&db, base.as_ref().map(|x| x.max_series)).await;
+        let mut indexes = INDEXES.lock().unwrap();
+        let st = match indexes.get_mut(&backend) {
+            Some(x) => x,
+            None => return,
+        };
+        st.refreshing = false;
+        match res {
+            Ok(channels) => {
+                let now = Instant::now();
+                match base {
+                    Some(base) => {
+                        if !channels.is_empty() {
+                            // The copy shares the parts of the base, only the new channels get indexed.
+                            let mut index = SearchIndex::clone(&base);
+                            index.extend(channels);
+                            st.index = Some(Arc::new(index));
+                        }
+                    }
+                    None => {
+                        st.index = Some(Arc::new(SearchIndex::new(channels)));
+                        st.reloaded = Some(now);
+                    }
+                }
+                st.refreshed = Some(now);
+                let n = st.index.as_ref().map_or(0, |x| x.len());
+                debug!(
+                    "search index {backend}  full {full}  channels {n}  took {:?}",
+                    now - ts1
+                );
+            }
+            Err(e) => {
+                warn!("search index {backend}  can not update: {e}");
+                st.retry_after = Some(Instant::now() + REFRESH_INTERVAL);
+            }
+        }
+    };
+    tokio::spawn(fut);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn channel(name: &str, source: &str) -> ChannelSearchSingleResult {
+        ChannelSearchSingleResult {
+            backend: "b1".into(),
+            name: name.into(),
+            series: 0,
+            source: source.into(),
+            ty: "f64".into(),
+            shape: Vec::new(),
+            unit: String::new(),
+            description: String::new(),
+            is_api_0: None,
+            alias_of: Vec::new(),
+        }
+    }
+
+    fn names(res: &ChannelSearchResult) -> Vec<&str> {
+        res.channels.iter().map(|x| x.name.as_str()).collect()
+    }
+
+    #[test]
+    fn search_index_trigrams() {
+        let mut index = SearchIndex::new(vec![
+            channel("SARFE10-PSSS059:SPECTRUM_X", "tcp://a"),
+            channel("SARFE10-PSSS059:SPECTRUM_Y", "tcp://a"),
+            channel("SINEG01-RCIR-PUP10:SIG-AMPLT", "tcp://b"),
+        ]);
+        let mut q = ChannelSearchQuery {
+            name_regex: "spectrum".into(),
+            ..Default::default()
+        };
+        let res = index.search(&q).unwrap();
+        assert_eq!(res.total, Some(2));
+        q.name_regex = "PSSS059:SPECTRUM_Y$".into();
+        assert_eq!(names(&index.search(&q).unwrap()), ["SARFE10-PSSS059:SPECTRUM_Y"]);
+        q.name_regex = "sig-am".into();
+        assert_eq!(names(&index.search(&q).unwrap()), ["SINEG01-RCIR-PUP10:SIG-AMPLT"]);
+        q.name_regex = "S.*_X".into();
+        assert_eq!(names(&index.search(&q).unwrap()), ["SARFE10-PSSS059:SPECTRUM_X"]);
+        q.name_regex = "nothing".into();
+        assert_eq!(index.search(&q).unwrap().total, Some(0));
+        q.name_regex = String::new();
+        q.source_regex = "tcp://b".into();
+        assert_eq!(index.search(&q).unwrap().total, Some(1));
+        index.extend(vec![
+            channel("SINEG01-RCIR-PUP10:SIG-AMPLT", "tcp://c"),
+            channel("X-SIG", "tcp://b"),
+        ]);
+        assert_eq!(index.len(), 4);
+        assert_eq!(names(&index.search(&q).unwrap()), ["X-SIG"]);
+    }
+}
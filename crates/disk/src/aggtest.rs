@@ -28,6 +28,7 @@ pub fn make_test_node(id: u32) -> Node {
             data_base_path: test_data_base_path_databuffer().join(format!("node{:02}", id)),
             ksprefix: "ks".into(),
             splits: None,
+            pulse_index: None,
         }),
         archiver_appliance: None,
         channel_archiver: None,
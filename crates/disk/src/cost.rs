@@ -0,0 +1,74 @@
+use crate::dataopen::get_timebins;
+use crate::index::parse_channel_header;
+use crate::index::parse_event;
+use crate::paths;
+use err::Error;
+use netpod::log::*;
+use netpod::range::evrange::NanoRange;
+use netpod::Node;
+use netpod::QueryCost;
+use netpod::SfChFetchInfo;
+use std::path::Path;
+use taskrun::tokio;
+use tokio::io::AsyncReadExt;
+
+// Enough for the channel header and the first event of a file without index.
+const PROBE_LEN: usize = 1024;
+
+const INDEX_HEADER_LEN: u64 = 2;
+const INDEX_ENTRY_LEN: u64 = 16;
+
+/// The number of events in a data file.
+/// Files with index are counted by the index entries, else from the length of the first event.
+async fn file_event_count(path: &Path, data_len: u64) -> Result<Option<u64>, Error> {
+    let mut index_path = path.as_os_str().to_owned();
+    index_path.push("_Index");
+    if let Ok(md) = tokio::fs::metadata(&index_path).await {
+        return Ok(Some(md.len().saturating_sub(INDEX_HEADER_LEN) / INDEX_ENTRY_LEN));
+    }
+    let mut buf = Vec::with_capacity(PROBE_LEN);
+    tokio::fs::File::open(path)
+        .await?
+        .take(PROBE_LEN as u64)
+        .read_to_end(&mut buf)
+        .await?;
+    let ret = parse_channel_header(&buf).and_then(|(hlen,)| {
+        let hlen = 2 + hlen as usize;
+        parse_event(&buf[hlen.min(buf.len())..]).map(|(evlen, _)| data_len.saturating_sub(hlen as u64) / evlen as u64)
+    });
+    match ret {
+        Ok(x) => Ok(Some(x)),
+        Err(e) => {
+            debug!("can not estimate the events in {}: {e}", path.display());
+            Ok(None)
+        }
+    }
+}
+
+/// Estimates what it takes to read the range of the channel from the data files of this node.
+/// Events and bytes are counted in proportion to how much of each timebin lies in the range.
+pub async fn estimate_local(range: &NanoRange, fetch_info: &SfChFetchInfo, node: &Node) -> Result<QueryCost, Error> {
+    let bs = fetch_info.bs().ns();
+    let mut ret = QueryCost::default();
+    for tb in get_timebins(fetch_info, node.clone()).await? {
+        let beg = tb * bs;
+        let end = beg + bs;
+        if end <= range.beg || beg >= range.end {
+            continue;
+        }
+        let frac = (end.min(range.end) - beg.max(range.beg)) as f64 / bs as f64;
+        ret.timebins += 1;
+        for path in paths::datapaths_for_timebin(tb, fetch_info, node).await? {
+            let len = match tokio::fs::metadata(&path).await {
+                Ok(x) => x.len(),
+                Err(_) => continue,
+            };
+            ret.files += 1;
+            ret.bytes += (len as f64 * frac) as u64;
+            if let Some(n) = file_event_count(&path, len).await? {
+                ret.events += (n as f64 * frac) as u64;
+            }
+        }
+    }
+    Ok(ret)
+}
@@ -1,5 +1,4 @@
 use super::paths;
-use bytes::BytesMut;
 use err::ErrStr;
 use err::Error;
 use futures_util::StreamExt;
@@ -14,7 +13,6 @@ use std::time::Instant;
 use taskrun::tokio;
 use tokio::fs::File;
 use tokio::fs::OpenOptions;
-use tokio::io::AsyncReadExt;
 use tokio::io::AsyncSeekExt;
 use tokio::io::ErrorKind;
 use tokio::io::SeekFrom;
@@ -48,46 +46,37 @@ async fn position_file(
     match OpenOptions::new().read(true).open(&path).await {
         Ok(file) => {
             let index_path = PathBuf::from(format!("{}_Index", path.to_str().unwrap()));
-            match OpenOptions::new().read(true).open(&index_path).await {
-                Ok(mut index_file) => {
-                    let meta = index_file.metadata().await?;
-                    if meta.len() > 1024 * 1024 * 120 {
-                        let msg = format!("too large index file  {} bytes  for {:?}", meta.len(), index_path);
+            match super::index::open_index_mapped(index_path.clone()).await {
+                Ok(index) => {
+                    let index_len = index.file_len();
+                    if index_len > 1024 * 1024 * 120 {
+                        let msg = format!("too large index file  {} bytes  for {:?}", index_len, index_path);
                         error!("{}", msg);
                         return Err(Error::with_msg(msg));
-                    } else if meta.len() > 1024 * 1024 * 80 {
-                        let msg = format!("very large index file  {} bytes  for {:?}", meta.len(), index_path);
+                    } else if index_len > 1024 * 1024 * 80 {
+                        let msg = format!("very large index file  {} bytes  for {:?}", index_len, index_path);
                         warn!("{}", msg);
-                    } else if meta.len() > 1024 * 1024 * 20 {
-                        let msg = format!("large index file  {} bytes  for {:?}", meta.len(), index_path);
+                    } else if index_len > 1024 * 1024 * 20 {
+                        let msg = format!("large index file  {} bytes  for {:?}", index_len, index_path);
                         info!("{}", msg);
                     }
-                    if meta.len() < 2 {
+                    if index_len < 2 {
                         return Err(Error::with_msg(format!(
                             "bad meta len {}  for {:?}",
-                            meta.len(),
-                            index_path
+                            index_len, index_path
                         )));
                     }
-                    if meta.len() % 16 != 2 {
+                    if index_len % 16 != 2 {
                         return Err(Error::with_msg(format!(
                             "bad meta len {}  for {:?}",
-                            meta.len(),
-                            index_path
+                            index_len, index_path
                         )));
                     }
-                    let mut buf = BytesMut::with_capacity(meta.len() as usize);
-                    buf.resize(buf.capacity(), 0);
-                    index_file.read_exact(&mut buf).await?;
-                    let gg = if expand_left {
-                        super::index::find_largest_smaller_than(range.clone(), expand_right, &buf[2..])
-                    } else {
-                        super::index::find_ge(range.clone(), expand_right, &buf[2..])
-                    };
+                    let gg = super::index::find_in_mapped_index(index, range.clone(), expand_left, expand_right).await;
                     let gg = match gg {
                         Ok(x) => x,
                         Err(e) => {
-                            error!("can not position file for  range {range:?}  expand_right {expand_right:?}  buflen {buflen}", buflen = buf.len());
+                            error!("can not position file for  range {range:?}  expand_right {expand_right:?}  buflen {index_len}");
                             return Err(e);
                         }
                     };
@@ -307,7 +296,7 @@ pub fn open_expanded_files(
     chrx
 }
 
-async fn get_timebins(fetch_info: &SfChFetchInfo, node: Node) -> Result<Vec<u64>, Error> {
+pub(crate) async fn get_timebins(fetch_info: &SfChFetchInfo, node: Node) -> Result<Vec<u64>, Error> {
     let mut timebins = Vec::new();
     let p0 = paths::channel_timebins_dir_path(&fetch_info, &node)?;
     match tokio::fs::read_dir(&p0).await {
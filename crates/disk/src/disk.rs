@@ -3,6 +3,7 @@ pub mod aggtest;
 pub mod binnedstream;
 pub mod cache;
 pub mod channelconfig;
+pub mod cost;
 pub mod dataopen;
 pub mod decode;
 pub mod eventchunker;
@@ -11,8 +12,10 @@ pub mod eventfilter;
 pub mod frame;
 pub mod gen;
 pub mod index;
+pub mod iouring;
 pub mod merge;
 pub mod paths;
+pub mod pulseindex;
 pub mod raw;
 pub mod read3;
 pub mod read4;
@@ -882,6 +885,14 @@ where
             Box::pin(s) as _
         }
         ReadSys::BlockingTaskIntoChannel => blocking_task_into_channel(path, file, disk_io_tune, reqid),
+        ReadSys::IoUring => {
+            if iouring::io_uring_supported() {
+                let s = iouring::FileContentStreamUring::new(path, file, disk_io_tune, reqid);
+                Box::pin(s) as _
+            } else {
+                blocking_task_into_channel(path, file, disk_io_tune, reqid)
+            }
+        }
     }
 }
 
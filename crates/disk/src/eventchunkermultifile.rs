@@ -9,6 +9,7 @@ use futures_util::StreamExt;
 use items_0::streamitem::LogItem;
 use items_0::streamitem::RangeCompletableItem;
 use items_0::streamitem::Sitemty;
+use items_0::streamitem::StatsItem;
 use items_0::streamitem::StreamItem;
 use items_0::WithLen;
 use items_2::eventfull::EventFull;
@@ -20,11 +21,18 @@ use netpod::DiskIoTune;
 use netpod::Node;
 use netpod::ReqCtxArc;
 use netpod::SfChFetchInfo;
+use netpod::SplitReadStats;
 use std::collections::VecDeque;
+use std::path::Path;
+use std::path::PathBuf;
 use std::pin::Pin;
 use std::task::Context;
 use std::task::Poll;
+use std::time::Instant;
 use streams::rangefilter2::RangeFilter2;
+use taskrun::tokio;
+use tokio::fs::File;
+use tracing::Instrument;
 
 pub trait InputTraits: Stream<Item = Sitemty<EventFull>> {}
 
@@ -96,6 +104,60 @@ impl EventChunkerMultifile {
             reqctx,
         }
     }
+
+    /// Reads one split in its own task, ahead of the consumer by at most `read_queue_len` items,
+    /// so that all splits of a timebin are read concurrently.
+    fn split_reader(
+        &self,
+        timebin: u64,
+        split: u32,
+        path: PathBuf,
+        file: File,
+    ) -> Pin<Box<dyn Stream<Item = Sitemty<EventFull>> + Send>> {
+        let inp = crate::file_content_stream(path.clone(), file, self.disk_io_tune.clone(), self.reqctx.reqid());
+        let chunker = EventChunker::from_event_boundary(
+            inp,
+            self.fetch_info.clone(),
+            self.range.clone(),
+            self.event_chunker_conf.clone(),
+            self.node_ix,
+            path,
+            self.expand,
+        );
+        let (tx, rx) = async_channel::bounded(self.disk_io_tune.read_queue_len.max(1));
+        let span = span!(Level::INFO, "split_reader", node_ix = self.node_ix, split);
+        let fut = async move {
+            let ts1 = Instant::now();
+            let mut stats = SplitReadStats::new(split, timebin);
+            let mut chunker = chunker;
+            while let Some(item) = chunker.next().await {
+                match &item {
+                    Ok(StreamItem::DataItem(RangeCompletableItem::Data(h))) => {
+                        stats.events += h.len() as u64;
+                    }
+                    Ok(StreamItem::Stats(StatsItem::EventDataReadStats(k))) => {
+                        stats.parsed_bytes += k.parsed_bytes;
+                    }
+                    _ => {}
+                }
+                let ts2 = Instant::now();
+                if tx.send(item).await.is_err() {
+                    // The consumer is gone, e.g. because the end of the range was reached.
+                    return;
+                }
+                stats.stalled += ts2.elapsed();
+            }
+            stats.duration = ts1.elapsed();
+            let item = StreamItem::Stats(StatsItem::SplitReadStats(stats));
+            tx.send(Ok(item)).await.ok();
+        };
+        tokio::spawn(fut.instrument(span));
+        Box::pin(rx)
+    }
+}
+
+fn split_from_path(path: &Path) -> Option<u32> {
+    path.parent()?.file_name()?.to_str()?.parse().ok()
 }
 
 impl Stream for EventChunkerMultifile {
@@ -181,67 +243,30 @@ impl Stream for EventChunkerMultifile {
                         Ready(Some(k)) => match k {
                             Ok(ofs) => {
                                 self.files_count += ofs.files.len() as u32;
-                                if ofs.files.len() == 1 {
-                                    let mut ofs = ofs;
-                                    let file = ofs.files.pop().unwrap();
-                                    let path = file.path;
-                                    let msg = format!("use opened files {:?}", ofs);
-                                    let item = LogItem::from_node(self.node_ix, Level::DEBUG, msg);
-                                    match file.file {
-                                        Some(file) => {
-                                            let inp = Box::pin(crate::file_content_stream(
-                                                path.clone(),
-                                                file,
-                                                self.disk_io_tune.clone(),
-                                                self.reqctx.reqid(),
-                                            ));
-                                            let chunker = EventChunker::from_event_boundary(
-                                                inp,
-                                                self.fetch_info.clone(),
-                                                self.range.clone(),
-                                                self.event_chunker_conf.clone(),
-                                                self.node_ix,
-                                                path.clone(),
-                                                self.expand,
-                                            );
-                                            let filtered = RangeFilter2::new(chunker, self.range.clone(), self.expand);
-                                            self.evs = Some(Box::pin(filtered));
-                                        }
-                                        None => {}
-                                    }
-                                    Ready(Some(Ok(StreamItem::Log(item))))
-                                } else if ofs.files.len() == 0 {
+                                if ofs.files.len() == 0 {
                                     let msg = format!("use opened files {:?}  no files", ofs);
                                     let item = LogItem::from_node(self.node_ix, Level::DEBUG, msg);
                                     Ready(Some(Ok(StreamItem::Log(item))))
                                 } else {
-                                    // let paths: Vec<_> = ofs.files.iter().map(|x| &x.path).collect();
-                                    let msg = format!("use opened files {:?}  locally merged", ofs);
+                                    let msg = format!("use opened files {:?}", ofs);
                                     let item = LogItem::from_node(self.node_ix, Level::DEBUG, msg);
-                                    let mut chunkers = Vec::new();
-                                    for of in ofs.files {
+                                    let timebin = ofs.timebin;
+                                    let mut readers = Vec::new();
+                                    for (i, of) in ofs.files.into_iter().enumerate() {
                                         if let Some(file) = of.file {
-                                            let inp = crate::file_content_stream(
-                                                of.path.clone(),
-                                                file,
-                                                self.disk_io_tune.clone(),
-                                                self.reqctx.reqid(),
-                                            );
-                                            let chunker = EventChunker::from_event_boundary(
-                                                inp,
-                                                self.fetch_info.clone(),
-                                                self.range.clone(),
-                                                self.event_chunker_conf.clone(),
-                                                self.node_ix,
-                                                of.path.clone(),
-                                                self.expand,
-                                            );
-                                            chunkers.push(Box::pin(chunker) as _);
+                                            let split = split_from_path(&of.path).unwrap_or(i as u32);
+                                            readers.push(self.split_reader(timebin, split, of.path, file));
                                         }
                                     }
-                                    let merged = Merger::new(chunkers, self.out_max_len);
-                                    let filtered = RangeFilter2::new(merged, self.range.clone(), self.expand);
-                                    self.evs = Some(Box::pin(filtered));
+                                    if readers.len() == 1 {
+                                        let inp = readers.pop().unwrap();
+                                        let filtered = RangeFilter2::new(inp, self.range.clone(), self.expand);
+                                        self.evs = Some(Box::pin(filtered));
+                                    } else if readers.len() > 1 {
+                                        let merged = Merger::new(readers, self.out_max_len);
+                                        let filtered = RangeFilter2::new(merged, self.range.clone(), self.expand);
+                                        self.evs = Some(Box::pin(filtered));
+                                    }
                                     Ready(Some(Ok(StreamItem::Log(item))))
                                 }
                             }
@@ -273,6 +298,132 @@ impl Stream for EventChunkerMultifile {
     }
 }
 
+#[cfg(test)]
+mod test_splits {
+    use super::*;
+    use netpod::timeunits::DAY;
+    use netpod::timeunits::MS;
+    use netpod::ByteOrder;
+    use netpod::ByteSize;
+    use netpod::DtNano;
+    use netpod::ReqCtx;
+    use netpod::ScalarType;
+    use netpod::SfDatabuffer;
+    use netpod::Shape;
+    use streams::dtflags::BIG_ENDIAN;
+
+    const CHANNEL: &str = "split-test-i32";
+
+    fn write_split(base: &Path, split: u32, tss: &[u64]) -> Result<(), Error> {
+        let dir = base
+            .join("ks_2/byTime")
+            .join(CHANNEL)
+            .join(format!("{:019}", 0))
+            .join(format!("{:010}", split));
+        std::fs::create_dir_all(&dir)?;
+        let mut buf = Vec::new();
+        let len1 = CHANNEL.len() as i32 + 8;
+        buf.extend_from_slice(&0i16.to_be_bytes());
+        buf.extend_from_slice(&len1.to_be_bytes());
+        buf.extend_from_slice(CHANNEL.as_bytes());
+        buf.extend_from_slice(&len1.to_be_bytes());
+        for &ts in tss {
+            let mut ev = Vec::new();
+            ev.extend_from_slice(&0u32.to_be_bytes());
+            ev.extend_from_slice(&0u64.to_be_bytes());
+            ev.extend_from_slice(&ts.to_be_bytes());
+            ev.extend_from_slice(&(ts / MS).to_be_bytes());
+            ev.extend_from_slice(&0u64.to_be_bytes());
+            ev.extend_from_slice(&[0, 0]);
+            ev.extend_from_slice(&(-1i32).to_be_bytes());
+            ev.extend_from_slice(&[BIG_ENDIAN, ScalarType::I32.index()]);
+            ev.extend_from_slice(&((ts / MS) as i32).to_be_bytes());
+            let len = ev.len() as u32 + 4;
+            ev.extend_from_slice(&len.to_be_bytes());
+            ev[0..4].copy_from_slice(&len.to_be_bytes());
+            buf.extend_from_slice(&ev);
+        }
+        std::fs::write(dir.join(format!("{:019}_00000_Data", DAY / MS)), buf)?;
+        Ok(())
+    }
+
+    #[test]
+    fn splits_merged_in_order() -> Result<(), Error> {
+        let base = std::env::temp_dir().join(format!("daqbuffer-split-test-{}", std::process::id()));
+        let tss: Vec<u64> = (1..=30).map(|i| MS * i).collect();
+        for split in 0..3 {
+            let tss_split: Vec<_> = tss.iter().copied().filter(|x| x / MS % 3 == split).collect();
+            write_split(&base, split as u32, &tss_split)?;
+        }
+        let node = Node {
+            host: "localhost".into(),
+            listen: None,
+            port: 0,
+            port_raw: 0,
+            sf_databuffer: Some(SfDatabuffer {
+                data_base_path: base.clone(),
+                ksprefix: "ks".into(),
+                splits: None,
+                pulse_index: None,
+            }),
+            archiver_appliance: None,
+            channel_archiver: None,
+            prometheus_api_bind: None,
+        };
+        let fetch_info = SfChFetchInfo::new(
+            "testbackend-00",
+            CHANNEL,
+            2,
+            DtNano::from_ns(DAY),
+            ByteOrder::Big,
+            ScalarType::I32,
+            Shape::Scalar,
+        );
+        let range = NanoRange { beg: 0, end: DAY };
+        let mut disk_io_tune = DiskIoTune::default_for_testing();
+        // Let the readers run ahead by a single item only, the merged order must not depend on it.
+        disk_io_tune.read_queue_len = 1;
+        let fut = async move {
+            let mut inp = EventChunkerMultifile::new(
+                range,
+                fetch_info,
+                node,
+                0,
+                disk_io_tune,
+                EventChunkerConf::new(ByteSize::from_kb(1024)),
+                false,
+                4,
+                ReqCtx::new("split-test"),
+            );
+            let mut tss = Vec::new();
+            let mut stats = Vec::new();
+            while let Some(item) = inp.next().await {
+                match item? {
+                    StreamItem::DataItem(RangeCompletableItem::Data(h)) => {
+                        assert!(h.len() <= 4);
+                        tss.extend(h.tss);
+                    }
+                    StreamItem::Stats(StatsItem::SplitReadStats(k)) => stats.push(k),
+                    _ => {}
+                }
+            }
+            Ok::<_, Error>((tss, stats))
+        };
+        let res = taskrun::run(fut);
+        std::fs::remove_dir_all(&base)?;
+        let (tss_out, mut stats) = res?;
+        assert_eq!(tss_out, tss);
+        stats.sort_by_key(|x| x.split);
+        assert_eq!(stats.len(), 3);
+        for (i, k) in stats.iter().enumerate() {
+            assert_eq!((k.split, k.timebin, k.events), (i as u32, 0, 10));
+            assert!(k.parsed_bytes > 0);
+            assert!(k.duration >= k.stalled);
+        }
+        Ok(())
+    }
+}
+
 // TODO re-enable tests generate data on the fly.
 #[cfg(DISABLED)]
 #[cfg(test)]
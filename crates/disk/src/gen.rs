@@ -120,6 +120,7 @@ pub async fn gen_test_data() -> Result<(), Error> {
                 data_base_path: data_base_path.join(format!("node{:02}", i1)),
                 ksprefix: ksprefix.clone(),
                 splits: None,
+                pulse_index: None,
             }),
             archiver_appliance: None,
             channel_archiver: None,
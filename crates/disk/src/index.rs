@@ -1,15 +1,106 @@
 use arrayref::array_ref;
 use err::Error;
+use memmap2::Advice;
+use memmap2::Mmap;
 use netpod::log::*;
 use netpod::range::evrange::NanoRange;
 use netpod::TsNano;
+use std::collections::VecDeque;
+use std::io;
 use std::mem::size_of;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
 use taskrun::tokio;
 use tokio::fs::File;
-use tokio::io::AsyncReadExt;
 use tokio::io::AsyncSeekExt;
 use tokio::io::SeekFrom;
 
+/// Number of index files for which we keep the mapping around.
+const INDEX_CACHE_CAP: usize = 128;
+
+/// Most recently used first.
+static INDEX_CACHE: Mutex<VecDeque<Arc<MappedIndex>>> = Mutex::new(VecDeque::new());
+
+/// A memory mapped `_Index` file.
+///
+/// Index files of the current timebin still get appended to, therefore a cached mapping is only
+/// reused as long as length and modification time of the file are unchanged.
+pub struct MappedIndex {
+    path: PathBuf,
+    len: u64,
+    mtime: Option<SystemTime>,
+    map: Mmap,
+}
+
+impl MappedIndex {
+    pub fn file_len(&self) -> u64 {
+        self.len
+    }
+
+    /// The index entries, without the 2 byte version header.
+    pub fn entries(&self) -> &[u8] {
+        &self.map[2.min(self.map.len())..]
+    }
+}
+
+fn map_index_blocking(path: &Path) -> Result<Arc<MappedIndex>, io::Error> {
+    let file = std::fs::File::open(path)?;
+    let meta = file.metadata()?;
+    let mtime = meta.modified().ok();
+    let mut cache = INDEX_CACHE.lock().unwrap();
+    if let Some(i) = cache.iter().position(|x| x.path == path) {
+        let e = cache.remove(i).unwrap();
+        if e.len == meta.len() && e.mtime == mtime {
+            cache.push_front(e.clone());
+            return Ok(e);
+        }
+    }
+    drop(cache);
+    // Safety: the databuffer writer only appends to index files, the mapping covers the length at open time.
+    let map = unsafe { Mmap::map(&file)? };
+    map.advise(Advice::Random)?;
+    let ret = Arc::new(MappedIndex {
+        path: path.into(),
+        len: meta.len(),
+        mtime,
+        map,
+    });
+    let mut cache = INDEX_CACHE.lock().unwrap();
+    cache.retain(|x| x.path != path);
+    cache.push_front(ret.clone());
+    cache.truncate(INDEX_CACHE_CAP);
+    Ok(ret)
+}
+
+/// Maps the index file or returns the cached mapping if the file did not change since.
+pub async fn open_index_mapped(path: PathBuf) -> Result<Arc<MappedIndex>, io::Error> {
+    tokio::task::spawn_blocking(move || map_index_blocking(&path))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Binary search on the mapped index. Runs on the blocking pool because touching
+/// pages which are not yet resident means disk reads.
+pub async fn find_in_mapped_index(
+    index: Arc<MappedIndex>,
+    range: NanoRange,
+    expand_left: bool,
+    expand_right: bool,
+) -> Result<Option<(u64, u64)>, Error> {
+    tokio::task::spawn_blocking(move || {
+        if expand_left {
+            find_largest_smaller_than(range, expand_right, index.entries())
+        } else {
+            find_ge(range, expand_right, index.entries())
+        }
+    })
+    .await
+    .map_err(Error::from_string)?
+}
+
 pub fn find_ge(range: NanoRange, expand_right: bool, buf: &[u8]) -> Result<Option<(u64, u64)>, Error> {
     type VT = u64;
     const NT: usize = size_of::<VT>();
@@ -135,22 +226,6 @@ pub fn find_largest_smaller_than(
     }
 }
 
-async fn read(buf: &mut [u8], file: &mut File) -> Result<usize, Error> {
-    let mut wp = 0;
-    loop {
-        let n1 = file.read(&mut buf[wp..]).await?;
-        if n1 == 0 {
-            break;
-        } else {
-            wp += n1;
-        }
-        if wp >= buf.len() {
-            break;
-        }
-    }
-    Ok(wp)
-}
-
 pub fn parse_channel_header(buf: &[u8]) -> Result<(u32,), Error> {
     if buf.len() < 6 {
         return Err(Error::with_msg(format!("parse_channel_header  buf len: {}", buf.len())));
@@ -200,12 +275,27 @@ pub fn parse_event(buf: &[u8]) -> Result<(u32, TsNano), Error> {
     Ok((len1 as u32, TsNano(ts)))
 }
 
-pub async fn read_event_at(pos: u64, file: &mut File) -> Result<(u32, TsNano), Error> {
-    file.seek(SeekFrom::Start(pos)).await?;
-    let mut buf = vec![0; 1024];
-    let _n1 = read(&mut buf, file).await?;
-    let ev = parse_event(&buf)?;
-    Ok(ev)
+fn event_at(map: &[u8], pos: u64) -> Result<(u32, TsNano), Error> {
+    let buf = map
+        .get(pos as usize..)
+        .ok_or_else(|| Error::with_msg(format!("event position {pos} beyond file length {}", map.len())))?;
+    parse_event(buf)
+}
+
+/// Maps a data file which has no index but fixed length events and runs the search
+/// on the blocking pool.
+async fn search_static_len_datafile<F>(file: &File, f: F) -> Result<(bool, u32, u64), Error>
+where
+    F: FnOnce(&[u8]) -> Result<(bool, u32, u64), Error> + Send + 'static,
+{
+    // Safety: databuffer data files are only ever appended to.
+    let map = unsafe { Mmap::map(file)? };
+    tokio::task::spawn_blocking(move || {
+        map.advise(Advice::Random)?;
+        f(&map)
+    })
+    .await
+    .map_err(Error::from_string)?
 }
 
 pub async fn position_static_len_datafile(
@@ -213,18 +303,22 @@ pub async fn position_static_len_datafile(
     range: NanoRange,
     expand_right: bool,
 ) -> Result<(File, bool, u32, u64), Error> {
-    let flen = file.seek(SeekFrom::End(0)).await?;
-    file.seek(SeekFrom::Start(0)).await?;
-    let mut buf = vec![0; 1024];
-    let _n1 = read(&mut buf, &mut file).await?;
-    let hres = parse_channel_header(&buf)?;
+    let (found, nreads, pos) =
+        search_static_len_datafile(&file, move |map| position_static_len(map, range, expand_right)).await?;
+    file.seek(SeekFrom::Start(pos)).await?;
+    Ok((file, found, nreads, pos))
+}
+
+fn position_static_len(map: &[u8], range: NanoRange, expand_right: bool) -> Result<(bool, u32, u64), Error> {
+    let flen = map.len() as u64;
+    let hres = parse_channel_header(map)?;
     let headoff = 2 + hres.0 as u64;
-    let ev = parse_event(&buf[headoff as usize..])?;
+    let ev = event_at(map, headoff)?;
     let evlen = ev.0 as u64;
     let mut j = headoff;
     let mut k = ((flen - headoff) / evlen - 1) * evlen + headoff;
     let x = ev.1.ns();
-    let t = read_event_at(k, &mut file).await?;
+    let t = event_at(map, k)?;
     if t.0 != evlen as u32 {
         Err(Error::with_msg(format!(
             "inconsistent event lengths:  {}  vs  {}",
@@ -235,24 +329,19 @@ pub async fn position_static_len_datafile(
     let mut nreads = 2;
     if x >= range.end {
         if expand_right {
-            file.seek(SeekFrom::Start(j)).await?;
-            return Ok((file, true, nreads, j));
+            return Ok((true, nreads, j));
         } else {
-            file.seek(SeekFrom::Start(0)).await?;
-            return Ok((file, false, nreads, 0));
+            return Ok((false, nreads, 0));
         }
     }
     if y < range.beg {
-        file.seek(SeekFrom::Start(j)).await?;
-        return Ok((file, false, nreads, j));
+        return Ok((false, nreads, j));
     }
     if x >= range.beg {
         if x < range.end || expand_right {
-            file.seek(SeekFrom::Start(j)).await?;
-            return Ok((file, true, nreads, j));
+            return Ok((true, nreads, j));
         } else {
-            file.seek(SeekFrom::Start(0)).await?;
-            return Ok((file, false, nreads, 0));
+            return Ok((false, nreads, 0));
         }
     }
     let mut x = x;
@@ -262,15 +351,13 @@ pub async fn position_static_len_datafile(
         assert_eq!((k - j) % evlen, 0);
         if k - j < 2 * evlen {
             if y < range.end || expand_right {
-                file.seek(SeekFrom::Start(k)).await?;
-                return Ok((file, true, nreads, k));
+                return Ok((true, nreads, k));
             } else {
-                file.seek(SeekFrom::Start(0)).await?;
-                return Ok((file, false, nreads, 0));
+                return Ok((false, nreads, 0));
             }
         }
         let m = j + (k - j) / 2 / evlen * evlen;
-        let t = read_event_at(m, &mut file).await?;
+        let t = event_at(map, m)?;
         if t.0 != evlen as u32 {
             Err(Error::with_msg(format!(
                 "inconsistent event lengths:  {}  vs  {}",
@@ -294,18 +381,24 @@ pub async fn position_static_len_datafile_at_largest_smaller_than(
     range: NanoRange,
     _expand_right: bool,
 ) -> Result<(File, bool, u32, u64), Error> {
-    let flen = file.seek(SeekFrom::End(0)).await?;
-    file.seek(SeekFrom::Start(0)).await?;
-    let mut buf = vec![0; 1024];
-    let _n1 = read(&mut buf, &mut file).await?;
-    let hres = parse_channel_header(&buf)?;
+    let (found, nreads, pos) = search_static_len_datafile(&file, move |map| {
+        position_static_len_at_largest_smaller_than(map, range)
+    })
+    .await?;
+    file.seek(SeekFrom::Start(pos)).await?;
+    Ok((file, found, nreads, pos))
+}
+
+fn position_static_len_at_largest_smaller_than(map: &[u8], range: NanoRange) -> Result<(bool, u32, u64), Error> {
+    let flen = map.len() as u64;
+    let hres = parse_channel_header(map)?;
     let headoff = 2 + hres.0 as u64;
-    let ev = parse_event(&buf[headoff as usize..])?;
+    let ev = event_at(map, headoff)?;
     let evlen = ev.0 as u64;
     let mut j = headoff;
     let mut k = ((flen - headoff) / evlen - 1) * evlen + headoff;
     let x = ev.1.ns();
-    let t = read_event_at(k, &mut file).await?;
+    let t = event_at(map, k)?;
     if t.0 != evlen as u32 {
         Err(Error::with_msg(format!(
             "inconsistent event lengths:  {}  vs  {}",
@@ -315,20 +408,17 @@ pub async fn position_static_len_datafile_at_largest_smaller_than(
     let y = t.1.ns();
     let mut nreads = 2;
     if x >= range.beg {
-        file.seek(SeekFrom::Start(j)).await?;
-        return Ok((file, false, nreads, j));
+        return Ok((false, nreads, j));
     }
     if y < range.beg {
-        file.seek(SeekFrom::Start(k)).await?;
-        return Ok((file, true, nreads, k));
+        return Ok((true, nreads, k));
     }
     loop {
         if k - j < 2 * evlen {
-            file.seek(SeekFrom::Start(j)).await?;
-            return Ok((file, true, nreads, j));
+            return Ok((true, nreads, j));
         }
         let m = j + (k - j) / 2 / evlen * evlen;
-        let t = read_event_at(m, &mut file).await?;
+        let t = event_at(map, m)?;
         if t.0 != evlen as u32 {
             Err(Error::with_msg(format!(
                 "inconsistent event lengths:  {}  vs  {}",
@@ -0,0 +1,431 @@
+use crate::file_tokio_to_std;
+use bytes::BytesMut;
+use err::Error;
+use futures_util::FutureExt;
+use futures_util::Stream;
+use futures_util::StreamExt;
+use io_uring::opcode;
+use io_uring::types;
+use io_uring::IoUring;
+use netpod::log::*;
+use netpod::DiskIoTune;
+use std::collections::BTreeMap;
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::io::Seek;
+use std::os::fd::AsRawFd;
+use std::os::fd::FromRawFd;
+use std::os::fd::OwnedFd;
+use std::os::fd::RawFd;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::OnceLock;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Instant;
+use streams::filechunkread::FileChunkRead;
+use taskrun::tokio;
+use tokio::fs::File;
+use tokio::io::unix::AsyncFd;
+
+const QUEUE_DEPTH_MAX: usize = 256;
+
+/// Probe once per process whether the kernel lets us set up an io_uring at all.
+/// Seccomp profiles of container runtimes commonly deny it even on recent kernels.
+pub fn io_uring_supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(|| match IoUring::new(2) {
+        Ok(_) => true,
+        Err(e) => {
+            warn!("io_uring not available, fall back to BlockingTaskIntoChannel: {e}");
+            false
+        }
+    })
+}
+
+struct EventFd(OwnedFd);
+
+impl EventFd {
+    fn new() -> io::Result<Self> {
+        let fd = unsafe { libc::eventfd(0, libc::EFD_NONBLOCK | libc::EFD_CLOEXEC) };
+        if fd < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            // SAFETY we just created the fd and nobody else owns it.
+            Ok(Self(unsafe { OwnedFd::from_raw_fd(fd) }))
+        }
+    }
+
+    fn reset(&self) {
+        let mut v = 0u64;
+        unsafe {
+            libc::read(self.0.as_raw_fd(), &mut v as *mut u64 as *mut libc::c_void, 8);
+        }
+    }
+}
+
+impl AsRawFd for EventFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+struct Inflight {
+    off: u64,
+    len: usize,
+    buf_ix: u16,
+    ts1: Instant,
+}
+
+/// One ring per file, with `read_queue_len` registered buffers of `read_buffer_len` each.
+/// Completions are signaled through an eventfd which is polled by the tokio reactor,
+/// therefore the reads do not hop through a blocking thread.
+struct Uring {
+    path: PathBuf,
+    file: std::fs::File,
+    ring: IoUring,
+    evfd: AsyncFd<EventFd>,
+    bufs: Vec<Vec<u8>>,
+    buf_free: VecDeque<u16>,
+    inflight: BTreeMap<u64, Inflight>,
+    // Reads which were cut short by the kernel, the remainder gets submitted again.
+    holes: VecDeque<(u64, usize)>,
+    done_reads: BTreeMap<u64, FileChunkRead>,
+    next_ud: u64,
+    submit_off: u64,
+    emit_off: u64,
+    file_len: u64,
+    eof: bool,
+    // An error was emitted, the stream ends once the reads in flight are drained.
+    errored: bool,
+}
+
+impl Uring {
+    fn new(path: PathBuf, file: std::fs::File, disk_io_tune: &DiskIoTune) -> io::Result<Self> {
+        let mut file = file;
+        let pos = file.stream_position()?;
+        let file_len = file.metadata()?.len();
+        let depth = disk_io_tune.read_queue_len.clamp(1, QUEUE_DEPTH_MAX);
+        let buflen = disk_io_tune.read_buffer_len.max(1024);
+        let ring = IoUring::new(depth.next_power_of_two() as u32)?;
+        let bufs: Vec<_> = (0..depth).map(|_| vec![0u8; buflen]).collect();
+        let iovecs: Vec<_> = bufs
+            .iter()
+            .map(|b| libc::iovec {
+                iov_base: b.as_ptr() as *mut _,
+                iov_len: b.len(),
+            })
+            .collect();
+        // SAFETY the buffers live as long as the ring, we drain all reads before drop.
+        unsafe { ring.submitter().register_buffers(&iovecs)? };
+        let evfd = EventFd::new()?;
+        ring.submitter().register_eventfd(evfd.as_raw_fd())?;
+        let evfd = AsyncFd::new(evfd)?;
+        let ret = Self {
+            path,
+            file,
+            ring,
+            evfd,
+            bufs,
+            buf_free: (0..depth as u16).collect(),
+            inflight: BTreeMap::new(),
+            holes: VecDeque::new(),
+            done_reads: BTreeMap::new(),
+            next_ud: 1,
+            submit_off: pos,
+            emit_off: pos,
+            file_len,
+            eof: false,
+            errored: false,
+        };
+        Ok(ret)
+    }
+
+    fn submit_reads(&mut self) -> io::Result<()> {
+        let mut pushed = 0;
+        loop {
+            if self.buf_free.is_empty() {
+                break;
+            }
+            let (off, len) = if let Some(x) = self.holes.pop_front() {
+                x
+            } else if !self.eof && self.submit_off < self.file_len {
+                let buflen = self.bufs[0].len();
+                let len = (self.file_len - self.submit_off).min(buflen as u64) as usize;
+                let off = self.submit_off;
+                self.submit_off += len as u64;
+                (off, len)
+            } else {
+                break;
+            };
+            let buf_ix = self.buf_free.pop_front().unwrap();
+            let ud = self.next_ud;
+            self.next_ud += 1;
+            let buf = self.bufs[buf_ix as usize].as_mut_ptr();
+            let sqe = opcode::ReadFixed::new(types::Fd(self.file.as_raw_fd()), buf, len as u32, buf_ix)
+                .offset(off)
+                .build()
+                .user_data(ud);
+            // SAFETY the buffer is registered and stays untouched until its completion.
+            if unsafe { self.ring.submission().push(&sqe) }.is_err() {
+                self.buf_free.push_front(buf_ix);
+                self.holes.push_front((off, len));
+                break;
+            }
+            let inf = Inflight {
+                off,
+                len,
+                buf_ix,
+                ts1: Instant::now(),
+            };
+            self.inflight.insert(ud, inf);
+            pushed += 1;
+        }
+        if pushed > 0 {
+            self.ring.submit()?;
+        }
+        Ok(())
+    }
+
+    fn reap(&mut self) -> io::Result<usize> {
+        let mut n = 0;
+        // Keep going after an error, every completion must release its inflight entry.
+        let mut err = None;
+        let cqes: Vec<_> = self.ring.completion().map(|x| (x.user_data(), x.result())).collect();
+        for (ud, res) in cqes {
+            n += 1;
+            let inf = match self.inflight.remove(&ud) {
+                Some(x) => x,
+                None => {
+                    error!("io_uring completion for unknown user data {ud}");
+                    continue;
+                }
+            };
+            self.buf_free.push_back(inf.buf_ix);
+            if res < 0 {
+                err.get_or_insert(io::Error::from_raw_os_error(-res));
+                continue;
+            }
+            let nread = res as usize;
+            if nread == 0 {
+                // The file was truncated under us: treat as end of file.
+                self.eof = true;
+                self.file_len = self.file_len.min(inf.off);
+                continue;
+            }
+            if nread < inf.len {
+                self.holes.push_back((inf.off + nread as u64, inf.len - nread));
+            }
+            let mut buf = BytesMut::with_capacity(nread);
+            buf.extend_from_slice(&self.bufs[inf.buf_ix as usize][..nread]);
+            let item = FileChunkRead::with_buf_dur(buf, inf.ts1.elapsed());
+            self.done_reads.insert(inf.off, item);
+        }
+        match err {
+            Some(e) => Err(e),
+            None => Ok(n),
+        }
+    }
+
+    fn poll_next_chunk(&mut self, cx: &mut Context) -> Poll<Option<Result<FileChunkRead, Error>>> {
+        use Poll::*;
+        loop {
+            if let Some(item) = self.done_reads.remove(&self.emit_off) {
+                self.emit_off += item.buf().len() as u64;
+                return Ready(Some(Ok(item)));
+            }
+            if self.inflight.is_empty() && self.holes.is_empty() && self.submit_off >= self.file_len {
+                if self.errored {
+                    return Ready(None);
+                }
+                if self.emit_off < self.file_len {
+                    // Nothing in flight can fill the gap, the stream would silently miss data.
+                    self.eof = true;
+                    self.errored = true;
+                    let msg = format!(
+                        "io_uring no data at {} of {}  {:?}",
+                        self.emit_off, self.file_len, self.path
+                    );
+                    return Ready(Some(Err(io::Error::new(io::ErrorKind::UnexpectedEof, msg).into())));
+                }
+                if self.eof {
+                    return Ready(None);
+                }
+                // Data files may still be appended to while we read them.
+                match self.file.metadata() {
+                    Ok(x) if x.len() > self.file_len => {
+                        self.file_len = x.len();
+                    }
+                    Ok(_) => {
+                        self.eof = true;
+                        return Ready(None);
+                    }
+                    Err(e) => {
+                        self.eof = true;
+                        self.errored = true;
+                        return Ready(Some(Err(e.into())));
+                    }
+                }
+            }
+            if let Err(e) = self.submit_reads() {
+                self.eof = true;
+                self.errored = true;
+                return Ready(Some(Err(e.into())));
+            }
+            match self.reap() {
+                Ok(n) if n > 0 => continue,
+                Ok(_) => {}
+                Err(e) => {
+                    error!("io_uring read error  {e}  {:?}", self.path);
+                    self.eof = true;
+                    self.errored = true;
+                    return Ready(Some(Err(e.into())));
+                }
+            }
+            match self.evfd.poll_read_ready(cx) {
+                Ready(Ok(mut guard)) => {
+                    guard.get_inner().reset();
+                    guard.clear_ready();
+                }
+                Ready(Err(e)) => {
+                    self.eof = true;
+                    self.errored = true;
+                    return Ready(Some(Err(e.into())));
+                }
+                Pending => return Pending,
+            }
+        }
+    }
+}
+
+impl Drop for Uring {
+    fn drop(&mut self) {
+        // The kernel may still write into our buffers, wait for all outstanding reads.
+        while !self.inflight.is_empty() {
+            if let Err(e) = self.ring.submit_and_wait(1) {
+                error!("io_uring can not drain on drop  {e}");
+                std::mem::forget(std::mem::take(&mut self.bufs));
+                return;
+            }
+            let uds: Vec<_> = self.ring.completion().map(|x| x.user_data()).collect();
+            for ud in uds {
+                self.inflight.remove(&ud);
+            }
+        }
+    }
+}
+
+enum State {
+    ConvertFile(Pin<Box<dyn Future<Output = std::fs::File> + Send>>),
+    Reading(Box<Uring>),
+    Fallback(Pin<Box<dyn Stream<Item = Result<FileChunkRead, Error>> + Send>>),
+    Done,
+}
+
+pub struct FileContentStreamUring {
+    path: PathBuf,
+    disk_io_tune: DiskIoTune,
+    reqid: String,
+    state: State,
+}
+
+impl FileContentStreamUring {
+    pub fn new(path: PathBuf, file: File, disk_io_tune: DiskIoTune, reqid: String) -> Self {
+        Self {
+            path,
+            disk_io_tune,
+            reqid,
+            state: State::ConvertFile(Box::pin(file_tokio_to_std(file))),
+        }
+    }
+}
+
+impl Stream for FileContentStreamUring {
+    type Item = Result<FileChunkRead, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        use Poll::*;
+        loop {
+            break match &mut self.state {
+                State::ConvertFile(fut) => match fut.poll_unpin(cx) {
+                    Ready(file) => {
+                        // Keep a handle in case the ring setup fails, e.g. due to RLIMIT_MEMLOCK.
+                        let file2 = file.try_clone();
+                        match Uring::new(self.path.clone(), file, &self.disk_io_tune) {
+                            Ok(x) => {
+                                self.state = State::Reading(Box::new(x));
+                            }
+                            Err(e) => match file2 {
+                                Ok(file) => {
+                                    warn!("io_uring setup failed, fall back  {e}");
+                                    let s = crate::blocking_task_into_channel(
+                                        self.path.clone(),
+                                        File::from_std(file),
+                                        self.disk_io_tune.clone(),
+                                        self.reqid.clone(),
+                                    );
+                                    self.state = State::Fallback(s);
+                                }
+                                Err(e2) => {
+                                    error!("io_uring setup failed  {e}  can not fall back  {e2}");
+                                    self.state = State::Done;
+                                    return Ready(Some(Err(e.into())));
+                                }
+                            },
+                        }
+                        continue;
+                    }
+                    Pending => Pending,
+                },
+                State::Reading(inner) => match inner.poll_next_chunk(cx) {
+                    Ready(Some(Ok(x))) => Ready(Some(Ok(x))),
+                    Ready(Some(Err(e))) => {
+                        self.state = State::Done;
+                        Ready(Some(Err(e)))
+                    }
+                    Ready(None) => {
+                        self.state = State::Done;
+                        Ready(None)
+                    }
+                    Pending => Pending,
+                },
+                State::Fallback(inp) => inp.poll_next_unpin(cx),
+                State::Done => Ready(None),
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use netpod::ReadSys;
+    use tokio::io::AsyncSeekExt;
+
+    #[test]
+    fn read_file_in_order() -> Result<(), Error> {
+        let path = std::env::temp_dir().join(format!("daqbuffer-iouring-test-{}", std::process::id()));
+        let data: Vec<u8> = (0..100_000u32).map(|x| (x % 251) as u8).collect();
+        std::fs::write(&path, &data)?;
+        let fut = async {
+            let mut file = File::open(&path).await?;
+            file.seek(std::io::SeekFrom::Start(77)).await?;
+            let disk_io_tune = DiskIoTune {
+                read_sys: ReadSys::IoUring,
+                read_buffer_len: 1024 * 4,
+                read_queue_len: 3,
+            };
+            let mut stream = crate::file_content_stream(path.clone(), file, disk_io_tune, "test");
+            let mut got = Vec::new();
+            while let Some(item) = stream.next().await {
+                got.extend_from_slice(item?.buf());
+            }
+            Ok::<_, Error>(got)
+        };
+        let got = taskrun::run(fut);
+        std::fs::remove_file(&path)?;
+        assert_eq!(got?, data[77..]);
+        Ok(())
+    }
+}
@@ -0,0 +1,760 @@
+//! Local pulse map index of a node, kept outside of the database.
+//!
+//! The index file starts with an 8 byte magic, followed by fixed size records sorted by pulse:
+//! pulse, timestamp and position of the event in its data file as `u64`, then the number of the
+//! data file as `u32` and 4 bytes of padding, all big endian like the databuffer files.
+//! The data files are listed in a companion `.files` file, one path relative to the data base
+//! path per line. Both files are only ever appended to, so that a mapping of the index stays valid.
+//! Each event of the timer channels gets a record, so a pulse seen by several timer channels has
+//! several records. Updates and verifications hold a lock on the companion `.lock` file.
+
+use err::Error;
+use memmap2::Advice;
+use memmap2::Mmap;
+use netpod::log::*;
+use netpod::timeunits::SEC;
+use netpod::SfDatabuffer;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufWriter;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use taskrun::tokio;
+
+const MAGIC: &[u8; 8] = b"DQPULIX1";
+const HEAD_LEN: u64 = MAGIC.len() as u64;
+const RECORD_LEN: u64 = 32;
+
+// The timer channel files are binned by day.
+const TIMEBIN: u64 = SEC * 86400;
+
+// Event length, ttl, timestamp and pulse.
+const EVENT_HEAD_LEN: usize = 28;
+
+/// At most this many problems are listed by the verification.
+const VERIFY_ERRORS_MAX: usize = 100;
+
+static PULSE_INDEX: Mutex<Option<Arc<PulseIndex>>> = Mutex::new(None);
+
+pub fn timer_channel_names() -> Vec<String> {
+    let sections = ["SINEG01", "SINSB01", "SINSB02", "SINSB03", "SINSB04", "SINXB01"];
+    let suffixes = ["MASTER"];
+    let mut all: Vec<_> = sections
+        .iter()
+        .flat_map(|sec| {
+            suffixes
+                .iter()
+                .map(move |suf| format!("{}-RLLE-STA:{}-EVRPULSEID", sec, suf))
+        })
+        .collect();
+    all.push("SIN-CVME-TIFGUN-EVR0:RX-PULSEID".into());
+    all.push("SAR-CVME-TIFALL4:EvtSet".into());
+    all.push("SAR-CVME-TIFALL5:EvtSet".into());
+    all.push("SAR-CVME-TIFALL6:EvtSet".into());
+    all.push("SAT-CVME-TIFALL5:EvtSet".into());
+    all.push("SAT-CVME-TIFALL6:EvtSet".into());
+    all
+}
+
+fn files_path(path: &Path) -> PathBuf {
+    let mut ret = path.as_os_str().to_owned();
+    ret.push(".files");
+    ret.into()
+}
+
+/// Locks the index against changes by other tasks and processes until the returned file is dropped.
+/// Readers of the mapping do not need the lock because the index is only appended to.
+fn lock_index(path: &Path, exclusive: bool) -> Result<File, Error> {
+    use fs2::FileExt;
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(lock_path)?;
+    if exclusive {
+        file.lock_exclusive()?;
+    } else {
+        file.lock_shared()?;
+    }
+    Ok(file)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Record {
+    pulse: u64,
+    ts: u64,
+    pos: u64,
+    file: u32,
+}
+
+impl Record {
+    fn from_bytes(b: &[u8]) -> Self {
+        Self {
+            pulse: u64::from_be_bytes(b[0..8].try_into().unwrap()),
+            ts: u64::from_be_bytes(b[8..16].try_into().unwrap()),
+            pos: u64::from_be_bytes(b[16..24].try_into().unwrap()),
+            file: u32::from_be_bytes(b[24..28].try_into().unwrap()),
+        }
+    }
+
+    fn to_bytes(self) -> [u8; RECORD_LEN as usize] {
+        let mut ret = [0; RECORD_LEN as usize];
+        ret[0..8].copy_from_slice(&self.pulse.to_be_bytes());
+        ret[8..16].copy_from_slice(&self.ts.to_be_bytes());
+        ret[16..24].copy_from_slice(&self.pos.to_be_bytes());
+        ret[24..28].copy_from_slice(&self.file.to_be_bytes());
+        ret
+    }
+}
+
+/// Which side of the mapping to search by. Pulses and timestamps both increase through the index.
+#[derive(Clone, Copy, Debug)]
+pub enum PulseIndexKey {
+    Pulse(u64),
+    Ts(u64),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PulseIndexEntry {
+    pub pulse: u64,
+    pub ts: u64,
+    /// Position of the event in the data file.
+    pub pos: u64,
+    /// Data file, relative to the data base path.
+    pub file: PathBuf,
+}
+
+impl PulseIndexEntry {
+    /// The timer channel of the data file.
+    pub fn channel(&self) -> Option<&str> {
+        // Keyspace, `byTime`, channel, timebin, split and file name.
+        self.file.iter().nth(2).and_then(|x| x.to_str())
+    }
+}
+
+/// A memory mapped pulse index.
+pub struct PulseIndex {
+    path: PathBuf,
+    len: u64,
+    mtime: Option<SystemTime>,
+    map: Mmap,
+    files: Vec<PathBuf>,
+}
+
+impl PulseIndex {
+    /// Number of records. A partially written record at the end is not counted.
+    pub fn len(&self) -> u64 {
+        (self.map.len() as u64).saturating_sub(HEAD_LEN) / RECORD_LEN
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn record(&self, i: u64) -> Record {
+        let p = (HEAD_LEN + RECORD_LEN * i) as usize;
+        Record::from_bytes(&self.map[p..p + RECORD_LEN as usize])
+    }
+
+    pub fn entry(&self, i: u64) -> Option<PulseIndexEntry> {
+        if i >= self.len() {
+            return None;
+        }
+        let r = self.record(i);
+        let ret = PulseIndexEntry {
+            pulse: r.pulse,
+            ts: r.ts,
+            pos: r.pos,
+            file: self.files.get(r.file as usize).cloned().unwrap_or_default(),
+        };
+        Some(ret)
+    }
+
+    /// The number of the first record at or after the key.
+    pub fn partition_point(&self, key: PulseIndexKey) -> u64 {
+        let mut lo = 0;
+        let mut hi = self.len();
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            let r = self.record(mid);
+            let less = match key {
+                PulseIndexKey::Pulse(x) => r.pulse < x,
+                PulseIndexKey::Ts(x) => r.ts < x,
+            };
+            if less {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        lo
+    }
+
+    /// The first of the records of the pulse.
+    pub fn find_pulse(&self, pulse: u64) -> Option<PulseIndexEntry> {
+        self.entry(self.partition_point(PulseIndexKey::Pulse(pulse)))
+            .filter(|x| x.pulse == pulse)
+    }
+
+    /// The records of the pulse, one for each timer channel which has the pulse.
+    pub fn find_pulse_all(&self, pulse: u64) -> Vec<PulseIndexEntry> {
+        (self.partition_point(PulseIndexKey::Pulse(pulse))..self.len())
+            .map_while(|i| self.entry(i).filter(|x| x.pulse == pulse))
+            .collect()
+    }
+}
+
+fn read_files_table(path: &Path) -> Result<Vec<PathBuf>, io::Error> {
+    let s = match std::fs::read_to_string(files_path(path)) {
+        Ok(x) => x,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e),
+    };
+    // A line is only complete with its newline.
+    let n = s.rfind('\n').map_or(0, |x| x + 1);
+    Ok(s[..n].lines().map(PathBuf::from).collect())
+}
+
+fn check_magic(map: &[u8], path: &Path) -> Result<(), Error> {
+    if map.len() >= MAGIC.len() && &map[..MAGIC.len()] != MAGIC {
+        return Err(Error::with_msg_no_trace(format!(
+            "not a pulse index: {}",
+            path.display()
+        )));
+    }
+    Ok(())
+}
+
+fn open_blocking(path: &Path) -> Result<Arc<PulseIndex>, Error> {
+    let file = File::open(path)?;
+    let meta = file.metadata()?;
+    let mtime = meta.modified().ok();
+    if let Some(x) = PULSE_INDEX.lock().unwrap().as_ref() {
+        if x.path == path && x.len == meta.len() && x.mtime == mtime {
+            return Ok(x.clone());
+        }
+    }
+    // Safety: the index is only appended to, the mapping covers the length at open time.
+    let map = unsafe { Mmap::map(&file)? };
+    map.advise(Advice::Random)?;
+    check_magic(&map, path)?;
+    // The files table is written before the records which refer to it.
+    let files = read_files_table(path)?;
+    let ret = Arc::new(PulseIndex {
+        path: path.into(),
+        len: meta.len(),
+        mtime,
+        map,
+        files,
+    });
+    *PULSE_INDEX.lock().unwrap() = Some(ret.clone());
+    Ok(ret)
+}
+
+/// Maps the index or returns the cached mapping if the file did not change since.
+pub async fn open_pulse_index(path: PathBuf) -> Result<Arc<PulseIndex>, Error> {
+    tokio::task::spawn_blocking(move || open_blocking(&path))
+        .await
+        .map_err(Error::from_string)?
+}
+
+/// Reads the events of one timer channel file in order.
+struct EventCursor {
+    data: Mmap,
+    index: Option<Mmap>,
+    first: u64,
+    event_len: u64,
+    n: u64,
+    i: u64,
+    file: u32,
+}
+
+impl EventCursor {
+    fn open(path: &Path, file: u32) -> Result<Option<Self>, Error> {
+        let data = File::open(path)?;
+        // Safety: data files are only ever appended to.
+        let data = unsafe { Mmap::map(&data)? };
+        let mut path_index = path.as_os_str().to_owned();
+        path_index.push("_Index");
+        let ret = match File::open(&path_index) {
+            Ok(index) => {
+                let index = unsafe { Mmap::map(&index)? };
+                let n = (index.len() as u64).saturating_sub(2) / 16;
+                Self {
+                    data,
+                    index: Some(index),
+                    first: 0,
+                    event_len: 0,
+                    n,
+                    i: 0,
+                    file,
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let (len1,) = crate::index::parse_channel_header(&data)?;
+                let first = 2 + len1 as u64;
+                let event_len = match data.get(first as usize..first as usize + 4) {
+                    Some(b) => u32::from_be_bytes(b.try_into().unwrap()) as u64,
+                    None => return Ok(None),
+                };
+                if (event_len as usize) < EVENT_HEAD_LEN {
+                    return Err(Error::with_msg_no_trace(format!(
+                        "unexpected event len {event_len} in {}",
+                        path.display()
+                    )));
+                }
+                let n = (data.len() as u64 - first) / event_len;
+                Self {
+                    data,
+                    index: None,
+                    first,
+                    event_len,
+                    n,
+                    i: 0,
+                    file,
+                }
+            }
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Some(ret))
+    }
+
+    /// Pulse, timestamp and position of the event.
+    fn event(&self, i: u64) -> Option<(u64, u64, u64)> {
+        let pos = match &self.index {
+            Some(index) => {
+                let p = 2 + 16 * i as usize;
+                u64::from_be_bytes(index.get(p + 8..p + 16)?.try_into().ok()?)
+            }
+            None => self.first + self.event_len * i,
+        };
+        let b = self.data.get(pos as usize..pos as usize + EVENT_HEAD_LEN)?;
+        let ts = u64::from_be_bytes(b[12..20].try_into().ok()?);
+        let pulse = u64::from_be_bytes(b[20..28].try_into().ok()?);
+        Some((pulse, ts, pos))
+    }
+
+    /// Moves to the first event at or after the pulse, assuming pulses increase through the file.
+    fn skip_to(&mut self, pulse: u64) {
+        let mut lo = 0;
+        let mut hi = self.n;
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.event(mid) {
+                Some(x) if x.0 < pulse => lo = mid + 1,
+                _ => hi = mid,
+            }
+        }
+        self.i = lo;
+    }
+
+    fn next(&mut self) -> Option<Record> {
+        if self.i >= self.n {
+            return None;
+        }
+        let (pulse, ts, pos) = self.event(self.i)?;
+        self.i += 1;
+        let ret = Record {
+            pulse,
+            ts,
+            pos,
+            file: self.file,
+        };
+        Some(ret)
+    }
+}
+
+/// The timer channel data files below the data base path, by timebin.
+fn timer_files(sfc: &SfDatabuffer, timebin_min: u64) -> Result<BTreeMap<u64, Vec<PathBuf>>, Error> {
+    let mut ret: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for ks in [2, 3] {
+        let ksdir = PathBuf::from(format!("{}_{}", sfc.ksprefix, ks)).join("byTime");
+        for channel in timer_channel_names() {
+            let chdir = ksdir.join(&channel);
+            let rd = match std::fs::read_dir(sfc.data_base_path.join(&chdir)) {
+                Ok(x) => x,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+            };
+            for e1 in rd {
+                let e1 = e1?;
+                let timebin: u64 = match e1.file_name().to_str().and_then(|x| x.parse().ok()) {
+                    Some(x) => x,
+                    None => continue,
+                };
+                if timebin < timebin_min {
+                    continue;
+                }
+                for e2 in std::fs::read_dir(e1.path())? {
+                    let e2 = e2?;
+                    for e3 in std::fs::read_dir(e2.path())? {
+                        let e3 = e3?;
+                        if e3.file_name().to_string_lossy().ends_with("_00000_Data") {
+                            let rel = chdir.join(e1.file_name()).join(e2.file_name()).join(e3.file_name());
+                            ret.entry(timebin).or_default().push(rel);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    Ok(ret)
+}
+
+#[derive(Debug, Default)]
+pub struct PulseIndexUpdate {
+    /// Data files which were read.
+    pub files: usize,
+    /// Records appended by this update.
+    pub appended: u64,
+    /// Records in the index after the update.
+    pub records: u64,
+    pub last_pulse: Option<u64>,
+}
+
+fn update_blocking(path: &Path, sfc: &SfDatabuffer) -> Result<PulseIndexUpdate, Error> {
+    let _lock = lock_index(path, true)?;
+    update_locked(path, sfc)
+}
+
+fn update_locked(path: &Path, sfc: &SfDatabuffer) -> Result<PulseIndexUpdate, Error> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)?;
+    let len = file.metadata()?.len();
+    if len < HEAD_LEN {
+        file.set_len(0)?;
+        file.write_all(MAGIC)?;
+    } else {
+        let mut magic = [0; MAGIC.len()];
+        file.read_exact(&mut magic)?;
+        check_magic(&magic, path)?;
+    }
+    // Drop a record which was not completely written.
+    let records = (len.max(HEAD_LEN) - HEAD_LEN) / RECORD_LEN;
+    let len = HEAD_LEN + RECORD_LEN * records;
+    file.set_len(len)?;
+    let mut last = None;
+    // The records of the last pulse, other timer channels may still add theirs.
+    let mut last_events = Vec::new();
+    for i in (0..records).rev() {
+        file.seek(SeekFrom::Start(HEAD_LEN + RECORD_LEN * i))?;
+        let mut buf = [0; RECORD_LEN as usize];
+        file.read_exact(&mut buf)?;
+        let r = Record::from_bytes(&buf);
+        if last.is_some_and(|x: Record| x.pulse != r.pulse) {
+            break;
+        }
+        last.get_or_insert(r);
+        last_events.push((r.file, r.pos));
+    }
+    file.seek(SeekFrom::End(0))?;
+    let mut files = read_files_table(path)?;
+    let mut files_out = OpenOptions::new().create(true).append(true).open(files_path(path))?;
+    // Drop a line which was not completely written.
+    let files_len = files.iter().map(|x| x.as_os_str().len() as u64 + 1).sum();
+    files_out.set_len(files_len)?;
+    let mut out = BufWriter::new(file);
+    let mut ret = PulseIndexUpdate {
+        records,
+        last_pulse: last.map(|x| x.pulse),
+        ..Default::default()
+    };
+    // Timer channels which lag behind the others do not add pulses before the last indexed one.
+    let timebin_min = last.map_or(0, |x| x.ts / TIMEBIN);
+    for (timebin, paths) in timer_files(sfc, timebin_min)? {
+        let mut cursors = Vec::new();
+        for rel in paths {
+            let fno = match files.iter().position(|x| x == &rel) {
+                Some(x) => x,
+                None => {
+                    writeln!(files_out, "{}", rel.display())?;
+                    files.push(rel.clone());
+                    files.len() - 1
+                }
+            };
+            match EventCursor::open(&sfc.data_base_path.join(&rel), fno as u32) {
+                Ok(Some(mut x)) => {
+                    if let Some(pulse) = ret.last_pulse {
+                        x.skip_to(pulse);
+                    }
+                    cursors.push(x);
+                }
+                Ok(None) => {}
+                Err(e) => warn!("can not index {}: {e}", rel.display()),
+            }
+        }
+        files_out.sync_data()?;
+        ret.files += cursors.len();
+        // Merge the channels by pulse, a record for each event.
+        let mut heap = BinaryHeap::new();
+        let mut heads: Vec<Option<Record>> = vec![None; cursors.len()];
+        for (i, c) in cursors.iter_mut().enumerate() {
+            if let Some(r) = c.next() {
+                heap.push(Reverse((r.pulse, i)));
+                heads[i] = Some(r);
+            }
+        }
+        while let Some(Reverse((_, i))) = heap.pop() {
+            let r = heads[i].take().unwrap();
+            let new = match ret.last_pulse {
+                None => true,
+                Some(x) if r.pulse > x => true,
+                Some(x) => r.pulse == x && !last_events.contains(&(r.file, r.pos)),
+            };
+            if new {
+                out.write_all(&r.to_bytes())?;
+                ret.last_pulse = Some(r.pulse);
+                ret.appended += 1;
+                ret.records += 1;
+            }
+            if let Some(r) = cursors[i].next() {
+                heap.push(Reverse((r.pulse, i)));
+                heads[i] = Some(r);
+            }
+        }
+        out.flush()?;
+        trace!("pulse index timebin {timebin} done, {} records", ret.records);
+    }
+    out.into_inner().map_err(|e| e.into_error())?.sync_data()?;
+    Ok(ret)
+}
+
+/// Appends the pulses of the timer channels which are newer than the last one in the index.
+/// Creates the index if it does not exist yet.
+pub async fn update_pulse_index(path: PathBuf, sfc: SfDatabuffer) -> Result<PulseIndexUpdate, Error> {
+    tokio::task::spawn_blocking(move || update_blocking(&path, &sfc))
+        .await
+        .map_err(Error::from_string)?
+}
+
+fn rebuild_blocking(path: &Path, sfc: &SfDatabuffer) -> Result<PulseIndexUpdate, Error> {
+    let _lock = lock_index(path, true)?;
+    for p in [files_path(path), path.into()] {
+        match std::fs::remove_file(&p) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    update_locked(path, sfc)
+}
+
+/// Builds the index again from all timer channel files.
+pub async fn rebuild_pulse_index(path: PathBuf, sfc: SfDatabuffer) -> Result<PulseIndexUpdate, Error> {
+    tokio::task::spawn_blocking(move || rebuild_blocking(&path, &sfc))
+        .await
+        .map_err(Error::from_string)?
+}
+
+#[derive(Debug, Default)]
+pub struct PulseIndexVerify {
+    pub records: u64,
+    pub files: usize,
+    /// Records which were compared against the event in the data file.
+    pub checked: u64,
+    pub errors: u64,
+    /// The first problems found.
+    pub messages: Vec<String>,
+}
+
+impl PulseIndexVerify {
+    fn error(&mut self, msg: String) {
+        self.errors += 1;
+        if self.messages.len() < VERIFY_ERRORS_MAX {
+            self.messages.push(msg);
+        }
+    }
+}
+
+fn verify_blocking(path: &Path, sfc: &SfDatabuffer, stride: u64) -> Result<PulseIndexVerify, Error> {
+    // A record which an update is just writing would count as incomplete.
+    let _lock = lock_index(path, false)?;
+    let idx = open_blocking(path)?;
+    let mut ret = PulseIndexVerify {
+        records: idx.len(),
+        files: idx.files.len(),
+        ..Default::default()
+    };
+    if idx.map.len() < MAGIC.len() {
+        ret.error("index has no header".into());
+        return Ok(ret);
+    }
+    let rest = (idx.map.len() as u64 - HEAD_LEN) % RECORD_LEN;
+    if rest != 0 {
+        ret.error(format!("{rest} bytes of an incomplete record at the end"));
+    }
+    let mut datas: BTreeMap<u32, Option<Mmap>> = BTreeMap::new();
+    let mut prev: Option<Record> = None;
+    for i in 0..idx.len() {
+        let r = idx.record(i);
+        if let Some(p) = prev {
+            if r.pulse < p.pulse {
+                ret.error(format!("record {i} pulse {} after {}", r.pulse, p.pulse));
+            }
+            if r.ts < p.ts {
+                ret.error(format!("record {i} ts {} before {}", r.ts, p.ts));
+            }
+        }
+        prev = Some(r);
+        let rel = match idx.files.get(r.file as usize) {
+            Some(x) => x,
+            None => {
+                ret.error(format!("record {i} refers to unknown file {}", r.file));
+                continue;
+            }
+        };
+        if i % stride.max(1) != 0 && i + 1 != idx.len() {
+            continue;
+        }
+        let data = datas.entry(r.file).or_insert_with(|| {
+            let f = File::open(sfc.data_base_path.join(rel)).ok()?;
+            unsafe { Mmap::map(&f) }.ok()
+        });
+        let data = match data {
+            Some(x) => x,
+            None => {
+                ret.error(format!("record {i} can not open {}", rel.display()));
+                continue;
+            }
+        };
+        ret.checked += 1;
+        match data.get(r.pos as usize..r.pos as usize + EVENT_HEAD_LEN) {
+            Some(b) => {
+                let ts = u64::from_be_bytes(b[12..20].try_into().unwrap());
+                let pulse = u64::from_be_bytes(b[20..28].try_into().unwrap());
+                if ts != r.ts || pulse != r.pulse {
+                    ret.error(format!(
+                        "record {i} pulse {} ts {} but {} has pulse {pulse} ts {ts} at {}",
+                        r.pulse,
+                        r.ts,
+                        rel.display(),
+                        r.pos
+                    ));
+                }
+            }
+            None => ret.error(format!("record {i} position {} beyond end of {}", r.pos, rel.display())),
+        }
+    }
+    Ok(ret)
+}
+
+/// Checks that the records are ordered and match the events in the data files.
+/// Of the records, every `stride`-th and the last are compared against the data files.
+pub async fn verify_pulse_index(path: PathBuf, sfc: SfDatabuffer, stride: u64) -> Result<PulseIndexVerify, Error> {
+    tokio::task::spawn_blocking(move || verify_blocking(&path, &sfc, stride))
+        .await
+        .map_err(Error::from_string)?
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write_timer_file(base: &Path, channel: &str, pulses: std::ops::Range<u64>) -> Result<(), Error> {
+        let dir = base
+            .join("ks_2/byTime")
+            .join(channel)
+            .join(format!("{:019}", 0))
+            .join(format!("{:010}", 0));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(format!("{:019}_00000_Data", 86400000));
+        let mut buf = Vec::new();
+        if !path.exists() {
+            buf.extend_from_slice(&0i16.to_be_bytes());
+            buf.extend_from_slice(&10u32.to_be_bytes());
+            buf.extend_from_slice(&[0, 0]);
+            buf.extend_from_slice(&10u32.to_be_bytes());
+        }
+        for pulse in pulses {
+            buf.extend_from_slice(&32u32.to_be_bytes());
+            buf.extend_from_slice(&0u64.to_be_bytes());
+            buf.extend_from_slice(&(1000 * pulse).to_be_bytes());
+            buf.extend_from_slice(&pulse.to_be_bytes());
+            buf.extend_from_slice(&32u32.to_be_bytes());
+        }
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?
+            .write_all(&buf)?;
+        Ok(())
+    }
+
+    #[test]
+    fn build_update_verify() -> Result<(), Error> {
+        let base = std::env::temp_dir().join(format!("daqbuffer-pulseindex-test-{}", std::process::id()));
+        let chs = timer_channel_names();
+        write_timer_file(&base, &chs[0], 10..20)?;
+        write_timer_file(&base, &chs[1], 15..25)?;
+        let sfc = SfDatabuffer {
+            data_base_path: base.clone(),
+            ksprefix: "ks".into(),
+            splits: None,
+            pulse_index: None,
+        };
+        let path = base.join("pulse.index");
+        let fut = async {
+            let res = rebuild_pulse_index(path.clone(), sfc.clone()).await?;
+            assert_eq!(res.records, 20);
+            assert_eq!(res.last_pulse, Some(24));
+            write_timer_file(&base, &chs[1], 25..30)?;
+            // Pulses of the lagging channel before the last indexed one are not added.
+            write_timer_file(&base, &chs[0], 20..26)?;
+            let res = update_pulse_index(path.clone(), sfc.clone()).await?;
+            assert_eq!(res.appended, 7);
+            let res = update_pulse_index(path.clone(), sfc.clone()).await?;
+            assert_eq!(res.appended, 0);
+            let idx = open_pulse_index(path.clone()).await?;
+            assert_eq!(idx.len(), 27);
+            let e = idx.find_pulse(12).unwrap();
+            assert_eq!((e.ts, e.channel()), (12000, Some(chs[0].as_str())));
+            let e = idx.find_pulse(27).unwrap();
+            assert_eq!((e.ts, e.channel()), (27000, Some(chs[1].as_str())));
+            assert_eq!(idx.find_pulse(9), None);
+            let mut all: Vec<_> = idx
+                .find_pulse_all(17)
+                .iter()
+                .map(|x| x.channel().map(String::from))
+                .collect();
+            all.sort();
+            assert_eq!(all, [Some(chs[0].clone()), Some(chs[1].clone())]);
+            assert_eq!(idx.find_pulse_all(24).len(), 2);
+            assert_eq!(idx.find_pulse_all(9).len(), 0);
+            assert_eq!(idx.partition_point(PulseIndexKey::Ts(14500)), 5);
+            let res = verify_pulse_index(path.clone(), sfc.clone(), 1).await?;
+            assert_eq!((res.checked, res.errors), (27, 0));
+            // Concurrent updates do not append the same events twice.
+            write_timer_file(&base, &chs[1], 30..40)?;
+            let (a, b) = futures_util::future::join(
+                update_pulse_index(path.clone(), sfc.clone()),
+                update_pulse_index(path.clone(), sfc.clone()),
+            )
+            .await;
+            assert_eq!(a?.appended + b?.appended, 10);
+            let res = verify_pulse_index(path.clone(), sfc.clone(), 1).await?;
+            assert_eq!((res.records, res.errors), (37, 0));
+            Ok::<_, Error>(())
+        };
+        let res = taskrun::run(fut);
+        std::fs::remove_dir_all(&base)?;
+        res
+    }
+}
@@ -21,9 +21,13 @@ use netpod::ReqCtxArc;
 use netpod::SfChFetchInfo;
 use query::api4::events::EventsSubQuery;
 use std::pin::Pin;
+use taskrun::tokio;
 
 const TEST_BACKEND: &str = "testbackend-00";
 
+/// Number of event batches which get decompressed at the same time.
+const DECOMPRESS_PARALLEL: usize = 4;
+
 fn make_num_pipeline_stream_evs(
     fetch_info: SfChFetchInfo,
     agg_kind: AggKind,
@@ -62,7 +66,7 @@ pub async fn make_event_pipe(
 ) -> Result<Pin<Box<dyn Stream<Item = Sitemty<ChannelEvents>> + Send>>, Error> {
     // sf-databuffer type backends identify channels by their (backend, name) only.
     let range = evq.range().clone();
-    let one_before = evq.transform().need_one_before_range();
+    let one_before = evq.need_one_before_range();
     info!(
         "make_event_pipe  need_expand {need_expand}  {evq:?}",
         need_expand = one_before
@@ -128,7 +132,7 @@ pub fn make_event_blobs_pipe_real(
     reqctx: ReqCtxArc,
     node_config: &NodeConfigCached,
 ) -> Result<Pin<Box<dyn Stream<Item = Sitemty<EventFull>> + Send>>, Error> {
-    let expand = subq.transform().need_one_before_range();
+    let expand = subq.need_one_before_range();
     let range = subq.range();
     let event_chunker_conf = EventChunkerConf::new(ByteSize::from_kb(1024));
     let event_blobs = make_event_blobs_stream(
@@ -194,9 +198,37 @@ pub fn make_event_blobs_pipe(
     node_config: &NodeConfigCached,
 ) -> Result<Pin<Box<dyn Stream<Item = Sitemty<EventFull>> + Send>>, Error> {
     debug!("make_event_blobs_pipe {subq:?}");
-    if subq.backend() == TEST_BACKEND {
-        make_event_blobs_pipe_test(subq, node_config)
+    let pipe = if subq.backend() == TEST_BACKEND {
+        make_event_blobs_pipe_test(subq, node_config)?
+    } else {
+        make_event_blobs_pipe_real(subq, fetch_info, reqctx, node_config)?
+    };
+    if subq.transform().is_event_blobs_uncompressed() {
+        Ok(decompress_event_blobs(pipe))
     } else {
-        make_event_blobs_pipe_real(subq, fetch_info, reqctx, node_config)
+        Ok(pipe)
     }
 }
+
+/// Decompresses the event blobs on the blocking pool, several batches in parallel, order is kept.
+fn decompress_event_blobs(
+    inp: Pin<Box<dyn Stream<Item = Sitemty<EventFull>> + Send>>,
+) -> Pin<Box<dyn Stream<Item = Sitemty<EventFull>> + Send>> {
+    let stream = inp
+        .map(|item| async move {
+            match item {
+                Ok(StreamItem::DataItem(RangeCompletableItem::Data(evs))) => {
+                    let mut evs = evs;
+                    let jh = tokio::task::spawn_blocking(move || evs.decompress_all().map(|_| evs));
+                    match jh.await {
+                        Ok(Ok(evs)) => Ok(StreamItem::DataItem(RangeCompletableItem::Data(evs))),
+                        Ok(Err(e)) => Err(Error::with_msg_no_trace(e.to_string())),
+                        Err(e) => Err(Error::from_string(e)),
+                    }
+                }
+                x => x,
+            }
+        })
+        .buffered(DECOMPRESS_PARALLEL);
+    Box::pin(stream)
+}
@@ -9,6 +9,7 @@ use netpod::range::evrange::NanoRange;
 use netpod::ByteOrder;
 use netpod::ByteSize;
 use netpod::SfChFetchInfo;
+use netpod::SfDatabuffer;
 use std::path::PathBuf;
 use tokio::fs::File;
 use tokio::io::AsyncReadExt;
@@ -26,6 +27,8 @@ pub struct Opts {
 pub enum SubCmd {
     ReadDatabufferConfigfile(ReadDatabufferConfigfile),
     ReadDatabufferDatafile(ReadDatabufferDatafile),
+    PulseIndexVerify(PulseIndexVerify),
+    PulseIndexRebuild(PulseIndexRebuild),
 }
 
 #[derive(Debug, Parser)]
@@ -42,6 +45,43 @@ pub struct ReadDatabufferDatafile {
     datafile: PathBuf,
 }
 
+#[derive(Debug, Parser)]
+pub struct PulseIndexArgs {
+    #[arg(long)]
+    data_base_path: PathBuf,
+    #[arg(long)]
+    ksprefix: String,
+    /// The pulse index file.
+    #[arg(long)]
+    index: PathBuf,
+}
+
+impl PulseIndexArgs {
+    fn sfc(&self) -> SfDatabuffer {
+        SfDatabuffer {
+            data_base_path: self.data_base_path.clone(),
+            ksprefix: self.ksprefix.clone(),
+            splits: None,
+            pulse_index: Some(self.index.clone()),
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+pub struct PulseIndexVerify {
+    #[command(flatten)]
+    args: PulseIndexArgs,
+    /// Compare only every n-th record against the data files.
+    #[arg(long, default_value_t = 1)]
+    stride: u64,
+}
+
+#[derive(Debug, Parser)]
+pub struct PulseIndexRebuild {
+    #[command(flatten)]
+    args: PulseIndexArgs,
+}
+
 pub fn main() -> Result<(), Error> {
     taskrun::run(async {
         if false {
@@ -98,6 +138,29 @@ pub fn main() -> Result<(), Error> {
                 err::todo();
                 Ok(())
             }
+            SubCmd::PulseIndexVerify(sub) => {
+                let res =
+                    disk::pulseindex::verify_pulse_index(sub.args.index.clone(), sub.args.sfc(), sub.stride).await?;
+                for msg in &res.messages {
+                    eprintln!("{msg}");
+                }
+                eprintln!(
+                    "records {}  files {}  checked {}  errors {}",
+                    res.records, res.files, res.checked, res.errors
+                );
+                if res.errors != 0 {
+                    return Err(Error::with_msg_no_trace(format!(
+                        "pulse index has {} errors",
+                        res.errors
+                    )));
+                }
+                Ok(())
+            }
+            SubCmd::PulseIndexRebuild(sub) => {
+                let res = disk::pulseindex::rebuild_pulse_index(sub.args.index.clone(), sub.args.sfc()).await?;
+                eprintln!("Rebuilt pulse index: {:?}", res);
+                Ok(())
+            }
         }
     })
 }
@@ -2,7 +2,9 @@ use bytes::Bytes;
 use err::Error;
 use err::PublicError;
 use futures_util::pin_mut;
+use futures_util::Future;
 use http::header;
+use http::HeaderValue;
 use http::Request;
 use http::Response;
 use http::StatusCode;
@@ -15,6 +17,7 @@ use netpod::ChannelConfigQuery;
 use netpod::ChannelConfigResponse;
 use netpod::NodeConfigCached;
 use std::pin::Pin;
+use std::sync::OnceLock;
 use std::task::Context;
 use std::task::Poll;
 use tokio::io;
@@ -40,13 +43,56 @@ impl<T, E: Convable> ErrConv<T> for Result<T, E> {
 impl Convable for http::Error {}
 impl Convable for hyper::Error {}
 
+static NODE_AUTHORIZATION: OnceLock<HeaderValue> = OnceLock::new();
+static NODE_TOKEN: OnceLock<String> = OnceLock::new();
+
+tokio::task_local! {
+    static FORWARD_AUTHORIZATION: HeaderValue;
+}
+
+/// Sets the token which this node presents to the other nodes of its cluster.
+pub fn set_node_token(token: &str) -> Result<(), Error> {
+    let v = HeaderValue::from_str(&format!("Bearer {token}")).map_err(|e| Error::with_msg_no_trace(e.to_string()))?;
+    let _ = NODE_AUTHORIZATION.set(v);
+    let _ = NODE_TOKEN.set(token.into());
+    Ok(())
+}
+
+/// The token which this node presents to the other nodes of its cluster, if one is set.
+pub fn node_token() -> Option<&'static str> {
+    NODE_TOKEN.get().map(String::as_str)
+}
+
+/// Runs the future such that `with_authorization` passes on the credentials of the request being served.
+pub async fn forward_authorization<F>(value: Option<HeaderValue>, fut: F) -> F::Output
+where
+    F: Future,
+{
+    match value {
+        Some(value) => FORWARD_AUTHORIZATION.scope(value, fut).await,
+        None => fut.await,
+    }
+}
+
+/// Adds the credentials for a request to another service: the forwarded credentials of the request
+/// being served if there are any, else the node token.
+pub fn with_authorization(req: http::request::Builder) -> http::request::Builder {
+    match FORWARD_AUTHORIZATION.try_with(|x| x.clone()) {
+        Ok(v) => req.header(header::AUTHORIZATION, v),
+        Err(_) => match NODE_AUTHORIZATION.get() {
+            Some(v) => req.header(header::AUTHORIZATION, v.clone()),
+            None => req,
+        },
+    }
+}
+
 pub struct HttpResponse {
     pub head: http::response::Parts,
     pub body: Bytes,
 }
 
 pub async fn http_get(url: Url, accept: &str) -> Result<HttpResponse, Error> {
-    let req = Request::builder()
+    let req = with_authorization(Request::builder())
         .method(http::Method::GET)
         .uri(url.to_string())
         .header(header::ACCEPT, accept)
@@ -62,7 +108,7 @@ pub async fn http_get(url: Url, accept: &str) -> Result<HttpResponse, Error> {
 }
 
 pub async fn http_post(url: Url, accept: &str, body: String) -> Result<Bytes, Error> {
-    let req = Request::builder()
+    let req = with_authorization(Request::builder())
         .method(http::Method::POST)
         .uri(url.to_string())
         .header(header::ACCEPT, accept)
@@ -159,7 +205,7 @@ pub async fn get_channel_config(
         node_config.node.host, node_config.node.port
     ))?;
     q.append_to_url(&mut url);
-    let req = hyper::Request::builder()
+    let req = with_authorization(hyper::Request::builder())
         .method(Method::GET)
         .uri(url.as_str())
         .body(Body::empty())
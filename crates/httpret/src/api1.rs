@@ -144,16 +144,23 @@ pub async fn channel_search_list_v1(req: Request<Body>, proxy_config: &ProxyConf
                     name_regex: query.regex.map_or(String::new(), |k| k),
                     source_regex: query.source_regex.map_or(String::new(), |k| k),
                     description_regex: query.description_regex.map_or(String::new(), |k| k),
+                    ..Default::default()
                 };
+                let mut leases = Vec::new();
                 let urls = proxy_config
                     .backends
                     .iter()
-                    .map(|sh| match Url::parse(&format!("{}/api/4/search/channel", sh.url)) {
-                        Ok(mut url) => {
-                            query.append_to_url(&mut url);
-                            Ok(url)
+                    .map(|sh| {
+                        let lease = crate::proxy::balance::pick_one(sh)?;
+                        let url = Url::parse(&format!("{}/api/4/search/channel", lease.url()));
+                        leases.push(lease);
+                        match url {
+                            Ok(mut url) => {
+                                query.append_to_url(&mut url);
+                                Ok(url)
+                            }
+                            Err(e) => Err(Error::with_msg(format!("parse error for: {:?}  {:?}", sh, e))),
                         }
-                        Err(e) => Err(Error::with_msg(format!("parse error for: {:?}  {:?}", sh, e))),
                     })
                     .fold_ok(vec![], |mut a, x| {
                         a.push(x);
@@ -165,7 +172,10 @@ pub async fn channel_search_list_v1(req: Request<Body>, proxy_config: &ProxyConf
                         let body = hyper::body::to_bytes(res).await?;
                         let res: ChannelSearchResult = match serde_json::from_slice(&body) {
                             Ok(k) => k,
-                            Err(_) => ChannelSearchResult { channels: vec![] },
+                            Err(_) => ChannelSearchResult {
+                                channels: vec![],
+                                total: None,
+                            },
                         };
                         let ret = SubRes {
                             tag,
@@ -224,6 +234,7 @@ pub async fn channel_search_list_v1(req: Request<Body>, proxy_config: &ProxyConf
                     Duration::from_millis(3000),
                 )
                 .await?;
+                drop(leases);
                 Ok(ret)
             } else {
                 Ok(response(StatusCode::NOT_ACCEPTABLE).body(Body::empty())?)
@@ -250,16 +261,23 @@ pub async fn channel_search_configs_v1(
                     name_regex: query.regex.map_or(String::new(), |k| k),
                     source_regex: query.source_regex.map_or(String::new(), |k| k),
                     description_regex: query.description_regex.map_or(String::new(), |k| k),
+                    ..Default::default()
                 };
+                let mut leases = Vec::new();
                 let urls = proxy_config
                     .backends
                     .iter()
-                    .map(|sh| match Url::parse(&format!("{}/api/4/search/channel", sh.url)) {
-                        Ok(mut url) => {
-                            query.append_to_url(&mut url);
-                            Ok(url)
+                    .map(|sh| {
+                        let lease = crate::proxy::balance::pick_one(sh)?;
+                        let url = Url::parse(&format!("{}/api/4/search/channel", lease.url()));
+                        leases.push(lease);
+                        match url {
+                            Ok(mut url) => {
+                                query.append_to_url(&mut url);
+                                Ok(url)
+                            }
+                            Err(e) => Err(Error::with_msg(format!("parse error for: {:?}  {:?}", sh, e))),
                         }
-                        Err(e) => Err(Error::with_msg(format!("parse error for: {:?}  {:?}", sh, e))),
                     })
                     .fold_ok(vec![], |mut a, x| {
                         a.push(x);
@@ -271,7 +289,10 @@ pub async fn channel_search_configs_v1(
                         let body = hyper::body::to_bytes(res).await?;
                         let res: ChannelSearchResult = match serde_json::from_slice(&body) {
                             Ok(k) => k,
-                            Err(_) => ChannelSearchResult { channels: vec![] },
+                            Err(_) => ChannelSearchResult {
+                                channels: vec![],
+                                total: None,
+                            },
                         };
                         let ret = SubRes {
                             tag,
@@ -348,6 +369,7 @@ pub async fn channel_search_configs_v1(
                     Duration::from_millis(3000),
                 )
                 .await?;
+                drop(leases);
                 Ok(ret)
             } else {
                 Ok(response(StatusCode::NOT_ACCEPTABLE).body(Body::empty())?)
@@ -739,10 +761,16 @@ impl DataApiPython3DataStream {
 
     // TODO this stream can currently only handle sf-databuffer type backend anyway.
     fn handle_config_fut_ready(&mut self, fetch_info: SfChFetchInfo) -> Result<(), Error> {
+        // Let the nodes do the decompression, they do it in parallel.
+        let transform = if self.do_decompress {
+            TransformQuery::for_event_blobs_uncompressed()
+        } else {
+            TransformQuery::for_event_blobs()
+        };
         let select = EventsSubQuerySelect::new(
             ChannelTypeConfigGen::SfDatabuffer(fetch_info.clone()),
             self.range.clone().into(),
-            TransformQuery::for_event_blobs(),
+            transform,
         );
         let subq = EventsSubQuery::from_parts(select, self.settings.clone(), self.reqctx.reqid().into());
         debug!("query for event blobs retrieval  subq {subq:?}");
@@ -1,6 +1,9 @@
 pub mod binned;
+pub mod cost;
+pub mod csv;
 pub mod databuffer_tools;
 pub mod eventdata;
 pub mod events;
+pub mod latest;
 pub mod search;
 pub mod status;
@@ -1,17 +1,21 @@
+use crate::api4::csv;
 use crate::bodystream::response;
 use crate::bodystream::ToPublicResponse;
 use crate::channelconfig::ch_conf_from_binned;
 use crate::err::Error;
 use crate::response_err;
+use futures_util::stream;
 use http::Method;
 use http::Request;
 use http::Response;
 use http::StatusCode;
 use hyper::Body;
+use netpod::get_url_query_pairs;
 use netpod::log::*;
 use netpod::timeunits::SEC;
 use netpod::FromUrl;
 use netpod::NodeConfigCached;
+use netpod::TEXT_CSV;
 use query::api4::binned::BinnedQuery;
 use tracing::Instrument;
 use url::Url;
@@ -28,7 +32,7 @@ async fn binned_json(url: Url, req: Request<Body>, node_config: &NodeConfigCache
         e.add_public_msg(msg)
     })?;
     // TODO handle None case better and return 404
-    let ch_conf = ch_conf_from_binned(&query, node_config)
+    let ch_confs = ch_conf_from_binned(&query, node_config)
         .await?
         .ok_or_else(|| Error::with_msg_no_trace("channel not found"))?;
     let span1 = span!(
@@ -42,14 +46,54 @@ async fn binned_json(url: Url, req: Request<Body>, node_config: &NodeConfigCache
     span1.in_scope(|| {
         debug!("begin");
     });
-    let item = streams::timebinnedjson::timebinned_json(query, ch_conf, reqid, node_config.node_config.cluster.clone())
-        .instrument(span1)
-        .await?;
+    let item =
+        streams::timebinnedjson::timebinned_json(query, ch_confs, reqid, node_config.node_config.cluster.clone())
+            .instrument(span1)
+            .await?;
     let buf = serde_json::to_vec(&item)?;
     let ret = response(StatusCode::OK).body(Body::from(buf))?;
     Ok(ret)
 }
 
+async fn binned_csv(url: Url, req: Request<Body>, node_config: &NodeConfigCached) -> Result<Response<Body>, Error> {
+    debug!("{:?}", req);
+    let reqid = crate::status_board()
+        .map_err(|e| Error::with_msg_no_trace(e.to_string()))?
+        .new_status_id();
+    let (_head, _body) = req.into_parts();
+    let mut pairs = get_url_query_pairs(&url);
+    let ts_format = csv::CsvTsFormat::from_pairs(&pairs)?;
+    let names = csv::channel_names_from_url(&url);
+    let mut cols = Vec::new();
+    for name in &names {
+        pairs.insert("channelName".into(), name.clone());
+        let query = BinnedQuery::from_pairs(&pairs).map_err(|e| {
+            let msg = format!("can not parse query: {}", e.msg());
+            e.add_public_msg(msg)
+        })?;
+        let ch_confs = ch_conf_from_binned(&query, node_config)
+            .await?
+            .ok_or_else(|| Error::with_public_msg_no_trace(format!("channel not found: {name}")))?;
+        for seg in &ch_confs {
+            csv::ensure_scalar(&seg.ch_conf)?;
+        }
+        let item = streams::timebinnedjson::timebinned_json(
+            query,
+            ch_confs,
+            reqid.clone(),
+            node_config.node_config.cluster.clone(),
+        )
+        .await?;
+        cols.push(csv::CsvColumns::from_bins_json(&item)?);
+    }
+    let rows = csv::CsvRows::new(csv::CsvKind::Bins, ts_format, names, cols);
+    let s = stream::iter(rows.map(Ok::<_, Error>));
+    let ret = response(StatusCode::OK)
+        .header(http::header::CONTENT_TYPE, TEXT_CSV)
+        .body(Body::wrap_stream(s))?;
+    Ok(ret)
+}
+
 async fn binned(req: Request<Body>, node_config: &NodeConfigCached) -> Result<Response<Body>, Error> {
     let url = {
         let s1 = format!("dummy:{}", req.uri());
@@ -64,7 +108,9 @@ async fn binned(req: Request<Body>, node_config: &NodeConfigCached) -> Result<Re
     {
         Err(Error::with_msg_no_trace("hidden message").add_public_msg("PublicMessage"))?;
     }
-    if crate::accepts_json(&req.headers()) {
+    if crate::accepts_csv(&req.headers()) {
+        Ok(binned_csv(url, req, node_config).await?)
+    } else if crate::accepts_json(&req.headers()) {
         Ok(binned_json(url, req, node_config).await?)
     } else if crate::accepts_octets(&req.headers()) {
         Ok(response_err(
@@ -0,0 +1,257 @@
+use crate::err::Error;
+use crate::gather::gather_get_json_generic;
+use crate::gather::SubRes;
+use crate::response;
+use crate::ToPublicResponse;
+use http::Method;
+use http::Request;
+use http::Response;
+use http::StatusCode;
+use hyper::Body;
+use netpod::log::*;
+use netpod::range::evrange::NanoRange;
+use netpod::range::evrange::SeriesRange;
+use netpod::ChannelConfigSegment;
+use netpod::ChannelTypeConfigGen;
+use netpod::NodeConfigCached;
+use netpod::QueryBudget;
+use netpod::QueryCost;
+use netpod::SfChFetchInfo;
+use netpod::APP_JSON;
+use query::api4::events::PlainEventsQuery;
+use serde::Deserialize;
+use serde::Serialize;
+use std::time::Duration;
+
+const PATH_LOCAL: &str = "/api/4/private/events/cost/local";
+
+/// A part of an event query which is read from the data files of a node.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LocalCostSegment {
+    range: NanoRange,
+    fetch_info: SfChFetchInfo,
+}
+
+/// Estimates the cost of reading segments from the data files of this node.
+pub struct EventsCostLocal {}
+
+impl EventsCostLocal {
+    pub fn handler(req: &Request<Body>) -> Option<Self> {
+        if req.uri().path() == PATH_LOCAL {
+            Some(Self {})
+        } else {
+            None
+        }
+    }
+
+    pub async fn handle(&self, req: Request<Body>, node_config: &NodeConfigCached) -> Result<Response<Body>, Error> {
+        if req.method() != Method::POST {
+            return Ok(response(StatusCode::METHOD_NOT_ALLOWED).body(Body::empty())?);
+        }
+        match self.estimate(req, node_config).await {
+            Ok(ret) => Ok(ret),
+            Err(e) => {
+                error!("EventsCostLocal sees: {e}");
+                Ok(e.to_public_response())
+            }
+        }
+    }
+
+    async fn estimate(&self, req: Request<Body>, node_config: &NodeConfigCached) -> Result<Response<Body>, Error> {
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        let segs: Vec<LocalCostSegment> = serde_json::from_slice(&body)?;
+        let mut ret = QueryCost::default();
+        for seg in &segs {
+            let cost = disk::cost::estimate_local(&seg.range, &seg.fetch_info, &node_config.node).await?;
+            ret.merge(&cost);
+        }
+        let ret = response(StatusCode::OK)
+            .header(http::header::CONTENT_TYPE, APP_JSON)
+            .body(Body::from(serde_json::to_vec(&ret)?))?;
+        Ok(ret)
+    }
+}
+
+async fn estimate_databuffer(segs: Vec<LocalCostSegment>, node_config: &NodeConfigCached) -> Result<QueryCost, Error> {
+    let cluster = &node_config.node_config.cluster;
+    // With central storage every node sees all files.
+    let nodes = if cluster.is_central_storage {
+        &cluster.nodes[..1.min(cluster.nodes.len())]
+    } else {
+        &cluster.nodes[..]
+    };
+    let mut urls = Vec::new();
+    let mut bodies = Vec::new();
+    for node in nodes {
+        urls.push(node.baseurl().join(PATH_LOCAL)?);
+        bodies.push(Some(Body::from(serde_json::to_vec(&segs)?)));
+    }
+    let tags = urls.iter().map(|x| x.to_string()).collect();
+    let nt = |tag, res: Response<Body>| {
+        let fut = async {
+            let status = res.status();
+            let body = hyper::body::to_bytes(res).await?;
+            if status != StatusCode::OK {
+                let msg = String::from_utf8_lossy(&body);
+                return Err(Error::with_msg_no_trace(format!("cost estimate failed {status} {msg}")));
+            }
+            let val: QueryCost = serde_json::from_slice(&body)?;
+            Ok(SubRes { tag, status, val })
+        };
+        Box::pin(fut) as _
+    };
+    let ft = |all: Vec<(_, Result<SubRes<QueryCost>, Error>)>| {
+        let mut ret = QueryCost::default();
+        for (_, res) in all {
+            ret.merge(&res?.val);
+        }
+        Ok(ret)
+    };
+    gather_get_json_generic(Method::POST, urls, bodies, tags, nt, ft, Duration::from_millis(10000)).await
+}
+
+/// Estimates the cost of an event query over the data files of all nodes and the `ts_msp` partitions in Scylla.
+/// Segments over a pulse range are not estimated.
+pub async fn estimate_events_cost(
+    segs: &[ChannelConfigSegment],
+    node_config: &NodeConfigCached,
+) -> Result<QueryCost, Error> {
+    let mut ret = QueryCost::default();
+    let mut local = Vec::new();
+    for seg in segs {
+        let range = match &seg.range {
+            SeriesRange::TimeRange(x) => x.clone(),
+            SeriesRange::PulseRange(_) => {
+                debug!("no cost estimate for pulse range {:?}", seg.range);
+                continue;
+            }
+        };
+        match &seg.ch_conf {
+            ChannelTypeConfigGen::SfDatabuffer(fetch_info) => local.push(LocalCostSegment {
+                range,
+                fetch_info: fetch_info.clone(),
+            }),
+            ChannelTypeConfigGen::Scylla(ch_conf) => {
+                if node_config.node_config.cluster.is_json_backend(ch_conf.backend()) {
+                    // Held in memory, nothing to read from storage.
+                    continue;
+                }
+                let scyco = node_config
+                    .node_config
+                    .cluster
+                    .scylla
+                    .as_ref()
+                    .ok_or_else(|| Error::with_public_msg_no_trace("No Scylla configured"))?;
+                let stmts = scyllaconn::shared_stmts(scyco).await?;
+                let n =
+                    scyllaconn::events::ts_msp_partition_count(ch_conf.series(), (&seg.range).into(), stmts).await?;
+                ret.ts_msp_partitions += n;
+            }
+        }
+    }
+    if !local.is_empty() {
+        ret.merge(&estimate_databuffer(local, node_config).await?);
+    }
+    Ok(ret)
+}
+
+#[derive(Serialize)]
+struct DryRun<'a> {
+    #[serde(flatten)]
+    cost: QueryCost,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    budget: Option<&'a QueryBudget>,
+    #[serde(rename = "overBudget", skip_serializing_if = "Option::is_none")]
+    over_budget: Option<String>,
+}
+
+/// The answer to a `dryRun` query: the estimated cost and how it compares to the configured budget.
+pub fn dry_run_response(cost: QueryCost, node_config: &NodeConfigCached) -> Result<Response<Body>, Error> {
+    let budget = node_config.node_config.cluster.query_budget.as_ref();
+    cost_response(StatusCode::OK, cost, budget)
+}
+
+/// Refuses a query which can not continue via `continueAt`, like the csv of several channels,
+/// if it is over the configured budget. The body tells the estimated cost like a `dryRun`.
+pub fn refuse_over_budget(cost: QueryCost, node_config: &NodeConfigCached) -> Result<Option<Response<Body>>, Error> {
+    let budget = node_config.node_config.cluster.query_budget.as_ref();
+    refuse_over(cost, budget)
+}
+
+fn refuse_over(cost: QueryCost, budget: Option<&QueryBudget>) -> Result<Option<Response<Body>>, Error> {
+    match budget {
+        Some(b) if cost.exceeds(b).is_some() => Ok(Some(cost_response(StatusCode::BAD_REQUEST, cost, budget)?)),
+        _ => Ok(None),
+    }
+}
+
+fn cost_response(status: StatusCode, cost: QueryCost, budget: Option<&QueryBudget>) -> Result<Response<Body>, Error> {
+    let over_budget = budget.and_then(|x| cost.exceeds(x));
+    let ret = DryRun {
+        cost,
+        budget,
+        over_budget,
+    };
+    let ret = response(status)
+        .header(http::header::CONTENT_TYPE, APP_JSON)
+        .body(Body::from(serde_json::to_vec(&ret)?))?;
+    Ok(ret)
+}
+
+/// Holds the query to the configured budget: it is refused, or limited in the number of events
+/// so that the client continues via `continueAt`.
+pub async fn apply_budget(
+    query: &mut PlainEventsQuery,
+    segs: &[ChannelConfigSegment],
+    node_config: &NodeConfigCached,
+) -> Result<(), Error> {
+    let budget = match node_config.node_config.cluster.query_budget.as_ref() {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+    let cost = estimate_events_cost(segs, node_config).await?;
+    if let Some(msg) = cost.exceeds(budget) {
+        if budget.reject {
+            let e = ::err::Error::with_public_msg_no_trace(format!("query refused: {msg}")).mark_bad_request();
+            return Err(e.into());
+        }
+        if let Some(n) = cost.events_within(budget) {
+            if n < query.events_max() {
+                debug!("limit events_max to {n}: {msg}");
+                query.set_events_max_by_budget(n);
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_json::Value as JsonValue;
+
+    #[test]
+    fn refuse_over_budget_with_cost() {
+        let cost = QueryCost {
+            events: 2000,
+            ..Default::default()
+        };
+        let budget = QueryBudget {
+            max_events: Some(1000),
+            ..Default::default()
+        };
+        assert!(refuse_over(cost.clone(), None).unwrap().is_none());
+        let within = QueryBudget {
+            max_events: Some(4000),
+            ..Default::default()
+        };
+        assert!(refuse_over(cost.clone(), Some(&within)).unwrap().is_none());
+        let res = refuse_over(cost, Some(&budget)).unwrap().unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        let body = taskrun::run(hyper::body::to_bytes(res.into_body())).unwrap();
+        let js: JsonValue = serde_json::from_slice(&body).unwrap();
+        assert_eq!(js["events"], 2000);
+        assert_eq!(js["budget"]["max_events"], 1000);
+        assert!(js["overBudget"].as_str().unwrap().contains("events"));
+    }
+}
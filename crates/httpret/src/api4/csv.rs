@@ -0,0 +1,295 @@
+use crate::err::Error;
+use chrono::TimeZone;
+use chrono::Utc;
+use netpod::timeunits::MS;
+use netpod::timeunits::SEC;
+use netpod::ChannelTypeConfigGen;
+use netpod::Shape;
+use netpod::DATETIME_FMT_9MS;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use url::Url;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsvTsFormat {
+    Iso,
+    EpochNs,
+}
+
+impl CsvTsFormat {
+    pub fn from_pairs(pairs: &BTreeMap<String, String>) -> Result<Self, Error> {
+        match pairs.get("tsFormat").map(String::as_str) {
+            None | Some("iso") => Ok(Self::Iso),
+            Some("ns") => Ok(Self::EpochNs),
+            Some(x) => Err(Error::with_public_msg_no_trace(format!(
+                "unknown tsFormat {x:?}, expect one of iso, ns"
+            ))),
+        }
+    }
+
+    fn format(&self, ts: u64) -> String {
+        match self {
+            CsvTsFormat::Iso => Utc.timestamp_nanos(ts as i64).format(DATETIME_FMT_9MS).to_string(),
+            CsvTsFormat::EpochNs => format!("{ts}"),
+        }
+    }
+}
+
+/// The channel names of a CSV request. The first name is always the one from `channelName`.
+/// Further columns for a wide table are requested by repeating the `channelName` parameter.
+pub fn channel_names_from_url(url: &Url) -> Vec<String> {
+    url.query_pairs()
+        .filter(|(k, _)| k == "channelName")
+        .map(|(_, v)| v.to_string())
+        .collect()
+}
+
+pub fn ensure_scalar(ch_conf: &ChannelTypeConfigGen) -> Result<(), Error> {
+    match ch_conf.shape() {
+        Shape::Scalar => Ok(()),
+        _ => Err(Error::with_public_msg_no_trace(format!(
+            "CSV output is only available for scalar channels, {} has shape {:?}",
+            ch_conf.name(),
+            ch_conf.shape()
+        ))),
+    }
+}
+
+fn json_u64_arr(jsval: &JsonValue, key: &str) -> Result<Vec<u64>, Error> {
+    let a = jsval
+        .get(key)
+        .and_then(JsonValue::as_array)
+        .ok_or_else(|| Error::with_msg_no_trace(format!("collected result without {key}")))?;
+    a.iter()
+        .map(|x| {
+            x.as_u64()
+                .ok_or_else(|| Error::with_msg_no_trace(format!("non-integer in {key}")))
+        })
+        .collect()
+}
+
+fn json_cells(jsval: &JsonValue, key: &str) -> Result<Vec<String>, Error> {
+    let a = jsval
+        .get(key)
+        .and_then(JsonValue::as_array)
+        .ok_or_else(|| Error::with_msg_no_trace(format!("collected result without {key}")))?;
+    a.iter().map(cell_from_json).collect()
+}
+
+fn cell_from_json(x: &JsonValue) -> Result<String, Error> {
+    match x {
+        JsonValue::Null => Ok(String::new()),
+        JsonValue::Bool(x) => Ok(format!("{x}")),
+        JsonValue::Number(x) => Ok(format!("{x}")),
+        JsonValue::String(x) => Ok(escape(x)),
+        _ => Err(Error::with_public_msg_no_trace(
+            "CSV output is only available for scalar values",
+        )),
+    }
+}
+
+fn escape(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.into()
+    }
+}
+
+fn abs_ts(anchor_sec: u64, off_ms: &[u64], off_ns: &[u64]) -> Result<Vec<u64>, Error> {
+    if off_ms.len() != off_ns.len() {
+        return Err(Error::with_msg_no_trace("mismatched timestamp offset lengths"));
+    }
+    let ret = off_ms
+        .iter()
+        .zip(off_ns.iter())
+        .map(|(&ms, &ns)| anchor_sec * SEC + ms * MS + ns)
+        .collect();
+    Ok(ret)
+}
+
+/// One channel worth of rows, extracted from the collected json result.
+/// Each row is keyed by its timestamp(s) and carries the already formatted cells.
+#[derive(Debug)]
+pub struct CsvColumns {
+    keys: Vec<(u64, u64)>,
+    cells: Vec<Vec<String>>,
+    width: usize,
+}
+
+impl CsvColumns {
+    pub fn from_events_json(jsval: &JsonValue) -> Result<Self, Error> {
+        let anchor = jsval.get("tsAnchor").and_then(JsonValue::as_u64).unwrap_or(0);
+        let tss = abs_ts(anchor, &json_u64_arr(jsval, "tsMs")?, &json_u64_arr(jsval, "tsNs")?)?;
+        let pulse_anchor = jsval.get("pulseAnchor").and_then(JsonValue::as_u64).unwrap_or(0);
+        let pulses = json_u64_arr(jsval, "pulseOff")?;
+        let values = json_cells(jsval, "values")?;
+        if tss.len() != pulses.len() || tss.len() != values.len() {
+            return Err(Error::with_msg_no_trace("mismatched lengths in collected events"));
+        }
+        let keys = tss.iter().map(|&ts| (ts, 0)).collect();
+        let cells = pulses
+            .into_iter()
+            .zip(values)
+            .map(|(pulse, value)| vec![format!("{}", pulse_anchor + pulse), value])
+            .collect();
+        let ret = Self { keys, cells, width: 2 };
+        Ok(ret)
+    }
+
+    pub fn from_bins_json(jsval: &JsonValue) -> Result<Self, Error> {
+        let anchor = jsval.get("tsAnchor").and_then(JsonValue::as_u64).unwrap_or(0);
+        let ts1s = abs_ts(anchor, &json_u64_arr(jsval, "ts1Ms")?, &json_u64_arr(jsval, "ts1Ns")?)?;
+        let ts2s = abs_ts(anchor, &json_u64_arr(jsval, "ts2Ms")?, &json_u64_arr(jsval, "ts2Ns")?)?;
+        let counts = json_u64_arr(jsval, "counts")?;
+        let mins = json_cells(jsval, "mins")?;
+        let maxs = json_cells(jsval, "maxs")?;
+        let avgs = json_cells(jsval, "avgs")?;
+        let n = ts1s.len();
+        if ts2s.len() != n || counts.len() != n || mins.len() != n || maxs.len() != n || avgs.len() != n {
+            return Err(Error::with_msg_no_trace("mismatched lengths in collected bins"));
+        }
+        let keys = ts1s.into_iter().zip(ts2s).collect();
+        let mut cells = Vec::with_capacity(n);
+        for (((count, min), max), avg) in counts.into_iter().zip(mins).zip(maxs).zip(avgs) {
+            cells.push(vec![format!("{count}"), min, max, avg]);
+        }
+        let ret = Self { keys, cells, width: 4 };
+        Ok(ret)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CsvKind {
+    Events,
+    Bins,
+}
+
+/// Yields the CSV document line by line.
+/// With more than one channel the columns are joined on the timestamp(s) into a wide table,
+/// cells of channels without an entry at some timestamp are left empty.
+pub struct CsvRows {
+    kind: CsvKind,
+    ts_format: CsvTsFormat,
+    names: Vec<String>,
+    cols: Vec<CsvColumns>,
+    ixs: Vec<usize>,
+    header_done: bool,
+}
+
+impl CsvRows {
+    pub fn new(kind: CsvKind, ts_format: CsvTsFormat, names: Vec<String>, cols: Vec<CsvColumns>) -> Self {
+        let ixs = vec![0; cols.len()];
+        Self {
+            kind,
+            ts_format,
+            names,
+            cols,
+            ixs,
+            header_done: false,
+        }
+    }
+
+    fn header(&self) -> String {
+        let (keys, fields): (&[&str], &[&str]) = match self.kind {
+            CsvKind::Events => (&["ts"], &["pulse", "value"]),
+            CsvKind::Bins => (&["ts1", "ts2"], &["count", "min", "max", "avg"]),
+        };
+        let mut hs: Vec<String> = keys.iter().map(|&x| x.into()).collect();
+        if self.names.len() == 1 {
+            hs.extend(fields.iter().map(|&x| x.to_string()));
+        } else {
+            for name in &self.names {
+                hs.extend(fields.iter().map(|x| escape(&format!("{name}:{x}"))));
+            }
+        }
+        let mut s = hs.join(",");
+        s.push('\n');
+        s
+    }
+
+    fn next_key(&self) -> Option<(u64, u64)> {
+        self.cols
+            .iter()
+            .zip(self.ixs.iter())
+            .filter_map(|(c, &i)| c.keys.get(i).copied())
+            .min()
+    }
+}
+
+impl Iterator for CsvRows {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if !self.header_done {
+            self.header_done = true;
+            return Some(self.header());
+        }
+        let key = self.next_key()?;
+        let mut row = vec![self.ts_format.format(key.0)];
+        if self.kind == CsvKind::Bins {
+            row.push(self.ts_format.format(key.1));
+        }
+        for (col, ix) in self.cols.iter().zip(self.ixs.iter_mut()) {
+            if col.keys.get(*ix) == Some(&key) {
+                row.extend(col.cells[*ix].iter().cloned());
+                *ix += 1;
+            } else {
+                row.extend((0..col.width).map(|_| String::new()));
+            }
+        }
+        let mut s = row.join(",");
+        s.push('\n');
+        Some(s)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn events_wide_table() {
+        let a = serde_json::json!({
+            "tsAnchor": 10, "tsMs": [0, 5], "tsNs": [0, 7],
+            "pulseAnchor": 100, "pulseOff": [1, 2], "values": [1.5, 2.5],
+        });
+        let b = serde_json::json!({
+            "tsAnchor": 10, "tsMs": [5], "tsNs": [7],
+            "pulseAnchor": 200, "pulseOff": [0], "values": ["a,b"],
+        });
+        let cols = vec![
+            CsvColumns::from_events_json(&a).unwrap(),
+            CsvColumns::from_events_json(&b).unwrap(),
+        ];
+        let rows = CsvRows::new(
+            CsvKind::Events,
+            CsvTsFormat::EpochNs,
+            vec!["a".into(), "b".into()],
+            cols,
+        );
+        let s: String = rows.collect();
+        assert_eq!(
+            s,
+            "ts,a:pulse,a:value,b:pulse,b:value\n10000000000,101,1.5,,\n10005000007,102,2.5,200,\"a,b\"\n"
+        );
+    }
+
+    #[test]
+    fn bins_iso() {
+        let a = serde_json::json!({
+            "tsAnchor": 0, "ts1Ms": [0], "ts1Ns": [0], "ts2Ms": [1000], "ts2Ns": [0],
+            "counts": [3], "mins": [1], "maxs": [4], "avgs": [2.5],
+        });
+        let cols = vec![CsvColumns::from_bins_json(&a).unwrap()];
+        let rows = CsvRows::new(CsvKind::Bins, CsvTsFormat::Iso, vec!["a".into()], cols);
+        let s: String = rows.collect();
+        assert_eq!(
+            s,
+            concat!(
+                "ts1,ts2,count,min,max,avg\n",
+                "1970-01-01T00:00:00.000000000Z,1970-01-01T00:00:01.000000000Z,3,1,4,2.5\n"
+            )
+        );
+    }
+}
@@ -73,7 +73,7 @@ impl EventDataHandler {
             nodenet::conn::events_get_input_frames(body.map_err(|e| err::Error::with_msg_no_trace(e.to_string())))
                 .await
                 .map_err(|_| EventDataError::InternalError)?;
-        let (evsubq,) = nodenet::conn::events_parse_input_query(frames).map_err(|_| EventDataError::QueryParse)?;
+        let (evsubq,) = nodenet::conn::events_parse_input_query(frames, ncc).map_err(|_| EventDataError::QueryParse)?;
         let stream = nodenet::conn::create_response_bytes_stream(evsubq, ncc)
             .await
             .map_err(|e| EventDataError::Error(Box::new(e)))?;
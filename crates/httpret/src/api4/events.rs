@@ -1,3 +1,8 @@
+use crate::api4::cost::apply_budget;
+use crate::api4::cost::dry_run_response;
+use crate::api4::cost::estimate_events_cost;
+use crate::api4::cost::refuse_over_budget;
+use crate::api4::csv;
 use crate::channelconfig::chconf_from_events_quorum;
 use crate::err::Error;
 use crate::response;
@@ -10,12 +15,15 @@ use http::Request;
 use http::Response;
 use http::StatusCode;
 use hyper::Body;
+use netpod::get_url_query_pairs;
 use netpod::log::*;
 use netpod::FromUrl;
 use netpod::NodeConfigCached;
+use netpod::QueryCost;
 use netpod::ACCEPT_ALL;
 use netpod::APP_JSON;
 use netpod::APP_OCTET;
+use netpod::TEXT_CSV;
 use query::api4::events::PlainEventsQuery;
 use url::Url;
 
@@ -56,7 +64,9 @@ async fn plain_events(req: Request<Body>, node_config: &NodeConfigCached) -> Res
             .map_err(Error::from)
             .map_err(|e| e.add_public_msg(format!("Can not parse query url")))?
     };
-    if accept.contains(APP_JSON) || accept.contains(ACCEPT_ALL) {
+    if accept.contains(TEXT_CSV) {
+        Ok(plain_events_csv(url, req, node_config).await?)
+    } else if accept.contains(APP_JSON) || accept.contains(ACCEPT_ALL) {
         Ok(plain_events_json(url, req, node_config).await?)
     } else if accept == APP_OCTET {
         Ok(plain_events_binary(url, req, node_config).await?)
@@ -88,16 +98,21 @@ async fn plain_events_json(
     let reqid = crate::status_board()?.new_status_id();
     info!("plain_events_json  req: {:?}", req);
     let (_head, _body) = req.into_parts();
-    let query = PlainEventsQuery::from_url(&url)?;
+    let mut query = PlainEventsQuery::from_url(&url)?;
     info!("plain_events_json  query {query:?}");
     // TODO handle None case better and return 404
-    let ch_conf = chconf_from_events_quorum(&query, node_config)
+    let ch_confs = chconf_from_events_quorum(&query, node_config)
         .await
         .map_err(Error::from)?
         .ok_or_else(|| Error::with_msg_no_trace("channel not found"))?;
-    info!("plain_events_json  chconf_from_events_quorum: {ch_conf:?}");
+    info!("plain_events_json  chconf_from_events_quorum: {ch_confs:?}");
+    if query.dry_run() {
+        let cost = estimate_events_cost(&ch_confs, node_config).await?;
+        return dry_run_response(cost, node_config);
+    }
+    apply_budget(&mut query, &ch_confs, node_config).await?;
     let item =
-        streams::plaineventsjson::plain_events_json(&query, ch_conf, reqid, &node_config.node_config.cluster).await;
+        streams::plaineventsjson::plain_events_json(&query, ch_confs, reqid, &node_config.node_config.cluster).await;
     let item = match item {
         Ok(item) => item,
         Err(e) => {
@@ -109,3 +124,61 @@ async fn plain_events_json(
     let ret = response(StatusCode::OK).body(Body::from(buf))?;
     Ok(ret)
 }
+
+async fn plain_events_csv(
+    url: Url,
+    req: Request<Body>,
+    node_config: &NodeConfigCached,
+) -> Result<Response<Body>, Error> {
+    let reqid = crate::status_board()?.new_status_id();
+    info!("plain_events_csv  req: {:?}", req);
+    let (_head, _body) = req.into_parts();
+    let mut pairs = get_url_query_pairs(&url);
+    let ts_format = csv::CsvTsFormat::from_pairs(&pairs)?;
+    let names = csv::channel_names_from_url(&url);
+    let mut queries = Vec::new();
+    for name in &names {
+        pairs.insert("channelName".into(), name.clone());
+        let query = PlainEventsQuery::from_pairs(&pairs)?;
+        let ch_confs = chconf_from_events_quorum(&query, node_config)
+            .await
+            .map_err(Error::from)?
+            .ok_or_else(|| Error::with_public_msg_no_trace(format!("channel not found: {name}")))?;
+        for seg in &ch_confs {
+            csv::ensure_scalar(&seg.ch_conf)?;
+        }
+        queries.push((query, ch_confs));
+    }
+    // The columns are joined on the timestamp, so the channels can not be cut by the budget and continued
+    // one by one. Queries over the budget are refused as a whole instead.
+    let dry_run = pairs.get("dryRun").is_some_and(|x| x == "true");
+    if dry_run || node_config.node_config.cluster.query_budget.is_some() {
+        let mut cost = QueryCost::default();
+        for (_, ch_confs) in &queries {
+            cost.merge(&estimate_events_cost(ch_confs, node_config).await?);
+        }
+        if dry_run {
+            return dry_run_response(cost, node_config);
+        }
+        if let Some(res) = refuse_over_budget(cost, node_config)? {
+            return Ok(res);
+        }
+    }
+    let mut cols = Vec::new();
+    for (query, ch_confs) in queries {
+        let item = streams::plaineventsjson::plain_events_json(
+            &query,
+            ch_confs,
+            reqid.clone(),
+            &node_config.node_config.cluster,
+        )
+        .await?;
+        cols.push(csv::CsvColumns::from_events_json(&item)?);
+    }
+    let rows = csv::CsvRows::new(csv::CsvKind::Events, ts_format, names, cols);
+    let s = stream::iter(rows.map(Ok::<_, Error>));
+    let ret = response(StatusCode::OK)
+        .header(http::header::CONTENT_TYPE, TEXT_CSV)
+        .body(Body::wrap_stream(s))?;
+    Ok(ret)
+}
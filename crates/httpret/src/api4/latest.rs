@@ -0,0 +1,137 @@
+use crate::bodystream::response;
+use crate::bodystream::ToPublicResponse;
+use crate::channelconfig::chconf_from_events_quorum;
+use crate::err::Error;
+use futures_util::StreamExt;
+use http::Method;
+use http::Request;
+use http::Response;
+use http::StatusCode;
+use hyper::Body;
+use netpod::log::*;
+use netpod::FromUrl;
+use netpod::HasBackend;
+use netpod::NodeConfigCached;
+use query::api4::latest::LatestQuery;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use url::Url;
+
+/// How long a found latest event is served from the cache.
+const CACHE_TTL: Duration = Duration::from_millis(2000);
+
+/// How many channels are looked up at the same time.
+const CHANNELS_CONCURRENT: usize = 16;
+
+// Keyed by backend, channel name and the requested time, `None` meaning "now".
+type CacheKey = (String, String, Option<u64>);
+
+static CACHE: Mutex<BTreeMap<CacheKey, (Instant, JsonValue)>> = Mutex::new(BTreeMap::new());
+
+fn cache_get(key: &CacheKey) -> Option<JsonValue> {
+    let cache = CACHE.lock().unwrap();
+    match cache.get(key) {
+        Some((ts, v)) if ts.elapsed() < CACHE_TTL => Some(v.clone()),
+        _ => None,
+    }
+}
+
+fn cache_put(key: CacheKey, v: JsonValue) {
+    let mut cache = CACHE.lock().unwrap();
+    cache.retain(|_, (ts, _)| ts.elapsed() < CACHE_TTL);
+    cache.insert(key, (Instant::now(), v));
+}
+
+pub struct LatestHandler {}
+
+impl LatestHandler {
+    pub fn handler(req: &Request<Body>) -> Option<Self> {
+        if req.uri().path() == "/api/4/latest" {
+            Some(Self {})
+        } else {
+            None
+        }
+    }
+
+    pub async fn handle(&self, req: Request<Body>, node_config: &NodeConfigCached) -> Result<Response<Body>, Error> {
+        let query = if req.method() == Method::GET {
+            let url = Url::parse(&format!("dummy:{}", req.uri()))?;
+            LatestQuery::from_url(&url)
+        } else if req.method() == Method::POST {
+            let body = hyper::body::to_bytes(req.into_body()).await?;
+            serde_json::from_slice(&body)
+                .map_err(|e| err::Error::with_public_msg_no_trace(format!("can not parse latest query: {e}")))
+        } else {
+            return Ok(response(StatusCode::METHOD_NOT_ALLOWED).body(Body::empty())?);
+        };
+        let query = match query {
+            Ok(x) => x,
+            Err(e) => return Ok(Error::from(e).to_public_response()),
+        };
+        match latest(&query, node_config).await {
+            Ok(ret) => {
+                let buf = serde_json::to_vec(&ret)?;
+                Ok(response(StatusCode::OK).body(Body::from(buf))?)
+            }
+            Err(e) => {
+                error!("LatestHandler sees: {e}");
+                Ok(e.to_public_response())
+            }
+        }
+    }
+}
+
+async fn latest(query: &LatestQuery, ncc: &NodeConfigCached) -> Result<JsonValue, Error> {
+    debug!("latest  {query:?}");
+    let at = query.at_or_now();
+    // Collected first, so that the stream does not hold the closure which borrows the query.
+    let futs: Vec<_> = query
+        .channels()
+        .iter()
+        .map(|name| latest_entry(query, name.clone(), at, ncc))
+        .collect();
+    let channels: Vec<_> = futures_util::stream::iter(futs)
+        .buffered(CHANNELS_CONCURRENT)
+        .collect()
+        .await;
+    let ret = serde_json::json!({
+        "backend": query.backend(),
+        "atNs": at,
+        "channels": channels,
+    });
+    Ok(ret)
+}
+
+async fn latest_entry(query: &LatestQuery, name: String, at: u64, ncc: &NodeConfigCached) -> JsonValue {
+    let key = (query.backend().to_string(), name.clone(), query.at());
+    let res = match cache_get(&key) {
+        Some(x) => Ok(x),
+        None => {
+            let res = latest_channel(query, &name, at, ncc).await;
+            if let Ok(x) = &res {
+                cache_put(key, x.clone());
+            }
+            res
+        }
+    };
+    match res {
+        Ok(event) => serde_json::json!({ "name": name, "event": event }),
+        Err(e) => {
+            warn!("latest  channel {name}  {e}");
+            serde_json::json!({ "name": name, "error": e.0.to_public_error() })
+        }
+    }
+}
+
+async fn latest_channel(query: &LatestQuery, name: &str, at: u64, ncc: &NodeConfigCached) -> Result<JsonValue, Error> {
+    let evq = query.events_query(name, at);
+    let ch_confs = chconf_from_events_quorum(&evq, ncc)
+        .await?
+        .ok_or_else(|| Error::with_public_msg_no_trace(format!("channel not found: {name}")))?;
+    let reqid = crate::status_board()?.new_status_id();
+    let ret = streams::latest::latest_event_json(&evq, ch_confs, reqid, &ncc.node_config.cluster).await?;
+    Ok(ret)
+}
@@ -1,5 +1,6 @@
 use crate::bodystream::response;
 use crate::err::Error;
+use crate::ratelimit;
 use crate::ReqCtx;
 use http::Request;
 use http::Response;
@@ -112,6 +113,8 @@ impl StatusNodesRecursive {
             //table_sizes: Some(table_sizes(node_config).await.map_err(Into::into)),
             archiver_appliance_status,
             subs: VecDeque::new(),
+            backend_endpoints: Vec::new(),
+            client_usage: ratelimit::usage(),
         };
         Ok(ret)
     }
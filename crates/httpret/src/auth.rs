@@ -0,0 +1,497 @@
+use crate::bodystream::response;
+use crate::err::Error;
+use futures_util::Future;
+use http::header;
+use http::HeaderMap;
+use http::Method;
+use http::StatusCode;
+use http::Uri;
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use jsonwebtoken::jwk::AlgorithmParameters;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::Algorithm;
+use jsonwebtoken::DecodingKey;
+use jsonwebtoken::Validation;
+use netpod::log::*;
+use netpod::token_eq;
+use netpod::AuthConfig;
+use netpod::AuthRule;
+use serde_json::Value as JsonValue;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use taskrun::tokio;
+use url::form_urlencoded;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AuthMethod {
+    Anonymous,
+    Token,
+    Jwt,
+    Node,
+}
+
+/// The caller of a request, as far as the credentials tell.
+#[derive(Clone, Debug)]
+pub struct Identity {
+    pub subject: String,
+    pub roles: Vec<String>,
+    pub method: AuthMethod,
+}
+
+impl Identity {
+    fn anonymous(conf: &AuthConfig) -> Self {
+        Self {
+            subject: String::from("anonymous"),
+            roles: conf.anonymous_roles.clone(),
+            method: AuthMethod::Anonymous,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Denied {
+    /// The credentials are missing where required, or are not valid.
+    Unauthenticated(String),
+    /// The caller is known, but not allowed to do this.
+    Forbidden(String),
+}
+
+impl Denied {
+    pub fn response(&self) -> Result<Response<Body>, Error> {
+        let ret = match self {
+            Denied::Unauthenticated(msg) => response(StatusCode::UNAUTHORIZED)
+                .header(header::WWW_AUTHENTICATE, "Bearer")
+                .body(Body::from(msg.clone()))?,
+            Denied::Forbidden(msg) => response(StatusCode::FORBIDDEN).body(Body::from(msg.clone()))?,
+        };
+        Ok(ret)
+    }
+}
+
+/// What a request wants to access.
+#[derive(Debug, PartialEq)]
+pub enum Access {
+    /// Documentation, version and preflight requests.
+    Open,
+    /// Endpoints which change the state of the service or reveal its internals.
+    Admin,
+    /// Data of channels. The channels are `None` if they can not be told from the url.
+    Channels {
+        backend: Option<String>,
+        channels: Option<Vec<String>>,
+    },
+    /// Everything else, allowed to callers with any rule.
+    Any,
+}
+
+const ADMIN_PATHS: &[&str] = &[
+    "/api/4/clear_cache",
+    "/api/4/update_db_with_channel_names",
+    "/api/4/update_db_with_all_channel_configs",
+    "/api/4/update_search_cache",
+    "/api/4/status/board/all",
+    "/api/4/test/generate/scylla",
+    "/api/4/private/cache/purge",
+    "/api/1/map/index/full",
+];
+
+const ADMIN_PREFIXES: &[&str] = &["/api/4/tool/", "/api/4/test/download/", "/api/1/map/pulse/mark/closed/"];
+
+const CHANNEL_PATHS: &[&str] = &[
+    "/api/4/events",
+    "/api/4/binned",
+    "/api/4/latest",
+    "/api/4/prebinned",
+    "/api/4/status/connection/events",
+    "/api/4/status/channel/events",
+    "/api/1/query",
+];
+
+const CHANNEL_PREFIXES: &[&str] = &["/api/4/channel/", "/api/4/scylla/", "/api/4/private/eventdata/"];
+
+pub fn access_of(method: &Method, uri: &Uri) -> Access {
+    let path = uri.path();
+    if let Some(suffix) = path.strip_prefix("/api/4/gather/") {
+        // The nodes are asked for `/api/4/<suffix>` on behalf of the caller, which needs the access for that.
+        let inner = match uri.query() {
+            Some(q) => format!("/api/4/{suffix}?{q}"),
+            None => format!("/api/4/{suffix}"),
+        };
+        return match inner.parse::<Uri>() {
+            Ok(inner) => access_of(method, &inner),
+            Err(_) => Access::Admin,
+        };
+    }
+    if method == Method::OPTIONS
+        || path == "/api/4/private/version"
+        || path.starts_with("/api/4/documentation/")
+        || path.starts_with("/api/1/documentation/")
+    {
+        Access::Open
+    } else if ADMIN_PATHS.contains(&path)
+        || ADMIN_PREFIXES.iter().any(|x| path.starts_with(x))
+        || (path.starts_with("/api/4/settings/") && method != Method::GET)
+    {
+        Access::Admin
+    } else if CHANNEL_PATHS.contains(&path) || CHANNEL_PREFIXES.iter().any(|x| path.starts_with(x)) {
+        let mut backend = None;
+        let mut channels: Option<Vec<String>> = None;
+        for (k, v) in form_urlencoded::parse(uri.query().unwrap_or("").as_bytes()) {
+            match k.as_ref() {
+                "backend" => backend = Some(v.into_owned()),
+                "channelName" => channels.get_or_insert_with(Vec::new).push(v.into_owned()),
+                "channelNames" => channels
+                    .get_or_insert_with(Vec::new)
+                    .extend(v.split(',').filter(|x| !x.is_empty()).map(String::from)),
+                _ => {}
+            }
+        }
+        Access::Channels { backend, channels }
+    } else {
+        Access::Any
+    }
+}
+
+fn bearer_token(headers: &HeaderMap) -> Result<Option<&str>, Denied> {
+    match headers.get(header::AUTHORIZATION) {
+        Some(v) => {
+            let v = v
+                .to_str()
+                .map_err(|_| Denied::Unauthenticated(String::from("invalid authorization header")))?;
+            match v.split_once(' ') {
+                Some((scheme, token)) if scheme.eq_ignore_ascii_case("bearer") => Ok(Some(token.trim())),
+                _ => Err(Denied::Unauthenticated(String::from(
+                    "only bearer tokens are supported",
+                ))),
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+struct JwksFile {
+    path: PathBuf,
+    mtime: Option<SystemTime>,
+    set: Arc<JwkSet>,
+}
+
+static JWKS: Mutex<Option<JwksFile>> = Mutex::new(None);
+
+/// The key set of the file, read again when its modification time changes.
+fn jwks(path: &Path) -> Result<Arc<JwkSet>, Error> {
+    let mtime = std::fs::metadata(path)?.modified().ok();
+    let mut g = JWKS.lock().unwrap();
+    if let Some(f) = g.as_ref() {
+        if f.path == path && f.mtime == mtime && mtime.is_some() {
+            return Ok(f.set.clone());
+        }
+    }
+    debug!("read jwks file {}", path.display());
+    let buf = std::fs::read(path)?;
+    let set: Arc<JwkSet> = Arc::new(serde_json::from_slice(&buf)?);
+    *g = Some(JwksFile {
+        path: path.into(),
+        mtime,
+        set: set.clone(),
+    });
+    Ok(set)
+}
+
+// The algorithm in the token header must fit the kind of key, else a public key could be used as hmac secret.
+fn alg_fits_key(alg: Algorithm, key: &AlgorithmParameters) -> bool {
+    use Algorithm::*;
+    match key {
+        AlgorithmParameters::RSA(_) => matches!(alg, RS256 | RS384 | RS512 | PS256 | PS384 | PS512),
+        AlgorithmParameters::EllipticCurve(_) => matches!(alg, ES256 | ES384),
+        AlgorithmParameters::OctetKeyPair(_) => matches!(alg, EdDSA),
+        AlgorithmParameters::OctetKey(_) => matches!(alg, HS256 | HS384 | HS512),
+    }
+}
+
+fn jwt_identity(token: &str, jwks_path: &Path, conf: &AuthConfig) -> Result<Identity, Denied> {
+    let invalid = |e: &dyn std::fmt::Display| {
+        debug!("jwt rejected: {e}");
+        Denied::Unauthenticated(String::from("invalid token"))
+    };
+    let set = jwks(jwks_path).map_err(|e| {
+        error!("can not read jwks {}: {e}", jwks_path.display());
+        Denied::Unauthenticated(String::from("can not validate token"))
+    })?;
+    let head = jsonwebtoken::decode_header(token).map_err(|e| invalid(&e))?;
+    let jwk = match &head.kid {
+        Some(kid) => set.find(kid),
+        None if set.keys.len() == 1 => set.keys.first(),
+        None => None,
+    }
+    .ok_or_else(|| invalid(&"no matching key"))?;
+    if !alg_fits_key(head.alg, &jwk.algorithm) {
+        return Err(invalid(&format!("algorithm {:?} does not fit the key", head.alg)));
+    }
+    let key = DecodingKey::from_jwk(jwk).map_err(|e| invalid(&e))?;
+    let mut validation = Validation::new(head.alg);
+    match &conf.jwt_audience {
+        Some(aud) => validation.set_audience(&[aud]),
+        None => validation.validate_aud = false,
+    }
+    if let Some(iss) = &conf.jwt_issuer {
+        validation.set_issuer(&[iss]);
+    }
+    let data = jsonwebtoken::decode::<JsonValue>(token, &key, &validation).map_err(|e| invalid(&e))?;
+    let claims = data.claims;
+    let subject = claims
+        .get("sub")
+        .and_then(JsonValue::as_str)
+        .unwrap_or("(no subject)")
+        .to_string();
+    let roles_claim = conf.jwt_roles_claim.as_deref().unwrap_or("roles");
+    // Some issuers list the roles space separated, like scopes.
+    let roles = match claims.get(roles_claim) {
+        Some(JsonValue::Array(a)) => a.iter().filter_map(JsonValue::as_str).map(String::from).collect(),
+        Some(JsonValue::String(s)) => s.split_whitespace().map(String::from).collect(),
+        _ => Vec::new(),
+    };
+    Ok(Identity {
+        subject,
+        roles,
+        method: AuthMethod::Jwt,
+    })
+}
+
+/// Tells who the caller is. Requests without credentials are anonymous, invalid credentials are refused.
+pub fn authenticate(headers: &HeaderMap, conf: &AuthConfig) -> Result<Identity, Denied> {
+    let token = match bearer_token(headers)? {
+        Some(x) => x,
+        None => return Ok(Identity::anonymous(conf)),
+    };
+    if conf.is_node_token(token) {
+        return Ok(Identity {
+            subject: String::from("node"),
+            roles: Vec::new(),
+            method: AuthMethod::Node,
+        });
+    }
+    for t in &conf.tokens {
+        if token_eq(token, &t.token) {
+            return Ok(Identity {
+                subject: t.subject.clone(),
+                roles: t.roles.clone(),
+                method: AuthMethod::Token,
+            });
+        }
+    }
+    match &conf.jwks {
+        Some(path) if token.split('.').count() == 3 => jwt_identity(token, path, conf),
+        _ => Err(Denied::Unauthenticated(String::from("invalid token"))),
+    }
+}
+
+fn channel_matches(rule: &AuthRule, channel: &str) -> bool {
+    match &rule.channel_pattern {
+        None => true,
+        Some(pattern) => pattern.is_match(channel),
+    }
+}
+
+pub fn authorize(identity: &Identity, access: &Access, conf: &AuthConfig) -> Result<(), Denied> {
+    if identity.method == AuthMethod::Node || *access == Access::Open {
+        return Ok(());
+    }
+    let rules: Vec<_> = conf.rules.iter().filter(|r| identity.roles.contains(&r.role)).collect();
+    let allowed = match access {
+        Access::Open => true,
+        Access::Any => !rules.is_empty(),
+        Access::Admin => rules.iter().any(|r| r.admin),
+        Access::Channels { backend, channels } => {
+            let rules: Vec<_> = rules
+                .into_iter()
+                .filter(|r| r.backends.is_empty() || backend.as_ref().is_some_and(|b| r.backends.contains(b)))
+                .collect();
+            match channels {
+                Some(chs) if !chs.is_empty() => chs.iter().all(|ch| rules.iter().any(|r| channel_matches(r, ch))),
+                Some(_) => !rules.is_empty(),
+                None => rules.iter().any(|r| r.channel_pattern.is_none()),
+            }
+        }
+    };
+    if allowed {
+        Ok(())
+    } else if identity.method == AuthMethod::Anonymous {
+        Err(Denied::Unauthenticated(String::from("credentials required")))
+    } else {
+        Err(Denied::Forbidden(format!(
+            "{} is not allowed to access this",
+            identity.subject
+        )))
+    }
+}
+
+/// Authenticates and authorizes the request. Gives `None` if no authentication is configured.
+pub fn check<T>(req: &Request<T>, conf: Option<&AuthConfig>) -> Result<Option<Identity>, Denied> {
+    let conf = match conf {
+        Some(x) => x,
+        None => return Ok(None),
+    };
+    let identity = authenticate(req.headers(), conf)?;
+    let access = access_of(req.method(), req.uri());
+    if let Err(e) = authorize(&identity, &access, conf) {
+        info!("denied {} {}  {:?}  {:?}", req.method(), req.uri(), identity, e);
+        return Err(e);
+    }
+    Ok(Some(identity))
+}
+
+/// Checks the channels which the request reads in the end, like the channels behind an alias,
+/// for the caller of the request being served.
+pub fn check_channels(backend: &str, channels: &[&str], conf: Option<&AuthConfig>) -> Result<(), Denied> {
+    let conf = match conf {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+    let identity = current_identity().unwrap_or_else(|| Identity::anonymous(conf));
+    let access = Access::Channels {
+        backend: Some(backend.into()),
+        channels: Some(channels.iter().map(|&x| x.into()).collect()),
+    };
+    if let Err(e) = authorize(&identity, &access, conf) {
+        info!("denied channels {backend} {channels:?}  {:?}  {:?}", identity, e);
+        return Err(e);
+    }
+    Ok(())
+}
+
+tokio::task_local! {
+    static IDENTITY: Identity;
+}
+
+/// Runs the handling of a request such that `current_identity` tells its caller.
+pub async fn scope<F>(identity: Identity, fut: F) -> F::Output
+where
+    F: Future,
+{
+    IDENTITY.scope(identity, fut).await
+}
+
+/// The caller of the request being served, if the service authenticates.
+pub fn current_identity() -> Option<Identity> {
+    IDENTITY.try_with(|x| x.clone()).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use netpod::AuthToken;
+    use netpod::ChannelPattern;
+
+    fn conf() -> AuthConfig {
+        AuthConfig {
+            tokens: vec![AuthToken {
+                token: "tok-a".into(),
+                subject: "alice".into(),
+                roles: vec!["beamline".into()],
+            }],
+            anonymous_roles: vec!["public".into()],
+            rules: vec![
+                AuthRule {
+                    role: "public".into(),
+                    backends: vec!["sf-databuffer".into()],
+                    channel_pattern: Some(ChannelPattern::new("SARFE10-.*").unwrap()),
+                    admin: false,
+                },
+                AuthRule {
+                    role: "beamline".into(),
+                    backends: Vec::new(),
+                    channel_pattern: None,
+                    admin: false,
+                },
+            ],
+            node_token: Some("tok-node".into()),
+            ..Default::default()
+        }
+    }
+
+    fn req(path: &str, token: Option<&str>) -> Request<()> {
+        let mut b = Request::get(path);
+        if let Some(token) = token {
+            b = b.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        b.body(()).unwrap()
+    }
+
+    #[test]
+    fn auth_rules() {
+        let conf = conf();
+        let c = Some(&conf);
+        let q = "/api/4/events?backend=sf-databuffer&channelName=SARFE10-PSSS059:FIT-COM";
+        assert_eq!(check(&req(q, None), c).unwrap().unwrap().subject, "anonymous");
+        let q = "/api/4/events?backend=sf-databuffer&channelName=SINEG01-DBPM340:X1";
+        assert!(matches!(check(&req(q, None), c), Err(Denied::Unauthenticated(_))));
+        assert_eq!(check(&req(q, Some("tok-a")), c).unwrap().unwrap().subject, "alice");
+        assert!(matches!(
+            check(&req(q, Some("tok-x")), c),
+            Err(Denied::Unauthenticated(_))
+        ));
+        let q = "/api/4/clear_cache";
+        assert!(matches!(check(&req(q, Some("tok-a")), c), Err(Denied::Forbidden(_))));
+        assert!(check(&req(q, Some("tok-node")), c).is_ok());
+        let q = "/api/4/documentation/";
+        assert!(check(&req(q, None), c).is_ok());
+        assert!(check(&req(q, None), None).unwrap().is_none());
+    }
+
+    #[test]
+    fn gather_checks_inner_path() {
+        let conf = conf();
+        let c = Some(&conf);
+        let q = "/api/4/gather/clear_cache";
+        assert!(matches!(check(&req(q, Some("tok-a")), c), Err(Denied::Forbidden(_))));
+        assert!(matches!(check(&req(q, None), c), Err(Denied::Unauthenticated(_))));
+        assert!(check(&req(q, Some("tok-node")), c).is_ok());
+        let q = "/api/4/gather/gather/clear_cache";
+        assert!(matches!(check(&req(q, Some("tok-a")), c), Err(Denied::Forbidden(_))));
+        let q = "/api/4/gather/events?backend=sf-databuffer&channelName=SINEG01-DBPM340:X1";
+        assert!(matches!(check(&req(q, None), c), Err(Denied::Unauthenticated(_))));
+        assert!(check(&req(q, Some("tok-a")), c).is_ok());
+        let uri: Uri = "/api/4/gather/status/board/all".parse().unwrap();
+        assert_eq!(access_of(&Method::GET, &uri), Access::Admin);
+    }
+
+    #[test]
+    fn access_of_channel_query() {
+        let uri: Uri = "/api/4/latest?backend=b1&channelNames=c1,c2".parse().unwrap();
+        let exp = Access::Channels {
+            backend: Some("b1".into()),
+            channels: Some(vec!["c1".into(), "c2".into()]),
+        };
+        assert_eq!(access_of(&Method::GET, &uri), exp);
+        let uri: Uri = "/api/4/settings/read3/threads_max".parse().unwrap();
+        assert_eq!(access_of(&Method::GET, &uri), Access::Any);
+        assert_eq!(access_of(&Method::PUT, &uri), Access::Admin);
+    }
+
+    #[test]
+    fn alias_channels_checked() {
+        let conf = conf();
+        let c = Some(&conf);
+        // An alias named like a public channel must not open up the channels behind it.
+        let q = "/api/4/events?backend=sf-databuffer&channelName=SARFE10-ALIAS";
+        assert!(check(&req(q, None), c).is_ok());
+        assert!(check_channels("sf-databuffer", &["SARFE10-PSSS059:FIT-COM"], c).is_ok());
+        assert!(matches!(
+            check_channels("sf-databuffer", &["SINEG01-DBPM340:X1"], c),
+            Err(Denied::Unauthenticated(_))
+        ));
+        let alice = Identity {
+            subject: "alice".into(),
+            roles: vec!["beamline".into()],
+            method: AuthMethod::Token,
+        };
+        let fut = async { check_channels("sf-databuffer", &["SINEG01-DBPM340:X1"], c).map_err(|e| format!("{e:?}")) };
+        assert!(taskrun::run(scope(alice, fut)).is_ok());
+        assert!(check_channels("sf-databuffer", &["SINEG01-DBPM340:X1"], None).is_ok());
+    }
+}
@@ -68,6 +68,10 @@ impl ConnectionStatusEvents {
         q: &ChannelStateEventsQuery,
         node_config: &NodeConfigCached,
     ) -> Result<Vec<ConnStatusEvent>, Error> {
+        if node_config.node_config.cluster.is_json_backend(q.channel().backend()) {
+            // JSON backends record no status.
+            return Ok(Vec::new());
+        }
         let scyco = node_config
             .node_config
             .cluster
@@ -142,6 +146,10 @@ impl ChannelStatusEvents {
         q: &ChannelStateEventsQuery,
         node_config: &NodeConfigCached,
     ) -> Result<Vec<ChannelStatusEvent>, Error> {
+        if node_config.node_config.cluster.is_json_backend(q.channel().backend()) {
+            // JSON backends record no status.
+            return Ok(Vec::new());
+        }
         let scyco = node_config
             .node_config
             .cluster
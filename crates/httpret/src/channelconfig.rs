@@ -1,3 +1,5 @@
+use crate::auth;
+use crate::auth::Denied;
 use crate::err::Error;
 use crate::response;
 use crate::ToPublicResponse;
@@ -14,6 +16,7 @@ use netpod::query::prebinned::PreBinnedQuery;
 use netpod::timeunits::*;
 use netpod::ChannelConfigQuery;
 use netpod::ChannelConfigResponse;
+use netpod::ChannelConfigSegment;
 use netpod::ChannelTypeConfigGen;
 use netpod::FromUrl;
 use netpod::NodeConfigCached;
@@ -23,6 +26,7 @@ use netpod::Shape;
 use netpod::ACCEPT_ALL;
 use netpod::APP_JSON;
 use nodenet::configquorum::find_config_basics_quorum;
+use nodenet::configquorum::find_config_segments_quorum;
 use query::api4::binned::BinnedQuery;
 use query::api4::events::PlainEventsQuery;
 use scyllaconn::errconv::ErrConv;
@@ -31,11 +35,31 @@ use serde::Serialize;
 use std::collections::BTreeMap;
 use url::Url;
 
+/// The request was allowed for the name in the url, which may be an alias.
+/// The channels which are actually read are checked here.
+fn authorize_segments(segs: &[ChannelConfigSegment], ncc: &NodeConfigCached) -> Result<(), Error> {
+    let conf = ncc.node_config.cluster.auth.as_ref();
+    for seg in segs {
+        let ch = &seg.ch_conf;
+        if let Err(e) = auth::check_channels(ch.backend(), &[ch.name()], conf) {
+            let msg = match e {
+                Denied::Unauthenticated(x) => x,
+                Denied::Forbidden(x) => x,
+            };
+            return Err(Error::with_public_msg_no_trace(format!("{msg}  channel {}", ch.name())));
+        }
+    }
+    Ok(())
+}
+
 pub async fn chconf_from_events_quorum(
     q: &PlainEventsQuery,
     ncc: &NodeConfigCached,
-) -> Result<Option<ChannelTypeConfigGen>, Error> {
-    let ret = find_config_basics_quorum(q.channel().clone(), q.range().clone(), ncc).await?;
+) -> Result<Option<Vec<ChannelConfigSegment>>, Error> {
+    let ret = find_config_segments_quorum(q.channel().clone(), q.range().clone(), ncc).await?;
+    if let Some(segs) = &ret {
+        authorize_segments(segs, ncc)?;
+    }
     Ok(ret)
 }
 
@@ -50,8 +74,11 @@ pub async fn chconf_from_prebinned(
 pub async fn ch_conf_from_binned(
     q: &BinnedQuery,
     ncc: &NodeConfigCached,
-) -> Result<Option<ChannelTypeConfigGen>, Error> {
-    let ret = find_config_basics_quorum(q.channel().clone(), q.range().clone(), ncc).await?;
+) -> Result<Option<Vec<ChannelConfigSegment>>, Error> {
+    let ret = find_config_segments_quorum(q.channel().clone(), q.range().clone(), ncc).await?;
+    if let Some(segs) = &ret {
+        authorize_segments(segs, ncc)?;
+    }
     Ok(ret)
 }
 
@@ -209,6 +236,106 @@ impl ChannelConfigQuorumHandler {
     }
 }
 
+pub struct ChannelConfigHistoryHandler {}
+
+impl ChannelConfigHistoryHandler {
+    pub fn handler(req: &Request<Body>) -> Option<Self> {
+        if req.uri().path() == "/api/4/channel/config/history" {
+            Some(Self {})
+        } else {
+            None
+        }
+    }
+
+    pub async fn handle(&self, req: Request<Body>, node_config: &NodeConfigCached) -> Result<Response<Body>, Error> {
+        if req.method() == Method::GET {
+            let accept_def = APP_JSON;
+            let accept = req
+                .headers()
+                .get(http::header::ACCEPT)
+                .map_or(accept_def, |k| k.to_str().unwrap_or(accept_def));
+            if accept.contains(APP_JSON) || accept.contains(ACCEPT_ALL) {
+                match self.channel_config_history(req, &node_config).await {
+                    Ok(k) => Ok(k),
+                    Err(e) => {
+                        warn!("from channel_config_history: {e}");
+                        Ok(e.to_public_response())
+                    }
+                }
+            } else {
+                Ok(response(StatusCode::BAD_REQUEST).body(Body::empty())?)
+            }
+        } else {
+            Ok(response(StatusCode::METHOD_NOT_ALLOWED).body(Body::empty())?)
+        }
+    }
+
+    async fn channel_config_history(
+        &self,
+        req: Request<Body>,
+        ncc: &NodeConfigCached,
+    ) -> Result<Response<Body>, Error> {
+        let url = Url::parse(&format!("dummy:{}", req.uri()))?;
+        let q = ChannelConfigQuery::from_url(&url)?;
+        debug!("channel_config_history  for q {q:?}");
+        match nodenet::configquorum::find_config_history_quorum(q, ncc).await? {
+            Some(hist) => {
+                let ret = response(StatusCode::OK)
+                    .header(http::header::CONTENT_TYPE, APP_JSON)
+                    .body(Body::from(serde_json::to_string(&hist)?))?;
+                Ok(ret)
+            }
+            None => {
+                let ret = response(StatusCode::NOT_FOUND)
+                    .header(http::header::CONTENT_TYPE, APP_JSON)
+                    .body(Body::empty())?;
+                Ok(ret)
+            }
+        }
+    }
+}
+
+/// The config history of a channel as this node knows it, asked for by `ChannelConfigHistoryHandler`.
+pub struct ChannelConfigHistoryLocalHandler {}
+
+impl ChannelConfigHistoryLocalHandler {
+    pub fn handler(req: &Request<Body>) -> Option<Self> {
+        if req.uri().path() == "/api/4/channel/config/history/local" {
+            Some(Self {})
+        } else {
+            None
+        }
+    }
+
+    pub async fn handle(&self, req: Request<Body>, node_config: &NodeConfigCached) -> Result<Response<Body>, Error> {
+        if req.method() == Method::GET {
+            match self.channel_config_history_local(req, &node_config).await {
+                Ok(k) => Ok(k),
+                Err(e) => {
+                    warn!("from channel_config_history_local: {e}");
+                    Ok(e.to_public_response())
+                }
+            }
+        } else {
+            Ok(response(StatusCode::METHOD_NOT_ALLOWED).body(Body::empty())?)
+        }
+    }
+
+    async fn channel_config_history_local(
+        &self,
+        req: Request<Body>,
+        ncc: &NodeConfigCached,
+    ) -> Result<Response<Body>, Error> {
+        let url = Url::parse(&format!("dummy:{}", req.uri()))?;
+        let q = ChannelConfigQuery::from_url(&url)?;
+        let hist = nodenet::channelconfig::channel_config_history_local(q.channel, ncc).await?;
+        let ret = response(StatusCode::OK)
+            .header(http::header::CONTENT_TYPE, APP_JSON)
+            .body(Body::from(serde_json::to_string(&hist)?))?;
+        Ok(ret)
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ConfigsHisto {
     scalar_types: Vec<(ScalarType, Vec<(Shape, u32)>)>,
@@ -1,6 +1,7 @@
 use crate::err::Error;
 use crate::response;
 use futures_util::TryStreamExt;
+use http::header;
 use http::Method;
 use http::StatusCode;
 use hyper::Body;
@@ -11,7 +12,14 @@ use netpod::log::*;
 use netpod::DiskIoTune;
 use netpod::FromUrl;
 use netpod::NodeConfigCached;
+use netpod::APP_JSON;
+use netpod::APP_OCTET;
+use percent_encoding::percent_decode_str;
+use serde::Serialize;
+use std::io::SeekFrom;
+use std::path::Path;
 use taskrun::tokio;
+use tokio::io::AsyncSeekExt;
 use url::Url;
 
 #[derive(Clone, Debug)]
@@ -50,6 +58,51 @@ impl FromUrl for DownloadQuery {
     }
 }
 
+#[derive(Serialize)]
+struct DirEntry {
+    name: String,
+    dir: bool,
+    len: u64,
+}
+
+/// Parses a `Range` header with a single range into the half-open byte range `[beg, end)`.
+/// Returns `None` for headers which are ignored, `Err` if the range does not overlap the file.
+fn parse_range(v: &str, len: u64) -> Result<Option<(u64, u64)>, ()> {
+    let spec = match v.trim().strip_prefix("bytes=") {
+        Some(k) if !k.contains(',') => k.trim(),
+        _ => return Ok(None),
+    };
+    let (a, b) = match spec.split_once('-') {
+        Some(k) => k,
+        None => return Ok(None),
+    };
+    let (beg, end) = if a.is_empty() {
+        // Suffix range, the last n bytes.
+        let n: u64 = b.parse().map_err(|_| ())?;
+        if n == 0 {
+            return Err(());
+        }
+        (len.saturating_sub(n), len)
+    } else {
+        let beg: u64 = a.parse().map_err(|_| ())?;
+        let end = if b.is_empty() {
+            len
+        } else {
+            let last: u64 = b.parse().map_err(|_| ())?;
+            if last < beg {
+                return Err(());
+            }
+            (last + 1).min(len)
+        };
+        (beg, end)
+    };
+    if beg >= len {
+        Err(())
+    } else {
+        Ok(Some((beg, end)))
+    }
+}
+
 pub struct DownloadHandler {}
 
 impl DownloadHandler {
@@ -67,19 +120,104 @@ impl DownloadHandler {
 
     pub async fn get(&self, req: Request<Body>, ncc: &NodeConfigCached) -> Result<Response<Body>, Error> {
         let (head, _body) = req.into_parts();
+        let cluster = &ncc.node_config.cluster;
+        let roots = match &cluster.download {
+            Some(k) if cluster.auth.is_some() => &k.roots,
+            _ => {
+                debug!("download requested but not enabled");
+                return Ok(response(StatusCode::NOT_FOUND).body(Body::empty())?);
+            }
+        };
         let p2 = &head.uri.path()[Self::path_prefix().len()..];
-        let base = match &ncc.node.sf_databuffer {
-            Some(k) => k.data_base_path.clone(),
-            None => "/UNDEFINED".into(),
+        let p2 = percent_decode_str(p2)
+            .decode_utf8()
+            .map_err(|_| Error::with_public_msg_no_trace("path is not valid utf-8"))?;
+        let pp = match tokio::fs::canonicalize(Path::new("/").join(p2.as_ref())).await {
+            Ok(k) => k,
+            Err(_) => return Ok(response(StatusCode::NOT_FOUND).body(Body::empty())?),
         };
+        let mut allowed = false;
+        for root in roots {
+            match tokio::fs::canonicalize(root).await {
+                Ok(root) if pp.starts_with(&root) => {
+                    allowed = true;
+                    break;
+                }
+                Ok(_) => {}
+                Err(e) => warn!("download root {}: {e}", root.display()),
+            }
+        }
+        if !allowed {
+            info!("download outside of the roots denied {}", pp.display());
+            return Ok(response(StatusCode::FORBIDDEN).body(Body::empty())?);
+        }
+        let md = tokio::fs::metadata(&pp).await?;
+        if md.is_dir() {
+            return self.list_dir(&pp).await;
+        }
         let url = url::Url::parse(&format!("http://dummy{}", head.uri))?;
         let query = DownloadQuery::from_url(&url)?;
-        // TODO wrap file operation to return a better error.
-        let pp = base.join(p2);
-        info!("Try to open {pp:?}");
-        let file = tokio::fs::OpenOptions::new().read(true).open(&pp).await?;
-        let s = disk::file_content_stream(pp, file, query.disk_io_tune.clone(), "download").map_ok(|x| x.into_buf());
-        Ok(response(StatusCode::OK).body(Body::wrap_stream(s))?)
+        let len = md.len();
+        let range = match head.headers.get(header::RANGE).and_then(|x| x.to_str().ok()) {
+            Some(v) => match parse_range(v, len) {
+                Ok(k) => k,
+                Err(()) => {
+                    let ret = response(StatusCode::RANGE_NOT_SATISFIABLE)
+                        .header(header::CONTENT_RANGE, format!("bytes */{len}"))
+                        .body(Body::empty())?;
+                    return Ok(ret);
+                }
+            },
+            None => None,
+        };
+        info!("Try to open {pp:?}  range {range:?}");
+        let mut file = tokio::fs::OpenOptions::new().read(true).open(&pp).await?;
+        let (beg, end) = range.unwrap_or((0, len));
+        if beg != 0 {
+            file.seek(SeekFrom::Start(beg)).await?;
+        }
+        let s = disk::file_content_stream(pp, file, query.disk_io_tune.clone(), "download");
+        let mut remaining = end - beg;
+        let s = s
+            .map_ok(move |x| {
+                let mut buf = x.into_buf();
+                buf.truncate(remaining.min(buf.len() as u64) as usize);
+                remaining -= buf.len() as u64;
+                buf
+            })
+            .try_take_while(|x| futures_util::future::ready(Ok(!x.is_empty())));
+        let ret = response(if range.is_some() {
+            StatusCode::PARTIAL_CONTENT
+        } else {
+            StatusCode::OK
+        })
+        .header(header::CONTENT_TYPE, APP_OCTET)
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_LENGTH, end - beg);
+        let ret = if range.is_some() {
+            ret.header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", beg, end - 1, len))
+        } else {
+            ret
+        };
+        Ok(ret.body(Body::wrap_stream(s))?)
+    }
+
+    async fn list_dir(&self, path: &Path) -> Result<Response<Body>, Error> {
+        let mut ret = Vec::new();
+        let mut rd = tokio::fs::read_dir(path).await?;
+        while let Some(e) = rd.next_entry().await? {
+            let md = e.metadata().await?;
+            ret.push(DirEntry {
+                name: e.file_name().to_string_lossy().into_owned(),
+                dir: md.is_dir(),
+                len: md.len(),
+            });
+        }
+        ret.sort_by(|a, b| a.name.cmp(&b.name));
+        let ret = response(StatusCode::OK)
+            .header(header::CONTENT_TYPE, APP_JSON)
+            .body(Body::from(serde_json::to_vec(&ret)?))?;
+        Ok(ret)
     }
 
     pub async fn handle(&self, req: Request<Body>, node_config: &NodeConfigCached) -> Result<Response<Body>, Error> {
@@ -90,3 +228,99 @@ impl DownloadHandler {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::auth;
+    use netpod::AuthConfig;
+    use netpod::AuthRule;
+    use netpod::AuthToken;
+    use netpod::DownloadConfig;
+    use netpod::NodeConfig;
+
+    #[test]
+    fn range_header() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Ok(Some((0, 100))));
+        assert_eq!(parse_range("bytes=900-", 1000), Ok(Some((900, 1000))));
+        assert_eq!(parse_range("bytes=-100", 1000), Ok(Some((900, 1000))));
+        assert_eq!(parse_range("bytes=990-2000", 1000), Ok(Some((990, 1000))));
+        assert_eq!(parse_range("bytes=0-1,5-6", 1000), Ok(None));
+        assert_eq!(parse_range("bytes=1000-", 1000), Err(()));
+        assert_eq!(parse_range("bytes=5-4", 1000), Err(()));
+    }
+
+    fn node_config(root: &Path) -> NodeConfigCached {
+        let mut cluster = netpod::test_cluster();
+        cluster.download = Some(DownloadConfig {
+            roots: vec![root.into()],
+        });
+        let token = |token: &str, role: &str| AuthToken {
+            token: token.into(),
+            subject: role.into(),
+            roles: vec![role.into()],
+        };
+        let rule = |role: &str, admin| AuthRule {
+            role: role.into(),
+            backends: Vec::new(),
+            channel_pattern: None,
+            admin,
+        };
+        cluster.auth = Some(AuthConfig {
+            tokens: vec![token("tok-admin", "admin"), token("tok-user", "user")],
+            rules: vec![rule("admin", true), rule("user", false)],
+            ..Default::default()
+        });
+        NodeConfigCached {
+            node: cluster.nodes[0].clone(),
+            ix: 0,
+            node_config: NodeConfig {
+                name: "test".into(),
+                cluster,
+            },
+        }
+    }
+
+    /// Serves the request like the service does, with the authorization in front of the handler.
+    async fn status(path: &Path, token: &str, ncc: &NodeConfigCached) -> Result<StatusCode, Error> {
+        let path = path.to_str().unwrap().trim_start_matches('/');
+        let req = Request::get(format!("{}{}", DownloadHandler::path_prefix(), path))
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())?;
+        let res = match auth::check(&req, ncc.node_config.cluster.auth.as_ref()) {
+            Ok(_) => DownloadHandler {}.handle(req, ncc).await?,
+            Err(e) => e.response()?,
+        };
+        Ok(res.status())
+    }
+
+    #[test]
+    fn download_outside_roots_denied() -> Result<(), Error> {
+        let base = std::env::temp_dir().join(format!("daqbuffer-download-test-{}", std::process::id()));
+        let root = base.join("root");
+        std::fs::create_dir_all(&root)?;
+        std::fs::write(root.join("inside.dat"), b"inside")?;
+        std::fs::write(base.join("outside.dat"), b"outside")?;
+        std::os::unix::fs::symlink(base.join("outside.dat"), root.join("link.dat"))?;
+        let ncc = node_config(&root);
+        let fut = async {
+            let ret = [
+                status(&root.join("inside.dat"), "tok-admin", &ncc).await?,
+                status(&root.join("../outside.dat"), "tok-admin", &ncc).await?,
+                status(&root.join("link.dat"), "tok-admin", &ncc).await?,
+                status(&base.join("outside.dat"), "tok-admin", &ncc).await?,
+                status(&root.join("inside.dat"), "tok-user", &ncc).await?,
+            ];
+            Ok::<_, Error>(ret)
+        };
+        let res = taskrun::run(fut);
+        std::fs::remove_dir_all(&base)?;
+        let [inside, traversal, symlink, absolute, non_admin] = res?;
+        assert_eq!(inside, StatusCode::OK);
+        assert_eq!(traversal, StatusCode::FORBIDDEN);
+        assert_eq!(symlink, StatusCode::FORBIDDEN);
+        assert_eq!(absolute, StatusCode::FORBIDDEN);
+        assert_eq!(non_admin, StatusCode::FORBIDDEN);
+        Ok(())
+    }
+}
@@ -125,7 +125,9 @@ pub async fn gather_get_json(req: Request<Body>, node_config: &NodeConfigCached)
         .iter()
         .map(|node| {
             let uri = format!("http://{}:{}/api/4/{}", node.host, node.port, pathsuf);
-            let req = Request::builder().method(Method::GET).uri(uri);
+            let req = httpclient::with_authorization(Request::builder())
+                .method(Method::GET)
+                .uri(uri);
             let req = req.header(http::header::ACCEPT, APP_JSON);
             let req = req.body(Body::empty());
             let task = tokio::spawn(async move {
@@ -214,10 +216,11 @@ where
         .map(move |((url, body), tag)| {
             info!("Try gather from {}", url);
             let url_str = url.as_str();
+            let req = httpclient::with_authorization(Request::builder());
             let req = if body.is_some() {
-                Request::builder().method(Method::POST).uri(url_str)
+                req.method(Method::POST).uri(url_str)
             } else {
-                Request::builder().method(Method::GET).uri(url_str)
+                req.method(Method::GET).uri(url_str)
             };
             let req = req.header(http::header::ACCEPT, APP_JSON);
             let req = if body.is_some() {
@@ -1,5 +1,6 @@
 pub mod api1;
 pub mod api4;
+pub mod auth;
 pub mod bodystream;
 pub mod channel_status;
 pub mod channelconfig;
@@ -9,6 +10,7 @@ pub mod gather;
 pub mod prometheus;
 pub mod proxy;
 pub mod pulsemap;
+pub mod ratelimit;
 pub mod settings;
 
 use self::bodystream::ToPublicResponse;
@@ -118,6 +120,16 @@ pub fn accepts_octets(hm: &http::HeaderMap) -> bool {
     }
 }
 
+pub fn accepts_csv(hm: &http::HeaderMap) -> bool {
+    match hm.get(http::header::ACCEPT) {
+        Some(x) => match x.to_str() {
+            Ok(x) => x.contains(netpod::TEXT_CSV),
+            Err(_) => false,
+        },
+        None => false,
+    }
+}
+
 pub async fn host(node_config: NodeConfigCached, service_version: ServiceVersion) -> Result<(), RetrievalError> {
     static STATUS_BOARD_INIT: Once = Once::new();
     STATUS_BOARD_INIT.call_once(|| {
@@ -126,9 +138,33 @@ pub async fn host(node_config: NodeConfigCached, service_version: ServiceVersion
         let x = Box::new(a);
         STATUS_BOARD.store(Box::into_raw(x), Ordering::SeqCst);
     });
+    if let Some(token) = node_config
+        .node_config
+        .cluster
+        .auth
+        .as_ref()
+        .and_then(|x| x.node_token.as_ref())
+    {
+        httpclient::set_node_token(token)?;
+    }
+    if node_config.node_config.cluster.rate_limits.is_some() {
+        ratelimit::exempt_cluster_nodes(&node_config.node_config.cluster);
+    }
     if let Some(bind) = node_config.node.prometheus_api_bind {
         tokio::spawn(prometheus::host(bind));
     }
+    {
+        let db = node_config.node_config.cluster.database.clone();
+        tokio::spawn(async move {
+            let res = match dbconn::shared_connection(&db).await {
+                Ok(dbc) => dbconn::scan::make_tables(&dbc).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                warn!("can not create tables: {e}");
+            }
+        });
+    }
     // let rawjh = taskrun::spawn(nodenet::conn::events_service(node_config.clone()));
     use std::str::FromStr;
     let addr = SocketAddr::from_str(&format!("{}:{}", node_config.node.listen(), node_config.node.port))?;
@@ -148,7 +184,7 @@ pub async fn host(node_config: NodeConfigCached, service_version: ServiceVersion
                         req.uri(),
                         req.headers()
                     );
-                    let f = http_service(req, node_config.clone(), service_version.clone());
+                    let f = http_service(req, addr, node_config.clone(), service_version.clone());
                     Cont { f: Box::pin(f) }
                 });
                 Ok::<_, Error>(ret)
@@ -165,10 +201,11 @@ pub async fn host(node_config: NodeConfigCached, service_version: ServiceVersion
 
 async fn http_service(
     req: Request<Body>,
+    peer: SocketAddr,
     node_config: NodeConfigCached,
     service_version: ServiceVersion,
 ) -> Result<Response<Body>, Error> {
-    match http_service_try(req, &node_config, &service_version).await {
+    match http_service_try(req, peer, &node_config, &service_version).await {
         Ok(k) => Ok(k),
         Err(e) => {
             error!("daqbuffer node http_service sees error: {}", e);
@@ -305,6 +342,7 @@ macro_rules! static_http_api1 {
 
 async fn http_service_try(
     req: Request<Body>,
+    peer: SocketAddr,
     node_config: &NodeConfigCached,
     service_version: &ServiceVersion,
 ) -> Result<Response<Body>, Error> {
@@ -318,10 +356,24 @@ async fn http_service_try(
         }
     }
     let ctx = ReqCtx::with_node(&req, node_config);
-    let mut res = http_service_inner(req, &ctx, node_config, service_version).await?;
+    let limits = node_config.node_config.cluster.rate_limits.as_ref();
+    // Requests to the other nodes on behalf of the caller, like gather, carry the credentials of the caller
+    // instead of the node token, so that the other nodes check them.
+    let authorization = req.headers().get(http::header::AUTHORIZATION).cloned();
+    let mut res = match auth::check(&req, node_config.node_config.cluster.auth.as_ref()) {
+        Ok(identity) => {
+            let key = limits.and_then(|x| ratelimit::client_key(peer.ip(), req.headers(), identity.as_ref(), x));
+            let fut = ratelimit::limited(key, limits, http_service_inner(req, &ctx, node_config, service_version));
+            match identity {
+                Some(identity) => httpclient::forward_authorization(authorization, auth::scope(identity, fut)).await?,
+                None => httpclient::forward_authorization(authorization, fut).await?,
+            }
+        }
+        Err(e) => e.response()?,
+    };
     let hm = res.headers_mut();
     hm.append("Access-Control-Allow-Origin", "*".parse().unwrap());
-    hm.append("Access-Control-Allow-Headers", "*".parse().unwrap());
+    hm.append("Access-Control-Allow-Headers", "*, Authorization".parse().unwrap());
     for m in &ctx.marks {
         hm.append(PSI_DAQBUFFER_SERVICE_MARK, m.parse().unwrap());
     }
@@ -389,6 +441,10 @@ async fn http_service_inner(
         Ok(h.handle(req, &node_config).await?)
     } else if let Some(h) = channelconfig::ChannelConfigQuorumHandler::handler(&req) {
         Ok(h.handle(req, &node_config).await?)
+    } else if let Some(h) = channelconfig::ChannelConfigHistoryHandler::handler(&req) {
+        Ok(h.handle(req, &node_config).await?)
+    } else if let Some(h) = channelconfig::ChannelConfigHistoryLocalHandler::handler(&req) {
+        Ok(h.handle(req, &node_config).await?)
     } else if let Some(h) = channelconfig::ChannelConfigsHandler::handler(&req) {
         Ok(h.handle(req, &node_config).await?)
     } else if let Some(h) = channelconfig::ChannelConfigHandler::handler(&req) {
@@ -405,6 +461,10 @@ async fn http_service_inner(
         Ok(h.handle(req, &node_config).await?)
     } else if let Some(h) = api4::events::EventsHandler::handler(&req) {
         Ok(h.handle(req, &node_config).await?)
+    } else if let Some(h) = api4::cost::EventsCostLocal::handler(&req) {
+        Ok(h.handle(req, &node_config).await?)
+    } else if let Some(h) = api4::latest::LatestHandler::handler(&req) {
+        Ok(h.handle(req, &node_config).await?)
     } else if let Some(h) = channel_status::ConnectionStatusEvents::handler(&req) {
         Ok(h.handle(req, ctx, &node_config).await?)
     } else if let Some(h) = channel_status::ChannelStatusEvents::handler(&req) {
@@ -469,6 +529,12 @@ async fn http_service_inner(
         Ok(h.handle(req, &node_config).await?)
     } else if let Some(h) = pulsemap::MapPulseHttpFunction::handler(&req) {
         Ok(h.handle(req, &node_config).await?)
+    } else if let Some(h) = pulsemap::batch::MapPulseBatchLocalHttpFunction::handler(&req) {
+        Ok(h.handle(req, &node_config).await?)
+    } else if let Some(h) = pulsemap::batch::MapPulseBatchHttpFunction::handler(&req) {
+        Ok(h.handle(req, &node_config).await?)
+    } else if let Some(h) = pulsemap::batch::MapPulseRangeHttpFunction::handler(&req) {
+        Ok(h.handle(req, &node_config).await?)
     } else if let Some(h) = pulsemap::Api4MapPulse2HttpFunction::handler(&req) {
         Ok(h.handle(req, &node_config).await?)
     } else if let Some(h) = pulsemap::Api4MapPulseHttpFunction::handler(&req) {
@@ -537,9 +603,18 @@ impl StatusBoardAllHandler {
         _req: Request<Body>,
         _node_config: &NodeConfigCached,
     ) -> Result<Response<Body>, RetrievalError> {
-        use std::ops::Deref;
+        #[derive(Serialize)]
+        struct StatusBoardAll<'a> {
+            #[serde(flatten)]
+            board: &'a StatusBoard,
+            client_usage: Vec<netpod::ClientUsage>,
+        }
         let sb = status_board().unwrap();
-        let buf = serde_json::to_vec(sb.deref()).unwrap();
+        let ret = StatusBoardAll {
+            board: &sb,
+            client_usage: ratelimit::usage(),
+        };
+        let buf = serde_json::to_vec(&ret).unwrap();
         let res = response(StatusCode::OK).body(Body::from(buf))?;
         Ok(res)
     }
@@ -712,6 +787,8 @@ pub struct StatusBoardEntry {
     ts_created: SystemTime,
     #[serde(serialize_with = "instant_serde::ser")]
     ts_updated: SystemTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    identity: Option<String>,
     // #[serde(skip_serializing_if = "is_false")]
     done: bool,
     // #[serde(skip_serializing_if = "Vec::is_empty")]
@@ -755,6 +832,7 @@ impl StatusBoardEntry {
         Self {
             ts_created: SystemTime::now(),
             ts_updated: SystemTime::now(),
+            identity: None,
             done: false,
             errors: Vec::new(),
             error_count: 0,
@@ -785,6 +863,17 @@ pub struct StatusBoardEntryUser {
     errors: Vec<::err::PublicError>,
 }
 
+impl StatusBoardEntryUser {
+    pub fn not_found() -> Self {
+        Self {
+            error_count: 1,
+            warn_count: 0,
+            channel_not_found: 0,
+            errors: vec![::err::Error::with_public_msg_no_trace(STATUS_ID_NOT_FOUND).into()],
+        }
+    }
+}
+
 impl From<&StatusBoardEntry> for StatusBoardEntryUser {
     fn from(e: &StatusBoardEntry) -> Self {
         Self {
@@ -796,6 +885,9 @@ impl From<&StatusBoardEntry> for StatusBoardEntryUser {
     }
 }
 
+/// The error of a status which this process does not know.
+pub const STATUS_ID_NOT_FOUND: &'static str = "request-id not found";
+
 #[derive(Debug, Serialize)]
 pub struct StatusBoard {
     entries: BTreeMap<String, StatusBoardEntry>,
@@ -818,7 +910,9 @@ impl StatusBoard {
         let n = u32::from_le_bytes(buf);
         let s = format!("{:08x}", n);
         debug!("new_status_id {s}");
-        self.entries.insert(s.clone(), StatusBoardEntry::new());
+        let mut entry = StatusBoardEntry::new();
+        entry.identity = auth::current_identity().map(|x| x.subject);
+        self.entries.insert(s.clone(), entry);
         s
     }
 
@@ -881,12 +975,7 @@ impl StatusBoard {
             None => {
                 error!("can not find status id {}", status_id);
                 let _e = ::err::Error::with_public_msg_no_trace(format!("Request status ID unknown {status_id}"));
-                StatusBoardEntryUser {
-                    error_count: 1,
-                    warn_count: 0,
-                    channel_not_found: 0,
-                    errors: vec![::err::Error::with_public_msg_no_trace("request-id not found").into()],
-                }
+                StatusBoardEntryUser::not_found()
             }
         }
     }
@@ -1,15 +1,22 @@
 pub mod api1;
 pub mod api4;
+pub mod balance;
+pub mod cache;
 
 use crate::api1::channel_search_configs_v1;
 use crate::api1::channel_search_list_v1;
 use crate::api1::gather_json_2_v1;
 use crate::api_1_docs;
 use crate::api_4_docs;
+use crate::auth;
 use crate::err::Error;
 use crate::gather::gather_get_json_generic;
 use crate::gather::SubRes;
+use crate::pulsemap::batch::MapPulseRangeHttpFunction;
+use crate::pulsemap::batch::MapPulseRangeQuery;
 use crate::pulsemap::MapPulseQuery;
+use crate::ratelimit;
+use crate::ratelimit::X_FORWARDED_FOR;
 use crate::response;
 use crate::response_err;
 use crate::Cont;
@@ -17,8 +24,10 @@ use crate::ReqCtx;
 use crate::PSI_DAQBUFFER_SERVICE_MARK;
 use futures_util::pin_mut;
 use futures_util::Stream;
+use http::HeaderName;
 use http::Method;
 use http::StatusCode;
+use hyper::server::conn::AddrStream;
 use hyper::service::make_service_fn;
 use hyper::service::service_fn;
 use hyper::Body;
@@ -36,15 +45,15 @@ use netpod::ChannelSearchSingleResult;
 use netpod::FromUrl;
 use netpod::HasBackend;
 use netpod::HasTimeout;
+use netpod::ProxyBackend;
 use netpod::ProxyConfig;
 use netpod::ServiceVersion;
-use netpod::ACCEPT_ALL;
 use netpod::APP_JSON;
+use netpod::X_DAQBUF_REQID;
 use query::api4::binned::BinnedQuery;
 use query::api4::events::PlainEventsQuery;
 use serde::Deserialize;
 use serde::Serialize;
-use serde_json::Value as JsonValue;
 use std::future::Future;
 use std::net::SocketAddr;
 use std::pin::Pin;
@@ -62,8 +71,10 @@ const DISTRI_PRE: &str = "/distri/";
 pub async fn proxy(proxy_config: ProxyConfig, service_version: ServiceVersion) -> Result<(), Error> {
     use std::str::FromStr;
     let addr = SocketAddr::from_str(&format!("{}:{}", proxy_config.listen, proxy_config.port))?;
+    balance::spawn_health_checks(proxy_config.clone());
     let make_service = make_service_fn({
-        move |_conn| {
+        move |conn: &AddrStream| {
+            let peer = conn.remote_addr();
             let proxy_config = proxy_config.clone();
             let service_version = service_version.clone();
             async move {
@@ -76,7 +87,7 @@ pub async fn proxy(proxy_config: ProxyConfig, service_version: ServiceVersion) -
                             req.uri(),
                             req.headers()
                         );
-                        let f = proxy_http_service(req, proxy_config.clone(), service_version.clone());
+                        let f = proxy_http_service(req, peer, proxy_config.clone(), service_version.clone());
                         Cont { f: Box::pin(f) }
                     }
                 }))
@@ -89,10 +100,11 @@ pub async fn proxy(proxy_config: ProxyConfig, service_version: ServiceVersion) -
 
 async fn proxy_http_service(
     req: Request<Body>,
+    peer: SocketAddr,
     proxy_config: ProxyConfig,
     service_version: ServiceVersion,
 ) -> Result<Response<Body>, Error> {
-    match proxy_http_service_try(req, &proxy_config, &service_version).await {
+    match proxy_http_service_try(req, peer, &proxy_config, &service_version).await {
         Ok(k) => Ok(k),
         Err(e) => {
             error!("data_api_proxy sees error: {:?}", e);
@@ -102,15 +114,36 @@ async fn proxy_http_service(
 }
 
 async fn proxy_http_service_try(
-    req: Request<Body>,
+    mut req: Request<Body>,
+    peer: SocketAddr,
     proxy_config: &ProxyConfig,
     service_version: &ServiceVersion,
 ) -> Result<Response<Body>, Error> {
     let ctx = ReqCtx::with_proxy(&req, proxy_config);
-    let mut res = proxy_http_service_inner(req, &ctx, proxy_config, &service_version).await?;
+    // Requests to the backends carry the credentials of the caller, the backends check them again.
+    let authorization = req.headers().get(http::header::AUTHORIZATION).cloned();
+    let limits = proxy_config.rate_limits.as_ref();
+    let mut res = match auth::check(&req, proxy_config.auth.as_ref()) {
+        Ok(identity) => {
+            let key = limits.and_then(|x| ratelimit::client_key(peer.ip(), req.headers(), identity.as_ref(), x));
+            // The backends limit by the address of the caller, not by the address of the proxy.
+            let fwd = peer.ip().to_string();
+            req.headers_mut().append(X_FORWARDED_FOR, fwd.parse().unwrap());
+            let fut = proxy_http_service_inner(req, &ctx, proxy_config, service_version);
+            let fut = ratelimit::limited(key, limits, fut);
+            match identity {
+                Some(identity) => {
+                    info!("proxy request by {}", identity.subject);
+                    httpclient::forward_authorization(authorization, auth::scope(identity, fut)).await?
+                }
+                None => httpclient::forward_authorization(authorization, fut).await?,
+            }
+        }
+        Err(e) => e.response()?,
+    };
     let hm = res.headers_mut();
     hm.insert("Access-Control-Allow-Origin", "*".parse().unwrap());
-    hm.insert("Access-Control-Allow-Headers", "*".parse().unwrap());
+    hm.insert("Access-Control-Allow-Headers", "*, Authorization".parse().unwrap());
     for m in &ctx.marks {
         hm.append(PSI_DAQBUFFER_SERVICE_MARK, m.parse().unwrap());
     }
@@ -150,21 +183,32 @@ async fn proxy_http_service_inner(
     } else if path == "/api/4/backends" {
         Ok(backends(req, proxy_config).await?)
     } else if let Some(h) = api4::ChannelSearchAggHandler::handler(&req) {
-        h.handle(req, &proxy_config).await
+        cache::cached(req, proxy_config.cache.as_ref(), |req| h.handle(req, proxy_config)).await
+    } else if let Some(h) = cache::CachePurgeHandler::handler(&req) {
+        h.handle(req, proxy_config).await
     } else if path == "/api/4/events" {
         Ok(proxy_single_backend_query::<PlainEventsQuery>(req, ctx, proxy_config).await?)
+    } else if let Some(h) = api4::LatestProxy::handler(&req) {
+        h.handle(req, ctx, proxy_config).await
     } else if path == "/api/4/status/connection/events" {
         Ok(proxy_single_backend_query::<ChannelStateEventsQuery>(req, ctx, proxy_config).await?)
     } else if path == "/api/4/status/channel/events" {
         Ok(proxy_single_backend_query::<ChannelStateEventsQuery>(req, ctx, proxy_config).await?)
+    } else if let Some(h) = api4::MapPulseBatchProxy::handler(&req) {
+        h.handle(req, proxy_config).await
+    } else if path == MapPulseRangeHttpFunction::path() {
+        Ok(proxy_single_backend_query::<MapPulseRangeQuery>(req, ctx, proxy_config).await?)
     } else if path.starts_with("/api/4/map/pulse-v2/") {
         Ok(proxy_single_backend_query::<MapPulseQuery>(req, ctx, proxy_config).await?)
     } else if path.starts_with("/api/4/map/pulse/") {
         Ok(proxy_single_backend_query::<MapPulseQuery>(req, ctx, proxy_config).await?)
     } else if path == "/api/4/binned" {
-        Ok(proxy_single_backend_query::<BinnedQuery>(req, ctx, proxy_config).await?)
+        let f = |req| proxy_single_backend_query::<BinnedQuery>(req, ctx, proxy_config);
+        Ok(cache::cached(req, proxy_config.cache.as_ref(), f).await?)
     } else if path == "/api/4/channel/config" {
         Ok(proxy_single_backend_query::<ChannelConfigQuery>(req, ctx, proxy_config).await?)
+    } else if path == "/api/4/channel/config/history" {
+        Ok(proxy_single_backend_query::<ChannelConfigQuery>(req, ctx, proxy_config).await?)
     } else if path.starts_with("/api/1/documentation/") {
         if req.method() == Method::GET {
             Ok(api_1_docs(path)?)
@@ -292,15 +336,21 @@ pub async fn channel_search(req: Request<Body>, proxy_config: &ProxyConfig) -> R
                 let query = ChannelSearchQuery::from_url(&url)?;
                 let mut methods = vec![];
                 let mut bodies = vec![];
+                let mut leases = vec![];
                 let mut urls = proxy_config
                     .backends
                     .iter()
-                    .map(|sh| match Url::parse(&format!("{}/api/4/search/channel", sh.url)) {
-                        Ok(mut url) => {
-                            query.append_to_url(&mut url);
-                            Ok(url)
+                    .map(|sh| {
+                        let lease = balance::pick_one(sh)?;
+                        let url = Url::parse(&format!("{}/api/4/search/channel", lease.url()));
+                        leases.push(lease);
+                        match url {
+                            Ok(mut url) => {
+                                query.append_to_url(&mut url);
+                                Ok(url)
+                            }
+                            Err(_e) => Err(Error::with_msg(format!("parse error for: {:?}", sh))),
                         }
-                        Err(_e) => Err(Error::with_msg(format!("parse error for: {:?}", sh))),
                     })
                     .fold_ok(vec![], |mut a, x| {
                         a.push(x);
@@ -380,11 +430,16 @@ pub async fn channel_search(req: Request<Body>, proxy_config: &ProxyConfig) -> R
                                                         ty: c.ty.clone(),
                                                         unit: String::new(),
                                                         is_api_0: Some(true),
+                                                        alias_of: Vec::new(),
                                                     };
                                                     a.push(z);
                                                 }
                                             }
-                                            let ret = ChannelSearchResult { channels: a };
+                                            let total = a.len() as u64;
+                                            let ret = ChannelSearchResult {
+                                                channels: a,
+                                                total: Some(total),
+                                            };
                                             let ret = SubRes {
                                                 tag,
                                                 status: StatusCode::OK,
@@ -394,7 +449,10 @@ pub async fn channel_search(req: Request<Body>, proxy_config: &ProxyConfig) -> R
                                         }
                                         Err(_) => {
                                             error!("Channel search response parse failed");
-                                            let ret = ChannelSearchResult { channels: vec![] };
+                                            let ret = ChannelSearchResult {
+                                                channels: vec![],
+                                                total: Some(0),
+                                            };
                                             let ret = SubRes {
                                                 tag,
                                                 status: StatusCode::OK,
@@ -411,9 +469,11 @@ pub async fn channel_search(req: Request<Body>, proxy_config: &ProxyConfig) -> R
                 };
                 let ft = |all: Vec<(crate::gather::Tag, Result<SubRes<ChannelSearchResult>, Error>)>| {
                     let mut res = Vec::new();
+                    let mut total = 0;
                     for (_tag, j) in all {
                         match j {
                             Ok(j) => {
+                                total += j.val.total.unwrap_or(j.val.channels.len() as u64);
                                 for k in j.val.channels {
                                     res.push(k);
                                 }
@@ -423,7 +483,10 @@ pub async fn channel_search(req: Request<Body>, proxy_config: &ProxyConfig) -> R
                             }
                         }
                     }
-                    let res = ChannelSearchResult { channels: res };
+                    let res = ChannelSearchResult {
+                        channels: res,
+                        total: Some(total),
+                    };
                     let res = response(StatusCode::OK)
                         .header(http::header::CONTENT_TYPE, APP_JSON)
                         .body(Body::from(serde_json::to_string(&res)?))?;
@@ -443,6 +506,7 @@ pub async fn channel_search(req: Request<Body>, proxy_config: &ProxyConfig) -> R
                     Duration::from_millis(3000),
                 )
                 .await?;
+                drop(leases);
                 Ok(ret)
             } else {
                 Ok(response(StatusCode::NOT_ACCEPTABLE).body(Body::empty())?)
@@ -452,6 +516,85 @@ pub async fn channel_search(req: Request<Body>, proxy_config: &ProxyConfig) -> R
     }
 }
 
+/// Response headers which are passed on to the client when a backend response is forwarded.
+fn forward_response_header(name: &HeaderName) -> bool {
+    let n = name.as_str();
+    name == http::header::CONTENT_TYPE
+        || name == http::header::CONTENT_LENGTH
+        || name == http::header::CONTENT_ENCODING
+        || name == http::header::CONTENT_DISPOSITION
+        || n.starts_with("x-daqbuffer-")
+        || n.starts_with("psi-daqbuffer-")
+}
+
+/// Why an endpoint did not answer, so that the request can be sent to another endpoint.
+struct EndpointFailure {
+    status: StatusCode,
+    msg: String,
+}
+
+/// Forwards the request to the backend and streams the response body back as it arrives.
+/// Status code, content type and the daqbuffer headers of the backend are kept, the body is not looked at,
+/// so any content type which the client and the backend agree on can pass.
+/// The timeout applies until the backend starts to respond.
+async fn proxy_backend_stream(
+    url: Url,
+    head: &http::request::Parts,
+    timeout: Duration,
+) -> Result<Result<Response<Body>, EndpointFailure>, Error> {
+    let mut req = Request::builder().method(Method::GET).uri(url.as_str());
+    for (k, v) in head.headers.iter() {
+        if k == http::header::ACCEPT
+            || k == http::header::ACCEPT_ENCODING
+            || k == http::header::AUTHORIZATION
+            || k == X_DAQBUF_REQID
+            || k == X_FORWARDED_FOR
+        {
+            req = req.header(k, v);
+        }
+    }
+    let req = req.body(Body::empty())?;
+    let client = hyper::Client::new();
+    let res = match tokio::time::timeout(timeout, client.request(req)).await {
+        Ok(Ok(x)) => x,
+        Ok(Err(e)) => {
+            let msg = format!("no response from backend: {e}");
+            return Ok(Err(EndpointFailure {
+                status: StatusCode::BAD_GATEWAY,
+                msg,
+            }));
+        }
+        Err(_) => {
+            let msg = String::from("timeout while waiting for backend");
+            return Ok(Err(EndpointFailure {
+                status: StatusCode::GATEWAY_TIMEOUT,
+                msg,
+            }));
+        }
+    };
+    let (bhead, body) = res.into_parts();
+    if [
+        StatusCode::BAD_GATEWAY,
+        StatusCode::SERVICE_UNAVAILABLE,
+        StatusCode::GATEWAY_TIMEOUT,
+    ]
+    .contains(&bhead.status)
+    {
+        let msg = format!("backend responded with {}", bhead.status);
+        return Ok(Err(EndpointFailure {
+            status: bhead.status,
+            msg,
+        }));
+    }
+    let mut ret = response(bhead.status);
+    for (k, v) in bhead.headers.iter() {
+        if forward_response_header(k) {
+            ret = ret.header(k, v);
+        }
+    }
+    Ok(Ok(ret.body(body)?))
+}
+
 pub async fn proxy_single_backend_query<QT>(
     req: Request<Body>,
     _ctx: &ReqCtx,
@@ -462,127 +605,188 @@ where
 {
     let (head, _body) = req.into_parts();
     info!("proxy_single_backend_query {}", head.uri);
-    match head.headers.get(http::header::ACCEPT) {
-        Some(v) => {
-            if v == APP_JSON || v == ACCEPT_ALL {
-                let url = Url::parse(&format!("dummy:{}", head.uri))?;
-                let query = match QT::from_url(&url) {
-                    Ok(k) => k,
-                    Err(_) => {
-                        let msg = format!("Malformed request or missing parameters");
-                        return Ok(response_err(StatusCode::BAD_REQUEST, msg)?);
-                    }
-                };
-                let sh = get_query_host_for_backend(&query.backend(), proxy_config)?;
-                // TODO remove this special case
-                // SPECIAL CASE:
-                // Since the inner proxy is not yet handling map-pulse requests without backend,
-                // we can not simply copy the original url here.
-                // Instead, url needs to get parsed and formatted.
-                // In general, the caller of this function should be able to provide a url, or maybe
-                // better a closure so that the url can even depend on backend.
-                let uri_path: String = if url.as_str().contains("/map/pulse/") {
-                    match MapPulseQuery::from_url(&url) {
-                        Ok(qu) => {
-                            info!("qu {qu:?}");
-                            format!("/api/4/map/pulse/{}/{}", qu.backend, qu.pulse)
-                        }
-                        Err(e) => {
-                            error!("{e:?}");
-                            String::from("/BAD")
-                        }
-                    }
-                } else {
-                    head.uri.path().into()
-                };
-                info!("uri_path {uri_path}");
-                let urls = [sh]
-                    .iter()
-                    .map(|sh| match Url::parse(&format!("{}{}", sh, uri_path)) {
-                        Ok(mut url) => {
-                            query.append_to_url(&mut url);
-                            Ok(url)
-                        }
-                        Err(e) => Err(Error::with_msg(format!("parse error for: {:?}  {:?}", sh, e))),
-                    })
-                    .fold_ok(vec![], |mut a, x| {
-                        a.push(x);
-                        a
-                    })?;
-                let tags: Vec<_> = urls.iter().map(|k| k.to_string()).collect();
-                let nt = |tag: String, res: Response<Body>| {
-                    let fut = async {
-                        let (head, body) = res.into_parts();
-                        if head.status == StatusCode::OK {
-                            let body = hyper::body::to_bytes(body).await?;
-                            match serde_json::from_slice::<JsonValue>(&body) {
-                                Ok(val) => {
-                                    let ret = SubRes {
-                                        tag,
-                                        status: head.status,
-                                        val,
-                                    };
-                                    Ok(ret)
-                                }
-                                Err(e) => {
-                                    warn!("can not parse response: {e:?}");
-                                    Err(e.into())
-                                }
-                            }
-                        } else {
-                            let body = hyper::body::to_bytes(body).await?;
-                            let b = String::from_utf8_lossy(&body);
-                            let ret = SubRes {
-                                tag,
-                                status: head.status,
-                                // TODO would like to pass arbitrary type of body in these cases:
-                                val: serde_json::Value::String(format!("{}", b)),
-                            };
-                            Ok(ret)
-                        }
-                    };
-                    Box::pin(fut) as Pin<Box<dyn Future<Output = Result<SubRes<serde_json::Value>, Error>> + Send>>
-                };
-                let ft = |mut all: Vec<(crate::gather::Tag, Result<SubRes<JsonValue>, Error>)>| {
-                    if all.len() > 0 {
-                        all.truncate(1);
-                        let (_tag, z) = all.pop().unwrap();
-                        match z {
-                            Ok(z) => {
-                                let res = z.val;
-                                // TODO want to pass arbitrary body type:
-                                let res = response(z.status)
-                                    .header(http::header::CONTENT_TYPE, APP_JSON)
-                                    .body(Body::from(serde_json::to_string(&res)?))?;
-                                return Ok(res);
-                            }
-                            Err(e) => {
-                                warn!("FT sees: {e}");
-                                let res = crate::bodystream::ToPublicResponse::to_public_response(&e);
-                                return Ok(res);
-                            }
-                        }
-                    } else {
-                        return Err(Error::with_msg("no response from upstream"));
-                    }
-                };
-                let bodies = (0..urls.len()).into_iter().map(|_| None).collect();
-                let ret =
-                    gather_get_json_generic(http::Method::GET, urls, bodies, tags, nt, ft, query.timeout()).await?;
-                Ok(ret)
-            } else {
-                Ok(response(StatusCode::NOT_ACCEPTABLE).body(Body::empty())?)
+    if head.method != Method::GET {
+        return Ok(response(StatusCode::METHOD_NOT_ALLOWED).body(Body::empty())?);
+    }
+    let url = Url::parse(&format!("dummy:{}", head.uri))?;
+    let query = match QT::from_url(&url) {
+        Ok(k) => k,
+        Err(_) => {
+            let msg = format!("Malformed request or missing parameters");
+            return Ok(response_err(StatusCode::BAD_REQUEST, msg)?);
+        }
+    };
+    let back = find_backend(query.backend(), proxy_config)?;
+    // TODO remove this special case
+    // SPECIAL CASE:
+    // Since the inner proxy is not yet handling map-pulse requests without backend,
+    // we can not simply copy the original url here.
+    // Instead, url needs to get parsed and formatted.
+    // In general, the caller of this function should be able to provide a url, or maybe
+    // better a closure so that the url can even depend on backend.
+    let uri_path: String = if url.as_str().contains("/map/pulse/") {
+        match MapPulseQuery::from_url(&url) {
+            Ok(qu) => {
+                info!("qu {qu:?}");
+                format!("/api/4/map/pulse/{}/{}", qu.backend, qu.pulse)
+            }
+            Err(e) => {
+                error!("{e:?}");
+                String::from("/BAD")
             }
         }
-        None => Ok(response(StatusCode::NOT_ACCEPTABLE).body(Body::empty())?),
+    } else {
+        head.uri.path().into()
+    };
+    info!("uri_path {uri_path}");
+    // The request is a GET, so it is safe to try the next endpoint if one fails.
+    let mut tried = Vec::new();
+    let mut last_failure = None;
+    while let Some(lease) = balance::pick(back, &tried) {
+        let sh = lease.url();
+        let mut url = Url::parse(&format!("{}{}", sh, uri_path))
+            .map_err(|e| Error::with_msg(format!("parse error for: {:?}  {:?}", sh, e)))?;
+        query.append_to_url(&mut url);
+        match proxy_backend_stream(url, &head, query.timeout()).await? {
+            Ok(res) => return Ok(balance::hold_until_body_done(res, lease)),
+            Err(e) => {
+                lease.failed(&e.msg);
+                tried.push(sh.to_string());
+                last_failure = Some(e);
+            }
+        }
+    }
+    match last_failure {
+        Some(e) => Ok(response_err(e.status, e.msg)?),
+        None => Err(Error::with_msg(format!("no endpoint for backend {:?}", back.name))),
     }
 }
 
-fn get_query_host_for_backend(backend: &str, proxy_config: &ProxyConfig) -> Result<String, Error> {
+fn find_backend<'a>(backend: &str, proxy_config: &'a ProxyConfig) -> Result<&'a ProxyBackend, Error> {
     for back in &proxy_config.backends {
         if back.name == backend {
-            return Ok(back.url.clone());
+            return Ok(back);
         }
     }
-    return Err(Error::with_msg(format!("host not found for backend {:?}", backend)));
+    Err(Error::with_msg(format!("host not found for backend {:?}", backend)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use bytes::Bytes;
+    use std::convert::Infallible;
+
+    async fn backend_handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        if req.uri().path() == "/unavailable" {
+            return Ok(response(StatusCode::SERVICE_UNAVAILABLE).body(Body::empty()).unwrap());
+        }
+        let seen_auth = req.headers().contains_key(http::header::AUTHORIZATION);
+        let seen_cookie = req.headers().contains_key(http::header::COOKIE);
+        let chunks: Vec<_> = (0..4)
+            .map(|i| Ok::<_, Infallible>(Bytes::from(format!("chunk-{i};"))))
+            .collect();
+        let res = response(StatusCode::PARTIAL_CONTENT)
+            .header(http::header::CONTENT_TYPE, "application/octet-stream")
+            .header(http::header::SET_COOKIE, "a=b")
+            .header(http::header::SERVER, "backend")
+            .header("x-daqbuffer-seen-auth", seen_auth.to_string())
+            .header("x-daqbuffer-seen-cookie", seen_cookie.to_string())
+            .header("psi-daqbuffer-service", "backend")
+            .header("x-other", "1")
+            .body(Body::wrap_stream(futures_util::stream::iter(chunks)))
+            .unwrap();
+        Ok(res)
+    }
+
+    #[test]
+    fn backend_stream_passes_body_and_filters_headers() {
+        let fut = async {
+            let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(backend_handle)) });
+            let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+            let addr = server.local_addr();
+            tokio::spawn(server);
+            let (head, _) = Request::builder()
+                .uri("/api/4/events")
+                .header(http::header::AUTHORIZATION, "Bearer 123")
+                .header(http::header::COOKIE, "c=d")
+                .body(())?
+                .into_parts();
+            let timeout = Duration::from_millis(4000);
+            let url = Url::parse(&format!("http://{addr}/api/4/events"))?;
+            let res = match proxy_backend_stream(url, &head, timeout).await? {
+                Ok(x) => x,
+                Err(e) => panic!("unexpected failure {}  {}", e.status, e.msg),
+            };
+            assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+            let hs = res.headers();
+            assert_eq!(hs[http::header::CONTENT_TYPE], "application/octet-stream");
+            assert_eq!(hs["x-daqbuffer-seen-auth"], "true");
+            assert_eq!(hs["x-daqbuffer-seen-cookie"], "false");
+            assert_eq!(hs["psi-daqbuffer-service"], "backend");
+            assert!(!hs.contains_key(http::header::SET_COOKIE));
+            assert!(!hs.contains_key(http::header::SERVER));
+            assert!(!hs.contains_key("x-other"));
+            let body = hyper::body::to_bytes(res.into_body()).await?;
+            assert_eq!(&body[..], b"chunk-0;chunk-1;chunk-2;chunk-3;");
+            let url = Url::parse(&format!("http://{addr}/unavailable"))?;
+            match proxy_backend_stream(url, &head, timeout).await? {
+                Ok(_) => panic!("expect failure for unavailable backend"),
+                Err(e) => assert_eq!(e.status, StatusCode::SERVICE_UNAVAILABLE),
+            }
+            Ok::<_, Error>(())
+        };
+        taskrun::run(fut).unwrap();
+    }
+    async fn echo_handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+        let method = req.method().to_string();
+        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+        let mut ret = method.into_bytes();
+        ret.push(b' ');
+        ret.extend_from_slice(&body);
+        Ok(response(StatusCode::OK).body(Body::from(ret)).unwrap())
+    }
+
+    #[test]
+    fn latest_post_forwards_body() {
+        let fut = async {
+            let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(echo_handle)) });
+            let server = Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_svc);
+            let addr = server.local_addr();
+            tokio::spawn(server);
+            let proxy_config = ProxyConfig {
+                name: "test-proxy".into(),
+                listen: "127.0.0.1".into(),
+                port: 0,
+                backends: vec![ProxyBackend {
+                    name: "test-latest".into(),
+                    url: format!("http://{addr}"),
+                    urls: Vec::new(),
+                }],
+                status_subs: Vec::new(),
+                cache: None,
+                auth: None,
+                rate_limits: None,
+            };
+            let body = r#"{"backend":"test-latest","channelNames":["a","b"]}"#;
+            let req = Request::post("/api/4/latest").body(Body::from(body))?;
+            let ctx = ReqCtx::with_proxy(&req, &proxy_config);
+            let version = ServiceVersion {
+                major: 0,
+                minor: 0,
+                patch: 0,
+                pre: None,
+            };
+            let res = proxy_http_service_inner(req, &ctx, &proxy_config, &version).await?;
+            assert_eq!(res.status(), StatusCode::OK);
+            let ret = hyper::body::to_bytes(res.into_body()).await?;
+            assert_eq!(ret, format!("POST {body}"));
+            let body = r#"{"backend":"other","channelNames":["a"]}"#;
+            let req = Request::post("/api/4/latest").body(Body::from(body))?;
+            let res = proxy_http_service_inner(req, &ctx, &proxy_config, &version).await?;
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+            Ok::<_, Error>(())
+        };
+        taskrun::run(fut).unwrap();
+    }
 }
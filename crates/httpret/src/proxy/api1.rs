@@ -2,6 +2,7 @@ pub mod reqstatus;
 
 use crate::bodystream::response;
 use crate::err::Error;
+use crate::proxy::balance;
 use crate::ReqCtx;
 use http::HeaderValue;
 use http::Method;
@@ -71,9 +72,10 @@ impl PythonDataApi1Query {
             ret
         };
         if let Some(back) = back {
-            let url_str = format!("{}/api/1/query", back.url);
+            let lease = balance::pick_one(back)?;
+            let url_str = format!("{}/api/1/query", lease.url());
             info!("try to ask {url_str}");
-            let req = Request::builder()
+            let req = httpclient::with_authorization(Request::builder())
                 .method(Method::POST)
                 .uri(url_str)
                 .body(Body::from(body_data))?;
@@ -87,7 +89,11 @@ impl PythonDataApi1Query {
                 info!("backend returned OK");
                 let riq_def = HeaderValue::from_static("(none)");
                 let riq = head.headers.get(X_DAQBUF_REQID).unwrap_or(&riq_def);
-                Ok(response(StatusCode::OK).header(X_DAQBUF_REQID, riq).body(body)?)
+                if let Ok(status_id) = riq.to_str() {
+                    reqstatus::served_by(status_id, lease.url());
+                }
+                let ret = response(StatusCode::OK).header(X_DAQBUF_REQID, riq).body(body)?;
+                Ok(balance::hold_until_body_done(ret, lease))
             }
         } else {
             Ok(response(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty())?)
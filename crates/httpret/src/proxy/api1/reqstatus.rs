@@ -1,5 +1,9 @@
 use crate::bodystream::response;
 use crate::err::Error;
+use crate::StatusBoardEntryUser;
+use crate::STATUS_ID_NOT_FOUND;
+use bytes::Bytes;
+use futures_util::future::join_all;
 use http::Method;
 use http::Request;
 use http::Response;
@@ -10,6 +14,10 @@ use netpod::log::*;
 use netpod::ProxyConfig;
 use netpod::ACCEPT_ALL;
 use netpod::APP_JSON;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
 
 pub struct RequestStatusHandler {}
 
@@ -58,24 +66,111 @@ impl RequestStatusHandler {
             ret
         };
         if let Some(back) = back {
-            let url_str = format!("{}{}{}", back.url, Self::path_prefix(), status_id);
-            debug!("try to ask {url_str}");
-            let req = Request::builder()
-                .method(Method::GET)
-                .uri(url_str)
-                .body(Body::empty())?;
-            let client = Client::new();
-            let res = client.request(req).await?;
-            let (head, body) = res.into_parts();
-            if head.status != StatusCode::OK {
-                error!("backend returned error: {head:?}");
-                Ok(response(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty())?)
-            } else {
-                debug!("backend returned OK");
-                Ok(response(StatusCode::OK).body(body)?)
+            // Only the endpoint which served the query knows its status.
+            let urls = match served_by_lookup(status_id) {
+                Some(url) => vec![url],
+                None => back.endpoints().into_iter().map(String::from).collect(),
+            };
+            let futs = urls.iter().map(|url| ask_status(url, status_id));
+            let mut last = None;
+            for res in join_all(futs).await {
+                match res {
+                    Ok(Some(body)) => return Ok(response(StatusCode::OK).body(Body::from(body))?),
+                    Ok(None) => {}
+                    Err(e) => {
+                        error!("{e}");
+                        last = Some(e);
+                    }
+                }
+            }
+            match last {
+                Some(_) => Ok(response(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty())?),
+                None => {
+                    let body = serde_json::to_vec(&StatusBoardEntryUser::not_found())?;
+                    Ok(response(StatusCode::OK).body(Body::from(body))?)
+                }
             }
         } else {
             Ok(response(StatusCode::INTERNAL_SERVER_ERROR).body(Body::empty())?)
         }
     }
 }
+
+// Keyed by status id, the endpoint which served the query and when.
+static SERVED_BY: Mutex<BTreeMap<String, (String, Instant)>> = Mutex::new(BTreeMap::new());
+
+const SERVED_BY_MAX: usize = 15000;
+
+const SERVED_BY_KEEP: Duration = Duration::from_secs(60 * 60 * 6);
+
+/// Remember which endpoint served the query with the given status id.
+pub fn served_by(status_id: &str, url: &str) {
+    let mut g = SERVED_BY.lock().unwrap();
+    if g.len() >= SERVED_BY_MAX {
+        g.retain(|_, (_, ts)| ts.elapsed() < SERVED_BY_KEEP);
+        if g.len() >= SERVED_BY_MAX {
+            let k = g.keys().next().cloned();
+            if let Some(k) = k {
+                g.remove(&k);
+            }
+        }
+    }
+    g.insert(status_id.into(), (url.into(), Instant::now()));
+}
+
+fn served_by_lookup(status_id: &str) -> Option<String> {
+    SERVED_BY.lock().unwrap().get(status_id).map(|x| x.0.clone())
+}
+
+/// The status from the endpoint, or `None` if the endpoint does not know the status id.
+async fn ask_status(url: &str, status_id: &str) -> Result<Option<Bytes>, Error> {
+    let url_str = format!("{}{}{}", url, RequestStatusHandler::path_prefix(), status_id);
+    debug!("try to ask {url_str}");
+    let req = httpclient::with_authorization(Request::builder())
+        .method(Method::GET)
+        .uri(url_str)
+        .body(Body::empty())?;
+    let client = Client::new();
+    let res = client.request(req).await?;
+    let (head, body) = res.into_parts();
+    if head.status != StatusCode::OK {
+        return Err(Error::with_msg_no_trace(format!(
+            "backend {url} returned error: {head:?}"
+        )));
+    }
+    let body = hyper::body::to_bytes(body).await?;
+    if status_not_found(&body) {
+        Ok(None)
+    } else {
+        Ok(Some(body))
+    }
+}
+
+fn status_not_found(body: &[u8]) -> bool {
+    match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(v) => match v.get("errors").and_then(|x| x.as_array()) {
+            Some(errs) => errs
+                .iter()
+                .any(|e| e.get("msg").and_then(|x| x.as_str()) == Some(STATUS_ID_NOT_FOUND)),
+            None => false,
+        },
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn status_answers() {
+        let body = serde_json::to_vec(&StatusBoardEntryUser::not_found()).unwrap();
+        assert!(status_not_found(&body));
+        assert!(!status_not_found(
+            br#"{"error_count":0,"warn_count":0,"channel_not_found":0}"#
+        ));
+        served_by("0000abcd", "http://b");
+        assert_eq!(served_by_lookup("0000abcd").as_deref(), Some("http://b"));
+        assert_eq!(served_by_lookup("0000abce"), None);
+    }
+}
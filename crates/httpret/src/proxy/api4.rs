@@ -5,8 +5,14 @@ use crate::err::Error;
 use crate::gather::gather_get_json_generic;
 use crate::gather::SubRes;
 use crate::gather::Tag;
+use crate::proxy::balance;
+use crate::pulsemap::batch::MapPulseBatchHttpFunction;
+use crate::pulsemap::batch::MapPulseBatchQuery;
+use crate::ratelimit;
 use crate::response;
+use crate::response_err;
 use crate::ReqCtx;
+use bytes::Bytes;
 use futures_util::Future;
 use http::Method;
 use http::Request;
@@ -16,16 +22,20 @@ use hyper::Body;
 use netpod::log::*;
 use netpod::ChannelSearchQuery;
 use netpod::ChannelSearchResult;
+use netpod::HasBackend;
 use netpod::NodeStatus;
 use netpod::NodeStatusSub;
+use netpod::ProxyBackend;
 use netpod::ProxyConfig;
 use netpod::ServiceVersion;
 use netpod::ACCEPT_ALL;
 use netpod::APP_JSON;
+use query::api4::latest::LatestQuery;
 use serde_json::Value as JsVal;
 use std::collections::VecDeque;
 use std::pin::Pin;
 use std::time::Duration;
+use taskrun::tokio;
 use url::Url;
 
 // TODO model channel search according to StatusNodesRecursive.
@@ -39,21 +49,25 @@ pub async fn channel_search(req: Request<Body>, proxy_config: &ProxyConfig) -> R
     let (head, _body) = req.into_parts();
     let inpurl = Url::parse(&format!("dummy:{}", head.uri))?;
     let query = ChannelSearchQuery::from_url(&inpurl)?;
+    let subquery = query.for_sub_backend();
     let mut urls = Vec::new();
     let mut tags = Vec::new();
     let mut bodies = Vec::new();
+    let mut leases = Vec::new();
     for pb in &proxy_config.backends {
         if if let Some(b) = &query.backend {
             pb.name.contains(b)
         } else {
             true
         } {
-            match Url::parse(&format!("{}/api/4/search/channel", pb.url)) {
+            let lease = balance::pick_one(pb)?;
+            match Url::parse(&format!("{}/api/4/search/channel", lease.url())) {
                 Ok(mut url) => {
-                    query.append_to_url(&mut url);
+                    subquery.append_to_url(&mut url);
                     tags.push(url.to_string());
                     bodies.push(None);
                     urls.push(url);
+                    leases.push(lease);
                 }
                 Err(_) => return Err(Error::with_msg(format!("parse error for: {:?}", pb))),
             }
@@ -85,16 +99,14 @@ pub async fn channel_search(req: Request<Body>, proxy_config: &ProxyConfig) -> R
         for (_tag, j) in all {
             match j {
                 Ok(j) => {
-                    for k in j.val.channels {
-                        res.push(k);
-                    }
+                    res.push(j.val);
                 }
                 Err(e) => {
                     warn!("{e}");
                 }
             }
         }
-        let res = ChannelSearchResult { channels: res };
+        let res = query.merge_ranked(res);
         Ok(res)
     };
     let ret = gather_get_json_generic(
@@ -107,6 +119,7 @@ pub async fn channel_search(req: Request<Body>, proxy_config: &ProxyConfig) -> R
         Duration::from_millis(3000),
     )
     .await?;
+    drop(leases);
     Ok(ret)
 }
 
@@ -253,6 +266,8 @@ impl StatusNodesRecursive {
                 database_size: None,
                 archiver_appliance_status: None,
                 subs,
+                backend_endpoints: balance::endpoint_status(proxy_config),
+                client_usage: ratelimit::usage(),
             };
             Ok(ret)
         };
@@ -269,3 +284,128 @@ impl StatusNodesRecursive {
         Ok(ret)
     }
 }
+
+const MAP_PULSE_BATCH_TIMEOUT: Duration = Duration::from_millis(12000);
+
+/// Forwards a pulse map batch to the backend named in the body.
+pub struct MapPulseBatchProxy {}
+
+impl MapPulseBatchProxy {
+    pub fn handler(req: &Request<Body>) -> Option<Self> {
+        if req.uri().path() == MapPulseBatchHttpFunction::path() {
+            Some(Self {})
+        } else {
+            None
+        }
+    }
+
+    pub async fn handle(&self, req: Request<Body>, proxy_config: &ProxyConfig) -> Result<Response<Body>, Error> {
+        if req.method() != Method::POST {
+            return Ok(response(StatusCode::METHOD_NOT_ALLOWED).body(Body::empty())?);
+        }
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        let q: MapPulseBatchQuery = match serde_json::from_slice(&body) {
+            Ok(x) => x,
+            Err(e) => {
+                let msg = format!("can not parse query: {e}");
+                return Ok(response_err(StatusCode::BAD_REQUEST, msg)?);
+            }
+        };
+        let back = match proxy_config.backends.iter().find(|x| x.name == q.backend) {
+            Some(x) => x,
+            None => {
+                let msg = format!("unknown backend {}", q.backend);
+                return Ok(response_err(StatusCode::BAD_REQUEST, msg)?);
+            }
+        };
+        // The batch only reads, so it is safe to try the next endpoint if one fails.
+        proxy_backend_post(back, MapPulseBatchHttpFunction::path(), body, MAP_PULSE_BATCH_TIMEOUT).await
+    }
+}
+
+/// Forwards the latest events query to the backend, the query comes either in the url or as json body.
+pub struct LatestProxy {}
+
+impl LatestProxy {
+    pub fn handler(req: &Request<Body>) -> Option<Self> {
+        if req.uri().path() == "/api/4/latest" {
+            Some(Self {})
+        } else {
+            None
+        }
+    }
+
+    pub async fn handle(
+        &self,
+        req: Request<Body>,
+        ctx: &ReqCtx,
+        proxy_config: &ProxyConfig,
+    ) -> Result<Response<Body>, Error> {
+        if req.method() == Method::GET {
+            return crate::proxy::proxy_single_backend_query::<LatestQuery>(req, ctx, proxy_config).await;
+        } else if req.method() != Method::POST {
+            return Ok(response(StatusCode::METHOD_NOT_ALLOWED).body(Body::empty())?);
+        }
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        let q: LatestQuery = match serde_json::from_slice(&body) {
+            Ok(x) => x,
+            Err(e) => {
+                let msg = format!("can not parse latest query: {e}");
+                return Ok(response_err(StatusCode::BAD_REQUEST, msg)?);
+            }
+        };
+        let back = match proxy_config.backends.iter().find(|x| x.name == q.backend()) {
+            Some(x) => x,
+            None => {
+                let msg = format!("unknown backend {}", q.backend());
+                return Ok(response_err(StatusCode::BAD_REQUEST, msg)?);
+            }
+        };
+        // The query only reads, so it is safe to try the next endpoint if one fails.
+        proxy_backend_post(back, "/api/4/latest", body, q.timeout()).await
+    }
+}
+
+/// Posts the json body to an endpoint of the backend, and to the next endpoint if one fails.
+/// Only for requests which do not modify anything.
+async fn proxy_backend_post(
+    back: &ProxyBackend,
+    path: &str,
+    body: Bytes,
+    timeout: Duration,
+) -> Result<Response<Body>, Error> {
+    let mut tried = Vec::new();
+    let mut last_failure = None;
+    while let Some(lease) = balance::pick(back, &tried) {
+        let url = format!("{}{}", lease.url(), path);
+        let req = httpclient::with_authorization(Request::builder())
+            .method(Method::POST)
+            .uri(url)
+            .header(http::header::CONTENT_TYPE, APP_JSON)
+            .body(Body::from(body.clone()))?;
+        let res = tokio::time::timeout(timeout, hyper::Client::new().request(req)).await;
+        let (status, msg) = match res {
+            Ok(Ok(res)) if !res.status().is_server_error() => {
+                let (head, body) = res.into_parts();
+                let mut ret = response(head.status);
+                if let Some(v) = head.headers.get(http::header::CONTENT_TYPE) {
+                    ret = ret.header(http::header::CONTENT_TYPE, v);
+                }
+                return Ok(balance::hold_until_body_done(ret.body(body)?, lease));
+            }
+            Ok(Ok(res)) => (res.status(), format!("backend returned {}", res.status())),
+            Ok(Err(e)) => (StatusCode::BAD_GATEWAY, e.to_string()),
+            Err(_) => (StatusCode::GATEWAY_TIMEOUT, String::from("timeout")),
+        };
+        lease.failed(&msg);
+        tried.push(lease.url().to_string());
+        last_failure = Some((status, msg));
+    }
+    match last_failure {
+        Some((status, msg)) => Ok(response_err(status, msg)?),
+        None => Err(Error::with_msg_no_trace(format!(
+            "no endpoint for backend {}",
+            back.name
+        ))),
+    }
+}
@@ -0,0 +1,262 @@
+use crate::err::Error;
+use bytes::Bytes;
+use futures_util::future::join_all;
+use futures_util::Stream;
+use futures_util::StreamExt;
+use http::Method;
+use http::Request;
+use http::Response;
+use http::StatusCode;
+use hyper::Body;
+use hyper::Client;
+use netpod::log::*;
+use netpod::ProxyBackend;
+use netpod::ProxyConfig;
+use netpod::ProxyEndpointStatus;
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+use taskrun::tokio;
+
+/// How often each endpoint is asked for its version.
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+const CHECK_TIMEOUT: Duration = Duration::from_secs(3);
+
+struct Endpoint {
+    healthy: bool,
+    outstanding: u64,
+    requests: u64,
+    failures: u64,
+    last_check: Option<Instant>,
+    last_error: Option<String>,
+}
+
+impl Endpoint {
+    fn new() -> Self {
+        Self {
+            healthy: true,
+            outstanding: 0,
+            requests: 0,
+            failures: 0,
+            last_check: None,
+            last_error: None,
+        }
+    }
+}
+
+// Keyed by backend name and endpoint url.
+static ENDPOINTS: Mutex<BTreeMap<(String, String), Endpoint>> = Mutex::new(BTreeMap::new());
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+fn with_endpoint<F, T>(backend: &str, url: &str, f: F) -> T
+where
+    F: FnOnce(&mut Endpoint) -> T,
+{
+    let mut eps = ENDPOINTS.lock().unwrap();
+    let ep = eps.entry((backend.into(), url.into())).or_insert_with(Endpoint::new);
+    f(ep)
+}
+
+/// An endpoint chosen for a request. Counts as outstanding on the endpoint until dropped.
+pub struct EndpointLease {
+    backend: String,
+    url: String,
+}
+
+impl EndpointLease {
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// The endpoint could not serve the request. It is not used again until a health check succeeds.
+    pub fn failed(&self, msg: &str) {
+        warn!("endpoint failed  {}  {}  {}", self.backend, self.url, msg);
+        with_endpoint(&self.backend, &self.url, |ep| {
+            ep.healthy = false;
+            ep.failures += 1;
+            ep.last_error = Some(msg.into());
+        });
+    }
+}
+
+impl Drop for EndpointLease {
+    fn drop(&mut self) {
+        with_endpoint(&self.backend, &self.url, |ep| {
+            ep.outstanding = ep.outstanding.saturating_sub(1)
+        });
+    }
+}
+
+/// Chooses the endpoint with the fewest outstanding requests, ties are taken in turn.
+/// Unhealthy endpoints are only chosen if no healthy one is left, because the last check may be outdated.
+/// Returns `None` if every endpoint is in `exclude`.
+pub fn pick(back: &ProxyBackend, exclude: &[String]) -> Option<EndpointLease> {
+    let urls: Vec<_> = back
+        .endpoints()
+        .into_iter()
+        .filter(|x| !exclude.iter().any(|e| e == x))
+        .collect();
+    if urls.is_empty() {
+        return None;
+    }
+    let start = NEXT.fetch_add(1, Ordering::Relaxed);
+    let mut eps = ENDPOINTS.lock().unwrap();
+    let url = (0..urls.len())
+        .map(|i| urls[(start + i) % urls.len()])
+        .min_by_key(|url| {
+            let ep = eps
+                .entry((back.name.clone(), url.to_string()))
+                .or_insert_with(Endpoint::new);
+            (!ep.healthy, ep.outstanding)
+        })?;
+    let ep = eps
+        .entry((back.name.clone(), url.to_string()))
+        .or_insert_with(Endpoint::new);
+    ep.outstanding += 1;
+    ep.requests += 1;
+    Some(EndpointLease {
+        backend: back.name.clone(),
+        url: url.into(),
+    })
+}
+
+/// The endpoint to send a request to, for requests which are not retried on another endpoint.
+pub fn pick_one(back: &ProxyBackend) -> Result<EndpointLease, Error> {
+    pick(back, &[]).ok_or_else(|| Error::with_msg_no_trace(format!("no endpoint configured for backend {}", back.name)))
+}
+
+struct LeasedBody {
+    inner: Body,
+    _lease: EndpointLease,
+}
+
+impl Stream for LeasedBody {
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+/// The request counts as outstanding on the endpoint until the response body is transferred.
+pub fn hold_until_body_done(res: Response<Body>, lease: EndpointLease) -> Response<Body> {
+    let (head, body) = res.into_parts();
+    let body = Body::wrap_stream(LeasedBody {
+        inner: body,
+        _lease: lease,
+    });
+    Response::from_parts(head, body)
+}
+
+pub fn endpoint_status(proxy_config: &ProxyConfig) -> Vec<ProxyEndpointStatus> {
+    let mut ret = Vec::new();
+    for back in &proxy_config.backends {
+        for url in back.endpoints() {
+            let st = with_endpoint(&back.name, url, |ep| ProxyEndpointStatus {
+                backend: back.name.clone(),
+                url: url.into(),
+                healthy: ep.healthy,
+                outstanding: ep.outstanding,
+                requests: ep.requests,
+                failures: ep.failures,
+                last_check_ago_ms: ep.last_check.map(|x| x.elapsed().as_millis() as u64),
+                last_error: ep.last_error.clone(),
+            });
+            ret.push(st);
+        }
+    }
+    ret
+}
+
+async fn check_endpoint(url: &str) -> Result<(), String> {
+    let req = Request::builder()
+        .method(Method::GET)
+        .uri(format!("{url}/api/4/private/version"))
+        .body(Body::empty())
+        .map_err(|e| e.to_string())?;
+    let client = Client::new();
+    match tokio::time::timeout(CHECK_TIMEOUT, client.request(req)).await {
+        Ok(Ok(res)) if res.status() == StatusCode::OK => Ok(()),
+        Ok(Ok(res)) => Err(format!("status {}", res.status())),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(String::from("timeout")),
+    }
+}
+
+/// Checks all endpoints of all backends periodically for the lifetime of the proxy.
+pub fn spawn_health_checks(proxy_config: ProxyConfig) {
+    tokio::spawn(async move {
+        loop {
+            let futs = proxy_config.backends.iter().flat_map(|back| {
+                back.endpoints().into_iter().map(move |url| async move {
+                    let res = check_endpoint(url).await;
+                    if let Err(e) = &res {
+                        debug!("health check failed  {}  {url}  {e}", back.name);
+                    }
+                    with_endpoint(&back.name, url, |ep| {
+                        ep.healthy = res.is_ok();
+                        ep.last_check = Some(Instant::now());
+                        if let Err(e) = res {
+                            ep.last_error = Some(e);
+                        }
+                    });
+                })
+            });
+            join_all(futs).await;
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn pick_least_outstanding_healthy() {
+        let back = ProxyBackend {
+            name: "test-balance-00".into(),
+            url: "http://a".into(),
+            urls: vec!["http://a".into(), "http://b".into(), "http://c".into()],
+        };
+        assert_eq!(back.endpoints(), ["http://a", "http://b", "http://c"]);
+        let l1 = pick(&back, &[]).unwrap();
+        let l2 = pick(&back, &[]).unwrap();
+        let l3 = pick(&back, &[]).unwrap();
+        let mut urls = vec![l1.url(), l2.url(), l3.url()];
+        urls.sort();
+        assert_eq!(urls, ["http://a", "http://b", "http://c"]);
+        let free = l2.url().to_string();
+        drop(l2);
+        assert_eq!(pick(&back, &[]).unwrap().url(), free);
+        l1.failed("test");
+        let l4 = pick(&back, &[l3.url().into()]).unwrap();
+        assert_ne!(l4.url(), l1.url());
+        assert!(pick(&back, &back.urls).is_none());
+    }
+
+    #[test]
+    fn lease_held_by_body() {
+        let back = ProxyBackend {
+            name: "test-balance-01".into(),
+            url: "http://a".into(),
+            urls: Vec::new(),
+        };
+        let outstanding = || with_endpoint(&back.name, "http://a", |ep| ep.outstanding);
+        let lease = pick_one(&back).unwrap();
+        let res = Response::new(Body::from("body"));
+        let res = hold_until_body_done(res, lease);
+        assert_eq!(outstanding(), 1);
+        let body = taskrun::run(hyper::body::to_bytes(res.into_body())).unwrap();
+        assert_eq!(&body[..], b"body");
+        assert_eq!(outstanding(), 0);
+    }
+}
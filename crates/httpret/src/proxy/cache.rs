@@ -0,0 +1,622 @@
+use crate::auth;
+use crate::err::Error;
+use crate::response;
+use bytes::Bytes;
+use bytes::BytesMut;
+use futures_util::StreamExt;
+use http::header;
+use http::HeaderMap;
+use http::HeaderValue;
+use http::Method;
+use http::Request;
+use http::Response;
+use http::StatusCode;
+use hyper::Body;
+use md5::Digest;
+use md5::Md5;
+use netpod::get_url_query_pairs;
+use netpod::log::*;
+use netpod::AppendToUrl;
+use netpod::ChannelSearchQuery;
+use netpod::FromUrl;
+use netpod::ProxyCacheConfig;
+use netpod::ProxyConfig;
+use netpod::ACCEPT_ALL;
+use netpod::X_DAQBUF_CACHE;
+use query::api4::binned::BinnedQuery;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use taskrun::tokio;
+use url::Url;
+
+/// A range which ends less than this before now may still receive data.
+const RECENT_MARGIN: Duration = Duration::from_secs(60);
+
+const TTL_RECENT: Duration = Duration::from_secs(5);
+
+const TTL_PAST: Duration = Duration::from_secs(600);
+
+/// For results which the backend marks with `rangeFinal`.
+const TTL_FINAL: Duration = Duration::from_secs(3600 * 24);
+
+const TTL_SEARCH: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy, Debug)]
+enum CacheKind {
+    Binned { range_end: u64 },
+    Search,
+}
+
+struct Entry {
+    status: StatusCode,
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+    stored: Instant,
+    ttl: Duration,
+    last_used: Instant,
+}
+
+impl Entry {
+    fn fresh(&self) -> bool {
+        self.stored.elapsed() < self.ttl
+    }
+
+    fn to_response(&self) -> Result<Response<Body>, Error> {
+        let age = self.stored.elapsed();
+        let mut ret = response(self.status)
+            .header(header::CACHE_CONTROL, cache_control(self.ttl.saturating_sub(age)))
+            .header(header::AGE, age.as_secs())
+            .header(X_DAQBUF_CACHE, "hit");
+        if let Some(x) = &self.content_type {
+            ret = ret.header(header::CONTENT_TYPE, x);
+        }
+        Ok(ret.body(Body::from(self.body.clone()))?)
+    }
+}
+
+fn cache_control(max_age: Duration) -> String {
+    format!("max-age={}", max_age.as_secs())
+}
+
+struct Cache {
+    entries: BTreeMap<String, Entry>,
+    bytes: u64,
+}
+
+impl Cache {
+    const fn new() -> Self {
+        Self {
+            entries: BTreeMap::new(),
+            bytes: 0,
+        }
+    }
+
+    fn get(&mut self, key: &str) -> Option<&Entry> {
+        let fresh = match self.entries.get_mut(key) {
+            Some(e) => {
+                e.last_used = Instant::now();
+                e.fresh()
+            }
+            None => return None,
+        };
+        if fresh {
+            self.entries.get(key)
+        } else {
+            self.remove(key);
+            None
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(e) = self.entries.remove(key) {
+            self.bytes -= e.body.len() as u64;
+        }
+    }
+
+    /// Drops expired entries and then the least recently used ones until the content fits into `max_bytes`.
+    fn insert(&mut self, key: String, entry: Entry, max_bytes: u64) {
+        self.remove(&key);
+        self.bytes += entry.body.len() as u64;
+        self.entries.insert(key, entry);
+        if self.bytes > max_bytes {
+            let expired: Vec<_> = self
+                .entries
+                .iter()
+                .filter(|(_, e)| !e.fresh())
+                .map(|(k, _)| k.clone())
+                .collect();
+            for k in expired {
+                self.remove(&k);
+            }
+        }
+        while self.bytes > max_bytes {
+            match self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone())
+            {
+                Some(k) => self.remove(&k),
+                None => break,
+            }
+        }
+    }
+
+    fn purge<F>(&mut self, matches: F) -> usize
+    where
+        F: Fn(&str) -> bool,
+    {
+        let keys: Vec<_> = self.entries.keys().filter(|k| matches(k)).cloned().collect();
+        for k in &keys {
+            self.remove(k);
+        }
+        keys.len()
+    }
+}
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache::new());
+
+/// Bytes of the bodies in each cache directory, as far as known without a scan of the directory.
+static DISK_BYTES: Mutex<BTreeMap<PathBuf, u64>> = Mutex::new(BTreeMap::new());
+
+/// The key is the path with the query as formatted by `AppendToUrl`, so that equivalent urls share an entry,
+/// and the accepted content type.
+/// With authentication, the backend may answer differently depending on the caller, e.g. for the channels
+/// behind an alias, therefore the key also contains the subject and roles of the caller.
+fn cache_key(req: &Request<Body>, identity: Option<&auth::Identity>) -> Option<(String, CacheKind)> {
+    if req.method() != Method::GET {
+        return None;
+    }
+    let path = req.uri().path();
+    let url = Url::parse(&format!("dummy:{}", req.uri())).ok()?;
+    let mut norm = Url::parse(&format!("dummy:{path}")).ok()?;
+    let kind = if path == "/api/4/binned" {
+        let q = BinnedQuery::from_url(&url).ok()?;
+        q.append_to_url(&mut norm);
+        CacheKind::Binned {
+            range_end: q.range().end_u64(),
+        }
+    } else if path == "/api/4/search/channel" {
+        let q = ChannelSearchQuery::from_url(&url).ok()?;
+        q.append_to_url(&mut norm);
+        CacheKind::Search
+    } else {
+        return None;
+    };
+    let accept = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|x| x.to_str().ok())
+        .unwrap_or(ACCEPT_ALL);
+    let mut key = format!("{}?{}  {}", path, norm.query().unwrap_or(""), accept);
+    if let Some(identity) = identity {
+        key.push_str(&format!("  {} {}", identity.subject, identity.roles.join(",")));
+    }
+    Some((key, kind))
+}
+
+/// Whether the directives contain any of `names`.
+fn has_directive(headers: &HeaderMap, names: &[&str]) -> bool {
+    headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|x| x.to_str().ok())
+        .flat_map(|x| x.split(','))
+        .any(|x| names.contains(&x.trim().to_ascii_lowercase().as_str()))
+}
+
+fn now_unix() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or(Duration::ZERO)
+}
+
+fn is_range_final(content_type: Option<&HeaderValue>, body: &[u8]) -> bool {
+    let is_json = content_type
+        .and_then(|x| x.to_str().ok())
+        .is_none_or(|x| x.contains("json"));
+    is_json
+        && serde_json::from_slice::<JsonValue>(body)
+            .ok()
+            .and_then(|x| x.get("rangeFinal").and_then(JsonValue::as_bool))
+            .unwrap_or(false)
+}
+
+/// How long a result can be served from the cache, and whether it can not change anymore.
+fn time_to_live(kind: CacheKind, content_type: Option<&HeaderValue>, body: &[u8]) -> (Duration, bool) {
+    match kind {
+        CacheKind::Search => (TTL_SEARCH, false),
+        CacheKind::Binned { range_end } => {
+            if is_range_final(content_type, body) {
+                (TTL_FINAL, true)
+            } else if Duration::from_nanos(range_end) + RECENT_MARGIN >= now_unix() {
+                (TTL_RECENT, false)
+            } else {
+                (TTL_PAST, false)
+            }
+        }
+    }
+}
+
+/// Reads the body if it has at most `max` bytes, otherwise gives back a body which streams all of it unchanged.
+async fn read_capped(mut body: Body, max: u64) -> Result<Result<Bytes, Body>, Error> {
+    let mut buf = BytesMut::new();
+    while let Some(x) = body.next().await {
+        buf.extend_from_slice(&x?);
+        if buf.len() as u64 > max {
+            let head = futures_util::stream::iter([Ok::<_, hyper::Error>(buf.freeze())]);
+            return Ok(Err(Body::wrap_stream(head.chain(body))));
+        }
+    }
+    Ok(Ok(buf.freeze()))
+}
+
+/// Answers binned and search requests from the cache if possible, otherwise from `f`, keeping the result.
+/// A request with `Cache-Control: no-cache` is not answered from the cache, with `no-store` the cache is not used at all.
+/// Other requests, and all requests without a cache configured, are passed to `f` unchanged.
+pub async fn cached<F, Fut>(req: Request<Body>, conf: Option<&ProxyCacheConfig>, f: F) -> Result<Response<Body>, Error>
+where
+    F: FnOnce(Request<Body>) -> Fut,
+    Fut: Future<Output = Result<Response<Body>, Error>>,
+{
+    let identity = auth::current_identity();
+    // Credentials which only the backend checks must not let others read the cached result.
+    if req.headers().contains_key(header::AUTHORIZATION) && identity.is_none() {
+        return f(req).await;
+    }
+    let (key, kind, conf) = match (cache_key(&req, identity.as_ref()), conf) {
+        (Some((key, kind)), Some(conf)) if conf.max_bytes > 0 => (key, kind, conf),
+        _ => return f(req).await,
+    };
+    let no_store = has_directive(req.headers(), &["no-store"]);
+    if !no_store && !has_directive(req.headers(), &["no-cache", "max-age=0"]) {
+        if let Some(res) = lookup(&key, conf).await? {
+            return Ok(res);
+        }
+    }
+    let res = f(req).await?;
+    if no_store || res.status() != StatusCode::OK || has_directive(res.headers(), &["no-store", "no-cache", "private"])
+    {
+        return Ok(res);
+    }
+    // Large results would push out many small ones.
+    let body_max = conf.max_bytes / 8;
+    let content_length = res
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| x.parse::<u64>().ok());
+    if content_length.is_some_and(|x| x > body_max) {
+        return Ok(res);
+    }
+    let (mut head, body) = res.into_parts();
+    let body = match read_capped(body, body_max).await? {
+        Ok(x) => x,
+        Err(body) => return Ok(Response::from_parts(head, body)),
+    };
+    let content_type = head.headers.get(header::CONTENT_TYPE).cloned();
+    let (ttl, is_final) = time_to_live(kind, content_type.as_ref(), &body);
+    let now = Instant::now();
+    let entry = Entry {
+        status: head.status,
+        content_type,
+        body: body.clone(),
+        stored: now,
+        ttl,
+        last_used: now,
+    };
+    if let (true, Some(dir)) = (is_final, &conf.dir) {
+        if let Err(e) = disk_store(dir, &key, &entry, conf.disk_max_bytes).await {
+            warn!("can not store cache entry in {}: {e}", dir.display());
+        }
+    }
+    CACHE.lock().unwrap().insert(key, entry, conf.max_bytes);
+    head.headers
+        .insert(header::CACHE_CONTROL, cache_control(ttl).parse().unwrap());
+    head.headers.insert(X_DAQBUF_CACHE, HeaderValue::from_static("miss"));
+    Ok(Response::from_parts(head, Body::from(body)))
+}
+
+async fn lookup(key: &str, conf: &ProxyCacheConfig) -> Result<Option<Response<Body>>, Error> {
+    let hit = CACHE.lock().unwrap().get(key).map(Entry::to_response);
+    if let Some(res) = hit {
+        return Ok(Some(res?));
+    }
+    if let Some(dir) = &conf.dir {
+        match disk_load(dir, key).await {
+            Ok(Some(entry)) => {
+                let res = entry.to_response()?;
+                CACHE.lock().unwrap().insert(key.into(), entry, conf.max_bytes);
+                return Ok(Some(res));
+            }
+            Ok(None) => {}
+            Err(e) => warn!("can not read cache entry from {}: {e}", dir.display()),
+        }
+    }
+    Ok(None)
+}
+
+#[derive(Serialize, Deserialize)]
+struct DiskMeta {
+    key: String,
+    status: u16,
+    content_type: Option<String>,
+    stored_unix_ms: u64,
+    ttl_s: u64,
+}
+
+fn disk_name(key: &str) -> String {
+    format!("{:x}", Md5::digest(key.as_bytes()))
+}
+
+async fn disk_store(dir: &Path, key: &str, entry: &Entry, max_bytes: u64) -> Result<(), Error> {
+    tokio::fs::create_dir_all(dir).await?;
+    let name = disk_name(key);
+    let meta = DiskMeta {
+        key: key.into(),
+        status: entry.status.as_u16(),
+        content_type: entry
+            .content_type
+            .as_ref()
+            .and_then(|x| x.to_str().ok())
+            .map(Into::into),
+        stored_unix_ms: now_unix().as_millis() as u64,
+        ttl_s: entry.ttl.as_secs(),
+    };
+    tokio::fs::write(dir.join(format!("{name}.body")), &entry.body).await?;
+    tokio::fs::write(dir.join(format!("{name}.meta")), serde_json::to_vec(&meta)?).await?;
+    // The directory is only scanned when the running total crosses the limit, or is not known yet.
+    let total = DISK_BYTES.lock().unwrap().get_mut(dir).map(|x| {
+        *x += entry.body.len() as u64;
+        *x
+    });
+    if total.map_or(true, |x| x > max_bytes) {
+        let total = disk_prune(dir, max_bytes).await?;
+        DISK_BYTES.lock().unwrap().insert(dir.into(), total);
+    }
+    Ok(())
+}
+
+async fn disk_remove(dir: &Path, name: &str) {
+    for ext in ["meta", "body"] {
+        let _ = tokio::fs::remove_file(dir.join(format!("{name}.{ext}"))).await;
+    }
+}
+
+async fn disk_load(dir: &Path, key: &str) -> Result<Option<Entry>, Error> {
+    let name = disk_name(key);
+    let meta = match tokio::fs::read(dir.join(format!("{name}.meta"))).await {
+        Ok(x) => x,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let meta: DiskMeta = serde_json::from_slice(&meta)?;
+    if meta.key != key {
+        return Ok(None);
+    }
+    let age = now_unix().saturating_sub(Duration::from_millis(meta.stored_unix_ms));
+    let ttl = Duration::from_secs(meta.ttl_s);
+    if age >= ttl {
+        disk_remove(dir, &name).await;
+        return Ok(None);
+    }
+    let body = tokio::fs::read(dir.join(format!("{name}.body"))).await?;
+    let now = Instant::now();
+    let entry = Entry {
+        status: StatusCode::from_u16(meta.status).map_err(|e| Error::with_msg_no_trace(e.to_string()))?,
+        content_type: meta.content_type.and_then(|x| HeaderValue::from_str(&x).ok()),
+        body: body.into(),
+        stored: now.checked_sub(age).unwrap_or(now),
+        ttl,
+        last_used: now,
+    };
+    Ok(Some(entry))
+}
+
+/// Removes the oldest files until the bodies fit into `max_bytes`. Returns the bytes of the remaining bodies.
+async fn disk_prune(dir: &Path, max_bytes: u64) -> Result<u64, Error> {
+    let mut files = Vec::new();
+    let mut rd = tokio::fs::read_dir(dir).await?;
+    while let Some(e) = rd.next_entry().await? {
+        let path = e.path();
+        if path.extension().is_some_and(|x| x == "body") {
+            let md = e.metadata().await?;
+            if let Some(name) = path.file_stem().and_then(|x| x.to_str()) {
+                files.push((md.modified()?, md.len(), name.to_string()));
+            }
+        }
+    }
+    files.sort();
+    let mut total: u64 = files.iter().map(|x| x.1).sum();
+    for (_, len, name) in files {
+        if total <= max_bytes {
+            break;
+        }
+        disk_remove(dir, &name).await;
+        total -= len;
+    }
+    Ok(total)
+}
+
+async fn disk_purge<F>(dir: &Path, matches: F) -> Result<usize, Error>
+where
+    F: Fn(&str) -> bool,
+{
+    let mut n = 0;
+    let mut rd = match tokio::fs::read_dir(dir).await {
+        Ok(x) => x,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e.into()),
+    };
+    while let Some(e) = rd.next_entry().await? {
+        let path = e.path();
+        if path.extension().is_some_and(|x| x == "meta") {
+            let meta: DiskMeta = serde_json::from_slice(&tokio::fs::read(&path).await?)?;
+            if matches(&meta.key) {
+                if let Some(name) = path.file_stem().and_then(|x| x.to_str()) {
+                    disk_remove(dir, name).await;
+                    n += 1;
+                }
+            }
+        }
+    }
+    Ok(n)
+}
+
+/// Removes cached results, all of them or those for the given `path` and with the given text in the key.
+pub struct CachePurgeHandler {}
+
+impl CachePurgeHandler {
+    pub fn handler(req: &Request<Body>) -> Option<Self> {
+        if req.uri().path() == "/api/4/private/cache/purge" {
+            Some(Self {})
+        } else {
+            None
+        }
+    }
+
+    pub async fn handle(&self, req: Request<Body>, proxy_config: &ProxyConfig) -> Result<Response<Body>, Error> {
+        if req.method() != Method::POST {
+            return Ok(response(StatusCode::METHOD_NOT_ALLOWED).body(Body::empty())?);
+        }
+        // Purging makes the backends compute the results again, so only admins may do it.
+        let admin = match (proxy_config.auth.as_ref(), auth::current_identity()) {
+            (Some(conf), Some(identity)) => auth::authorize(&identity, &auth::Access::Admin, conf).is_ok(),
+            _ => false,
+        };
+        if !admin {
+            return Ok(response(StatusCode::FORBIDDEN).body(Body::from("cache purge is only allowed to admins"))?);
+        }
+        let cache = match &proxy_config.cache {
+            Some(x) => x,
+            None => return Ok(response(StatusCode::NOT_FOUND).body(Body::from("no cache configured"))?),
+        };
+        let url = Url::parse(&format!("dummy:{}", req.uri()))?;
+        let pairs = get_url_query_pairs(&url);
+        let path = pairs.get("path").map(|x| format!("{x}?"));
+        let contains = pairs.get("contains");
+        let matches = |key: &str| {
+            path.as_ref().is_none_or(|x| key.starts_with(x.as_str()))
+                && contains.is_none_or(|x| key.contains(x.as_str()))
+        };
+        let mut purged = CACHE.lock().unwrap().purge(matches);
+        if let Some(dir) = &cache.dir {
+            purged += disk_purge(dir, matches).await?;
+            DISK_BYTES.lock().unwrap().remove(dir);
+        }
+        info!("cache purge  path {path:?}  contains {contains:?}  purged {purged}");
+        let ret = serde_json::json!({ "purged": purged });
+        Ok(response(StatusCode::OK).body(Body::from(serde_json::to_vec(&ret)?))?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(len: usize, ttl: Duration) -> Entry {
+        let now = Instant::now();
+        Entry {
+            status: StatusCode::OK,
+            content_type: None,
+            body: Bytes::from(vec![0; len]),
+            stored: now,
+            ttl,
+            last_used: now,
+        }
+    }
+
+    #[test]
+    fn cache_evicts_least_recently_used() {
+        let mut cache = Cache::new();
+        let ttl = Duration::from_secs(60);
+        cache.insert("a".into(), entry(40, ttl), 100);
+        cache.insert("b".into(), entry(40, ttl), 100);
+        assert!(cache.get("a").is_some());
+        cache.insert("c".into(), entry(40, ttl), 100);
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert_eq!(cache.bytes, 80);
+        cache.insert("d".into(), entry(10, Duration::ZERO), 100);
+        assert!(cache.get("d").is_none());
+        assert_eq!(cache.bytes, 80);
+        assert_eq!(cache.purge(|k| k == "a"), 1);
+        assert_eq!(cache.bytes, 40);
+    }
+
+    #[test]
+    fn key_depends_on_identity() {
+        let req = Request::get("/api/4/search/channel?nameRegex=abc")
+            .body(Body::empty())
+            .unwrap();
+        let alice = auth::Identity {
+            subject: "alice".into(),
+            roles: vec!["read".into()],
+            method: auth::AuthMethod::Token,
+        };
+        let bob = auth::Identity {
+            subject: "bob".into(),
+            roles: vec!["read".into()],
+            method: auth::AuthMethod::Token,
+        };
+        let (k0, _) = cache_key(&req, None).unwrap();
+        let (k1, _) = cache_key(&req, Some(&alice)).unwrap();
+        let (k2, _) = cache_key(&req, Some(&bob)).unwrap();
+        assert_ne!(k0, k1);
+        assert_ne!(k1, k2);
+        assert!(k2.starts_with("/api/4/search/channel?"));
+    }
+
+    #[test]
+    fn range_final_detection() {
+        let ct = HeaderValue::from_static("application/json");
+        assert!(is_range_final(Some(&ct), br#"{"rangeFinal":true,"avgs":[]}"#));
+        assert!(!is_range_final(Some(&ct), br#"{"avgs":[]}"#));
+        let ct = HeaderValue::from_static("text/csv");
+        assert!(!is_range_final(Some(&ct), br#"{"rangeFinal":true}"#));
+    }
+
+    #[test]
+    fn large_body_passes_unchanged() {
+        let fut = async {
+            let chunks = vec![Ok::<_, hyper::Error>(Bytes::from(vec![1; 30])); 4];
+            let body = Body::wrap_stream(futures_util::stream::iter(chunks.clone()));
+            let body = match read_capped(body, 50).await? {
+                Ok(_) => panic!("body must not be read completely"),
+                Err(x) => x,
+            };
+            assert_eq!(hyper::body::to_bytes(body).await?.len(), 120);
+            let body = Body::wrap_stream(futures_util::stream::iter(chunks));
+            assert_eq!(read_capped(body, 120).await?.ok().map(|x| x.len()), Some(120));
+            Ok::<_, Error>(())
+        };
+        taskrun::run(fut).unwrap();
+    }
+
+    #[test]
+    fn purge_needs_admin() {
+        let proxy_config = ProxyConfig {
+            name: "test-proxy".into(),
+            listen: "127.0.0.1".into(),
+            port: 0,
+            backends: Vec::new(),
+            status_subs: Vec::new(),
+            cache: Some(ProxyCacheConfig::default()),
+            auth: None,
+            rate_limits: None,
+        };
+        let req = Request::post("/api/4/private/cache/purge").body(Body::empty()).unwrap();
+        let h = CachePurgeHandler::handler(&req).unwrap();
+        let res = taskrun::run(h.handle(req, &proxy_config)).unwrap();
+        assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    }
+}
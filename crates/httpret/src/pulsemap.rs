@@ -1,3 +1,6 @@
+pub mod batch;
+mod intervaltree;
+
 use crate::err::Error;
 use crate::response;
 use async_channel::Receiver;
@@ -23,6 +26,7 @@ use netpod::FromUrl;
 use netpod::HasBackend;
 use netpod::HasTimeout;
 use netpod::NodeConfigCached;
+use netpod::SfDatabuffer;
 use netpod::DATETIME_FMT_9MS;
 use scyllaconn::scylla;
 use serde::Deserialize;
@@ -176,6 +180,12 @@ const API_4_MAP_PULSE_URL_PREFIX: &'static str = "/api/4/map/pulse/";
 const MAP_PULSE_LOCAL_TIMEOUT: Duration = Duration::from_millis(8000);
 const MAP_PULSE_QUERY_TIMEOUT: Duration = Duration::from_millis(10000);
 
+/// The local pulse index of this node, if configured. It is used instead of the tables in the database.
+fn pulse_index_conf(node_config: &NodeConfigCached) -> Option<(&PathBuf, &SfDatabuffer)> {
+    let sfc = node_config.node.sf_databuffer.as_ref()?;
+    sfc.pulse_index.as_ref().map(|x| (x, sfc))
+}
+
 async fn make_tables(node_config: &NodeConfigCached) -> Result<(), Error> {
     let conn = dbconn::create_connection(&node_config.node_config.cluster.database).await?;
     let sql = "set client_min_messages = 'warning'";
@@ -199,27 +209,6 @@ async fn make_tables(node_config: &NodeConfigCached) -> Result<(), Error> {
     Ok(())
 }
 
-fn timer_channel_names() -> Vec<String> {
-    let sections = vec!["SINEG01", "SINSB01", "SINSB02", "SINSB03", "SINSB04", "SINXB01"];
-    let suffixes = vec!["MASTER"];
-    let mut all: Vec<_> = sections
-        .iter()
-        .map(|sec| {
-            suffixes
-                .iter()
-                .map(move |suf| format!("{}-RLLE-STA:{}-EVRPULSEID", sec, suf))
-        })
-        .flatten()
-        .collect();
-    all.push("SIN-CVME-TIFGUN-EVR0:RX-PULSEID".into());
-    all.push("SAR-CVME-TIFALL4:EvtSet".into());
-    all.push("SAR-CVME-TIFALL5:EvtSet".into());
-    all.push("SAR-CVME-TIFALL6:EvtSet".into());
-    all.push("SAT-CVME-TIFALL5:EvtSet".into());
-    all.push("SAT-CVME-TIFALL6:EvtSet".into());
-    all
-}
-
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
 enum MapfilePath {
     Scalar(PathBuf),
@@ -611,11 +600,18 @@ impl IndexFullHttpFunction {
     }
 
     pub async fn index(do_print: bool, node_config: &NodeConfigCached) -> Result<String, Error> {
+        if let Some((path, sfc)) = pulse_index_conf(node_config) {
+            let res = disk::pulseindex::update_pulse_index(path.clone(), sfc.clone()).await?;
+            if do_print {
+                info!("pulse index {path:?} updated {res:?}");
+            }
+            return Ok(format!("{res:?}"));
+        }
         // TODO avoid double-insert on central storage.
         let mut msg = format!("LOG");
         make_tables(node_config).await?;
         let conn = dbconn::create_connection(&node_config.node_config.cluster.database).await?;
-        let chs = timer_channel_names();
+        let chs = disk::pulseindex::timer_channel_names();
         for channel_name in &chs[..] {
             match Self::index_channel(channel_name.clone(), &conn, do_print, node_config).await {
                 Ok(m) => {
@@ -1018,6 +1014,20 @@ impl MapPulseLocalHttpFunction {
         let pulse: u64 = urls[MAP_PULSE_LOCAL_URL_PREFIX.len()..]
             .parse()
             .map_err(|_| Error::with_public_msg_no_trace(format!("can not understand pulse map url: {}", req.uri())))?;
+        if let Some((path, _)) = pulse_index_conf(node_config) {
+            let idx = disk::pulseindex::open_pulse_index(path.clone()).await?;
+            let mut ret = LocalMap {
+                pulse,
+                tss: Vec::new(),
+                channels: Vec::new(),
+            };
+            // Like the lookup through the database, each timer channel with the pulse gives an entry.
+            for e in idx.find_pulse_all(pulse) {
+                ret.tss.push(e.ts);
+                ret.channels.push(e.channel().unwrap_or("nochannel").into());
+            }
+            return Ok(response(StatusCode::OK).body(Body::from(serde_json::to_vec(&ret)?))?);
+        }
         let req_from = req.headers().get("x-req-from").map_or(None, |x| Some(format!("{x:?}")));
         let ts1 = Instant::now();
         let conn = dbconn::create_connection(&node_config.node_config.cluster.database).await?;
@@ -1179,7 +1189,7 @@ impl MapPulseHistoHttpFunction {
                 node.host, node.port, MAP_PULSE_LOCAL_URL_PREFIX, pulse
             );
             let uri: Uri = s.parse()?;
-            let req = Request::get(uri)
+            let req = httpclient::with_authorization(Request::get(uri))
                 .header("x-req-from", &node_config.node.host)
                 .body(Body::empty())?;
             let fut = hyper::Client::new().request(req);
@@ -0,0 +1,764 @@
+use super::intervaltree::IntervalTree;
+use super::pulse_index_conf;
+use super::read_first_chunk;
+use super::MAP_PULSE_LOCAL_TIMEOUT;
+use super::MAP_PULSE_QUERY_TIMEOUT;
+use crate::err::Error;
+use crate::gather::gather_get_json_generic;
+use crate::gather::SubRes;
+use crate::gather::Tag;
+use crate::response;
+use crate::response_err;
+use disk::pulseindex::PulseIndex;
+use disk::pulseindex::PulseIndexKey;
+use futures_util::stream;
+use futures_util::StreamExt;
+use http::Method;
+use http::StatusCode;
+use hyper::Body;
+use hyper::Request;
+use hyper::Response;
+use netpod::log::*;
+use netpod::query::PulseRangeQuery;
+use netpod::query::TimeRangeQuery;
+use netpod::range::evrange::SeriesRange;
+use netpod::timeunits::SEC;
+use netpod::AppendToUrl;
+use netpod::FromUrl;
+use netpod::HasBackend;
+use netpod::HasTimeout;
+use netpod::Node;
+use netpod::NodeConfigCached;
+use netpod::APP_JSON;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::SeekFrom;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+use taskrun::tokio;
+use tokio::fs::File;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncSeekExt;
+use url::Url;
+
+const MAP_PULSE_BATCH_PATH: &str = "/api/4/map/pulse-batch";
+const MAP_PULSE_RANGE_PATH: &str = "/api/4/map/pulse-range";
+const MAP_PULSE_BATCH_LOCAL_PATH: &str = "/api/4/private/map/pulse-batch/local";
+
+const MAP_PULSE_BATCH_MAX: usize = 20000;
+
+// The timer channel files are binned by day.
+const MAP_FILE_TIMEBIN: u64 = SEC * 86400;
+
+/// The index of the files is read again from the database after this time.
+const MAP_FILES_MAX_AGE: Duration = Duration::from_secs(60);
+
+const MAP_FILES_CONCURRENT: usize = 16;
+
+/// Which side of the mapping is given.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum MapBy {
+    Pulse,
+    Ts,
+}
+
+impl MapBy {
+    fn key(&self, p: &MapPoint) -> u64 {
+        match self {
+            MapBy::Pulse => p.pulse,
+            MapBy::Ts => p.ts,
+        }
+    }
+
+    fn other(&self, p: &MapPoint) -> u64 {
+        match self {
+            MapBy::Pulse => p.ts,
+            MapBy::Ts => p.pulse,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct MapPoint {
+    pulse: u64,
+    ts: u64,
+}
+
+/// What the timer channel files tell about a pulse or timestamp.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+struct Mapped {
+    /// Of every channel which has an event at the key, the other side of that event.
+    exact: Vec<u64>,
+    /// The last event at or before the key.
+    below: Option<MapPoint>,
+    /// The first event at or after the key.
+    above: Option<MapPoint>,
+    /// Why some of the files which may hold the key could not be searched.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Mapped {
+    fn merge(&mut self, other: Mapped, by: MapBy) {
+        self.exact.extend(other.exact);
+        if self.error.is_none() {
+            self.error = other.error;
+        }
+        if let Some(p) = other.below {
+            if self.below.is_none_or(|x| by.key(&x) < by.key(&p)) {
+                self.below = Some(p);
+            }
+        }
+        if let Some(p) = other.above {
+            if self.above.is_none_or(|x| by.key(&x) > by.key(&p)) {
+                self.above = Some(p);
+            }
+        }
+    }
+
+    /// The value found by most channels, the lowest one on ties.
+    fn majority(&self) -> Option<u64> {
+        let mut counts = BTreeMap::new();
+        for x in &self.exact {
+            *counts.entry(*x).or_insert(0u32) += 1;
+        }
+        counts
+            .into_iter()
+            .fold(None, |a: Option<(u64, u32)>, (x, n)| match a {
+                Some(a) if a.1 >= n => Some(a),
+                _ => Some((x, n)),
+            })
+            .map(|x| x.0)
+    }
+
+    /// The event closest to the key, the earlier one on ties.
+    fn nearest(&self, by: MapBy, key: u64) -> Option<MapPoint> {
+        match (self.below, self.above) {
+            (Some(a), Some(b)) => {
+                if key - by.key(&a) <= by.key(&b) - key {
+                    Some(a)
+                } else {
+                    Some(b)
+                }
+            }
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+struct MapFile {
+    channel: String,
+    timebin: u32,
+    split: u32,
+    ks: u32,
+}
+
+/// The timer channel files of this node, by the range of pulses and of time which they cover.
+struct MapFiles {
+    files: Vec<MapFile>,
+    by_pulse: IntervalTree<usize>,
+    by_ts: IntervalTree<usize>,
+    built: Instant,
+}
+
+impl MapFiles {
+    fn tree(&self, by: MapBy) -> &IntervalTree<usize> {
+        match by {
+            MapBy::Pulse => &self.by_pulse,
+            MapBy::Ts => &self.by_ts,
+        }
+    }
+}
+
+static MAP_FILES: Mutex<Option<Arc<MapFiles>>> = Mutex::new(None);
+
+/// The files from the `map_pulse_files` index, kept in memory for a while.
+async fn map_files(ncc: &NodeConfigCached) -> Result<Arc<MapFiles>, Error> {
+    if let Some(x) = MAP_FILES.lock().unwrap().as_ref() {
+        if x.built.elapsed() < MAP_FILES_MAX_AGE {
+            return Ok(x.clone());
+        }
+    }
+    let conn = dbconn::create_connection(&ncc.node_config.cluster.database).await?;
+    let sql = concat!(
+        "select channel, timebin, split, ks, pulse_min, pulse_max, closed",
+        " from map_pulse_files where hostname = $1"
+    );
+    let rows = conn.query(sql, &[&ncc.node.host]).await?;
+    let mut files = Vec::new();
+    let mut by_pulse = Vec::new();
+    let mut by_ts = Vec::new();
+    for r in &rows {
+        let timebin = r.try_get::<_, i32>(1)? as u32;
+        let pulse_min = r.try_get::<_, i64>(4)? as u64;
+        let pulse_max = r.try_get::<_, i64>(5)? as u64;
+        // Open files may have grown since they were indexed.
+        let pulse_max = if r.try_get::<_, i32>(6)? == 0 {
+            u64::MAX
+        } else {
+            pulse_max
+        };
+        let ts_beg = MAP_FILE_TIMEBIN * timebin as u64;
+        by_pulse.push((pulse_min, pulse_max, files.len()));
+        by_ts.push((ts_beg, ts_beg + MAP_FILE_TIMEBIN - 1, files.len()));
+        files.push(MapFile {
+            channel: r.try_get(0)?,
+            timebin,
+            split: r.try_get::<_, i32>(2)? as u32,
+            ks: r.try_get::<_, i32>(3)? as u32,
+        });
+    }
+    let ret = Arc::new(MapFiles {
+        files,
+        by_pulse: IntervalTree::new(by_pulse),
+        by_ts: IntervalTree::new(by_ts),
+        built: Instant::now(),
+    });
+    debug!("map files of {} rebuilt with {} files", ncc.node.host, ret.files.len());
+    *MAP_FILES.lock().unwrap() = Some(ret.clone());
+    Ok(ret)
+}
+
+/// Reads the events of a timer channel file by their position in the file.
+struct MapFileReader {
+    data: File,
+    index: Option<File>,
+    first: u64,
+    chunk_len: u64,
+    n: u64,
+}
+
+impl MapFileReader {
+    async fn open(file: &MapFile, node: &Node) -> Result<Option<Self>, Error> {
+        let path = disk::paths::data_path_tb(file.ks, &file.channel, file.timebin, 86400000, file.split, node)?;
+        if file.ks == 3 {
+            let mut path_index = path.clone().into_os_string();
+            path_index.push("_Index");
+            let index = File::open(path_index).await?;
+            let n = index.metadata().await?.len().saturating_sub(2) / 16;
+            let data = File::open(path).await?;
+            let ret = Self {
+                data,
+                index: Some(index),
+                first: 0,
+                chunk_len: 0,
+                n,
+            };
+            Ok(Some(ret))
+        } else if file.ks == 2 {
+            let (ck, data) = read_first_chunk(File::open(path).await?).await?;
+            let ck = match ck {
+                Some(x) => x,
+                None => return Ok(None),
+            };
+            let n = data.metadata().await?.len().saturating_sub(ck.pos) / ck.len;
+            let ret = Self {
+                data,
+                index: None,
+                first: ck.pos,
+                chunk_len: ck.len,
+                n,
+            };
+            Ok(Some(ret))
+        } else {
+            Err(Error::with_msg_no_trace(format!("bad keyspace {}", file.ks)))
+        }
+    }
+
+    async fn point(&mut self, i: u64) -> Result<MapPoint, Error> {
+        let pos = match &mut self.index {
+            Some(index) => {
+                index.seek(SeekFrom::Start(2 + 16 * i)).await?;
+                let mut buf = [0; 16];
+                index.read_exact(&mut buf).await?;
+                u64::from_be_bytes(buf[8..16].try_into()?)
+            }
+            None => self.first + self.chunk_len * i,
+        };
+        // Event length, ttl, timestamp and pulse.
+        self.data.seek(SeekFrom::Start(pos)).await?;
+        let mut buf = [0; 28];
+        self.data.read_exact(&mut buf).await?;
+        let ret = MapPoint {
+            ts: u64::from_be_bytes(buf[12..20].try_into()?),
+            pulse: u64::from_be_bytes(buf[20..28].try_into()?),
+        };
+        Ok(ret)
+    }
+
+    /// Binary search for the events around the key, assuming pulses and timestamps increase through the file.
+    async fn neighbors(&mut self, by: MapBy, key: u64) -> Result<Mapped, Error> {
+        let mut lo = 0;
+        let mut hi = self.n;
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if by.key(&self.point(mid).await?) < key {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        let mut ret = Mapped::default();
+        if lo < self.n {
+            let p = self.point(lo).await?;
+            if by.key(&p) == key {
+                ret.exact.push(by.other(&p));
+                ret.below = Some(p);
+            }
+            ret.above = Some(p);
+        }
+        if lo > 0 && ret.below.is_none() {
+            ret.below = Some(self.point(lo - 1).await?);
+        }
+        Ok(ret)
+    }
+}
+
+async fn search_file(file: &MapFile, by: MapBy, keys: &[u64], node: &Node) -> Result<Vec<Mapped>, Error> {
+    let mut rd = match MapFileReader::open(file, node).await? {
+        Some(x) => x,
+        None => return Ok(vec![Mapped::default(); keys.len()]),
+    };
+    let mut ret = Vec::with_capacity(keys.len());
+    for &key in keys {
+        ret.push(rd.neighbors(by, key).await?);
+    }
+    Ok(ret)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct LocalBatchQuery {
+    by: MapBy,
+    keys: Vec<u64>,
+    /// Search all files which overlap this range, instead of the files which contain each key.
+    within: Option<(u64, u64)>,
+}
+
+/// The neighbors of each key in the local pulse index, which covers all timer channels of the node at once.
+fn map_index(idx: &PulseIndex, q: &LocalBatchQuery) -> Vec<Mapped> {
+    let point = |i| {
+        idx.entry(i).map(|e| MapPoint {
+            pulse: e.pulse,
+            ts: e.ts,
+        })
+    };
+    let mut ret = Vec::with_capacity(q.keys.len());
+    for &key in &q.keys {
+        let i = idx.partition_point(match q.by {
+            MapBy::Pulse => PulseIndexKey::Pulse(key),
+            MapBy::Ts => PulseIndexKey::Ts(key),
+        });
+        let mut m = Mapped::default();
+        if let Some(p) = point(i) {
+            if q.by.key(&p) == key {
+                // A record for each timer channel with the key, like one file for each of them.
+                let mut j = i;
+                while let Some(p) = point(j).filter(|p| q.by.key(p) == key) {
+                    m.exact.push(q.by.other(&p));
+                    j += 1;
+                }
+                m.below = Some(p);
+            }
+            m.above = Some(p);
+        }
+        if i > 0 && m.below.is_none() {
+            m.below = point(i - 1);
+        }
+        ret.push(m);
+    }
+    ret
+}
+
+async fn map_local(q: &LocalBatchQuery, ncc: &NodeConfigCached) -> Result<Vec<Mapped>, Error> {
+    if let Some((path, _)) = pulse_index_conf(ncc) {
+        let idx = disk::pulseindex::open_pulse_index(path.clone()).await?;
+        return Ok(map_index(&idx, q));
+    }
+    let files = map_files(ncc).await?;
+    let tree = files.tree(q.by);
+    // The keys to search in each file.
+    let mut work: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    match q.within {
+        Some((beg, end)) => {
+            for &f in tree.overlapping(beg, end) {
+                work.entry(f).or_default().extend(0..q.keys.len());
+            }
+        }
+        None => {
+            for (i, &key) in q.keys.iter().enumerate() {
+                for &f in tree.stab(key) {
+                    work.entry(f).or_default().push(i);
+                }
+            }
+        }
+    }
+    let files2 = &files;
+    let futs = work.into_iter().map(|(f, ixs)| async move {
+        let file = &files2.files[f];
+        let keys: Vec<_> = ixs.iter().map(|&i| q.keys[i]).collect();
+        let res = search_file(file, q.by, &keys, &ncc.node).await;
+        (file, ixs, res)
+    });
+    let mut futs = stream::iter(futs).buffer_unordered(MAP_FILES_CONCURRENT);
+    let mut ret = vec![Mapped::default(); q.keys.len()];
+    while let Some((file, ixs, res)) = futs.next().await {
+        match res {
+            Ok(found) => {
+                for (i, m) in ixs.into_iter().zip(found) {
+                    ret[i].merge(m, q.by);
+                }
+            }
+            Err(e) => {
+                let msg = format!("can not map in {} {} {}: {e}", file.channel, file.split, file.timebin);
+                warn!("{msg}");
+                for i in ixs {
+                    ret[i].error.get_or_insert_with(|| msg.clone());
+                }
+            }
+        }
+    }
+    Ok(ret)
+}
+
+type SubResult = Result<SubRes<Vec<Mapped>>, Error>;
+
+/// Asks every node to search its timer channel files, and combines the answers.
+async fn map_cluster(q: LocalBatchQuery, ncc: &NodeConfigCached) -> Result<Vec<Mapped>, Error> {
+    let body = serde_json::to_vec(&q)?;
+    let mut urls = Vec::new();
+    let mut bodies = Vec::new();
+    for node in &ncc.node_config.cluster.nodes {
+        urls.push(node.baseurl().join(MAP_PULSE_BATCH_LOCAL_PATH)?);
+        bodies.push(Some(Body::from(body.clone())));
+    }
+    let tags = urls.iter().map(|x| x.to_string()).collect();
+    let nt = |tag, res: Response<Body>| {
+        let fut = async {
+            let status = res.status();
+            let body = hyper::body::to_bytes(res).await?;
+            if status != StatusCode::OK {
+                let msg = String::from_utf8_lossy(&body);
+                return Err(Error::with_msg_no_trace(format!(
+                    "pulse map sub request {status} {msg}"
+                )));
+            }
+            let val: Vec<Mapped> = serde_json::from_slice(&body)?;
+            Ok(SubRes { tag, status, val })
+        };
+        Box::pin(fut) as _
+    };
+    let by = q.by;
+    let n = q.keys.len();
+    let ft = move |all: Vec<(Tag, SubResult)>| {
+        let mut ret = vec![Mapped::default(); n];
+        for (tag, res) in all {
+            match res {
+                Ok(res) => {
+                    for (a, b) in ret.iter_mut().zip(res.val) {
+                        a.merge(b, by);
+                    }
+                }
+                Err(e) => {
+                    // Any of the entries may be in the files of the node.
+                    error!("pulse map sub request {tag:?}  {e}");
+                    let msg = format!("node {} failed: {e}", tag.0);
+                    for a in ret.iter_mut() {
+                        a.error.get_or_insert_with(|| msg.clone());
+                    }
+                }
+            }
+        }
+        Ok(ret)
+    };
+    gather_get_json_generic(Method::POST, urls, bodies, tags, nt, ft, MAP_PULSE_LOCAL_TIMEOUT).await
+}
+
+fn json_response<T: Serialize>(val: &T) -> Result<Response<Body>, Error> {
+    let ret = response(StatusCode::OK)
+        .header(http::header::CONTENT_TYPE, APP_JSON)
+        .body(Body::from(serde_json::to_vec(val)?))?;
+    Ok(ret)
+}
+
+/// Searches the timer channel files of this node for a batch of pulses or timestamps.
+pub struct MapPulseBatchLocalHttpFunction {}
+
+impl MapPulseBatchLocalHttpFunction {
+    pub fn handler(req: &Request<Body>) -> Option<Self> {
+        if req.uri().path() == MAP_PULSE_BATCH_LOCAL_PATH {
+            Some(Self {})
+        } else {
+            None
+        }
+    }
+
+    pub async fn handle(&self, req: Request<Body>, ncc: &NodeConfigCached) -> Result<Response<Body>, Error> {
+        if req.method() != Method::POST {
+            return Ok(response(StatusCode::METHOD_NOT_ALLOWED).body(Body::empty())?);
+        }
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        let q: LocalBatchQuery = serde_json::from_slice(&body)?;
+        json_response(&map_local(&q, ncc).await?)
+    }
+}
+
+/// Maps either a list of pulses to timestamps, or a list of timestamps to the nearest pulses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapPulseBatchQuery {
+    #[serde(default = "default_backend")]
+    pub backend: String,
+    #[serde(default)]
+    pub pulses: Vec<u64>,
+    #[serde(default)]
+    pub tss: Vec<u64>,
+}
+
+fn default_backend() -> String {
+    String::from("sf-databuffer")
+}
+
+#[derive(Debug, Serialize)]
+struct MapPulseBatchResponse {
+    pulses: Vec<Option<u64>>,
+    tss: Vec<Option<u64>>,
+    /// For each entry, why it may be incomplete. Left out if all entries are complete.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<Option<String>>,
+}
+
+pub struct MapPulseBatchHttpFunction {}
+
+impl MapPulseBatchHttpFunction {
+    pub fn path() -> &'static str {
+        MAP_PULSE_BATCH_PATH
+    }
+
+    pub fn handler(req: &Request<Body>) -> Option<Self> {
+        if req.uri().path() == MAP_PULSE_BATCH_PATH {
+            Some(Self {})
+        } else {
+            None
+        }
+    }
+
+    pub async fn handle(&self, req: Request<Body>, ncc: &NodeConfigCached) -> Result<Response<Body>, Error> {
+        if req.method() != Method::POST {
+            return Ok(response(StatusCode::METHOD_NOT_ALLOWED).body(Body::empty())?);
+        }
+        let body = hyper::body::to_bytes(req.into_body()).await?;
+        let q: MapPulseBatchQuery = match serde_json::from_slice(&body) {
+            Ok(x) => x,
+            Err(e) => {
+                return Ok(response_err(
+                    StatusCode::BAD_REQUEST,
+                    format!("can not parse query: {e}"),
+                )?)
+            }
+        };
+        let (by, keys) = match (q.pulses.is_empty(), q.tss.is_empty()) {
+            (false, true) => (MapBy::Pulse, q.pulses),
+            (true, false) => (MapBy::Ts, q.tss),
+            _ => {
+                let msg = "give either pulses or tss";
+                return Ok(response_err(StatusCode::BAD_REQUEST, msg)?);
+            }
+        };
+        if keys.len() > MAP_PULSE_BATCH_MAX {
+            let msg = format!("at most {MAP_PULSE_BATCH_MAX} values per request");
+            return Ok(response_err(StatusCode::BAD_REQUEST, msg)?);
+        }
+        let ts1 = Instant::now();
+        let lq = LocalBatchQuery {
+            by,
+            keys: keys.clone(),
+            within: None,
+        };
+        let found = map_cluster(lq, ncc).await?;
+        let errors = if found.iter().any(|x| x.error.is_some()) {
+            found.iter().map(|x| x.error.clone()).collect()
+        } else {
+            Vec::new()
+        };
+        let ret = match by {
+            MapBy::Pulse => MapPulseBatchResponse {
+                tss: found.iter().map(|x| x.majority()).collect(),
+                pulses: keys.into_iter().map(Some).collect(),
+                errors,
+            },
+            MapBy::Ts => {
+                let nearest: Vec<_> = keys.iter().zip(&found).map(|(&k, x)| x.nearest(by, k)).collect();
+                MapPulseBatchResponse {
+                    pulses: nearest.iter().map(|x| x.map(|x| x.pulse)).collect(),
+                    tss: nearest.iter().map(|x| x.map(|x| x.ts)).collect(),
+                    errors,
+                }
+            }
+        };
+        let dt = ts1.elapsed();
+        if dt > Duration::from_millis(1500) {
+            warn!(
+                "MapPulseBatchHttpFunction  {} values took {:.2}s",
+                found.len(),
+                dt.as_secs_f32()
+            );
+        }
+        json_response(&ret)
+    }
+}
+
+/// The range of time which spans a range of pulses, or the range of pulses in a range of time.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MapPulseRangeQuery {
+    backend: String,
+    range: SeriesRange,
+}
+
+impl HasBackend for MapPulseRangeQuery {
+    fn backend(&self) -> &str {
+        &self.backend
+    }
+}
+
+impl HasTimeout for MapPulseRangeQuery {
+    fn timeout(&self) -> Duration {
+        MAP_PULSE_QUERY_TIMEOUT
+    }
+}
+
+impl FromUrl for MapPulseRangeQuery {
+    fn from_url(url: &Url) -> Result<Self, err::Error> {
+        let pairs = netpod::get_url_query_pairs(url);
+        Self::from_pairs(&pairs)
+    }
+
+    fn from_pairs(pairs: &BTreeMap<String, String>) -> Result<Self, err::Error> {
+        let range = if let Ok(x) = PulseRangeQuery::from_pairs(pairs) {
+            SeriesRange::PulseRange(x.into())
+        } else if let Ok(x) = TimeRangeQuery::from_pairs(pairs) {
+            SeriesRange::TimeRange(x.into())
+        } else {
+            return Err(err::Error::with_public_msg_no_trace("no pulse or time range in url"));
+        };
+        let ret = Self {
+            backend: pairs.get("backend").cloned().unwrap_or_else(default_backend),
+            range,
+        };
+        Ok(ret)
+    }
+}
+
+impl AppendToUrl for MapPulseRangeQuery {
+    fn append_to_url(&self, url: &mut Url) {
+        match &self.range {
+            SeriesRange::TimeRange(k) => TimeRangeQuery::from(k).append_to_url(url),
+            SeriesRange::PulseRange(k) => PulseRangeQuery::from(k).append_to_url(url),
+        }
+        url.query_pairs_mut().append_pair("backend", &self.backend);
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct MapPulseRangeResponse {
+    #[serde(rename = "begPulse")]
+    beg_pulse: Option<u64>,
+    #[serde(rename = "endPulse")]
+    end_pulse: Option<u64>,
+    #[serde(rename = "begTs")]
+    beg_ts: Option<u64>,
+    #[serde(rename = "endTs")]
+    end_ts: Option<u64>,
+}
+
+pub struct MapPulseRangeHttpFunction {}
+
+impl MapPulseRangeHttpFunction {
+    pub fn path() -> &'static str {
+        MAP_PULSE_RANGE_PATH
+    }
+
+    pub fn handler(req: &Request<Body>) -> Option<Self> {
+        if req.uri().path() == MAP_PULSE_RANGE_PATH {
+            Some(Self {})
+        } else {
+            None
+        }
+    }
+
+    pub async fn handle(&self, req: Request<Body>, ncc: &NodeConfigCached) -> Result<Response<Body>, Error> {
+        if req.method() != Method::GET {
+            return Ok(response(StatusCode::METHOD_NOT_ALLOWED).body(Body::empty())?);
+        }
+        let url = Url::parse(&format!("dummy:{}", req.uri()))?;
+        let q = MapPulseRangeQuery::from_url(&url)?;
+        // A pulse range includes its end, a time range does not.
+        let (by, beg, last) = match &q.range {
+            SeriesRange::PulseRange(x) => (MapBy::Pulse, x.beg, x.end),
+            SeriesRange::TimeRange(x) => (MapBy::Ts, x.beg, x.end.saturating_sub(1)),
+        };
+        if last < beg {
+            return Ok(response_err(StatusCode::BAD_REQUEST, "empty range")?);
+        }
+        let lq = LocalBatchQuery {
+            by,
+            keys: vec![beg, last],
+            within: Some((beg, last)),
+        };
+        let found = map_cluster(lq, ncc).await?;
+        if let Some(e) = found.iter().find_map(|x| x.error.as_ref()) {
+            return Err(Error::with_msg_no_trace(format!("can not map the range: {e}")));
+        }
+        let first = found[0].above.filter(|x| by.key(x) <= last);
+        let end = found[1].below.filter(|x| by.key(x) >= beg);
+        let ret = MapPulseRangeResponse {
+            beg_pulse: first.map(|x| x.pulse),
+            end_pulse: end.map(|x| x.pulse),
+            beg_ts: first.map(|x| x.ts),
+            end_ts: end.map(|x| x.ts),
+        };
+        json_response(&ret)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn merge_mapped() {
+        let p = |pulse, ts| MapPoint { pulse, ts };
+        let mut a = Mapped {
+            exact: vec![100],
+            below: Some(p(10, 100)),
+            above: Some(p(10, 100)),
+            error: None,
+        };
+        let b = Mapped {
+            exact: vec![101, 100],
+            below: Some(p(10, 101)),
+            above: Some(p(10, 101)),
+            error: None,
+        };
+        a.merge(b, MapBy::Pulse);
+        assert_eq!(a.majority(), Some(100));
+        assert_eq!(a.error, None);
+        let failed = Mapped {
+            error: Some("node failed".into()),
+            ..Default::default()
+        };
+        a.merge(failed, MapBy::Pulse);
+        assert_eq!(a.majority(), Some(100));
+        assert_eq!(a.error.as_deref(), Some("node failed"));
+        let c = Mapped {
+            exact: Vec::new(),
+            below: Some(p(9, 90)),
+            above: Some(p(12, 120)),
+            error: None,
+        };
+        assert_eq!(c.nearest(MapBy::Ts, 100), Some(p(9, 90)));
+        assert_eq!(c.nearest(MapBy::Ts, 110), Some(p(12, 120)));
+        assert_eq!(c.majority(), None);
+    }
+}
@@ -0,0 +1,105 @@
+/// Static tree of closed intervals `[beg, end]`, to find the intervals which overlap a query range.
+/// The entries are sorted by begin and form an implicit balanced tree, where each node knows the
+/// largest end in its subtree.
+pub struct IntervalTree<T> {
+    entries: Vec<Entry<T>>,
+}
+
+struct Entry<T> {
+    beg: u64,
+    end: u64,
+    max_end: u64,
+    val: T,
+}
+
+impl<T> IntervalTree<T> {
+    pub fn new(items: Vec<(u64, u64, T)>) -> Self {
+        let mut entries: Vec<_> = items
+            .into_iter()
+            .map(|(beg, end, val)| Entry {
+                beg,
+                end,
+                max_end: end,
+                val,
+            })
+            .collect();
+        entries.sort_by_key(|x| x.beg);
+        let mut ret = Self { entries };
+        ret.fill_max_end(0, ret.entries.len());
+        ret
+    }
+
+    fn fill_max_end(&mut self, lo: usize, hi: usize) -> u64 {
+        if lo >= hi {
+            return 0;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let a = self.fill_max_end(lo, mid);
+        let b = self.fill_max_end(mid + 1, hi);
+        let e = &mut self.entries[mid];
+        e.max_end = e.end.max(a).max(b);
+        e.max_end
+    }
+
+    /// The values of the intervals which contain the point.
+    pub fn stab(&self, x: u64) -> Vec<&T> {
+        self.overlapping(x, x)
+    }
+
+    /// The values of the intervals which overlap `[beg, end]`, ordered by interval begin.
+    pub fn overlapping(&self, beg: u64, end: u64) -> Vec<&T> {
+        let mut ret = Vec::new();
+        self.collect(0, self.entries.len(), beg, end, &mut ret);
+        ret
+    }
+
+    fn collect<'a>(&'a self, lo: usize, hi: usize, beg: u64, end: u64, ret: &mut Vec<&'a T>) {
+        if lo >= hi {
+            return;
+        }
+        let mid = lo + (hi - lo) / 2;
+        let e = &self.entries[mid];
+        if e.max_end < beg {
+            return;
+        }
+        self.collect(lo, mid, beg, end, ret);
+        // All entries to the right begin later.
+        if e.beg > end {
+            return;
+        }
+        if e.end >= beg {
+            ret.push(&e.val);
+        }
+        self.collect(mid + 1, hi, beg, end, ret);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::IntervalTree;
+
+    #[test]
+    fn overlapping_intervals() {
+        let items = vec![
+            (10, 20, 'a'),
+            (0, 5, 'b'),
+            (15, 40, 'c'),
+            (30, 35, 'd'),
+            (21, 29, 'e'),
+            (50, 60, 'f'),
+        ];
+        let tree = IntervalTree::new(items.clone());
+        assert_eq!(tree.overlapping(0, u64::MAX).len(), 6);
+        assert_eq!(tree.stab(17), [&'a', &'c']);
+        assert_eq!(tree.stab(20), [&'a', &'c']);
+        assert_eq!(tree.stab(45), Vec::<&char>::new());
+        assert_eq!(tree.overlapping(25, 50), [&'c', &'e', &'d', &'f']);
+        for x in 0..70 {
+            let mut exp: Vec<_> = items.iter().filter(|k| k.0 <= x && k.1 >= x).map(|k| k.2).collect();
+            let mut got: Vec<_> = tree.stab(x).into_iter().copied().collect();
+            exp.sort();
+            got.sort();
+            assert_eq!(got, exp);
+        }
+    }
+}
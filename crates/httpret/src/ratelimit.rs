@@ -0,0 +1,310 @@
+use crate::auth::AuthMethod;
+use crate::auth::Identity;
+use crate::bodystream::response;
+use bytes::Bytes;
+use futures_util::Future;
+use futures_util::Stream;
+use futures_util::StreamExt;
+use http::header;
+use http::HeaderMap;
+use http::StatusCode;
+use hyper::Body;
+use hyper::Response;
+use netpod::log::*;
+use netpod::ClientUsage;
+use netpod::Cluster;
+use netpod::RateLimitConfig;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+use std::net::ToSocketAddrs;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Clients without running requests are forgotten after this time.
+const IDLE_FORGET: Duration = Duration::from_secs(600);
+
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+struct Client {
+    tokens: f64,
+    refilled: Instant,
+    running: u32,
+    window_start: Instant,
+    bytes_cur: u64,
+    bytes_prev: u64,
+    requests: u64,
+    rejected: u64,
+    last_seen: Instant,
+}
+
+impl Client {
+    fn new(now: Instant) -> Self {
+        Self {
+            tokens: f64::MAX,
+            refilled: now,
+            running: 0,
+            window_start: now,
+            bytes_cur: 0,
+            bytes_prev: 0,
+            requests: 0,
+            rejected: 0,
+            last_seen: now,
+        }
+    }
+
+    fn roll_window(&mut self, now: Instant) {
+        let dt = now.duration_since(self.window_start);
+        if dt >= WINDOW * 2 {
+            self.bytes_prev = 0;
+            self.bytes_cur = 0;
+            self.window_start = now;
+        } else if dt >= WINDOW {
+            self.bytes_prev = self.bytes_cur;
+            self.bytes_cur = 0;
+            self.window_start += WINDOW;
+        }
+    }
+
+    // Bytes of the last minute, estimated by weighting the previous window with the part of it that is still in range.
+    fn bytes_last_minute(&self, now: Instant) -> u64 {
+        let f = now.duration_since(self.window_start).as_secs_f64() / WINDOW.as_secs_f64();
+        self.bytes_cur + (self.bytes_prev as f64 * (1. - f.min(1.))) as u64
+    }
+
+    /// Takes a request slot, or tells how long to wait until a request would be admitted.
+    fn admit(&mut self, conf: &RateLimitConfig, now: Instant) -> Result<(), Duration> {
+        self.last_seen = now;
+        self.roll_window(now);
+        if let Some(rps) = conf.requests_per_second.filter(|x| *x > 0.) {
+            let burst = rps.max(1.);
+            let dt = now.duration_since(self.refilled).as_secs_f64();
+            self.tokens = (self.tokens.min(burst) + dt * rps).min(burst);
+            self.refilled = now;
+            if self.tokens < 1. {
+                return Err(Duration::from_secs_f64((1. - self.tokens) / rps));
+            }
+        }
+        if let Some(max) = conf.concurrent {
+            if self.running >= max {
+                return Err(Duration::from_secs(1));
+            }
+        }
+        if let Some(max) = conf.bytes_per_minute {
+            if self.bytes_last_minute(now) >= max {
+                return Err(WINDOW.saturating_sub(now.duration_since(self.window_start)));
+            }
+        }
+        if conf.requests_per_second.is_some_and(|x| x > 0.) {
+            self.tokens -= 1.;
+        }
+        self.running += 1;
+        self.requests += 1;
+        Ok(())
+    }
+}
+
+static CLIENTS: Mutex<BTreeMap<String, Client>> = Mutex::new(BTreeMap::new());
+
+// Addresses of the nodes of the cluster, their requests among each other are not limited.
+static NODE_ADDRS: Mutex<BTreeSet<IpAddr>> = Mutex::new(BTreeSet::new());
+
+fn with_client<F, T>(key: &str, f: F) -> T
+where
+    F: FnOnce(&mut Client) -> T,
+{
+    let now = Instant::now();
+    let mut clients = CLIENTS.lock().unwrap();
+    if clients.len() > 1000 {
+        clients.retain(|_, c| c.running > 0 || now.duration_since(c.last_seen) < IDLE_FORGET);
+    }
+    let c = clients.entry(key.into()).or_insert_with(|| Client::new(now));
+    f(c)
+}
+
+/// Looks up the addresses of the nodes of the cluster.
+pub fn exempt_cluster_nodes(cluster: &Cluster) {
+    let mut addrs = NODE_ADDRS.lock().unwrap();
+    for node in &cluster.nodes {
+        match (node.host.as_str(), node.port).to_socket_addrs() {
+            Ok(it) => addrs.extend(it.map(|x| x.ip())),
+            Err(e) => warn!("can not resolve node {}: {e}", node.host),
+        }
+    }
+}
+
+/// The client to account the request to, `None` if the request is not limited.
+pub fn client_key(
+    peer: IpAddr,
+    headers: &HeaderMap,
+    identity: Option<&Identity>,
+    conf: &RateLimitConfig,
+) -> Option<String> {
+    if let Some(identity) = identity {
+        match identity.method {
+            AuthMethod::Node => return None,
+            AuthMethod::Anonymous => {}
+            _ => {
+                return if conf.exempt.contains(&identity.subject) {
+                    None
+                } else {
+                    Some(identity.subject.clone())
+                };
+            }
+        }
+    }
+    if NODE_ADDRS.lock().unwrap().contains(&peer) {
+        return None;
+    }
+    let peer_str = peer.to_string();
+    let addr = if conf.trusted_proxies.contains(&peer_str) {
+        // The proxy appends the address of its caller as last entry.
+        let fwd = headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .next_back()
+            .and_then(|x| x.to_str().ok())
+            .and_then(|x| x.rsplit(',').next())
+            .map(|x| x.trim().to_string());
+        fwd?
+    } else {
+        peer_str
+    };
+    if conf.exempt.contains(&addr) {
+        None
+    } else {
+        Some(addr)
+    }
+}
+
+/// Counts a running request of the client, until dropped.
+pub struct Permit {
+    key: String,
+}
+
+impl Permit {
+    fn served(&self, n: usize) {
+        with_client(&self.key, |c| {
+            c.roll_window(Instant::now());
+            c.bytes_cur += n as u64;
+        });
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        with_client(&self.key, |c| c.running = c.running.saturating_sub(1));
+    }
+}
+
+pub fn admit(key: &str, conf: &RateLimitConfig) -> Result<Permit, Duration> {
+    with_client(key, |c| match c.admit(conf, Instant::now()) {
+        Ok(()) => Ok(Permit { key: key.into() }),
+        Err(e) => {
+            c.rejected += 1;
+            Err(e)
+        }
+    })
+}
+
+struct CountedBody {
+    inner: Body,
+    permit: Permit,
+}
+
+impl Stream for CountedBody {
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let ret = self.inner.poll_next_unpin(cx);
+        if let Poll::Ready(Some(Ok(b))) = &ret {
+            self.permit.served(b.len());
+        }
+        ret
+    }
+}
+
+/// Serves the request within the limits of the client.
+/// The request counts as running until its response body is transferred.
+pub async fn limited<F, E>(key: Option<String>, conf: Option<&RateLimitConfig>, fut: F) -> Result<Response<Body>, E>
+where
+    F: Future<Output = Result<Response<Body>, E>>,
+    E: From<http::Error>,
+{
+    let (key, conf) = match (key, conf) {
+        (Some(key), Some(conf)) => (key, conf),
+        _ => return fut.await,
+    };
+    match admit(&key, conf) {
+        Ok(permit) => {
+            let (head, body) = fut.await?.into_parts();
+            let body = Body::wrap_stream(CountedBody { inner: body, permit });
+            Ok(Response::from_parts(head, body))
+        }
+        Err(wait) => {
+            debug!("limit reached for {key}");
+            let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            let ret = response(StatusCode::TOO_MANY_REQUESTS)
+                .header(header::RETRY_AFTER, secs.max(1))
+                .body(Body::from("too many requests"))?;
+            Ok(ret)
+        }
+    }
+}
+
+pub fn usage() -> Vec<ClientUsage> {
+    let now = Instant::now();
+    let mut clients = CLIENTS.lock().unwrap();
+    clients
+        .iter_mut()
+        .map(|(k, c)| {
+            c.roll_window(now);
+            ClientUsage {
+                client: k.clone(),
+                running: c.running,
+                requests: c.requests,
+                rejected: c.rejected,
+                bytes_last_minute: c.bytes_last_minute(now),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn client_limits() {
+        let conf = RateLimitConfig {
+            requests_per_second: Some(2.),
+            concurrent: Some(1),
+            bytes_per_minute: Some(1000),
+            ..Default::default()
+        };
+        let t0 = Instant::now();
+        let mut c = Client::new(t0);
+        assert!(c.admit(&conf, t0).is_ok());
+        assert!(c.admit(&conf, t0).is_err());
+        c.running = 0;
+        assert!(c.admit(&conf, t0).is_ok());
+        c.running = 0;
+        let wait = c.admit(&conf, t0).unwrap_err();
+        assert!(wait > Duration::ZERO && wait <= Duration::from_millis(500));
+        let t1 = t0 + Duration::from_secs(1);
+        c.running = 0;
+        c.bytes_cur = 1000;
+        let wait = c.admit(&conf, t1).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(59));
+        let t2 = t0 + Duration::from_secs(90);
+        c.roll_window(t2);
+        assert_eq!(c.bytes_last_minute(t2), 500);
+        assert!(c.admit(&conf, t2).is_ok());
+    }
+}
@@ -75,6 +75,8 @@ pub trait CollectorType: fmt::Debug + Send + Unpin + WithLen {
     fn ingest(&mut self, src: &mut Self::Input);
     fn set_range_complete(&mut self);
     fn set_timed_out(&mut self);
+    /// The input was cut at a limit, the result tells via `continueAt` where to go on.
+    fn set_continue_at_here(&mut self);
 
     // TODO use this crate's Error instead:
     fn result(&mut self, range: Option<SeriesRange>, binrange: Option<BinnedRangeEnum>) -> Result<Self::Output, Error>;
@@ -84,6 +86,7 @@ pub trait Collector: fmt::Debug + Send + Unpin + WithLen {
     fn ingest(&mut self, src: &mut dyn Collectable);
     fn set_range_complete(&mut self);
     fn set_timed_out(&mut self);
+    fn set_continue_at_here(&mut self);
     // TODO factor the required parameters into new struct? Generic over events or binned?
     fn result(
         &mut self,
@@ -124,6 +127,10 @@ where
         T::set_timed_out(self)
     }
 
+    fn set_continue_at_here(&mut self) {
+        T::set_continue_at_here(self)
+    }
+
     fn result(
         &mut self,
         range: Option<SeriesRange>,
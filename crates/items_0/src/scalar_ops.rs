@@ -70,10 +70,16 @@ pub trait ScalarOps:
     fn find_vec_min(a: &Vec<Self>) -> Option<Self>;
     fn find_vec_max(a: &Vec<Self>) -> Option<Self>;
     fn avg_vec(a: &Vec<Self>) -> Option<Self>;
+    /// Types without a meaningful order or average (strings) are binned as "last value" and count:
+    /// min and max both carry the last value in the bin and the average is NaN.
+    fn last_value_binning() -> bool;
 }
 
 macro_rules! impl_scalar_ops {
     ($ty:ident, $zero:expr, $equal_slack:ident, $mac_add:ident, $mac_div:ident) => {
+        impl_scalar_ops!($ty, $zero, $equal_slack, $mac_add, $mac_div, false);
+    };
+    ($ty:ident, $zero:expr, $equal_slack:ident, $mac_add:ident, $mac_div:ident, $last_value_binning:expr) => {
         impl ScalarOps for $ty {
             fn zero_b() -> Self {
                 $zero
@@ -133,6 +139,10 @@ macro_rules! impl_scalar_ops {
                     Some(sum)
                 }
             }
+
+            fn last_value_binning() -> bool {
+                $last_value_binning
+            }
         }
     };
 }
@@ -211,4 +221,4 @@ impl_scalar_ops!(i64, 0, equal_int, add_int, div_int);
 impl_scalar_ops!(f32, 0., equal_f32, add_int, div_int);
 impl_scalar_ops!(f64, 0., equal_f64, add_int, div_int);
 impl_scalar_ops!(bool, false, equal_bool, add_bool, div_bool);
-impl_scalar_ops!(String, String::new(), equal_string, add_string, div_string);
+impl_scalar_ops!(String, String::new(), equal_string, add_string, div_string, true);
@@ -3,7 +3,10 @@ use err::Error;
 use netpod::log::Level;
 use netpod::DiskStats;
 use netpod::EventDataReadStats;
+use netpod::FrameCompressionStats;
 use netpod::RangeFilterStats;
+use netpod::ScyllaPartitionReadStats;
+use netpod::SplitReadStats;
 use serde::Deserialize;
 use serde::Serialize;
 
@@ -42,7 +45,10 @@ pub enum StatsItem {
     EventDataReadStats(EventDataReadStats),
     RangeFilterStats(RangeFilterStats),
     DiskStats(DiskStats),
+    SplitReadStats(SplitReadStats),
+    ScyllaPartitionReadStats(ScyllaPartitionReadStats),
     Warnings(),
+    FrameCompressionStats(FrameCompressionStats),
 }
 
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
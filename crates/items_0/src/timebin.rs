@@ -125,6 +125,12 @@ pub trait TimeBinner: fmt::Debug + Send {
     fn empty(&self) -> Box<dyn TimeBinned>;
 
     fn append_empty_until_end(&mut self);
+
+    /// The channel got disconnected at `ts`. Time-weighted binners must not carry the last value
+    /// forward from there on until the next event arrives.
+    /// Implemented by the binners of scalar and of x-binned events, waveforms get x-binned before
+    /// the time binning. Bins of bins see no connection status.
+    fn ingest_disconnect(&mut self, _ts: u64) {}
 }
 
 // TODO remove the Any bound. Factor out into custom AsAny trait.
@@ -439,6 +439,10 @@ impl<NTY: ScalarOps> CollectorType for BinsDim0Collector<NTY> {
         self.timed_out = true;
     }
 
+    fn set_continue_at_here(&mut self) {
+        // Bins tell where to continue from the missing bins.
+    }
+
     fn result(
         &mut self,
         _range: Option<SeriesRange>,
@@ -565,7 +569,9 @@ impl<NTY: ScalarOps> TimeBinnableTypeAggregator for BinsDim0Aggregator<NTY> {
             } else if ts2 <= beg {
             } else if ts1 >= end {
             } else {
-                if let Some((cmin, cmax)) = self.minmax.as_mut() {
+                if NTY::last_value_binning() {
+                    self.minmax = Some((min.clone(), max.clone()));
+                } else if let Some((cmin, cmax)) = self.minmax.as_mut() {
                     if min < cmin {
                         *cmin = min.clone();
                     }
@@ -588,7 +594,9 @@ impl<NTY: ScalarOps> TimeBinnableTypeAggregator for BinsDim0Aggregator<NTY> {
         } else {
             (NTY::zero_b(), NTY::zero_b())
         };
-        let avg = if self.sumc > 0 {
+        let avg = if NTY::last_value_binning() {
+            f32::NAN
+        } else if self.sumc > 0 {
             self.sum / self.sumc as f32
         } else {
             NTY::zero_b().as_prim_f32_b()
@@ -424,6 +424,10 @@ impl<NTY: ScalarOps> CollectorType for BinsXbinDim0Collector<NTY> {
         self.timed_out = true;
     }
 
+    fn set_continue_at_here(&mut self) {
+        // Bins tell where to continue from the missing bins.
+    }
+
     fn result(
         &mut self,
         _range: std::option::Option<SeriesRange>,
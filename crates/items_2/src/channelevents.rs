@@ -21,6 +21,8 @@ use items_0::TypeName;
 use items_0::WithLen;
 use netpod::log::*;
 use netpod::range::evrange::SeriesRange;
+use netpod::timeunits::MS;
+use netpod::timeunits::SEC;
 use netpod::BinnedRangeEnum;
 use serde::Deserialize;
 use serde::Serialize;
@@ -555,6 +557,52 @@ mod test_channel_events_serde {
             panic!()
         }
     }
+
+    #[test]
+    fn collected_status_rows() {
+        use crate::channelevents::ChannelEventsCollector;
+        use crate::channelevents::ConnStatus;
+        use items_0::collect_s::Collector;
+        let mut coll = ChannelEventsCollector::new();
+        let mut evs = EventsDim0::empty();
+        evs.push(2_000_000_000, 2, 3.0f32);
+        evs.push(3_000_000_000, 3, 3.2f32);
+        let status = |ts, status| ChannelEvents::Status(Some(ConnStatusEvent::new(ts, status)));
+        coll.ingest(&mut status(1_500_000_000, ConnStatus::Disconnect));
+        coll.ingest(&mut ChannelEvents::Events(Box::new(evs)));
+        coll.ingest(&mut status(2_500_000_005, ConnStatus::Connect));
+        let res = coll.result(None, None).unwrap();
+        let v = serde_json::to_value(&res).unwrap();
+        assert_eq!(v["tsAnchor"], 1);
+        assert_eq!(v["tsMs"], serde_json::json!([500, 1000, 1500, 2000]));
+        assert_eq!(v["tsNs"], serde_json::json!([0, 0, 5, 0]));
+        assert_eq!(v["values"], serde_json::json!([null, 3.0, null, 3.2f32]));
+        assert_eq!(v["pulseOff"], serde_json::json!([null, 2, null, 3]));
+        assert_eq!(
+            v["connStatus"],
+            serde_json::json!(["Disconnect", null, "Connect", null])
+        );
+        assert!(v.get("statusEvents").is_none());
+    }
+
+    #[test]
+    fn collected_status_only() {
+        use crate::channelevents::ChannelEventsCollector;
+        use crate::channelevents::ConnStatus;
+        use items_0::collect_s::Collector;
+        let mut coll = ChannelEventsCollector::new();
+        let status = |ts, status| ChannelEvents::Status(Some(ConnStatusEvent::new(ts, status)));
+        coll.ingest(&mut status(1_500_000_000, ConnStatus::Disconnect));
+        coll.ingest(&mut status(2_500_000_005, ConnStatus::Connect));
+        coll.set_range_complete();
+        let res = coll.result(None, None).unwrap();
+        let v = serde_json::to_value(&res).unwrap();
+        assert_eq!(v["tsAnchor"], 1);
+        assert_eq!(v["tsMs"], serde_json::json!([500, 1500]));
+        assert_eq!(v["tsNs"], serde_json::json!([0, 5]));
+        assert_eq!(v["connStatus"], serde_json::json!(["Disconnect", "Connect"]));
+        assert_eq!(v["rangeFinal"], true);
+    }
 }
 
 impl PartialEq for ChannelEvents {
@@ -887,7 +935,14 @@ impl TimeBinnerTy for ChannelEventsTimeBinner {
                 }
             }
             ChannelEvents::Status(item) => {
-                warn!("TODO consider channel status in time binning {item:?}");
+                if let Some(item) = item {
+                    if item.status == ConnStatus::Disconnect {
+                        if let Some(binner) = self.binner.as_mut() {
+                            binner.ingest_disconnect(item.ts);
+                        }
+                    }
+                    self.conn_state = item.status.clone();
+                }
             }
         }
     }
@@ -983,6 +1038,12 @@ impl TimeBinner for ChannelEventsTimeBinner {
             None => panic!(),
         }
     }
+
+    fn ingest_disconnect(&mut self, ts: u64) {
+        if let Some(binner) = self.binner.as_mut() {
+            binner.ingest_disconnect(ts);
+        }
+    }
 }
 
 impl TimeBinnableTy for ChannelEvents {
@@ -1004,8 +1065,121 @@ impl TimeBinnableTy for ChannelEvents {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct ChannelEventsCollectorOutput {}
+/// The collected events together with the connection status changes seen in the range.
+/// On output, each status change becomes a row of its own, in time order with the events:
+/// its value columns are `null`, and the `connStatus` column holds the status, `null` for event rows.
+/// Without any event in the range, only the timestamp and `connStatus` columns are present.
+#[derive(Debug)]
+pub struct ChannelEventsCollectorOutput {
+    events: Option<Box<dyn Collected>>,
+    status_events: Vec<ConnStatusEvent>,
+    range_final: bool,
+    timed_out: bool,
+}
+
+impl ChannelEventsCollectorOutput {
+    pub fn status_events(&self) -> &[ConnStatusEvent] {
+        &self.status_events
+    }
+}
+
+impl Serialize for ChannelEventsCollectorOutput {
+    fn serialize<S>(&self, ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        use serde::ser::Error;
+        let mut v = match &self.events {
+            Some(events) => serde_json::to_value(events).map_err(S::Error::custom)?,
+            None => {
+                let mut v = serde_json::json!({ "tsAnchor": 0, "tsMs": [], "tsNs": [] });
+                if self.range_final {
+                    v["rangeFinal"] = true.into();
+                }
+                if self.timed_out {
+                    v["timedOut"] = true.into();
+                }
+                v
+            }
+        };
+        if !merge_status_rows(&mut v, &self.status_events).map_err(S::Error::custom)? {
+            // Without timestamp columns the status changes can only be listed apart.
+            let status = serde_json::to_value(&self.status_events).map_err(S::Error::custom)?;
+            if let Some(obj) = v.as_object_mut() {
+                obj.insert("statusEvents".into(), status);
+            }
+        }
+        v.serialize(ser)
+    }
+}
+
+/// Inserts a row for each status change into the columns of the collected events.
+/// Returns false if the events have no timestamp columns.
+fn merge_status_rows(v: &mut serde_json::Value, status: &[ConnStatusEvent]) -> Result<bool, String> {
+    use serde_json::Value as JsVal;
+    let obj = match v.as_object_mut() {
+        Some(x) => x,
+        None => return Ok(false),
+    };
+    let col = |k: &str| -> Option<Vec<u64>> { obj.get(k)?.as_array()?.iter().map(JsVal::as_u64).collect() };
+    let (anchor, ms, ns) = match (obj.get("tsAnchor").and_then(JsVal::as_u64), col("tsMs"), col("tsNs")) {
+        (Some(a), Some(b), Some(c)) => (a, b, c),
+        _ => return Ok(false),
+    };
+    let n = ms.len();
+    if ns.len() != n {
+        return Err(format!("tsMs has {} entries but tsNs {}", n, ns.len()));
+    }
+    let tss: Vec<_> = ms
+        .iter()
+        .zip(ns.iter())
+        .map(|(ms, ns)| anchor * SEC + ms * MS + ns)
+        .collect();
+    // For each row the timestamp, and either the index of the event or of the status change.
+    let mut rows = Vec::with_capacity(n + status.len());
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < status.len() {
+        if j < status.len() && (i == n || status[j].ts < tss[i]) {
+            rows.push((status[j].ts, Err(j)));
+            j += 1;
+        } else {
+            rows.push((tss[i], Ok(i)));
+            i += 1;
+        }
+    }
+    for (k, col) in obj.iter_mut() {
+        if k == "tsMs" || k == "tsNs" {
+            continue;
+        }
+        if let JsVal::Array(a) = col {
+            if a.len() == n {
+                let mut old: Vec<_> = std::mem::take(a).into_iter().map(Some).collect();
+                *a = rows
+                    .iter()
+                    .map(|x| match x.1 {
+                        Ok(i) => old[i].take().unwrap_or(JsVal::Null),
+                        Err(_) => JsVal::Null,
+                    })
+                    .collect();
+            }
+        }
+    }
+    let tss: Vec<_> = rows.iter().map(|x| x.0).collect();
+    let (anchor, ms, ns) = crate::ts_offs_from_abs(&tss);
+    let conn_status: Vec<_> = rows
+        .iter()
+        .map(|x| match x.1 {
+            Ok(_) => Ok(JsVal::Null),
+            Err(j) => serde_json::to_value(&status[j].status),
+        })
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.to_string())?;
+    obj.insert("tsAnchor".into(), anchor.into());
+    obj.insert("tsMs".into(), ms.into_iter().collect());
+    obj.insert("tsNs".into(), ns.into_iter().collect());
+    obj.insert("connStatus".into(), JsVal::Array(conn_status));
+    Ok(true)
+}
 
 impl AsAnyRef for ChannelEventsCollectorOutput {
     fn as_any_ref(&self) -> &dyn Any {
@@ -1021,13 +1195,14 @@ impl AsAnyMut for ChannelEventsCollectorOutput {
 
 impl WithLen for ChannelEventsCollectorOutput {
     fn len(&self) -> usize {
-        todo!()
+        self.events.as_ref().map_or(0, |x| x.len())
     }
 }
 
 impl items_0::collect_s::ToJsonResult for ChannelEventsCollectorOutput {
     fn to_json_result(&self) -> Result<Box<dyn items_0::collect_s::ToJsonBytes>, err::Error> {
-        todo!()
+        let k = serde_json::to_value(self)?;
+        Ok(Box::new(k))
     }
 }
 
@@ -1036,16 +1211,20 @@ impl Collected for ChannelEventsCollectorOutput {}
 #[derive(Debug)]
 pub struct ChannelEventsCollector {
     coll: Option<Box<dyn Collector>>,
+    status: Vec<ConnStatusEvent>,
     range_complete: bool,
     timed_out: bool,
+    needs_continue_at: bool,
 }
 
 impl ChannelEventsCollector {
     pub fn new() -> Self {
         Self {
             coll: None,
+            status: Vec::new(),
             range_complete: false,
             timed_out: false,
+            needs_continue_at: false,
         }
     }
 }
@@ -1068,8 +1247,10 @@ impl Collector for ChannelEventsCollector {
                     let coll = self.coll.as_mut().unwrap();
                     coll.ingest(item.as_collectable_with_default_mut());
                 }
-                ChannelEvents::Status(_) => {
-                    // TODO decide on output format to collect also the connection status events
+                ChannelEvents::Status(item) => {
+                    if let Some(item) = item.take() {
+                        self.status.push(item);
+                    }
                 }
             }
         } else {
@@ -1085,6 +1266,10 @@ impl Collector for ChannelEventsCollector {
         self.timed_out = true;
     }
 
+    fn set_continue_at_here(&mut self) {
+        self.needs_continue_at = true;
+    }
+
     fn result(
         &mut self,
         range: Option<SeriesRange>,
@@ -1098,8 +1283,30 @@ impl Collector for ChannelEventsCollector {
                 if self.timed_out {
                     coll.set_timed_out();
                 }
+                if self.needs_continue_at {
+                    coll.set_continue_at_here();
+                }
                 let res = coll.result(range, binrange)?;
-                Ok(res)
+                if self.status.is_empty() {
+                    Ok(res)
+                } else {
+                    let ret = ChannelEventsCollectorOutput {
+                        events: Some(res),
+                        status_events: std::mem::take(&mut self.status),
+                        range_final: self.range_complete,
+                        timed_out: self.timed_out,
+                    };
+                    Ok(Box::new(ret))
+                }
+            }
+            None if !self.status.is_empty() => {
+                let ret = ChannelEventsCollectorOutput {
+                    events: None,
+                    status_events: std::mem::take(&mut self.status),
+                    range_final: self.range_complete,
+                    timed_out: self.timed_out,
+                };
+                Ok(Box::new(ret))
             }
             None => {
                 error!("nothing collected [caa8d2565]");
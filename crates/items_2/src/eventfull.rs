@@ -401,4 +401,19 @@ impl EventFull {
             Ok(Cow::Borrowed(data.as_slice()))
         }
     }
+
+    /// Replaces the compressed blobs by their decompressed data.
+    pub fn decompress_all(&mut self) -> Result<(), DecompError> {
+        let mut payload_max = 0;
+        for i in 0..self.blobs.len() {
+            if self.comps[i].is_some() {
+                let data = self.data_decompressed(i)?.into_owned();
+                self.blobs[i] = data;
+                self.comps[i] = None;
+            }
+            payload_max = payload_max.max(self.blobs[i].len() as u64);
+        }
+        self.entry_payload_max = payload_max;
+        Ok(())
+    }
 }
@@ -231,6 +231,7 @@ pub struct EventsDim0Collector<STY> {
     vals: EventsDim0<STY>,
     range_final: bool,
     timed_out: bool,
+    needs_continue_at: bool,
 }
 
 impl<STY> EventsDim0Collector<STY> {
@@ -243,6 +244,7 @@ impl<STY> EventsDim0Collector<STY> {
             vals: EventsDim0::empty(),
             range_final: false,
             timed_out: false,
+            needs_continue_at: false,
         }
     }
 }
@@ -305,6 +307,10 @@ impl<STY: ScalarOps> EventsDim0CollectorOutput<STY> {
         self.timed_out
     }
 
+    pub fn continue_at(&self) -> Option<&IsoDateTime> {
+        self.continue_at.as_ref()
+    }
+
     pub fn is_valid(&self) -> bool {
         if self.ts_off_ms.len() != self.ts_off_ns.len() {
             false
@@ -384,6 +390,10 @@ impl<STY: ScalarOps> CollectorType for EventsDim0Collector<STY> {
         self.timed_out = true;
     }
 
+    fn set_continue_at_here(&mut self) {
+        self.needs_continue_at = true;
+    }
+
     fn result(
         &mut self,
         range: Option<SeriesRange>,
@@ -395,7 +405,7 @@ impl<STY: ScalarOps> CollectorType for EventsDim0Collector<STY> {
         // The amount of the delta must take into account what kind of timestamp precision the client
         // can parse and handle.
         let vals = &mut self.vals;
-        let continue_at = if self.timed_out {
+        let continue_at = if self.timed_out || self.needs_continue_at {
             if let Some(ts) = vals.tss.back() {
                 Some(IsoDateTime::from_u64(*ts + MS))
             } else {
@@ -468,6 +478,8 @@ pub struct EventsDim0Aggregator<STY> {
     do_time_weight: bool,
     events_ignored_count: u64,
     items_seen: usize,
+    disconnected: bool,
+    gap: u64,
 }
 
 impl<STY> Drop for EventsDim0Aggregator<STY> {
@@ -502,6 +514,7 @@ impl<STY: ScalarOps> TimeAggregatorCommonV0Trait for EventsDim0Aggregator<STY> {
         //trace_ingest!("{self_name} ingest  {:6}  {:20}  {:10?}  BEFORE", i1, ts, val);
         self.last_ts = item.tss[j];
         self.last_val = Some(item.values[j].clone());
+        self.disconnected = false;
     }
 
     fn common_ingest_range(&mut self, item: &Self::Input, r: std::ops::Range<usize>) {
@@ -513,6 +526,7 @@ impl<STY: ScalarOps> TimeAggregatorCommonV0Trait for EventsDim0Aggregator<STY> {
             self.count += 1;
             self.last_ts = ts;
             self.last_val = Some(val.clone());
+            self.disconnected = false;
         }
     }
 }
@@ -536,7 +550,19 @@ impl<STY: ScalarOps> EventsDim0Aggregator<STY> {
             do_time_weight,
             events_ignored_count: 0,
             items_seen: 0,
+            disconnected: false,
+            gap: 0,
+        }
+    }
+
+    /// From `ts` on the channel has no value until the next event.
+    /// The disconnected time does not count towards the time-weighted average.
+    pub fn disconnect(&mut self, ts: u64) {
+        if self.do_time_weight && ts > self.int_ts {
+            self.apply_event_time_weight(ts);
         }
+        self.disconnected = true;
+        self.last_val = None;
     }
 
     // TODO reduce clone.. optimize via more traits to factor the trade-offs?
@@ -549,7 +575,9 @@ impl<STY: ScalarOps> EventsDim0Aggregator<STY> {
             self.sumc,
             self.minmax,
         );
-        if let Some((min, max)) = self.minmax.as_mut() {
+        if STY::last_value_binning() {
+            self.minmax = Some((val.clone(), val));
+        } else if let Some((min, max)) = self.minmax.as_mut() {
             if *min > val {
                 *min = val.clone();
             }
@@ -574,7 +602,10 @@ impl<STY: ScalarOps> EventsDim0Aggregator<STY> {
     }
 
     fn apply_event_time_weight(&mut self, px: u64) {
-        if let Some(v) = &self.last_val {
+        if self.disconnected {
+            self.gap += px.saturating_sub(self.int_ts);
+            self.int_ts = px;
+        } else if let Some(v) = &self.last_val {
             trace_ingest!("apply_event_time_weight with v {v:?}");
             let vf = v.as_prim_f32_b();
             let v2 = v.clone();
@@ -618,6 +649,7 @@ impl<STY: ScalarOps> EventsDim0Aggregator<STY> {
         self.sumc = 0;
         self.minmax = None;
         self.items_seen = 0;
+        self.gap = 0;
     }
 
     fn result_reset_unweight(&mut self, range: SeriesRange) -> BinsDim0<STY> {
@@ -626,7 +658,9 @@ impl<STY: ScalarOps> EventsDim0Aggregator<STY> {
         } else {
             (STY::zero_b(), STY::zero_b())
         };
-        let avg = if self.sumc > 0 {
+        let avg = if STY::last_value_binning() {
+            f32::NAN
+        } else if self.sumc > 0 {
             self.sum / self.sumc as f32
         } else {
             STY::zero_b().as_prim_f32_b()
@@ -668,8 +702,13 @@ impl<STY: ScalarOps> EventsDim0Aggregator<STY> {
         } else {
             (STY::zero_b(), STY::zero_b())
         };
-        let avg = if self.sumc > 0 {
-            self.sum / (self.range.delta_u64() as f32 * 1e-9)
+        let avg = if STY::last_value_binning() {
+            f32::NAN
+        } else if self.sumc > 0 {
+            let dt = self.range.delta_u64().saturating_sub(self.gap).max(1);
+            self.sum / (dt as f32 * 1e-9)
+        } else if self.disconnected && self.gap >= self.range.delta_u64() {
+            f32::NAN
         } else {
             if let Some(v) = self.last_val.as_ref() {
                 v.as_prim_f32_b()
@@ -1064,6 +1103,15 @@ impl<STY: ScalarOps> TimeBinner for EventsDim0TimeBinner<STY> {
     fn append_empty_until_end(&mut self) {
         // nothing to do for events
     }
+
+    fn ingest_disconnect(&mut self, ts: u64) {
+        while self.rng.is_some() && ts >= self.agg.range().end_u64() {
+            TimeBinnerCommonV0Func::cycle(self);
+        }
+        if self.rng.is_some() {
+            self.agg.disconnect(ts);
+        }
+    }
 }
 
 impl<STY> Appendable<STY> for EventsDim0<STY>
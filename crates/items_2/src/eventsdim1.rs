@@ -189,6 +189,7 @@ pub struct EventsDim1Collector<STY> {
     vals: EventsDim1<STY>,
     range_final: bool,
     timed_out: bool,
+    needs_continue_at: bool,
 }
 
 impl<STY> EventsDim1Collector<STY> {
@@ -201,6 +202,7 @@ impl<STY> EventsDim1Collector<STY> {
             vals: EventsDim1::empty(),
             range_final: false,
             timed_out: false,
+            needs_continue_at: false,
         }
     }
 }
@@ -345,6 +347,10 @@ impl<STY: ScalarOps> CollectorType for EventsDim1Collector<STY> {
         self.timed_out = true;
     }
 
+    fn set_continue_at_here(&mut self) {
+        self.needs_continue_at = true;
+    }
+
     // TODO unify with dim0 case
     fn result(
         &mut self,
@@ -357,7 +363,7 @@ impl<STY: ScalarOps> CollectorType for EventsDim1Collector<STY> {
         // The amount of the delta must take into account what kind of timestamp precision the client
         // can parse and handle.
         let vals = &mut self.vals;
-        let continue_at = if self.timed_out {
+        let continue_at = if self.timed_out || self.needs_continue_at {
             if let Some(ts) = vals.tss.back() {
                 Some(IsoDateTime::from_u64(*ts + MS))
             } else {
@@ -885,6 +891,21 @@ impl<STY: ScalarOps> Events for EventsDim1<STY> {
     }
 
     fn to_min_max_avg(&mut self) -> Box<dyn Events> {
+        if STY::last_value_binning() {
+            let lasts: VecDeque<_> = self
+                .values
+                .iter()
+                .map(|x| x.last().cloned().unwrap_or_else(|| STY::zero_b()))
+                .collect();
+            let item = EventsXbinDim0 {
+                tss: mem::replace(&mut self.tss, VecDeque::new()),
+                pulses: mem::replace(&mut self.pulses, VecDeque::new()),
+                mins: lasts.clone(),
+                maxs: lasts,
+                avgs: self.values.iter().map(|_| f32::NAN).collect(),
+            };
+            return Box::new(item);
+        }
         let mins = self
             .values
             .iter()
@@ -582,6 +582,15 @@ impl<STY: ScalarOps> TimeBinner for EventsXbinDim0TimeBinner<STY> {
     fn append_empty_until_end(&mut self) {
         // nothing to do for events
     }
+
+    fn ingest_disconnect(&mut self, ts: u64) {
+        while self.rng.is_some() && ts >= self.agg.range().end_u64() {
+            self.cycle();
+        }
+        if self.rng.is_some() {
+            self.agg.disconnect(ts);
+        }
+    }
 }
 
 impl<STY> TimeBinnableType for EventsXbinDim0<STY>
@@ -639,6 +648,8 @@ where
     did_min_max: bool,
     do_time_weight: bool,
     events_ignored_count: u64,
+    disconnected: bool,
+    gap: u64,
 }
 
 impl<STY> EventsXbinDim0Aggregator<STY>
@@ -664,14 +675,26 @@ where
             last_vals: None,
             events_ignored_count: 0,
             do_time_weight,
+            disconnected: false,
+            gap: 0,
         }
     }
 
+    /// From `ts` on the channel has no value until the next event.
+    /// The disconnected time does not count towards the time-weighted average.
+    pub fn disconnect(&mut self, ts: u64) {
+        if self.do_time_weight && ts > self.int_ts {
+            self.apply_event_time_weight(ts);
+        }
+        self.disconnected = true;
+        self.last_vals = None;
+    }
+
     fn apply_min_max(&mut self, min: &STY, max: &STY) {
         if self.did_min_max != (self.sumc > 0) {
             panic!("logic error apply_min_max  {}  {}", self.did_min_max, self.sumc);
         }
-        if self.sumc == 0 {
+        if self.sumc == 0 || STY::last_value_binning() {
             self.did_min_max = true;
             self.min = min.clone();
             self.max = max.clone();
@@ -705,7 +728,10 @@ where
             self.sumc,
             self.events_ignored_count
         );
-        if let Some((min, max, avg)) = self.last_vals.as_ref() {
+        if self.disconnected {
+            self.gap += px.saturating_sub(self.int_ts);
+            self.int_ts = px;
+        } else if let Some((min, max, avg)) = self.last_vals.as_ref() {
             let vf = *avg;
             {
                 let min = min.clone();
@@ -765,10 +791,12 @@ where
                 self.count += 1;
                 self.last_ts = ts;
                 self.last_vals = Some((min.clone(), max.clone(), avg.clone()));
+                self.disconnected = false;
             } else {
                 self.events_ignored_count += 1;
                 self.last_ts = ts;
                 self.last_vals = Some((min.clone(), max.clone(), avg.clone()));
+                self.disconnected = false;
             }
         }
     }
@@ -810,7 +838,12 @@ where
         let range_beg = self.range.beg_u64();
         let range_end = self.range.end_u64();
         let (min, max, avg) = if self.sumc > 0 {
-            let avg = self.sum / (self.range.delta_u64() as f32 * 1e-9);
+            let avg = if STY::last_value_binning() {
+                f32::NAN
+            } else {
+                let dt = self.range.delta_u64().saturating_sub(self.gap).max(1);
+                self.sum / (dt as f32 * 1e-9)
+            };
             (self.min.clone(), self.max.clone(), avg)
         } else {
             let (min, max, avg) = match &self.last_vals {
@@ -818,6 +851,7 @@ where
                     warn!("\n\n\n!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!!   SHOULD ALWAYS HAVE ACCUMULATED IN THIS CASE");
                     (min.clone(), max.clone(), avg.clone())
                 }
+                None if self.disconnected => (STY::zero_b(), STY::zero_b(), f32::NAN),
                 None => (STY::zero_b(), STY::zero_b(), 0.),
             };
             (min, max, avg)
@@ -838,6 +872,7 @@ where
         self.did_min_max = false;
         self.min = STY::zero_b();
         self.max = STY::zero_b();
+        self.gap = 0;
         ret
     }
 }
@@ -979,6 +1014,8 @@ where
         self.timed_out = true;
     }
 
+    fn set_continue_at_here(&mut self) {}
+
     fn result(
         &mut self,
         range: Option<SeriesRange>,
@@ -21,6 +21,9 @@ use serde::Deserialize;
 use serde::Serialize;
 
 pub const INMEM_FRAME_ENCID: u32 = 0x12121212;
+/// The payload starts with its uncompressed length as `u32`, followed by the compressed payload.
+pub const INMEM_FRAME_ENCID_LZ4: u32 = 0x12121213;
+pub const INMEM_FRAME_ENCID_ZSTD: u32 = 0x12121214;
 pub const INMEM_FRAME_HEAD: usize = 20;
 pub const INMEM_FRAME_FOOT: usize = 4;
 pub const INMEM_FRAME_MAGIC: u32 = 0xc6c3b73d;
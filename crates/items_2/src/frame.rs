@@ -1,5 +1,7 @@
 use crate::framable::FrameDecodable;
 use crate::framable::INMEM_FRAME_ENCID;
+use crate::framable::INMEM_FRAME_ENCID_LZ4;
+use crate::framable::INMEM_FRAME_ENCID_ZSTD;
 use crate::framable::INMEM_FRAME_FOOT;
 use crate::framable::INMEM_FRAME_HEAD;
 use crate::framable::INMEM_FRAME_MAGIC;
@@ -12,6 +14,7 @@ use bincode::config::WithOtherIntEncoding;
 use bincode::config::WithOtherTrailing;
 use bincode::DefaultOptions;
 use bytes::BufMut;
+use bytes::Bytes;
 use bytes::BytesMut;
 use err::Error;
 use items_0::bincode;
@@ -23,10 +26,15 @@ use items_0::streamitem::RANGE_COMPLETE_FRAME_TYPE_ID;
 use items_0::streamitem::STATS_FRAME_TYPE_ID;
 use items_0::streamitem::TERM_FRAME_TYPE_ID;
 use netpod::log::*;
+use netpod::FrameCompression;
+use netpod::FrameCompressionAlgo;
 use serde::Serialize;
 use std::any;
 use std::io;
 
+/// Upper limit of the payload of a compressed frame after decompression.
+const FRAME_DECOMPRESSED_MAX: usize = 1024 * 1024 * 256;
+
 trait EC {
     fn ec(self) -> err::Error;
 }
@@ -315,10 +323,109 @@ pub fn make_term_frame() -> Result<BytesMut, Error> {
     Ok(buf)
 }
 
+fn make_frame_encid(encid: u32, tyid: u32, parts: &[&[u8]]) -> BytesMut {
+    let len: usize = parts.iter().map(|x| x.len()).sum();
+    let mut h = crc32fast::Hasher::new();
+    for x in parts {
+        h.update(x);
+    }
+    let payload_crc = h.finalize();
+    let mut buf = BytesMut::with_capacity(INMEM_FRAME_HEAD + INMEM_FRAME_FOOT + len);
+    buf.put_u32_le(INMEM_FRAME_MAGIC);
+    buf.put_u32_le(encid);
+    buf.put_u32_le(tyid);
+    buf.put_u32_le(len as u32);
+    buf.put_u32_le(payload_crc);
+    for x in parts {
+        buf.put(*x);
+    }
+    let mut h = crc32fast::Hasher::new();
+    h.update(&buf);
+    let frame_crc = h.finalize();
+    buf.put_u32_le(frame_crc);
+    buf
+}
+
+/// Compresses the payload of a complete frame if it is at least as large as the threshold
+/// and becomes smaller by it. Otherwise returns the frame as it is.
+pub fn compress_frame(frame: BytesMut, comp: &FrameCompression) -> Result<BytesMut, Error> {
+    if frame.len() < INMEM_FRAME_HEAD + INMEM_FRAME_FOOT {
+        return Ok(frame);
+    }
+    let encid = u32::from_le_bytes(frame[4..8].try_into()?);
+    let tyid = u32::from_le_bytes(frame[8..12].try_into()?);
+    let len = u32::from_le_bytes(frame[12..16].try_into()?) as usize;
+    if encid != INMEM_FRAME_ENCID
+        || len < comp.threshold as usize
+        || frame.len() != INMEM_FRAME_HEAD + INMEM_FRAME_FOOT + len
+    {
+        return Ok(frame);
+    }
+    let payload = &frame[INMEM_FRAME_HEAD..INMEM_FRAME_HEAD + len];
+    let (encid, enc) = match comp.algo {
+        FrameCompressionAlgo::Lz4 => (INMEM_FRAME_ENCID_LZ4, lz4_flex::block::compress(payload)),
+        FrameCompressionAlgo::Zstd => {
+            let level = comp.level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL);
+            (INMEM_FRAME_ENCID_ZSTD, zstd::bulk::compress(payload, level)?)
+        }
+    };
+    if 4 + enc.len() >= len {
+        return Ok(frame);
+    }
+    Ok(make_frame_encid(encid, tyid, &[&(len as u32).to_le_bytes(), &enc]))
+}
+
+pub fn is_compressed_encid(encid: u32) -> bool {
+    encid == INMEM_FRAME_ENCID_LZ4 || encid == INMEM_FRAME_ENCID_ZSTD
+}
+
+/// The frame with its payload decompressed, if it was compressed by `compress_frame`.
+pub fn decompress_frame(frame: InMemoryFrame) -> Result<InMemoryFrame, Error> {
+    if !is_compressed_encid(frame.encid()) {
+        return Ok(frame);
+    }
+    let buf = frame.buf();
+    if buf.len() < 4 {
+        return Err(Error::with_msg_no_trace(format!("compressed frame too short {:?}", frame)));
+    }
+    let n = u32::from_le_bytes(buf[0..4].try_into()?) as usize;
+    if n > FRAME_DECOMPRESSED_MAX {
+        return Err(Error::with_msg_no_trace(format!("compressed frame too large {n}  {:?}", frame)));
+    }
+    let dec = if frame.encid() == INMEM_FRAME_ENCID_LZ4 {
+        lz4_flex::block::decompress(&buf[4..], n).map_err(|e| Error::with_msg_no_trace(format!("lz4 {e}")))?
+    } else {
+        zstd::bulk::decompress(&buf[4..], n)?
+    };
+    if dec.len() != n {
+        return Err(Error::with_msg_no_trace(format!(
+            "decompressed {} instead of {n}  {:?}",
+            dec.len(),
+            frame
+        )));
+    }
+    let ret = InMemoryFrame {
+        encid: INMEM_FRAME_ENCID,
+        tyid: frame.tyid(),
+        len: n as u32,
+        buf: Bytes::from(dec),
+    };
+    Ok(ret)
+}
+
 pub fn decode_frame<T>(frame: &InMemoryFrame) -> Result<T, Error>
 where
     T: FrameDecodable,
 {
+    if is_compressed_encid(frame.encid()) {
+        let frame = InMemoryFrame {
+            encid: frame.encid(),
+            tyid: frame.tyid(),
+            len: frame.len(),
+            buf: frame.buf().clone(),
+        };
+        return decode_frame(&decompress_frame(frame)?);
+    }
     if frame.encid() != INMEM_FRAME_ENCID {
         return Err(Error::with_msg(format!("unknown encoder id {:?}", frame)));
     }
@@ -1,5 +1,11 @@
 #[cfg(test)]
+pub mod eventfull;
+#[cfg(test)]
 pub mod eventsdim0;
+#[cfg(test)]
+pub mod eventsxbindim0;
+#[cfg(test)]
+pub mod frame;
 
 use crate::binnedcollected::BinnedCollected;
 use crate::binsdim0::BinsDim0CollectedResult;
@@ -0,0 +1,41 @@
+use crate::eventfull::EventFull;
+use bitshuffle::bitshuffle_compress;
+use items_0::Empty;
+use netpod::ScalarType;
+use netpod::Shape;
+use parse::channelconfig::CompressionMethod;
+
+fn compressed_blob(vals: &[i32]) -> Vec<u8> {
+    let inp: Vec<u8> = vals.iter().flat_map(|x| x.to_be_bytes()).collect();
+    let mut out = vec![0; 1024 + 2 * inp.len()];
+    let n = bitshuffle_compress(&inp, &mut out, vals.len(), 4, 0).unwrap();
+    let mut blob = Vec::new();
+    blob.extend_from_slice(&(inp.len() as u64).to_be_bytes());
+    blob.extend_from_slice(&0u32.to_be_bytes());
+    blob.extend_from_slice(&out[..n]);
+    blob
+}
+
+#[test]
+fn decompress_all_00() {
+    let vals: Vec<i32> = (0..64).map(|x| x * 3 - 7).collect();
+    let mut evs = EventFull::empty();
+    let scalar_type = ScalarType::I32;
+    let shape = Shape::Wave(vals.len() as u32);
+    evs.push(
+        10,
+        1,
+        compressed_blob(&vals),
+        scalar_type.clone(),
+        true,
+        shape.clone(),
+        Some(CompressionMethod::BitshuffleLZ4),
+    );
+    evs.push(11, 2, vec![1, 2, 3, 4], scalar_type, true, shape, None);
+    evs.decompress_all().unwrap();
+    let exp: Vec<u8> = vals.iter().flat_map(|x| x.to_be_bytes()).collect();
+    assert_eq!(evs.blobs[0], exp);
+    assert_eq!(evs.blobs[1], vec![1, 2, 3, 4]);
+    assert!(evs.comps.iter().all(Option::is_none));
+    assert_eq!(evs.entry_payload_max, exp.len() as u64);
+}
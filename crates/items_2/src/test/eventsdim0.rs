@@ -22,3 +22,49 @@ fn collect_c_00() {
     coll.ingest(&mut evs);
     assert_eq!(coll.len(), 2);
 }
+
+#[test]
+fn bin_string_last_value() {
+    use crate::eventsdim0::EventsDim0Aggregator;
+    use crate::TimeBinnableTypeAggregator;
+    use netpod::range::evrange::NanoRange;
+    use netpod::range::evrange::SeriesRange;
+    let range = SeriesRange::TimeRange(NanoRange { beg: 100, end: 200 });
+    let mut agg = EventsDim0Aggregator::<String>::new(range, true);
+    let mut evs = EventsDim0::empty();
+    evs.push(90, 1, "init".to_string());
+    evs.push(120, 2, "zzz".to_string());
+    evs.push(150, 3, "aaa".to_string());
+    agg.ingest(&evs);
+    let bins = agg.result_reset(SeriesRange::TimeRange(NanoRange { beg: 200, end: 300 }));
+    assert_eq!(bins.counts[0], 2);
+    assert_eq!(bins.mins[0], "aaa");
+    assert_eq!(bins.maxs[0], "aaa");
+    assert!(bins.avgs[0].is_nan());
+}
+
+#[test]
+fn bin_time_weight_disconnect() {
+    use crate::eventsdim0::EventsDim0Aggregator;
+    use crate::TimeBinnableTypeAggregator;
+    use netpod::range::evrange::NanoRange;
+    use netpod::range::evrange::SeriesRange;
+    let range = SeriesRange::TimeRange(NanoRange { beg: 1000, end: 2000 });
+    let mut agg = EventsDim0Aggregator::<f32>::new(range, true);
+    let mut evs = EventsDim0::empty();
+    evs.push(900, 1, 2.0);
+    agg.ingest(&evs);
+    agg.disconnect(1200);
+    let mut evs = EventsDim0::empty();
+    evs.push(1600, 2, 4.0);
+    agg.ingest(&evs);
+    let bins = agg.result_reset(SeriesRange::TimeRange(NanoRange { beg: 2000, end: 3000 }));
+    assert_eq!(bins.counts[0], 1);
+    assert!((bins.avgs[0] - 10. / 3.).abs() < 1e-3, "avg {}", bins.avgs[0]);
+    agg.disconnect(2100);
+    let bins = agg.result_reset(SeriesRange::TimeRange(NanoRange { beg: 3000, end: 4000 }));
+    assert!((bins.avgs[0] - 4.).abs() < 1e-3, "avg {}", bins.avgs[0]);
+    let bins = agg.result_reset(SeriesRange::TimeRange(NanoRange { beg: 4000, end: 5000 }));
+    assert_eq!(bins.counts[0], 0);
+    assert!(bins.avgs[0].is_nan());
+}
@@ -0,0 +1,29 @@
+use crate::eventsxbindim0::EventsXbinDim0;
+use crate::eventsxbindim0::EventsXbinDim0Aggregator;
+use crate::TimeBinnableTypeAggregator;
+use items_0::timebin::TimeBinned;
+use items_0::Empty;
+use netpod::range::evrange::NanoRange;
+use netpod::range::evrange::SeriesRange;
+
+#[test]
+fn bin_time_weight_disconnect() {
+    let range = SeriesRange::TimeRange(NanoRange { beg: 1000, end: 2000 });
+    let mut agg = EventsXbinDim0Aggregator::<f32>::new(range, true);
+    let mut evs = EventsXbinDim0::empty();
+    evs.push(900, 1, 1.0, 3.0, 2.0);
+    agg.ingest(&evs);
+    agg.disconnect(1200);
+    let mut evs = EventsXbinDim0::empty();
+    evs.push(1600, 2, 3.0, 5.0, 4.0);
+    agg.ingest(&evs);
+    let bins = agg.result_reset(SeriesRange::TimeRange(NanoRange { beg: 2000, end: 3000 }));
+    assert_eq!(bins.counts()[0], 1);
+    assert!((bins.avgs()[0] - 10. / 3.).abs() < 1e-3, "avg {}", bins.avgs()[0]);
+    agg.disconnect(2100);
+    let bins = agg.result_reset(SeriesRange::TimeRange(NanoRange { beg: 3000, end: 4000 }));
+    assert!((bins.avgs()[0] - 4.).abs() < 1e-3, "avg {}", bins.avgs()[0]);
+    let bins = agg.result_reset(SeriesRange::TimeRange(NanoRange { beg: 4000, end: 5000 }));
+    assert_eq!(bins.counts()[0], 0);
+    assert!(bins.avgs()[0].is_nan());
+}
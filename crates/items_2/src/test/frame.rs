@@ -0,0 +1,45 @@
+use crate::framable::INMEM_FRAME_ENCID;
+use crate::framable::INMEM_FRAME_FOOT;
+use crate::framable::INMEM_FRAME_HEAD;
+use crate::frame::compress_frame;
+use crate::frame::decompress_frame;
+use crate::frame::is_compressed_encid;
+use crate::frame::make_frame_2;
+use crate::inmem::InMemoryFrame;
+use bytes::Bytes;
+use err::Error;
+use netpod::FrameCompression;
+use netpod::FrameCompressionAlgo;
+
+#[test]
+fn compress_decompress() -> Result<(), Error> {
+    let item = vec![7u64; 2000];
+    let frame = make_frame_2(&item, 0x4242)?;
+    for algo in [FrameCompressionAlgo::Lz4, FrameCompressionAlgo::Zstd] {
+        let comp = FrameCompression {
+            algo,
+            threshold: 1024,
+            level: None,
+        };
+        let buf = compress_frame(frame.clone(), &comp)?;
+        assert!(buf.len() < frame.len() / 4);
+        let len = u32::from_le_bytes(buf[12..16].try_into()?);
+        let frame2 = InMemoryFrame {
+            encid: u32::from_le_bytes(buf[4..8].try_into()?),
+            tyid: 0x4242,
+            len,
+            buf: Bytes::copy_from_slice(&buf[INMEM_FRAME_HEAD..INMEM_FRAME_HEAD + len as usize]),
+        };
+        assert!(is_compressed_encid(frame2.encid()));
+        let frame2 = decompress_frame(frame2)?;
+        assert_eq!(frame2.encid(), INMEM_FRAME_ENCID);
+        assert_eq!(&frame2.buf()[..], &frame[INMEM_FRAME_HEAD..frame.len() - INMEM_FRAME_FOOT]);
+    }
+    let comp = FrameCompression {
+        algo: FrameCompressionAlgo::Lz4,
+        threshold: 1024 * 1024,
+        level: None,
+    };
+    assert_eq!(compress_frame(frame.clone(), &comp)?, frame);
+    Ok(())
+}
@@ -41,7 +41,9 @@ pub const APP_JSON: &str = "application/json";
 pub const APP_JSON_LINES: &str = "application/jsonlines";
 pub const APP_OCTET: &str = "application/octet-stream";
 pub const ACCEPT_ALL: &str = "*/*";
+pub const TEXT_CSV: &str = "text/csv";
 pub const X_DAQBUF_REQID: &str = "x-daqbuffer-request-id";
+pub const X_DAQBUF_CACHE: &str = "x-daqbuffer-cache";
 
 pub const CONNECTION_STATUS_DIV: u64 = timeunits::DAY;
 pub const TS_MSP_GRID_UNIT: u64 = timeunits::SEC * 10;
@@ -366,6 +368,9 @@ pub struct SfDatabuffer {
     pub data_base_path: PathBuf,
     pub ksprefix: String,
     pub splits: Option<Vec<u64>>,
+    /// Local pulse map index of the timer channels, used instead of the database if given.
+    #[serde(default)]
+    pub pulse_index: Option<PathBuf>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -478,6 +483,7 @@ impl Node {
                 data_base_path: PathBuf::new(),
                 ksprefix: "daqlocal".into(),
                 splits: None,
+                pulse_index: None,
             }),
             archiver_appliance: None,
             channel_archiver: None,
@@ -515,6 +521,60 @@ pub struct Database {
 pub struct ScyllaConfig {
     pub hosts: Vec<String>,
     pub keyspace: String,
+    /// Number of rows fetched per page when reading events.
+    #[serde(rename = "pageSize", default)]
+    pub page_size: Option<u32>,
+    /// Number of `ts_msp` partitions of a series which are read at the same time.
+    #[serde(rename = "partitionsConcurrent", default)]
+    pub partitions_concurrent: Option<u32>,
+}
+
+/// A backend whose channels and events are held in memory, optionally loaded from a JSON file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct JsonBackend {
+    pub backend: String,
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+}
+
+/// The channel which serves an alias from `beg` until `end`, or without end if `end` is not given.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelAliasSegment {
+    pub channel: String,
+    #[serde(rename = "tsBeg", default)]
+    pub beg: u64,
+    #[serde(rename = "tsEnd", default, skip_serializing_if = "Option::is_none")]
+    pub end: Option<u64>,
+}
+
+/// A channel name which stands for other channels over time, for example for a PV which was renamed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ChannelAlias {
+    pub backend: String,
+    pub name: String,
+    pub segments: Vec<ChannelAliasSegment>,
+}
+
+impl ChannelAlias {
+    /// The underlying channels within the range, in time order, each with the part of the range it serves.
+    /// Where segments overlap, the later one wins.
+    pub fn segments_in(&self, range: &NanoRange) -> Vec<(&str, NanoRange)> {
+        let mut segs: Vec<_> = self.segments.iter().collect();
+        segs.sort_by_key(|x| x.beg);
+        let mut ret = Vec::new();
+        for (i, seg) in segs.iter().enumerate() {
+            let mut end = seg.end.unwrap_or(u64::MAX);
+            if let Some(next) = segs.get(i + 1) {
+                end = end.min(next.beg);
+            }
+            let beg = seg.beg.max(range.beg);
+            let end = end.min(range.end);
+            if beg < end {
+                ret.push((seg.channel.as_str(), NanoRange { beg, end }));
+            }
+        }
+        ret
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -530,6 +590,20 @@ pub struct Cluster {
     pub file_io_buffer_size: FileIoBufferSize,
     pub scylla: Option<ScyllaConfig>,
     pub cache_scylla: Option<ScyllaConfig>,
+    #[serde(rename = "jsonBackends", default)]
+    pub json_backends: Vec<JsonBackend>,
+    #[serde(rename = "channelAliases", default)]
+    pub channel_aliases: Vec<ChannelAlias>,
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    #[serde(rename = "rateLimits", default)]
+    pub rate_limits: Option<RateLimitConfig>,
+    #[serde(rename = "queryBudget", default)]
+    pub query_budget: Option<QueryBudget>,
+    #[serde(default)]
+    pub download: Option<DownloadConfig>,
+    #[serde(rename = "nodeNet", default)]
+    pub node_net: Option<NodeNetConfig>,
 }
 
 impl Cluster {
@@ -540,6 +614,263 @@ impl Cluster {
             true
         }
     }
+
+    /// Whether the backend is served from a JSON file instead of the storage of the node.
+    pub fn is_json_backend(&self, backend: &str) -> bool {
+        self.json_backends.iter().any(|x| x.backend == backend)
+    }
+}
+
+/// Authentication and authorization of http requests. Without it, every request is allowed.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub tokens: Vec<AuthToken>,
+    /// JSON web key set file to validate JWT bearer tokens against.
+    pub jwks: Option<PathBuf>,
+    #[serde(rename = "jwtIssuer")]
+    pub jwt_issuer: Option<String>,
+    #[serde(rename = "jwtAudience")]
+    pub jwt_audience: Option<String>,
+    /// Claim of the JWT which lists the roles of the caller, `roles` if not given.
+    #[serde(rename = "jwtRolesClaim")]
+    pub jwt_roles_claim: Option<String>,
+    /// Roles of requests which come without credentials.
+    #[serde(rename = "anonymousRoles")]
+    pub anonymous_roles: Vec<String>,
+    pub rules: Vec<AuthRule>,
+    /// Token which the nodes of a cluster present to each other. It grants everything.
+    /// Subqueries on the raw events port are refused without it.
+    #[serde(rename = "nodeToken")]
+    pub node_token: Option<String>,
+}
+
+impl AuthConfig {
+    /// Whether `token` is the node token. False if no node token is configured.
+    pub fn is_node_token(&self, token: &str) -> bool {
+        self.node_token.as_ref().is_some_and(|x| token_eq(token, x))
+    }
+}
+
+/// Compares in time independent of where the tokens differ.
+/// Takes the same time for all tokens of the same length.
+pub fn token_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// A static bearer token.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthToken {
+    pub token: String,
+    pub subject: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+}
+
+/// Grants a role access to channels and, if `admin` is set, to the endpoints which maintain the service.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuthRule {
+    pub role: String,
+    /// The backends the rule applies to, all backends if empty.
+    #[serde(default)]
+    pub backends: Vec<String>,
+    /// All channels if not given.
+    #[serde(default, rename = "channelPattern")]
+    pub channel_pattern: Option<ChannelPattern>,
+    #[serde(default)]
+    pub admin: bool,
+}
+
+/// Regular expression which the whole channel name has to match, compiled when the config is read.
+#[derive(Clone, Debug)]
+pub struct ChannelPattern {
+    src: String,
+    re: regex::Regex,
+}
+
+impl ChannelPattern {
+    pub fn new(src: &str) -> Result<Self, Error> {
+        let re = regex::Regex::new(&format!("^(?:{src})$"))
+            .map_err(|e| Error::with_msg_no_trace(format!("invalid channel pattern {src:?}: {e}")))?;
+        let ret = Self { src: src.into(), re };
+        Ok(ret)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.src
+    }
+
+    pub fn is_match(&self, channel: &str) -> bool {
+        self.re.is_match(channel)
+    }
+}
+
+impl Serialize for ChannelPattern {
+    fn serialize<S>(&self, ser: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        ser.serialize_str(&self.src)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChannelPattern {
+    fn deserialize<D>(de: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let s = String::deserialize(de)?;
+        Self::new(&s).map_err(serde::de::Error::custom)
+    }
+}
+
+/// Limits per client. A client is the authenticated subject, or else the address of the caller.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimitConfig {
+    /// Sustained rate of requests. Bursts of up to one second worth of requests are allowed.
+    pub requests_per_second: Option<f64>,
+    /// Requests served at the same time, until the response body is transferred.
+    pub concurrent: Option<u32>,
+    pub bytes_per_minute: Option<u64>,
+    /// Clients which are not limited, by address or subject.
+    pub exempt: Vec<String>,
+    /// Addresses of proxies whose requests are limited by the client address in `X-Forwarded-For`.
+    /// Their requests without that header are not limited.
+    pub trusted_proxies: Vec<String>,
+}
+
+/// Raw file download for experts. Only served if `auth` is configured as well, and only to admins.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DownloadConfig {
+    /// Directories whose files may be downloaded, by their absolute path.
+    pub roots: Vec<PathBuf>,
+}
+
+/// How the nodes of a cluster request events from each other.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NodeNetConfig {
+    /// Carry all subqueries to a node over one persistent tcp connection instead of one http request each.
+    pub mux: bool,
+    /// Bytes a node may send for a subquery before it waits for the requester to take them.
+    #[serde(rename = "creditWindow")]
+    pub credit_window: Option<u32>,
+    /// Compression which the nodes are asked to apply to the frames they send.
+    pub compression: Option<FrameCompression>,
+    /// Subqueries which a node serves at the same time on one multiplexed connection.
+    #[serde(rename = "substreamsMax")]
+    pub substreams_max: Option<u32>,
+}
+
+impl NodeNetConfig {
+    pub const CREDIT_WINDOW_DEFAULT: u32 = 1024 * 1024 * 4;
+    pub const SUBSTREAMS_MAX_DEFAULT: u32 = 256;
+
+    pub fn credit_window(&self) -> u32 {
+        self.credit_window.unwrap_or(Self::CREDIT_WINDOW_DEFAULT).max(1024)
+    }
+
+    pub fn substreams_max(&self) -> u32 {
+        self.substreams_max.unwrap_or(Self::SUBSTREAMS_MAX_DEFAULT).max(1)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrameCompressionAlgo {
+    Lz4,
+    Zstd,
+}
+
+/// Compression of the frames which a node sends in response to a subquery.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrameCompression {
+    pub algo: FrameCompressionAlgo,
+    /// Frames with a smaller payload are sent as they are.
+    #[serde(default = "FrameCompression::threshold_default")]
+    pub threshold: u32,
+    /// Only used by zstd.
+    #[serde(default)]
+    pub level: Option<i32>,
+}
+
+impl FrameCompression {
+    fn threshold_default() -> u32 {
+        1024 * 4
+    }
+}
+
+/// Upper limits of the estimated cost of an event query.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryBudget {
+    pub max_events: Option<u64>,
+    pub max_bytes: Option<u64>,
+    pub max_files: Option<u64>,
+    pub max_ts_msp_partitions: Option<u64>,
+    /// Refuse queries over budget. Otherwise they are limited to `max_events` and continue via `continueAt`.
+    pub reject: bool,
+}
+
+/// Estimated cost of an event query.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct QueryCost {
+    pub timebins: u64,
+    pub files: u64,
+    pub bytes: u64,
+    pub events: u64,
+    #[serde(rename = "tsMspPartitions")]
+    pub ts_msp_partitions: u64,
+}
+
+impl QueryCost {
+    /// Adds the cost of another node or segment. Nodes share the timebins, they are not added.
+    pub fn merge(&mut self, other: &QueryCost) {
+        self.timebins = self.timebins.max(other.timebins);
+        self.files += other.files;
+        self.bytes += other.bytes;
+        self.events += other.events;
+        self.ts_msp_partitions += other.ts_msp_partitions;
+    }
+
+    /// Describes the first limit of the budget which is exceeded.
+    pub fn exceeds(&self, budget: &QueryBudget) -> Option<String> {
+        let checks = [
+            ("events", self.events, budget.max_events),
+            ("bytes", self.bytes, budget.max_bytes),
+            ("files", self.files, budget.max_files),
+            (
+                "ts_msp partitions",
+                self.ts_msp_partitions,
+                budget.max_ts_msp_partitions,
+            ),
+        ];
+        checks.into_iter().find_map(|(name, have, max)| match max {
+            Some(max) if have > max => Some(format!("estimated {name} {have} exceed the budget of {max}")),
+            _ => None,
+        })
+    }
+
+    /// How many events a query can return within the budget, assuming that the cost grows with the events.
+    /// `None` if the events are not estimated.
+    pub fn events_within(&self, budget: &QueryBudget) -> Option<u64> {
+        if self.events == 0 {
+            return budget.max_events;
+        }
+        let limits = [
+            (self.events, budget.max_events),
+            (self.bytes, budget.max_bytes),
+            (self.files, budget.max_files),
+            (self.ts_msp_partitions, budget.max_ts_msp_partitions),
+        ];
+        let frac = limits
+            .into_iter()
+            .filter_map(|(have, max)| max.filter(|_| have > 0).map(|max| max as f64 / have as f64))
+            .fold(1., f64::min);
+        Some(((self.events as f64 * frac) as u64).max(1))
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -645,6 +976,21 @@ pub struct NodeStatus {
     pub archiver_appliance_status: Option<NodeStatusArchiverAppliance>,
     #[serde(default, skip_serializing_if = "VecDeque::is_empty")]
     pub subs: VecDeque<NodeStatusSub>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backend_endpoints: Vec<ProxyEndpointStatus>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub client_usage: Vec<ClientUsage>,
+}
+
+/// What a client currently uses of its limits.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClientUsage {
+    pub client: String,
+    pub running: u32,
+    pub requests: u64,
+    /// Requests which were refused because a limit was reached.
+    pub rejected: u64,
+    pub bytes_last_minute: u64,
 }
 
 // Describes a swissfel-databuffer style "channel" which is a time-series with a unique name within a "backend".
@@ -2312,6 +2658,81 @@ impl ReadExactStats {
     }
 }
 
+/// Read statistics of one split of a timebin, emitted when the reader of that split is done.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SplitReadStats {
+    pub split: u32,
+    pub timebin: u64,
+    pub events: u64,
+    pub parsed_bytes: u64,
+    pub duration: Duration,
+    /// Time the reader waited because the read-ahead queue was full.
+    pub stalled: Duration,
+}
+
+impl SplitReadStats {
+    pub fn new(split: u32, timebin: u64) -> Self {
+        Self {
+            split,
+            timebin,
+            events: 0,
+            parsed_bytes: 0,
+            duration: Duration::ZERO,
+            stalled: Duration::ZERO,
+        }
+    }
+}
+
+/// Read statistics of one `ts_msp` partition of a scylla series.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScyllaPartitionReadStats {
+    pub ts_msp: u64,
+    pub rows: u64,
+    pub pages: u32,
+    pub duration: Duration,
+}
+
+impl ScyllaPartitionReadStats {
+    pub fn new(ts_msp: u64) -> Self {
+        Self {
+            ts_msp,
+            rows: 0,
+            pages: 0,
+            duration: Duration::ZERO,
+        }
+    }
+}
+
+/// Compressed frames received from a node, emitted when its stream ends.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct FrameCompressionStats {
+    pub frames: u64,
+    pub frames_compressed: u64,
+    /// Bytes of the frames as received.
+    pub bytes_wire: u64,
+    /// Bytes of the frames after decompression.
+    pub bytes_raw: u64,
+}
+
+impl FrameCompressionStats {
+    /// Uncompressed over received bytes.
+    pub fn ratio(&self) -> f64 {
+        if self.bytes_wire == 0 {
+            1.
+        } else {
+            self.bytes_raw as f64 / self.bytes_wire as f64
+        }
+    }
+
+    /// Sum up the stats of several streams, e.g. one per node.
+    pub fn add(&mut self, other: &Self) {
+        self.frames += other.frames;
+        self.frames_compressed += other.frames_compressed;
+        self.bytes_wire += other.bytes_wire;
+        self.bytes_raw += other.bytes_raw;
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Api1WarningStats {
     pub subreq_fail: usize,
@@ -2370,6 +2791,7 @@ pub enum ReadSys {
     Read4,
     Read5,
     BlockingTaskIntoChannel,
+    IoUring,
 }
 
 impl ReadSys {
@@ -2392,6 +2814,8 @@ impl From<&str> for ReadSys {
             Self::Read5
         } else if k == "BlockingTaskIntoChannel" {
             Self::BlockingTaskIntoChannel
+        } else if k == "IoUring" {
+            Self::IoUring
         } else {
             Self::default()
         }
@@ -2463,12 +2887,22 @@ impl FromUrl for DiskIoTune {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct ChannelSearchQuery {
     pub backend: Option<String>,
     pub name_regex: String,
     pub source_regex: String,
     pub description_regex: String,
+    #[serde(default)]
+    pub scalar_type: Option<ScalarType>,
+    #[serde(default)]
+    pub shape_rank: Option<u32>,
+    #[serde(default)]
+    pub unit: Option<String>,
+    #[serde(default)]
+    pub offset: usize,
+    #[serde(default)]
+    pub limit: Option<usize>,
 }
 
 impl ChannelSearchQuery {
@@ -2479,6 +2913,14 @@ impl ChannelSearchQuery {
             name_regex: pairs.get("nameRegex").map_or(String::new(), |k| k.clone()),
             source_regex: pairs.get("sourceRegex").map_or(String::new(), |k| k.clone()),
             description_regex: pairs.get("descriptionRegex").map_or(String::new(), |k| k.clone()),
+            scalar_type: pairs
+                .get("scalarType")
+                .map(|x| ScalarType::from_url_str(x))
+                .transpose()?,
+            shape_rank: pairs.get("shapeRank").map(|x| x.parse()).transpose()?,
+            unit: pairs.get("unit").map(Into::into),
+            offset: pairs.get("offset").map_or(Ok(0), |x| x.parse())?,
+            limit: pairs.get("limit").map(|x| x.parse()).transpose()?,
         };
         Ok(ret)
     }
@@ -2491,6 +2933,125 @@ impl ChannelSearchQuery {
         qp.append_pair("nameRegex", &self.name_regex);
         qp.append_pair("sourceRegex", &self.source_regex);
         qp.append_pair("descriptionRegex", &self.description_regex);
+        if let Some(v) = &self.scalar_type {
+            qp.append_pair("scalarType", v.to_variant_str());
+        }
+        if let Some(v) = &self.shape_rank {
+            qp.append_pair("shapeRank", &v.to_string());
+        }
+        if let Some(v) = &self.unit {
+            qp.append_pair("unit", v);
+        }
+        if self.offset != 0 {
+            qp.append_pair("offset", &self.offset.to_string());
+        }
+        if let Some(v) = &self.limit {
+            qp.append_pair("limit", &v.to_string());
+        }
+    }
+
+    /// The query to send to each backend when the pages of several backends are merged:
+    /// each backend must deliver its best ranked results up to the end of the requested page.
+    pub fn for_sub_backend(&self) -> Self {
+        Self {
+            backend: self.backend.clone(),
+            name_regex: self.name_regex.clone(),
+            source_regex: self.source_regex.clone(),
+            description_regex: self.description_regex.clone(),
+            scalar_type: self.scalar_type.clone(),
+            shape_rank: self.shape_rank,
+            unit: self.unit.clone(),
+            offset: 0,
+            limit: self.limit.map(|x| self.offset + x),
+        }
+    }
+
+    /// Whether the channel passes the filters on backend, scalar type, shape rank and unit.
+    pub fn filter_matches(&self, ch: &ChannelSearchSingleResult) -> bool {
+        if let Some(x) = &self.backend {
+            if &ch.backend != x {
+                return false;
+            }
+        }
+        if let Some(x) = &self.scalar_type {
+            let ty = ch.ty.to_lowercase();
+            if ty != x.to_variant_str() && ty != x.to_bsread_str() {
+                return false;
+            }
+        }
+        if let Some(x) = self.shape_rank {
+            if ch.shape.len() as u32 != x {
+                return false;
+            }
+        }
+        if let Some(x) = &self.unit {
+            if !ch.unit.eq_ignore_ascii_case(x) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Lower is better: exact name matches come first, then prefix matches, then names which
+    /// contain the searched text, then all other matches of the regex.
+    pub fn rank(&self, ch: &ChannelSearchSingleResult) -> u8 {
+        let needle = self.rank_needle();
+        if needle.is_empty() {
+            return 3;
+        }
+        let name = ch.name.to_lowercase();
+        if name == needle {
+            0
+        } else if name.starts_with(&needle) {
+            1
+        } else if name.contains(&needle) {
+            2
+        } else {
+            3
+        }
+    }
+
+    /// The text which `rank` looks for in the names.
+    pub fn rank_needle(&self) -> String {
+        self.name_regex
+            .trim_start_matches('^')
+            .trim_end_matches('$')
+            .to_lowercase()
+    }
+
+    /// Filters and ranks the matches of a backend and cuts out the requested page.
+    /// Equal ranks are ordered by name and backend so that pages are stable.
+    pub fn rank_and_page(&self, channels: Vec<ChannelSearchSingleResult>) -> ChannelSearchResult {
+        let mut channels: Vec<_> = channels.into_iter().filter(|x| self.filter_matches(x)).collect();
+        let total = channels.len() as u64;
+        self.sort_and_page(&mut channels);
+        ChannelSearchResult {
+            channels,
+            total: Some(total),
+        }
+    }
+
+    /// Merges the results of several backends, each asked with `Self::for_sub_backend`.
+    pub fn merge_ranked(&self, results: Vec<ChannelSearchResult>) -> ChannelSearchResult {
+        let mut total = 0;
+        let mut channels = Vec::new();
+        for res in results {
+            total += res.total.unwrap_or(res.channels.len() as u64);
+            channels.extend(res.channels.into_iter().filter(|x| self.filter_matches(x)));
+        }
+        self.sort_and_page(&mut channels);
+        ChannelSearchResult {
+            channels,
+            total: Some(total),
+        }
+    }
+
+    fn sort_and_page(&self, channels: &mut Vec<ChannelSearchSingleResult>) {
+        channels.sort_by(|a, b| (self.rank(a), &a.name, &a.backend).cmp(&(self.rank(b), &b.name, &b.backend)));
+        channels.drain(..self.offset.min(channels.len()));
+        if let Some(limit) = self.limit {
+            channels.truncate(limit);
+        }
     }
 }
 
@@ -2508,9 +3069,73 @@ mod test {
         let url = url::Url::parse("dummy:?123").unwrap();
         assert_eq!(url.query().unwrap(), "123")
     }
+
+    fn search_result(backend: &str, name: &str, ty: &str, shape: Vec<u32>) -> super::ChannelSearchSingleResult {
+        super::ChannelSearchSingleResult {
+            backend: backend.into(),
+            name: name.into(),
+            series: 0,
+            source: String::new(),
+            ty: ty.into(),
+            shape,
+            unit: String::new(),
+            description: String::new(),
+            is_api_0: None,
+            alias_of: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn search_rank_and_page() {
+        let url = url::Url::parse("dummy:?nameRegex=abc&scalarType=f64&limit=2&offset=1").unwrap();
+        let q = super::ChannelSearchQuery::from_url(&url).unwrap();
+        let chs = vec![
+            search_result("b1", "x-abc", "Float64", vec![]),
+            search_result("b1", "abc-2", "f64", vec![]),
+            search_result("b1", "ABC", "float64", vec![]),
+            search_result("b1", "abc-1", "f64", vec![4]),
+            search_result("b1", "abc-3", "i32", vec![]),
+        ];
+        let res = q.rank_and_page(chs);
+        assert_eq!(res.total, Some(4));
+        let names: Vec<_> = res.channels.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, ["abc-1", "abc-2"]);
+        let sub = q.for_sub_backend();
+        assert_eq!((sub.offset, sub.limit), (0, Some(3)));
+        let r1 = sub.rank_and_page(vec![search_result("b1", "abc-1", "f64", vec![])]);
+        let r2 = sub.rank_and_page(vec![
+            search_result("b2", "abc", "f64", vec![]),
+            search_result("b2", "abc-0", "f64", vec![]),
+        ]);
+        let res = q.merge_ranked(vec![r1, r2]);
+        assert_eq!(res.total, Some(3));
+        let names: Vec<_> = res.channels.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(names, ["abc-0", "abc-1"]);
+    }
+
+    #[test]
+    fn alias_segments_in_range() {
+        let seg = |channel: &str, beg, end| super::ChannelAliasSegment {
+            channel: channel.into(),
+            beg,
+            end,
+        };
+        let alias = super::ChannelAlias {
+            backend: "b1".into(),
+            name: "new".into(),
+            segments: vec![seg("c2", 30, None), seg("c1", 5, Some(40)), seg("c0", 0, Some(5))],
+        };
+        let range = super::NanoRange { beg: 2, end: 50 };
+        let segs = alias.segments_in(&range);
+        let segs: Vec<_> = segs.iter().map(|(ch, r)| (*ch, r.beg, r.end)).collect();
+        assert_eq!(segs, [("c0", 2, 5), ("c1", 5, 30), ("c2", 30, 50)]);
+        let range = super::NanoRange { beg: 10, end: 20 };
+        let segs: Vec<_> = alias.segments_in(&range).into_iter().map(|x| x.0).collect();
+        assert_eq!(segs, ["c1"]);
+    }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ChannelSearchSingleResult {
     pub backend: String,
     pub name: String,
@@ -2524,17 +3149,58 @@ pub struct ChannelSearchSingleResult {
     pub description: String,
     #[serde(rename = "isApi0", skip_serializing_if = "Option::is_none")]
     pub is_api_0: Option<bool>,
+    /// For an alias, the channels it stands for in time order.
+    #[serde(rename = "aliasOf", default, skip_serializing_if = "Vec::is_empty")]
+    pub alias_of: Vec<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ChannelSearchResult {
     pub channels: Vec<ChannelSearchSingleResult>,
+    /// The number of matches before pagination.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub total: Option<u64>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ProxyBackend {
     pub name: String,
+    /// A single endpoint, as in configs from before `urls` existed.
+    #[serde(default)]
+    pub url: String,
+    /// The endpoints which serve the backend. Requests are balanced across the healthy ones.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub urls: Vec<String>,
+}
+
+impl ProxyBackend {
+    /// All configured endpoints, `url` first if given.
+    pub fn endpoints(&self) -> Vec<&str> {
+        let mut ret: Vec<&str> = Vec::new();
+        for u in std::iter::once(&self.url).chain(self.urls.iter()) {
+            if !u.is_empty() && !ret.contains(&u.as_str()) {
+                ret.push(u);
+            }
+        }
+        ret
+    }
+}
+
+/// The health of a backend endpoint as seen by the proxy.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProxyEndpointStatus {
+    pub backend: String,
     pub url: String,
+    pub healthy: bool,
+    /// Requests which wait for the response head of this endpoint.
+    pub outstanding: u64,
+    pub requests: u64,
+    pub failures: u64,
+    /// Milliseconds since the last health check, if any was done yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_check_ago_ms: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -2549,6 +3215,34 @@ pub struct ProxyConfig {
     pub port: u16,
     pub backends: Vec<ProxyBackend>,
     pub status_subs: Vec<StatusSub>,
+    /// Without it, the proxy does not cache results.
+    #[serde(default)]
+    pub cache: Option<ProxyCacheConfig>,
+    #[serde(default)]
+    pub auth: Option<AuthConfig>,
+    #[serde(default)]
+    pub rate_limits: Option<RateLimitConfig>,
+}
+
+/// Limits of the response cache of the proxy. A `max_bytes` of zero disables the cache.
+/// Results larger than `max_bytes / 8` are not cached.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProxyCacheConfig {
+    pub max_bytes: u64,
+    /// If given, final results are also kept in this directory, up to `disk_max_bytes`.
+    pub dir: Option<PathBuf>,
+    pub disk_max_bytes: u64,
+}
+
+impl Default for ProxyCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_bytes: 1024 * 1024 * 256,
+            dir: None,
+            disk_max_bytes: 1024 * 1024 * 1024 * 4,
+        }
+    }
 }
 
 pub trait HasBackend {
@@ -2918,6 +3612,15 @@ impl From<SfChFetchInfo> for ChannelTypeConfigGen {
     }
 }
 
+/// The config of a channel which provides the events of the queried channel in `range`.
+/// A query for an alias resolves to one segment per underlying channel, any other query to a single
+/// segment over the whole query range.
+#[derive(Debug, Clone)]
+pub struct ChannelConfigSegment {
+    pub range: SeriesRange,
+    pub ch_conf: ChannelTypeConfigGen,
+}
+
 pub fn f32_close(a: f32, b: f32) -> bool {
     if (a - b).abs() < 1e-4 || (a / b > 0.999 && a / b < 1.001) {
         true
@@ -2946,6 +3649,7 @@ pub fn test_cluster() -> Cluster {
                 data_base_path: test_data_base_path_databuffer().join(format!("node{:02}", id)),
                 ksprefix: "ks".into(),
                 splits: None,
+                pulse_index: None,
             }),
             archiver_appliance: None,
             channel_archiver: None,
@@ -2964,6 +3668,13 @@ pub fn test_cluster() -> Cluster {
         },
         scylla: None,
         cache_scylla: None,
+        json_backends: Vec::new(),
+        channel_aliases: Vec::new(),
+        auth: None,
+        rate_limits: None,
+        query_budget: None,
+        download: None,
+        node_net: None,
         run_map_pulse_task: false,
         is_central_storage: false,
         file_io_buffer_size: Default::default(),
@@ -2998,6 +3709,13 @@ pub fn sls_test_cluster() -> Cluster {
         },
         scylla: None,
         cache_scylla: None,
+        json_backends: Vec::new(),
+        channel_aliases: Vec::new(),
+        auth: None,
+        rate_limits: None,
+        query_budget: None,
+        download: None,
+        node_net: None,
         run_map_pulse_task: false,
         is_central_storage: false,
         file_io_buffer_size: Default::default(),
@@ -3032,6 +3750,13 @@ pub fn archapp_test_cluster() -> Cluster {
         },
         scylla: None,
         cache_scylla: None,
+        json_backends: Vec::new(),
+        channel_aliases: Vec::new(),
+        auth: None,
+        rate_limits: None,
+        query_budget: None,
+        download: None,
+        node_net: None,
         run_map_pulse_task: false,
         is_central_storage: false,
         file_io_buffer_size: Default::default(),
@@ -3088,6 +3813,37 @@ mod test_parse {
         assert_eq!(a.get("scalarType").unwrap(), "f32");
         assert_eq!(a.get("shape").unwrap(), "Image(3, 4)");
     }
+
+    #[test]
+    fn query_cost_budget() {
+        let mut cost = QueryCost {
+            timebins: 2,
+            files: 4,
+            bytes: 1000,
+            events: 100,
+            ts_msp_partitions: 0,
+        };
+        cost.merge(&QueryCost {
+            timebins: 3,
+            files: 1,
+            bytes: 500,
+            events: 50,
+            ts_msp_partitions: 1,
+        });
+        assert_eq!(cost.timebins, 3);
+        assert_eq!(cost.files, 5);
+        assert_eq!(cost.events, 150);
+        let mut budget = QueryBudget {
+            max_events: Some(150),
+            max_bytes: Some(2000),
+            ..Default::default()
+        };
+        assert_eq!(cost.exceeds(&budget), None);
+        assert_eq!(cost.events_within(&budget), Some(150));
+        budget.max_files = Some(4);
+        assert!(cost.exceeds(&budget).unwrap().contains("files 5"));
+        assert_eq!(cost.events_within(&budget), Some(120));
+    }
 }
 
 pub struct ReqCtx {
@@ -1,3 +1,7 @@
+use crate::eventsource::event_source;
+use disk::parse::channelconfig::config_history;
+use disk::parse::channelconfig::ConfigEntryBasics;
+use disk::parse::channelconfig::ConfigParseError;
 use err::Error;
 use httpclient::url::Url;
 use netpod::log::*;
@@ -15,12 +19,14 @@ use netpod::ScalarType;
 use netpod::SfChFetchInfo;
 use netpod::SfDbChannel;
 use netpod::Shape;
+use netpod::TsNano;
 use netpod::APP_JSON;
 use serde::Serialize;
+use std::collections::BTreeMap;
 
 const TEST_BACKEND: &str = "testbackend-00";
 
-fn channel_config_test_backend(channel: SfDbChannel) -> Result<ChannelTypeConfigGen, Error> {
+pub(crate) fn channel_config_test_backend(channel: SfDbChannel) -> Result<ChannelTypeConfigGen, Error> {
     let backend = channel.backend();
     let ret = if channel.name() == "scalar-i32-be" {
         let ret = SfChFetchInfo::new(
@@ -101,42 +107,8 @@ pub async fn channel_config(
     channel: SfDbChannel,
     ncc: &NodeConfigCached,
 ) -> Result<Option<ChannelTypeConfigGen>, Error> {
-    if channel.backend() == TEST_BACKEND {
-        Ok(Some(channel_config_test_backend(channel)?))
-    } else if ncc.node_config.cluster.scylla.is_some() {
-        debug!("try to get ChConf for scylla type backend");
-        let ret = dbconn::channelconfig::chconf_from_scylla_type_backend(&channel, ncc)
-            .await
-            .map_err(Error::from)?;
-        Ok(Some(ChannelTypeConfigGen::Scylla(ret)))
-    } else if ncc.node.sf_databuffer.is_some() {
-        debug!("channel_config  channel {channel:?}");
-        let k = disk::channelconfig::channel_config_best_match(range, channel.clone(), ncc)
-            .await
-            .map_err(|e| Error::from(e.to_string()))?;
-        match k {
-            Some(config) => {
-                debug!("channel_config  config  {config:?}");
-                let ret = SfChFetchInfo::new(
-                    config.channel.backend(),
-                    config.channel.name(),
-                    config.keyspace,
-                    config.time_bin_size,
-                    config.byte_order,
-                    config.scalar_type,
-                    config.shape,
-                );
-                let ret = ChannelTypeConfigGen::SfDatabuffer(ret);
-                Ok(Some(ret))
-            }
-            None => Ok(None),
-        }
-    } else {
-        return Err(
-            Error::with_msg_no_trace(format!("no channel config for backend {}", channel.backend()))
-                .add_public_msg(format!("no channel config for backend {}", channel.backend())),
-        );
-    }
+    let source = event_source(channel.backend(), ncc).await?;
+    source.channel_config(range, channel, ncc).await
 }
 
 #[derive(Debug, Serialize)]
@@ -172,6 +144,41 @@ pub async fn channel_configs(channel: SfDbChannel, ncc: &NodeConfigCached) -> Re
     }
 }
 
+/// The config history of the sf-databuffer channel as this node knows it: the entries of its config file
+/// and of the configs which the file held before it was rewritten. `None` if the node has no config for the channel.
+pub async fn channel_config_history_local(
+    channel: SfDbChannel,
+    ncc: &NodeConfigCached,
+) -> Result<Option<Vec<(TsNano, ConfigEntryBasics)>>, Error> {
+    if ncc.node.sf_databuffer.is_none() {
+        return Err(Error::with_public_msg_no_trace(
+            "config history is only available for sf-databuffer backends",
+        ));
+    }
+    let mut entries = BTreeMap::new();
+    let olds = dbconn::scan::channel_configs_history(ncc, channel.name()).await?;
+    for old in &olds {
+        for (ts, e) in config_history(old)? {
+            entries.insert(ts, e);
+        }
+    }
+    // The config file wins over older copies of itself.
+    match disk::channelconfig::channel_configs(channel, ncc).await {
+        Ok(configs) => {
+            for (ts, e) in config_history(&configs)? {
+                entries.insert(ts, e);
+            }
+        }
+        Err(ConfigParseError::FileNotFound) => {}
+        Err(e) => return Err(Error::from(e.to_string())),
+    }
+    if entries.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(entries.into_iter().collect()))
+    }
+}
+
 pub async fn http_get_channel_config(
     qu: ChannelConfigQuery,
     baseurl: Url,
@@ -195,3 +202,25 @@ pub async fn http_get_channel_config(
         )))
     }
 }
+
+pub async fn http_get_channel_config_history_local(
+    qu: ChannelConfigQuery,
+    baseurl: Url,
+) -> Result<Option<Vec<(TsNano, ConfigEntryBasics)>>, Error> {
+    let url = baseurl;
+    let mut url = url.join("/api/4/channel/config/history/local").unwrap();
+    qu.append_to_url(&mut url);
+    let res = httpclient::http_get(url, APP_JSON).await?;
+    use httpclient::http::StatusCode;
+    if res.head.status == StatusCode::OK {
+        let ret = serde_json::from_slice(&res.body)?;
+        Ok(ret)
+    } else {
+        let b = &res.body;
+        let s = String::from_utf8_lossy(&b[0..b.len().min(256)]);
+        Err(Error::with_msg_no_trace(format!(
+            "http_get_channel_config_history_local  {}  {}",
+            res.head.status, s
+        )))
+    }
+}
@@ -1,48 +1,57 @@
 use crate::channelconfig::http_get_channel_config;
+use crate::channelconfig::http_get_channel_config_history_local;
+use crate::eventsource::event_source;
+use disk::parse::channelconfig::ConfigEntryBasics;
 use err::Error;
 use netpod::log::*;
+use netpod::range::evrange::NanoRange;
 use netpod::range::evrange::SeriesRange;
 use netpod::ChConf;
 use netpod::ChannelConfigQuery;
 use netpod::ChannelConfigResponse;
+use netpod::ChannelConfigSegment;
 use netpod::ChannelTypeConfigGen;
 use netpod::DtNano;
 use netpod::NodeConfigCached;
 use netpod::SfChFetchInfo;
 use netpod::SfDbChannel;
+use netpod::TsNano;
+use serde::Serialize;
 use std::collections::BTreeMap;
 use std::time::Duration;
 use taskrun::tokio;
 
-fn decide_sf_ch_config_quorum(inp: Vec<ChannelConfigResponse>) -> Result<Option<ChannelTypeConfigGen>, Error> {
+/// The value most of the nodes agree on, together with the number of nodes which have it.
+fn decide_quorum<T, I>(inp: I) -> Option<(T, u32)>
+where
+    T: Ord,
+    I: IntoIterator<Item = T>,
+{
     let mut histo = BTreeMap::new();
     for item in inp {
-        let item = match item {
-            ChannelConfigResponse::SfDatabuffer(k) => ChannelTypeConfigGen::SfDatabuffer(SfChFetchInfo::new(
-                k.backend,
-                k.name,
-                k.keyspace,
-                DtNano::from_ms(k.timebinsize),
-                k.byte_order,
-                k.scalar_type,
-                k.shape,
-            )),
-            ChannelConfigResponse::Daqbuf(k) => {
-                ChannelTypeConfigGen::Scylla(ChConf::new(k.backend, k.series, k.scalar_type, k.shape, k.name))
-            }
-        };
-        if histo.contains_key(&item) {
-            *histo.get_mut(&item).unwrap() += 1;
-        } else {
-            histo.insert(item, 0u32);
-        }
+        *histo.entry(item).or_insert(0u32) += 1;
     }
     let mut v: Vec<_> = histo.into_iter().collect();
     v.sort_unstable_by_key(|x| x.1);
-    match v.pop() {
-        Some((x, _)) => Ok(Some(x)),
-        None => Ok(None),
-    }
+    v.pop()
+}
+
+fn decide_sf_ch_config_quorum(inp: Vec<ChannelConfigResponse>) -> Result<Option<ChannelTypeConfigGen>, Error> {
+    let items = inp.into_iter().map(|item| match item {
+        ChannelConfigResponse::SfDatabuffer(k) => ChannelTypeConfigGen::SfDatabuffer(SfChFetchInfo::new(
+            k.backend,
+            k.name,
+            k.keyspace,
+            DtNano::from_ms(k.timebinsize),
+            k.byte_order,
+            k.scalar_type,
+            k.shape,
+        )),
+        ChannelConfigResponse::Daqbuf(k) => {
+            ChannelTypeConfigGen::Scylla(ChConf::new(k.backend, k.series, k.scalar_type, k.shape, k.name))
+        }
+    });
+    Ok(decide_quorum(items).map(|x| x.0))
 }
 
 async fn find_sf_ch_config_quorum(
@@ -86,7 +95,17 @@ pub async fn find_config_basics_quorum(
     range: SeriesRange,
     ncc: &NodeConfigCached,
 ) -> Result<Option<ChannelTypeConfigGen>, Error> {
-    if let Some(_cfg) = &ncc.node.sf_databuffer {
+    if ncc.node_config.cluster.is_json_backend(channel.backend()) {
+        // Every node holds the same data, no need to ask the others.
+        let range = match range {
+            SeriesRange::TimeRange(x) => x,
+            SeriesRange::PulseRange(_) => return Err(Error::with_msg_no_trace("expect TimeRange")),
+        };
+        event_source(channel.backend(), ncc)
+            .await?
+            .channel_config(range, channel, ncc)
+            .await
+    } else if let Some(_cfg) = &ncc.node.sf_databuffer {
         let channel = if channel.name().is_empty() {
             if let Some(_) = channel.series() {
                 let pgclient = dbconn::create_connection(&ncc.node_config.cluster.database).await?;
@@ -116,3 +135,300 @@ pub async fn find_config_basics_quorum(
         ))
     }
 }
+
+/// The configs of the underlying channels in the range, in time order.
+/// A channel which is not an alias is its own single segment covering the range.
+/// Segments of an alias for which the underlying channel has no config are left out.
+pub async fn find_config_segments_quorum(
+    channel: SfDbChannel,
+    range: SeriesRange,
+    ncc: &NodeConfigCached,
+) -> Result<Option<Vec<ChannelConfigSegment>>, Error> {
+    let alias = if channel.name().is_empty() {
+        None
+    } else {
+        dbconn::alias::channel_alias(channel.backend(), channel.name(), ncc).await
+    };
+    let alias = match alias {
+        Some(x) => x,
+        None => {
+            let ret = find_config_basics_quorum(channel, range.clone(), ncc)
+                .await?
+                .map(|ch_conf| vec![ChannelConfigSegment { range, ch_conf }]);
+            return Ok(ret);
+        }
+    };
+    let range = match range {
+        SeriesRange::TimeRange(x) => x,
+        SeriesRange::PulseRange(_) => {
+            return Err(Error::with_public_msg_no_trace(format!(
+                "channel alias {} only supports time ranges",
+                alias.name
+            )))
+        }
+    };
+    let mut ret = Vec::new();
+    for (name, range) in alias.segments_in(&range) {
+        let channel = SfDbChannel::from_name(&alias.backend, name);
+        let range = SeriesRange::TimeRange(range);
+        debug!(
+            "find_config_segments_quorum  alias {}  segment {name}  {range:?}",
+            alias.name
+        );
+        if let Some(ch_conf) = find_config_basics_quorum(channel, range.clone(), ncc).await? {
+            ret.push(ChannelConfigSegment { range, ch_conf });
+        }
+    }
+    if ret.is_empty() {
+        Ok(None)
+    } else {
+        check_segments_stitch(&alias.name, &ret)?;
+        Ok(Some(ret))
+    }
+}
+
+/// The events of the segments are joined into one stream, which only works if they have the same type.
+fn check_segments_stitch(alias: &str, segs: &[ChannelConfigSegment]) -> Result<(), Error> {
+    if let Some(first) = segs.first() {
+        for seg in &segs[1..] {
+            let (a, b) = (&first.ch_conf, &seg.ch_conf);
+            if a.scalar_type() != b.scalar_type() || a.shape() != b.shape() {
+                return Err(Error::with_public_msg_no_trace(format!(
+                    "channel alias {alias} joins {} {:?} {:?} with {} {:?} {:?}",
+                    a.name(),
+                    a.scalar_type(),
+                    a.shape(),
+                    b.name(),
+                    b.scalar_type(),
+                    b.shape(),
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// A node which uses a different config than the quorum.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct NodeConfigDisagreement {
+    #[serde(rename = "nodeIx")]
+    pub node_ix: usize,
+    /// `None` if the node has no config for this time.
+    pub config: Option<ConfigEntryBasics>,
+}
+
+/// The config which most nodes use from `beg` until `end`, or until now if there is no end.
+#[derive(Debug, Serialize)]
+pub struct ConfigHistoryEntry {
+    #[serde(rename = "tsBeg")]
+    pub beg: TsNano,
+    #[serde(rename = "tsEnd", skip_serializing_if = "Option::is_none")]
+    pub end: Option<TsNano>,
+    #[serde(flatten)]
+    pub config: ConfigEntryBasics,
+    #[serde(rename = "nodesAgree")]
+    pub agree: u32,
+    #[serde(rename = "nodesDisagree", skip_serializing_if = "Vec::is_empty")]
+    pub disagree: Vec<NodeConfigDisagreement>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChannelConfigHistory {
+    pub backend: String,
+    pub name: String,
+    pub entries: Vec<ConfigHistoryEntry>,
+    /// The nodes which could not be asked, they are not counted in the quorum.
+    #[serde(rename = "nodesFailed", skip_serializing_if = "Vec::is_empty")]
+    pub nodes_failed: Vec<usize>,
+}
+
+/// Splits the time at every config change seen on any node and decides the config of each
+/// piece by quorum. Neighbouring pieces with the same outcome are joined.
+/// `nodes` holds the node index and the config history of each node, `None` for nodes without a config.
+fn merge_config_histories(
+    nodes: &[(usize, Option<Vec<(TsNano, ConfigEntryBasics)>>)],
+    range: &NanoRange,
+) -> Vec<ConfigHistoryEntry> {
+    let mut tss: Vec<_> = nodes
+        .iter()
+        .filter_map(|x| x.1.as_ref())
+        .flatten()
+        .map(|x| x.0.ns())
+        .collect();
+    tss.sort_unstable();
+    tss.dedup();
+    let mut ret: Vec<ConfigHistoryEntry> = Vec::new();
+    for (i, &ts) in tss.iter().enumerate() {
+        let end = tss.get(i + 1).copied();
+        if ts >= range.end || end.is_some_and(|x| x <= range.beg) {
+            continue;
+        }
+        let confs: Vec<_> = nodes
+            .iter()
+            .map(|(node_ix, hist)| {
+                let conf = hist
+                    .as_ref()
+                    .and_then(|h| h.iter().rev().find(|x| x.0.ns() <= ts).map(|x| &x.1));
+                (*node_ix, conf)
+            })
+            .collect();
+        let (config, agree) = match decide_quorum(confs.iter().filter_map(|x| x.1)) {
+            Some(x) => x,
+            None => continue,
+        };
+        let disagree: Vec<_> = confs
+            .iter()
+            .filter(|(_, x)| *x != Some(config))
+            .map(|(node_ix, x)| NodeConfigDisagreement {
+                node_ix: *node_ix,
+                config: x.cloned(),
+            })
+            .collect();
+        if let Some(last) = ret.last_mut() {
+            if last.end == Some(TsNano(ts))
+                && &last.config == config
+                && last.agree == agree
+                && last.disagree == disagree
+            {
+                last.end = end.map(TsNano);
+                continue;
+            }
+        }
+        ret.push(ConfigHistoryEntry {
+            beg: TsNano(ts),
+            end: end.map(TsNano),
+            config: config.clone(),
+            agree,
+            disagree,
+        });
+    }
+    ret
+}
+
+/// The config history of a sf-databuffer channel as seen by all nodes of the cluster,
+/// limited to the range of the query. `None` if no node has a config for the channel.
+/// Nodes which can not be asked are left out, unless no node answers at all.
+pub async fn find_config_history_quorum(
+    qu: ChannelConfigQuery,
+    ncc: &NodeConfigCached,
+) -> Result<Option<ChannelConfigHistory>, Error> {
+    if ncc.node.sf_databuffer.is_none() {
+        return Err(Error::with_public_msg_no_trace(
+            "config history is only available for sf-databuffer backends",
+        ));
+    }
+    let futs = ncc.node_config.cluster.nodes.iter().map(|node| {
+        let qu = qu.clone();
+        async move {
+            tokio::time::timeout(
+                Duration::from_millis(4000),
+                http_get_channel_config_history_local(qu, node.baseurl()),
+            )
+            .await
+            .map_err(|_| Error::with_msg_no_trace("timeout"))?
+        }
+    });
+    let mut hists = Vec::new();
+    let mut nodes_failed = Vec::new();
+    let mut last_err = None;
+    for (node_ix, res) in futures_util::future::join_all(futs).await.into_iter().enumerate() {
+        match res {
+            Ok(hist) => hists.push((node_ix, hist)),
+            Err(e) => {
+                warn!("find_config_history_quorum  node {node_ix} failed: {e}");
+                nodes_failed.push(node_ix);
+                last_err = Some(e);
+            }
+        }
+    }
+    if hists.is_empty() {
+        if let Some(e) = last_err {
+            return Err(e);
+        }
+    }
+    if hists.iter().all(|x| x.1.is_none()) {
+        return Ok(None);
+    }
+    let ret = ChannelConfigHistory {
+        backend: qu.channel.backend().into(),
+        name: qu.channel.name().into(),
+        entries: merge_config_histories(&hists, &qu.range),
+        nodes_failed,
+    };
+    Ok(Some(ret))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use disk::parse::channelconfig::CompressionMethod;
+    use netpod::ByteOrder;
+    use netpod::ScalarType;
+    use netpod::Shape;
+
+    fn basics(ks: u8, scalar_type: ScalarType) -> ConfigEntryBasics {
+        ConfigEntryBasics {
+            scalar_type,
+            shape: Shape::Scalar,
+            byte_order: ByteOrder::Big,
+            compression: Some(CompressionMethod::BitshuffleLZ4),
+            keyspace: ks,
+            bin_size: DtNano::from_ms(1000 * 60 * 60 * 24),
+        }
+    }
+
+    #[test]
+    fn config_history_quorum() {
+        let a = (TsNano(10), basics(2, ScalarType::I32));
+        let b = (TsNano(20), basics(2, ScalarType::F64));
+        let c = (TsNano(30), basics(3, ScalarType::F64));
+        let nodes = vec![
+            (0, Some(vec![a.clone(), b.clone(), c.clone()])),
+            (1, Some(vec![a.clone(), b.clone(), c.clone()])),
+            (2, Some(vec![a.clone(), c.clone()])),
+            (4, None),
+        ];
+        let range = NanoRange { beg: 0, end: u64::MAX };
+        let hist = merge_config_histories(&nodes, &range);
+        assert_eq!(hist.len(), 3);
+        assert_eq!((hist[0].beg.ns(), hist[0].end.as_ref().map(TsNano::ns)), (10, Some(20)));
+        assert_eq!(hist[0].agree, 3);
+        assert_eq!(hist[0].disagree.len(), 1);
+        // Node 3 failed and is not counted.
+        assert_eq!(hist[0].disagree[0].node_ix, 4);
+        assert_eq!(hist[1].config.scalar_type, ScalarType::F64);
+        assert_eq!(hist[1].agree, 2);
+        let ixs: Vec<_> = hist[1].disagree.iter().map(|x| x.node_ix).collect();
+        assert_eq!(ixs, [2, 4]);
+        assert_eq!(
+            hist[1].disagree[0].config.as_ref().map(|x| &x.scalar_type),
+            Some(&ScalarType::I32)
+        );
+        assert_eq!((hist[2].beg.ns(), hist[2].end.as_ref()), (30, None));
+        assert_eq!(hist[2].config.keyspace, 3);
+        let range = NanoRange { beg: 25, end: 26 };
+        let hist = merge_config_histories(&nodes, &range);
+        assert_eq!(hist.len(), 1);
+        assert_eq!(hist[0].beg.ns(), 20);
+    }
+
+    #[test]
+    fn alias_segments_stitch() {
+        let seg = |name: &str, beg, end, scalar_type, shape| {
+            let ch_conf = ChannelTypeConfigGen::Scylla(ChConf::new("be", 1, scalar_type, shape, name));
+            let range = SeriesRange::TimeRange(NanoRange { beg, end });
+            ChannelConfigSegment { range, ch_conf }
+        };
+        let segs = vec![
+            seg("a", 0, 10, ScalarType::F64, Shape::Scalar),
+            seg("b", 10, 20, ScalarType::F64, Shape::Scalar),
+        ];
+        assert!(check_segments_stitch("al", &segs).is_ok());
+        let mut segs2 = segs.clone();
+        segs2.push(seg("c", 20, 30, ScalarType::I32, Shape::Scalar));
+        assert!(check_segments_stitch("al", &segs2).is_err());
+        let mut segs3 = segs.clone();
+        segs3.push(seg("c", 20, 30, ScalarType::F64, Shape::Wave(4)));
+        assert!(check_segments_stitch("al", &segs3).is_err());
+    }
+}
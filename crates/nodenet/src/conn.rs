@@ -1,4 +1,4 @@
-use crate::scylla::scylla_channel_event_stream;
+use crate::eventsource::event_source;
 use bytes::Bytes;
 use err::thiserror;
 use err::Error;
@@ -16,11 +16,14 @@ use items_2::channelevents::ChannelEvents;
 use items_2::empty::empty_events_dyn_ev;
 use items_2::framable::EventQueryJsonStringFrame;
 use items_2::framable::Framable;
+use items_2::frame::compress_frame;
 use items_2::frame::decode_frame;
 use items_2::frame::make_term_frame;
 use items_2::inmem::InMemoryFrame;
+use items_2::merger::Merger;
 use netpod::histo::HistoLog2;
 use netpod::log::*;
+use netpod::FrameCompression;
 use netpod::NodeConfigCached;
 use netpod::ReqCtxArc;
 use query::api4::events::EventsSubQuery;
@@ -29,18 +32,16 @@ use std::net::SocketAddr;
 use std::pin::Pin;
 use streams::frames::inmem::InMemoryFrameStream;
 use streams::frames::inmem::TcpReadAsBytes;
-use streams::generators::GenerateF64V00;
-use streams::generators::GenerateI32V00;
-use streams::generators::GenerateI32V01;
+use streams::mux::MUX_MAGIC;
 use streams::transform::build_event_transform;
 use taskrun::tokio;
+use tokio::io::AsyncReadExt;
 use tokio::io::AsyncWriteExt;
 use tokio::net::tcp::OwnedWriteHalf;
 use tokio::net::TcpStream;
 use tracing::Instrument;
 
-const TEST_BACKEND: &str = "testbackend-00";
-
+pub mod mux;
 #[cfg(test)]
 mod test;
 
@@ -80,64 +81,17 @@ async fn make_channel_events_stream_data(
     reqctx: ReqCtxArc,
     ncc: &NodeConfigCached,
 ) -> Result<Pin<Box<dyn Stream<Item = Sitemty<ChannelEvents>> + Send>>, Error> {
-    if subq.backend() == TEST_BACKEND {
-        debug!("use test backend data  {}", TEST_BACKEND);
-        let node_count = ncc.node_config.cluster.nodes.len() as u64;
-        let node_ix = ncc.ix as u64;
-        let chn = subq.name();
-        let range = subq.range().clone();
-        let one_before = subq.transform().need_one_before_range();
-        if chn == "test-gen-i32-dim0-v00" {
-            Ok(Box::pin(GenerateI32V00::new(node_ix, node_count, range, one_before)))
-        } else if chn == "test-gen-i32-dim0-v01" {
-            Ok(Box::pin(GenerateI32V01::new(node_ix, node_count, range, one_before)))
-        } else if chn == "test-gen-f64-dim1-v00" {
-            Ok(Box::pin(GenerateF64V00::new(node_ix, node_count, range, one_before)))
-        } else {
-            let na: Vec<_> = chn.split("-").collect();
-            if na.len() != 3 {
-                Err(Error::with_msg_no_trace(format!(
-                    "make_channel_events_stream_data can not understand test channel name: {chn:?}"
-                )))
-            } else {
-                if na[0] != "inmem" {
-                    Err(Error::with_msg_no_trace(format!(
-                        "make_channel_events_stream_data can not understand test channel name: {chn:?}"
-                    )))
-                } else {
-                    let _range = subq.range().clone();
-                    if na[1] == "d0" {
-                        if na[2] == "i32" {
-                            //generator::generate_i32(node_ix, node_count, range)
-                            panic!()
-                        } else if na[2] == "f32" {
-                            //generator::generate_f32(node_ix, node_count, range)
-                            panic!()
-                        } else {
-                            Err(Error::with_msg_no_trace(format!(
-                                "make_channel_events_stream_data can not understand test channel name: {chn:?}"
-                            )))
-                        }
-                    } else {
-                        Err(Error::with_msg_no_trace(format!(
-                            "make_channel_events_stream_data can not understand test channel name: {chn:?}"
-                        )))
-                    }
-                }
-            }
-        }
-    } else if let Some(scyconf) = &ncc.node_config.cluster.scylla {
-        let cfg = subq.ch_conf().to_scylla()?;
-        scylla_channel_event_stream(subq, cfg, scyconf, ncc).await
-    } else if let Some(_) = &ncc.node.channel_archiver {
-        let e = Error::with_msg_no_trace("archapp not built");
-        Err(e)
-    } else if let Some(_) = &ncc.node.archiver_appliance {
-        let e = Error::with_msg_no_trace("archapp not built");
-        Err(e)
+    let source = event_source(subq.backend(), ncc).await?;
+    let status = if subq.with_status() {
+        source.status(&subq, ncc).await?
     } else {
-        let cfg = subq.ch_conf().to_sf_databuffer()?;
-        Ok(disk::raw::conn::make_event_pipe(subq, cfg, reqctx, ncc).await?)
+        None
+    };
+    let out_len = subq.merger_out_len_max();
+    let stream = source.events(subq, reqctx, ncc).await?;
+    match status {
+        Some(status) => Ok(Box::pin(Merger::new(vec![stream, status], out_len))),
+        None => Ok(stream),
     }
 }
 
@@ -156,6 +110,27 @@ async fn make_channel_events_stream(
 
 pub type BytesStreamBox = Pin<Box<dyn Stream<Item = Result<Bytes, Error>> + Send>>;
 
+/// Frames up to this length are compressed in place, longer ones on the blocking thread pool.
+const COMPRESS_INLINE_MAX: usize = 1024 * 16;
+
+/// The frame of the item, compressed if the requester asked for it.
+async fn make_frame_bytes<T>(item: T, comp: Option<FrameCompression>) -> Result<Bytes, Error>
+where
+    T: Framable,
+{
+    let buf = item.make_frame()?;
+    let buf = match comp {
+        Some(comp) if buf.len() > COMPRESS_INLINE_MAX => {
+            tokio::task::spawn_blocking(move || compress_frame(buf, &comp))
+                .await
+                .map_err(|e| Error::with_msg_no_trace(format!("frame compression task failed: {e}")))??
+        }
+        Some(comp) => compress_frame(buf, &comp)?,
+        None => buf,
+    };
+    Ok(buf.freeze())
+}
+
 pub async fn create_response_bytes_stream(
     evq: EventsSubQuery,
     ncc: &NodeConfigCached,
@@ -174,12 +149,14 @@ pub async fn create_response_bytes_stream(
     if evq.is_event_blobs() {
         // TODO support event blobs as transform
         let fetch_info = evq.ch_conf().to_sf_databuffer()?;
+        let comp = evq.frame_compression().cloned();
         let stream = disk::raw::conn::make_event_blobs_pipe(&evq, &fetch_info, reqctx, ncc)?;
         // let stream = stream.map(|x| Box::new(x) as _);
-        let stream = stream.map(|x| x.make_frame().map(|x| x.freeze()));
+        let stream = stream.then(move |x| make_frame_bytes(x, comp.clone()));
         let ret = Box::pin(stream);
         Ok(ret)
     } else {
+        let comp = evq.frame_compression().cloned();
         let stream = make_channel_events_stream(evq.clone(), reqctx, ncc).await?;
         let mut tr = build_event_transform(evq.transform())?;
 
@@ -199,7 +176,7 @@ pub async fn create_response_bytes_stream(
             })
         });
         // let stream = stream.map(move |x| Box::new(x) as Box<dyn Framable + Send>);
-        let stream = stream.map(|x| x.make_frame().map(|x| x.freeze()));
+        let stream = stream.then(move |x| make_frame_bytes(x, comp.clone()));
         let ret = Box::pin(stream);
         Ok(ret)
     }
@@ -291,7 +268,23 @@ where
     Ok(frames)
 }
 
-pub fn events_parse_input_query(frames: Vec<InMemoryFrame>) -> Result<(EventsSubQuery,), Error> {
+/// With authentication configured, subqueries must carry the node token of the cluster.
+fn check_node_token(frame1: &Frame1Parts, ncc: &NodeConfigCached) -> Result<(), Error> {
+    let conf = match ncc.node_config.cluster.auth.as_ref() {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+    match frame1.node_token() {
+        Some(token) if conf.is_node_token(token) => Ok(()),
+        Some(_) => Err(Error::with_public_msg_no_trace("invalid node token")),
+        None => Err(Error::with_public_msg_no_trace("missing node token")),
+    }
+}
+
+pub fn events_parse_input_query(
+    frames: Vec<InMemoryFrame>,
+    ncc: &NodeConfigCached,
+) -> Result<(EventsSubQuery,), Error> {
     if frames.len() != 1 {
         error!("{:?}", frames);
         error!("missing command frame  len {}", frames.len());
@@ -317,10 +310,11 @@ pub fn events_parse_input_query(frames: Vec<InMemoryFrame>) -> Result<(EventsSub
         Err(e) => return Err(e),
     };
     let frame1: Frame1Parts = serde_json::from_str(&qitem.str()).map_err(|e| {
-        let e = Error::with_msg_no_trace(format!("json parse error: {}  inp {:?}", e, qitem.str()));
+        let e = Error::with_msg_no_trace(format!("json parse error: {}", e));
         error!("{e}");
         e
     })?;
+    check_node_token(&frame1, ncc)?;
     Ok(frame1.parts())
 }
 
@@ -338,7 +332,7 @@ where
         Ok(x) => x,
         Err(e) => return Err((e, netout).into()),
     };
-    let (evq,) = match events_parse_input_query(frames) {
+    let (evq,) = match events_parse_input_query(frames, ncc) {
         Ok(x) => x,
         Err(e) => return Err((e, netout).into()),
     };
@@ -370,12 +364,22 @@ where
 }
 
 async fn events_conn_handler(stream: TcpStream, addr: SocketAddr, node_config: NodeConfigCached) -> Result<(), Error> {
-    let (netin, netout) = stream.into_split();
-    let inp = Box::new(TcpReadAsBytes::new(netin));
+    let (mut netin, netout) = stream.into_split();
     let span1 = span!(Level::INFO, "events_conn_handler");
-    let r = events_conn_handler_inner(inp, netout, addr, &node_config)
-        .instrument(span1)
-        .await;
+    // A multiplexed connection starts with its magic instead of the query frame.
+    let mut head = [0; 4];
+    netin.read_exact(&mut head).await?;
+    let r = if u32::from_le_bytes(head) == MUX_MAGIC {
+        mux::mux_conn_handler(netin, netout, addr, node_config)
+            .instrument(span1)
+            .await
+    } else {
+        let head = futures_util::stream::iter([Ok(Bytes::copy_from_slice(&head))]);
+        let inp = Box::new(head.chain(TcpReadAsBytes::new(netin)));
+        events_conn_handler_inner(inp, netout, addr, &node_config)
+            .instrument(span1)
+            .await
+    };
     match r {
         Ok(k) => Ok(k),
         Err(e) => {
@@ -0,0 +1,235 @@
+//! Serves the subqueries of a multiplexed connection, see `streams::mux`.
+
+use super::create_response_bytes_stream;
+use super::events_get_input_frames;
+use super::events_parse_input_query;
+use bytes::Bytes;
+use err::Error;
+use futures_util::StreamExt;
+use items_0::streamitem::Sitemty;
+use items_2::channelevents::ChannelEvents;
+use items_2::framable::Framable;
+use items_2::frame::make_term_frame;
+use netpod::log::*;
+use netpod::NodeConfigCached;
+use netpod::NodeNetConfig;
+use query::api4::events::EventsSubQuery;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::AtomicI64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use streams::mux::read_mux_msg;
+use streams::mux::write_mux_msg;
+use streams::mux::MuxKind;
+use streams::mux::MuxMsg;
+use taskrun::tokio;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufWriter;
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::sync::mpsc;
+use tokio::sync::Notify;
+use tokio::task::AbortHandle;
+use tracing::Instrument;
+
+// Messages waiting for the socket, before the substreams have to wait.
+const OUT_QUEUE: usize = 64;
+
+/// Bytes which a substream may still send. It goes below zero when a frame is larger than the credit left.
+struct Credit {
+    avail: AtomicI64,
+    notify: Notify,
+}
+
+impl Credit {
+    fn new() -> Self {
+        Self {
+            avail: AtomicI64::new(0),
+            notify: Notify::new(),
+        }
+    }
+
+    fn add(&self, n: u32) {
+        self.avail.fetch_add(n as i64, Ordering::AcqRel);
+        self.notify.notify_one();
+    }
+
+    async fn take(&self, n: usize) {
+        while self.avail.load(Ordering::Acquire) <= 0 {
+            self.notify.notified().await;
+        }
+        self.avail.fetch_sub(n as i64, Ordering::AcqRel);
+    }
+}
+
+struct Sub {
+    credit: Arc<Credit>,
+    abort: AbortHandle,
+}
+
+type Subs = Arc<Mutex<BTreeMap<u32, Sub>>>;
+
+pub async fn mux_conn_handler(
+    mut netin: OwnedReadHalf,
+    netout: OwnedWriteHalf,
+    addr: SocketAddr,
+    ncc: NodeConfigCached,
+) -> Result<(), Error> {
+    debug!("mux connection from {addr}");
+    let (tx, mut rx) = mpsc::channel::<MuxMsg>(OUT_QUEUE);
+    let writer = taskrun::spawn(async move {
+        let mut out = BufWriter::new(netout);
+        while let Some(msg) = rx.recv().await {
+            write_mux_msg(&mut out, &msg).await?;
+            // Write all which is queued before the flush.
+            while let Ok(msg) = rx.try_recv() {
+                write_mux_msg(&mut out, &msg).await?;
+            }
+            out.flush().await?;
+        }
+        Ok::<_, Error>(())
+    });
+    let substreams_max = ncc
+        .node_config
+        .cluster
+        .node_net
+        .as_ref()
+        .map_or(NodeNetConfig::SUBSTREAMS_MAX_DEFAULT, |x| x.substreams_max()) as usize;
+    let subs: Subs = Arc::new(Mutex::new(BTreeMap::new()));
+    let res = loop {
+        let msg = match read_mux_msg(&mut netin).await {
+            Ok(Some(x)) => x,
+            Ok(None) => break Ok(()),
+            Err(e) => break Err(e),
+        };
+        match msg.kind {
+            MuxKind::Open => {
+                // A query which can not be read, or comes without the node token, ends the connection.
+                let frames = match events_get_input_frames(futures_util::stream::iter([Ok(msg.payload)])).await {
+                    Ok(x) => x,
+                    Err(e) => break Err(e),
+                };
+                let evq = match events_parse_input_query(frames, &ncc) {
+                    Ok((x,)) => x,
+                    Err(e) => {
+                        warn!("mux open {} from {addr} refused: {e}", msg.sid);
+                        break Err(e);
+                    }
+                };
+                if subs.lock().unwrap().len() >= substreams_max {
+                    debug!("mux open {} from {addr} over the limit of {substreams_max}", msg.sid);
+                    let e = Error::with_public_msg_no_trace(format!(
+                        "more than {substreams_max} subqueries on one connection"
+                    ));
+                    send_error(msg.sid, e, &tx).await;
+                    let _ = tx.send(MuxMsg::new(MuxKind::End, msg.sid, Bytes::new())).await;
+                    continue;
+                }
+                let credit = Arc::new(Credit::new());
+                // Hold the lock until the abort handle is in, the task removes itself when done.
+                let mut g = subs.lock().unwrap();
+                if g.contains_key(&msg.sid) {
+                    break Err(Error::with_msg_no_trace(format!(
+                        "mux substream {} already open",
+                        msg.sid
+                    )));
+                }
+                let fut = mux_sub(msg.sid, evq, credit.clone(), tx.clone(), ncc.clone(), subs.clone());
+                let jh = taskrun::spawn(fut);
+                let sub = Sub {
+                    credit,
+                    abort: jh.abort_handle(),
+                };
+                g.insert(msg.sid, sub);
+            }
+            MuxKind::Credit => {
+                let n = match msg.credit_bytes() {
+                    Ok(x) => x,
+                    Err(e) => break Err(e),
+                };
+                // Credit may arrive for a substream which just ended.
+                if let Some(sub) = subs.lock().unwrap().get(&msg.sid) {
+                    sub.credit.add(n);
+                }
+            }
+            MuxKind::Cancel => {
+                if let Some(sub) = subs.lock().unwrap().remove(&msg.sid) {
+                    debug!("mux cancel {} from {addr}", msg.sid);
+                    sub.abort.abort();
+                }
+            }
+            k => {
+                break Err(Error::with_msg_no_trace(format!(
+                    "unexpected mux message {k:?} from {addr}"
+                )))
+            }
+        }
+    };
+    let rest = std::mem::take(&mut *subs.lock().unwrap());
+    for (_, sub) in rest {
+        sub.abort.abort();
+    }
+    drop(tx);
+    match writer.await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => debug!("mux write to {addr}: {e}"),
+        Err(e) => debug!("mux writer of {addr}: {e}"),
+    }
+    debug!("mux connection from {addr} done");
+    res
+}
+
+async fn mux_sub(
+    sid: u32,
+    evq: EventsSubQuery,
+    credit: Arc<Credit>,
+    tx: mpsc::Sender<MuxMsg>,
+    ncc: NodeConfigCached,
+    subs: Subs,
+) {
+    if let Err(e) = mux_sub_inner(sid, evq, &credit, &tx, &ncc).await {
+        send_error(sid, e, &tx).await;
+    }
+    subs.lock().unwrap().remove(&sid);
+    let _ = tx.send(MuxMsg::new(MuxKind::End, sid, Bytes::new())).await;
+}
+
+async fn send_error(sid: u32, e: Error, tx: &mpsc::Sender<MuxMsg>) {
+    let item: Sitemty<ChannelEvents> = Err(e);
+    match item.make_frame() {
+        Ok(buf) => {
+            let _ = tx.send(MuxMsg::new(MuxKind::Data, sid, buf.freeze())).await;
+        }
+        Err(e) => error!("can not make error frame: {e}"),
+    }
+}
+
+async fn mux_sub_inner(
+    sid: u32,
+    evq: EventsSubQuery,
+    credit: &Credit,
+    tx: &mpsc::Sender<MuxMsg>,
+    ncc: &NodeConfigCached,
+) -> Result<(), Error> {
+    debug!("mux_sub {sid} sees:  {evq:?}");
+    let span = tracing::info_span!("subreq", reqid = evq.reqid());
+    let fut = async move {
+        let mut stream = create_response_bytes_stream(evq, ncc).await?;
+        let closed = || Error::with_msg_no_trace("mux connection closed");
+        while let Some(item) = stream.next().await {
+            let buf = item?;
+            credit.take(buf.len()).await;
+            tx.send(MuxMsg::new(MuxKind::Data, sid, buf))
+                .await
+                .map_err(|_| closed())?;
+        }
+        let buf = make_term_frame()?;
+        tx.send(MuxMsg::new(MuxKind::Data, sid, buf.freeze()))
+            .await
+            .map_err(|_| closed())?;
+        Ok(())
+    };
+    fut.instrument(span).await
+}
@@ -1,5 +1,6 @@
 use crate::conn::events_conn_handler;
 use crate::conn::Frame1Parts;
+use bytes::Bytes;
 use err::Error;
 use futures_util::StreamExt;
 use items_0::streamitem::sitem_data;
@@ -13,9 +14,11 @@ use items_2::channelevents::ChannelEvents;
 use items_2::framable::EventQueryJsonStringFrame;
 use items_2::framable::Framable;
 use items_2::frame::decode_frame;
+use items_2::frame::make_term_frame;
 use netpod::range::evrange::NanoRange;
 use netpod::timeunits::DAY;
 use netpod::timeunits::SEC;
+use netpod::AuthConfig;
 use netpod::ByteOrder;
 use netpod::Cluster;
 use netpod::Database;
@@ -24,6 +27,7 @@ use netpod::FileIoBufferSize;
 use netpod::Node;
 use netpod::NodeConfig;
 use netpod::NodeConfigCached;
+use netpod::NodeNetConfig;
 use netpod::ScalarType;
 use netpod::SfChFetchInfo;
 use netpod::SfDatabuffer;
@@ -32,57 +36,77 @@ use query::api4::events::EventsSubQuery;
 use query::api4::events::EventsSubQuerySelect;
 use query::api4::events::EventsSubQuerySettings;
 use query::transform::TransformQuery;
+use std::time::Duration;
 use streams::frames::inmem::InMemoryFrameStream;
 use streams::frames::inmem::TcpReadAsBytes;
+use streams::mux::read_mux_msg;
+use streams::mux::write_mux_msg;
+use streams::mux::MuxKind;
+use streams::mux::MuxMsg;
+use streams::mux::MUX_MAGIC;
 use taskrun::tokio;
 use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::OwnedReadHalf;
 use tokio::net::TcpListener;
 use tokio::net::TcpStream;
+use tokio::sync::mpsc;
 
 const TEST_BACKEND: &str = "testbackend-00";
 
+fn make_node_config() -> NodeConfigCached {
+    NodeConfigCached {
+        node_config: NodeConfig {
+            name: "node_name_dummy".into(),
+            cluster: Cluster {
+                backend: TEST_BACKEND.into(),
+                nodes: Vec::new(),
+                database: Database {
+                    name: "".into(),
+                    host: "".into(),
+                    port: 5432,
+                    user: "".into(),
+                    pass: "".into(),
+                },
+                run_map_pulse_task: false,
+                is_central_storage: false,
+                file_io_buffer_size: FileIoBufferSize(1024 * 8),
+                scylla: None,
+                cache_scylla: None,
+                json_backends: Vec::new(),
+                channel_aliases: Vec::new(),
+                auth: None,
+                rate_limits: None,
+                query_budget: None,
+                download: None,
+                node_net: None,
+            },
+        },
+        node: Node {
+            host: "empty".into(),
+            listen: None,
+            port: 9090,
+            port_raw: 9090,
+            sf_databuffer: Some(SfDatabuffer {
+                data_base_path: "/home/dominik/daqbuffer-testdata/databuffer/node00".into(),
+                ksprefix: "ks".into(),
+                splits: None,
+                pulse_index: None,
+            }),
+            archiver_appliance: None,
+            channel_archiver: None,
+            prometheus_api_bind: None,
+        },
+        ix: 0,
+    }
+}
+
 #[test]
 fn raw_data_00() {
     let fut = async {
         let lis = TcpListener::bind("127.0.0.1:0").await.unwrap();
         let mut con = TcpStream::connect(lis.local_addr().unwrap()).await.unwrap();
         let (client, addr) = lis.accept().await.unwrap();
-        let cfg = NodeConfigCached {
-            node_config: NodeConfig {
-                name: "node_name_dummy".into(),
-                cluster: Cluster {
-                    backend: TEST_BACKEND.into(),
-                    nodes: Vec::new(),
-                    database: Database {
-                        name: "".into(),
-                        host: "".into(),
-                        port: 5432,
-                        user: "".into(),
-                        pass: "".into(),
-                    },
-                    run_map_pulse_task: false,
-                    is_central_storage: false,
-                    file_io_buffer_size: FileIoBufferSize(1024 * 8),
-                    scylla: None,
-                    cache_scylla: None,
-                },
-            },
-            node: Node {
-                host: "empty".into(),
-                listen: None,
-                port: 9090,
-                port_raw: 9090,
-                sf_databuffer: Some(SfDatabuffer {
-                    data_base_path: "/home/dominik/daqbuffer-testdata/databuffer/node00".into(),
-                    ksprefix: "ks".into(),
-                    splits: None,
-                }),
-                archiver_appliance: None,
-                channel_archiver: None,
-                prometheus_api_bind: None,
-            },
-            ix: 0,
-        };
+        let cfg = make_node_config();
         let range = NanoRange {
             beg: SEC,
             end: SEC * 10,
@@ -138,3 +162,119 @@ fn raw_data_00() {
     };
     taskrun::run(fut).unwrap();
 }
+
+fn mux_open_payload(qu: &EventsSubQuery, node_token: Option<&str>) -> Result<Bytes, Error> {
+    let frame1 = Frame1Parts::new(qu.clone()).with_node_token(node_token.map(String::from));
+    let query = EventQueryJsonStringFrame(serde_json::to_string(&frame1)?);
+    let mut buf = sitem_data(query).make_frame()?;
+    buf.extend_from_slice(&make_term_frame()?);
+    Ok(buf.freeze())
+}
+
+/// Messages of the node, read by a task so that waiting with a timeout does not cut a message.
+fn mux_reader(mut netin: OwnedReadHalf) -> mpsc::UnboundedReceiver<Result<MuxMsg, Error>> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    taskrun::spawn(async move {
+        loop {
+            match read_mux_msg(&mut netin).await {
+                Ok(Some(msg)) => {
+                    if tx.send(Ok(msg)).is_err() {
+                        break;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = tx.send(Err(e));
+                    break;
+                }
+            }
+        }
+    });
+    rx
+}
+
+/// The messages which arrive until the node stays quiet for a while.
+async fn mux_recv_until_quiet(rx: &mut mpsc::UnboundedReceiver<Result<MuxMsg, Error>>) -> Result<Vec<MuxMsg>, Error> {
+    let mut ret = Vec::new();
+    loop {
+        match tokio::time::timeout(Duration::from_millis(400), rx.recv()).await {
+            Ok(Some(msg)) => ret.push(msg?),
+            Ok(None) | Err(_) => break,
+        }
+    }
+    Ok(ret)
+}
+
+fn data_bytes(msgs: &[MuxMsg], sid: u32) -> usize {
+    msgs.iter()
+        .filter(|x| x.sid == sid && x.kind == MuxKind::Data)
+        .map(|x| x.payload.len())
+        .sum()
+}
+
+#[test]
+fn mux_credit_cancel() {
+    let fut = async {
+        let mut cfg = make_node_config();
+        cfg.node_config.cluster.nodes = vec![cfg.node.clone()];
+        cfg.node_config.cluster.auth = Some(AuthConfig {
+            node_token: Some("tok-node".into()),
+            ..Default::default()
+        });
+        cfg.node_config.cluster.node_net = Some(NodeNetConfig {
+            substreams_max: Some(1),
+            ..Default::default()
+        });
+        let lis = TcpListener::bind("127.0.0.1:0").await?;
+        let con = TcpStream::connect(lis.local_addr()?).await?;
+        let (client, addr) = lis.accept().await?;
+        let jh = taskrun::spawn(events_conn_handler(client, addr, cfg));
+        let range = NanoRange { beg: 0, end: DAY / 2 };
+        let fetch_info = SfChFetchInfo::new(
+            TEST_BACKEND,
+            "test-gen-i32-dim0-v01",
+            2,
+            DtNano::from_ns(DAY),
+            ByteOrder::Big,
+            ScalarType::I32,
+            Shape::Scalar,
+        );
+        let select = EventsSubQuerySelect::new(fetch_info.into(), range.into(), TransformQuery::default_events());
+        let qu = EventsSubQuery::from_parts(select, EventsSubQuerySettings::default(), "dummy".into());
+        let query = mux_open_payload(&qu, Some("tok-node"))?;
+        let (netin, mut netout) = con.into_split();
+        let mut rx = mux_reader(netin);
+        netout.write_all(&MUX_MAGIC.to_le_bytes()).await?;
+        write_mux_msg(&mut netout, &MuxMsg::new(MuxKind::Open, 1, query.clone())).await?;
+        write_mux_msg(&mut netout, &MuxMsg::credit(1, 1024)).await?;
+        // The node stops when the credit is used up, without the substream being done.
+        let msgs = mux_recv_until_quiet(&mut rx).await?;
+        assert!(data_bytes(&msgs, 1) >= 1024);
+        assert!(msgs.iter().all(|x| x.kind == MuxKind::Data));
+        assert_eq!(mux_recv_until_quiet(&mut rx).await?.len(), 0);
+        // The connection serves only one substream at a time.
+        write_mux_msg(&mut netout, &MuxMsg::new(MuxKind::Open, 2, query.clone())).await?;
+        let msgs = mux_recv_until_quiet(&mut rx).await?;
+        assert_eq!(msgs.last().map(|x| (x.kind, x.sid)), Some((MuxKind::End, 2)));
+        write_mux_msg(&mut netout, &MuxMsg::credit(1, 4096)).await?;
+        let msgs = mux_recv_until_quiet(&mut rx).await?;
+        assert!(data_bytes(&msgs, 1) >= 4096);
+        // After the cancel, the task of the substream is gone and more credit has no effect.
+        write_mux_msg(&mut netout, &MuxMsg::new(MuxKind::Cancel, 1, Bytes::new())).await?;
+        mux_recv_until_quiet(&mut rx).await?;
+        write_mux_msg(&mut netout, &MuxMsg::credit(1, 1024 * 1024)).await?;
+        let msgs = mux_recv_until_quiet(&mut rx).await?;
+        assert_eq!(msgs.len(), 0);
+        // The id is free again.
+        write_mux_msg(&mut netout, &MuxMsg::new(MuxKind::Open, 1, query.clone())).await?;
+        write_mux_msg(&mut netout, &MuxMsg::credit(1, 1024)).await?;
+        let msgs = mux_recv_until_quiet(&mut rx).await?;
+        assert!(data_bytes(&msgs, 1) >= 1024);
+        // A query without the node token ends the connection.
+        let query = mux_open_payload(&qu, None)?;
+        write_mux_msg(&mut netout, &MuxMsg::new(MuxKind::Open, 3, query)).await?;
+        assert!(jh.await.unwrap().is_err());
+        Ok::<_, Error>(())
+    };
+    taskrun::run(fut).unwrap();
+}
@@ -0,0 +1,289 @@
+pub mod memory;
+
+use crate::channelconfig::channel_config_test_backend;
+use crate::scylla::scylla_channel_event_stream;
+use crate::scylla::scylla_channel_status_stream;
+use err::Error;
+use futures_util::Future;
+use futures_util::Stream;
+use items_0::streamitem::Sitemty;
+use items_0::timebin::TimeBinned;
+use items_2::channelevents::ChannelEvents;
+use memory::MemoryEventSource;
+use netpod::log::*;
+use netpod::range::evrange::NanoRange;
+use netpod::ChannelTypeConfigGen;
+use netpod::NodeConfigCached;
+use netpod::PreBinnedPatchCoordEnum;
+use netpod::ReqCtxArc;
+use netpod::SfChFetchInfo;
+use netpod::SfDbChannel;
+use query::api4::events::EventsSubQuery;
+use std::pin::Pin;
+use std::sync::Arc;
+use streams::generators::GenerateF64V00;
+use streams::generators::GenerateI32V00;
+use streams::generators::GenerateI32V01;
+
+const TEST_BACKEND: &str = "testbackend-00";
+
+pub type EventsStreamBox = Pin<Box<dyn Stream<Item = Sitemty<ChannelEvents>> + Send>>;
+
+pub type EventSourceFut<'a, T> = Pin<Box<dyn Future<Output = Result<T, Error>> + Send + 'a>>;
+
+/// The storage of a backend as seen by a node.
+pub trait EventSource: Send + Sync {
+    fn channel_config<'a>(
+        &'a self,
+        range: NanoRange,
+        channel: SfDbChannel,
+        ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, Option<ChannelTypeConfigGen>>;
+
+    /// The events of the channel in the range of the query.
+    /// If the transform needs it, the stream also contains the last event before the range.
+    fn events<'a>(
+        &'a self,
+        subq: EventsSubQuery,
+        reqctx: ReqCtxArc,
+        ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, EventsStreamBox>;
+
+    /// The connection status changes of the channel in the range of the query,
+    /// `None` if the backend does not record them.
+    fn status<'a>(
+        &'a self,
+        _subq: &'a EventsSubQuery,
+        _ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, Option<EventsStreamBox>> {
+        Box::pin(futures_util::future::ready(Ok(None)))
+    }
+
+    /// Already binned data for the patch, if the backend keeps a cache of bins.
+    fn cached_bins<'a>(
+        &'a self,
+        _ch_conf: &'a ChannelTypeConfigGen,
+        _coord: &'a PreBinnedPatchCoordEnum,
+        _ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, Option<Box<dyn TimeBinned>>> {
+        Box::pin(futures_util::future::ready(Ok(None)))
+    }
+}
+
+/// Returns the event source registered for the backend.
+/// JSON backends are listed by name in the cluster config, the test backend is always available,
+/// any other backend name is served by the storage the node is configured with.
+pub async fn event_source(backend: &str, ncc: &NodeConfigCached) -> Result<Arc<dyn EventSource>, Error> {
+    if backend == TEST_BACKEND {
+        Ok(Arc::new(TestEventSource))
+    } else if let Some(conf) = ncc
+        .node_config
+        .cluster
+        .json_backends
+        .iter()
+        .find(|x| x.backend == backend)
+    {
+        Ok(MemoryEventSource::for_config(conf).await?)
+    } else if ncc.node_config.cluster.scylla.is_some() {
+        Ok(Arc::new(ScyllaEventSource))
+    } else if ncc.node.channel_archiver.is_some() || ncc.node.archiver_appliance.is_some() {
+        Ok(Arc::new(ArchiverEventSource))
+    } else if ncc.node.sf_databuffer.is_some() {
+        Ok(Arc::new(DatabufferEventSource))
+    } else {
+        Err(
+            Error::with_msg_no_trace(format!("no event source for backend {backend}"))
+                .add_public_msg(format!("no event source for backend {backend}")),
+        )
+    }
+}
+
+fn test_channel_name_error(chn: &str) -> Error {
+    Error::with_msg_no_trace(format!(
+        "make_channel_events_stream_data can not understand test channel name: {chn:?}"
+    ))
+}
+
+struct TestEventSource;
+
+impl EventSource for TestEventSource {
+    fn channel_config<'a>(
+        &'a self,
+        _range: NanoRange,
+        channel: SfDbChannel,
+        _ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, Option<ChannelTypeConfigGen>> {
+        Box::pin(async move { Ok(Some(channel_config_test_backend(channel)?)) })
+    }
+
+    fn events<'a>(
+        &'a self,
+        subq: EventsSubQuery,
+        _reqctx: ReqCtxArc,
+        ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, EventsStreamBox> {
+        let fut = async move {
+            debug!("use test backend data  {}", TEST_BACKEND);
+            let node_count = ncc.node_config.cluster.nodes.len() as u64;
+            let node_ix = ncc.ix as u64;
+            let chn = subq.name();
+            let range = subq.range().clone();
+            let one_before = subq.need_one_before_range();
+            if chn == "test-gen-i32-dim0-v00" {
+                Ok(Box::pin(GenerateI32V00::new(node_ix, node_count, range, one_before)) as EventsStreamBox)
+            } else if chn == "test-gen-i32-dim0-v01" {
+                Ok(Box::pin(GenerateI32V01::new(node_ix, node_count, range, one_before)) as EventsStreamBox)
+            } else if chn == "test-gen-f64-dim1-v00" {
+                Ok(Box::pin(GenerateF64V00::new(node_ix, node_count, range, one_before)) as EventsStreamBox)
+            } else {
+                let na: Vec<_> = chn.split("-").collect();
+                if na.len() != 3 || na[0] != "inmem" || na[1] != "d0" {
+                    Err(test_channel_name_error(chn))
+                } else if na[2] == "i32" || na[2] == "f32" {
+                    //generator::generate_i32(node_ix, node_count, range)
+                    //generator::generate_f32(node_ix, node_count, range)
+                    Err(
+                        Error::with_msg_no_trace(format!("test channel {chn:?} has no generator"))
+                            .add_public_msg(format!("test channel {chn:?} has no generator")),
+                    )
+                } else {
+                    Err(test_channel_name_error(chn))
+                }
+            }
+        };
+        Box::pin(fut)
+    }
+}
+
+struct ScyllaEventSource;
+
+impl EventSource for ScyllaEventSource {
+    fn channel_config<'a>(
+        &'a self,
+        _range: NanoRange,
+        channel: SfDbChannel,
+        ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, Option<ChannelTypeConfigGen>> {
+        let fut = async move {
+            debug!("try to get ChConf for scylla type backend");
+            let ret = dbconn::channelconfig::chconf_from_scylla_type_backend(&channel, ncc)
+                .await
+                .map_err(Error::from)?;
+            Ok(Some(ChannelTypeConfigGen::Scylla(ret)))
+        };
+        Box::pin(fut)
+    }
+
+    fn events<'a>(
+        &'a self,
+        subq: EventsSubQuery,
+        _reqctx: ReqCtxArc,
+        ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, EventsStreamBox> {
+        let fut = async move {
+            let scyconf = ncc
+                .node_config
+                .cluster
+                .scylla
+                .as_ref()
+                .ok_or_else(|| Error::with_msg_no_trace("no scylla configured"))?;
+            let cfg = subq.ch_conf().to_scylla()?;
+            scylla_channel_event_stream(subq, cfg, scyconf, ncc).await
+        };
+        Box::pin(fut)
+    }
+
+    fn status<'a>(
+        &'a self,
+        subq: &'a EventsSubQuery,
+        ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, Option<EventsStreamBox>> {
+        let fut = async move {
+            let scyconf = ncc
+                .node_config
+                .cluster
+                .scylla
+                .as_ref()
+                .ok_or_else(|| Error::with_msg_no_trace("no scylla configured"))?;
+            let cfg = subq.ch_conf().to_scylla()?;
+            let ret = scylla_channel_status_stream(subq, &cfg, scyconf).await?;
+            Ok(Some(ret))
+        };
+        Box::pin(fut)
+    }
+}
+
+struct ArchiverEventSource;
+
+impl EventSource for ArchiverEventSource {
+    fn channel_config<'a>(
+        &'a self,
+        _range: NanoRange,
+        _channel: SfDbChannel,
+        _ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, Option<ChannelTypeConfigGen>> {
+        Box::pin(futures_util::future::ready(Err(Error::with_msg_no_trace(
+            "archapp not built",
+        ))))
+    }
+
+    fn events<'a>(
+        &'a self,
+        _subq: EventsSubQuery,
+        _reqctx: ReqCtxArc,
+        _ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, EventsStreamBox> {
+        Box::pin(futures_util::future::ready(Err(Error::with_msg_no_trace(
+            "archapp not built",
+        ))))
+    }
+}
+
+struct DatabufferEventSource;
+
+impl EventSource for DatabufferEventSource {
+    fn channel_config<'a>(
+        &'a self,
+        range: NanoRange,
+        channel: SfDbChannel,
+        ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, Option<ChannelTypeConfigGen>> {
+        let fut = async move {
+            debug!("channel_config  channel {channel:?}");
+            let k = disk::channelconfig::channel_config_best_match(range, channel.clone(), ncc)
+                .await
+                .map_err(|e| Error::from(e.to_string()))?;
+            match k {
+                Some(config) => {
+                    debug!("channel_config  config  {config:?}");
+                    let ret = SfChFetchInfo::new(
+                        config.channel.backend(),
+                        config.channel.name(),
+                        config.keyspace,
+                        config.time_bin_size,
+                        config.byte_order,
+                        config.scalar_type,
+                        config.shape,
+                    );
+                    let ret = ChannelTypeConfigGen::SfDatabuffer(ret);
+                    Ok(Some(ret))
+                }
+                None => Ok(None),
+            }
+        };
+        Box::pin(fut)
+    }
+
+    fn events<'a>(
+        &'a self,
+        subq: EventsSubQuery,
+        reqctx: ReqCtxArc,
+        ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, EventsStreamBox> {
+        let fut = async move {
+            let cfg = subq.ch_conf().to_sf_databuffer()?;
+            Ok(disk::raw::conn::make_event_pipe(subq, cfg, reqctx, ncc).await?)
+        };
+        Box::pin(fut)
+    }
+}
@@ -0,0 +1,310 @@
+use super::EventSource;
+use super::EventSourceFut;
+use super::EventsStreamBox;
+use err::Error;
+use items_0::scalar_ops::ScalarOps;
+use items_0::streamitem::sitem_data;
+use items_0::streamitem::RangeCompletableItem;
+use items_0::streamitem::StreamItem;
+use items_0::Appendable;
+use items_0::Empty;
+use items_0::Events;
+use items_2::channelevents::ChannelEvents;
+use items_2::eventsdim0::EventsDim0;
+use items_2::eventsdim1::EventsDim1;
+use netpod::log::*;
+use netpod::range::evrange::NanoRange;
+use netpod::range::evrange::SeriesRange;
+use netpod::ChConf;
+use netpod::ChannelTypeConfigGen;
+use netpod::JsonBackend;
+use netpod::NodeConfigCached;
+use netpod::ReqCtxArc;
+use netpod::ScalarType;
+use netpod::SfDbChannel;
+use netpod::Shape;
+use query::api4::events::EventsSubQuery;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use taskrun::tokio;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MemoryEvent {
+    pub ts: u64,
+    #[serde(default)]
+    pub pulse: u64,
+    pub value: JsonValue,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MemoryChannel {
+    pub name: String,
+    #[serde(rename = "scalarType")]
+    pub scalar_type: ScalarType,
+    pub shape: Shape,
+    #[serde(default)]
+    pub events: Vec<MemoryEvent>,
+}
+
+/// The content of the JSON file of a backend.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct MemoryChannels {
+    pub channels: Vec<MemoryChannel>,
+}
+
+struct Registered {
+    mtime: Option<SystemTime>,
+    source: Arc<MemoryEventSource>,
+}
+
+static SOURCES: Mutex<BTreeMap<String, Registered>> = Mutex::new(BTreeMap::new());
+
+/// Serves channels and events held in memory.
+/// All events are served by the first node of the cluster, so that the merged result contains each event once.
+pub struct MemoryEventSource {
+    backend: String,
+    channels: BTreeMap<String, (u64, MemoryChannel)>,
+}
+
+impl MemoryEventSource {
+    pub fn new(backend: &str, channels: MemoryChannels) -> Self {
+        let channels = channels
+            .channels
+            .into_iter()
+            .enumerate()
+            .map(|(i, mut ch)| {
+                ch.events.sort_by_key(|x| x.ts);
+                (ch.name.clone(), (1 + i as u64, ch))
+            })
+            .collect();
+        Self {
+            backend: backend.into(),
+            channels,
+        }
+    }
+
+    /// Makes the channels available under the backend name, replacing whatever was registered before.
+    /// Used by tests and by deployments which feed the data without a file.
+    pub fn register(backend: &str, channels: MemoryChannels) {
+        let source = Arc::new(Self::new(backend, channels));
+        let reg = Registered { mtime: None, source };
+        SOURCES.lock().unwrap().insert(backend.into(), reg);
+    }
+
+    /// Returns the source for the configured backend.
+    /// The JSON file is read again when its modification time changes.
+    /// The file is read without holding the lock, so that other backends are not blocked meanwhile.
+    pub async fn for_config(conf: &JsonBackend) -> Result<Arc<Self>, Error> {
+        let mtime = match &conf.path {
+            Some(path) => Some(tokio::fs::metadata(path).await?.modified()?),
+            None => None,
+        };
+        if let Some(reg) = SOURCES.lock().unwrap().get(&conf.backend) {
+            if conf.path.is_none() || reg.mtime == mtime {
+                return Ok(reg.source.clone());
+            }
+        }
+        let channels = match &conf.path {
+            Some(path) => Self::read_file(path).await?,
+            None => MemoryChannels::default(),
+        };
+        let source = Arc::new(Self::new(&conf.backend, channels));
+        let reg = Registered {
+            mtime,
+            source: source.clone(),
+        };
+        SOURCES.lock().unwrap().insert(conf.backend.clone(), reg);
+        Ok(source)
+    }
+
+    async fn read_file(path: &Path) -> Result<MemoryChannels, Error> {
+        debug!("read json backend file {}", path.display());
+        let buf = tokio::fs::read(path).await?;
+        let ret = serde_json::from_slice(&buf)?;
+        Ok(ret)
+    }
+
+    fn channel(&self, name: &str) -> Option<&(u64, MemoryChannel)> {
+        self.channels.get(name)
+    }
+
+    pub fn ch_conf(&self, name: &str) -> Option<ChConf> {
+        self.channel(name).map(|(series, ch)| {
+            ChConf::new(
+                &self.backend,
+                *series,
+                ch.scalar_type.clone(),
+                ch.shape.clone(),
+                &ch.name,
+            )
+        })
+    }
+
+    /// The events in the range, preceded by the last event before the range if `one_before` is set.
+    /// Pulse ranges select by pulse id.
+    pub fn events_in_range(&self, name: &str, range: &SeriesRange, one_before: bool) -> Result<Box<dyn Events>, Error> {
+        let (_, ch) = self
+            .channel(name)
+            .ok_or_else(|| Error::with_public_msg_no_trace(format!("no channel {name} in backend {}", self.backend)))?;
+        let key = |ev: &MemoryEvent| if range.is_pulse() { ev.pulse } else { ev.ts };
+        let (beg, end) = (range.beg_u64(), range.end_u64());
+        let mut evs: Vec<_> = ch.events.iter().filter(|ev| key(ev) >= beg && key(ev) < end).collect();
+        if one_before {
+            if let Some(ev) = ch.events.iter().filter(|ev| key(ev) < beg).last() {
+                evs.insert(0, ev);
+            }
+        }
+        match &ch.shape {
+            Shape::Scalar => match &ch.scalar_type {
+                ScalarType::U8 => events_dim0::<u8>(&evs),
+                ScalarType::U16 => events_dim0::<u16>(&evs),
+                ScalarType::U32 => events_dim0::<u32>(&evs),
+                ScalarType::U64 => events_dim0::<u64>(&evs),
+                ScalarType::I8 => events_dim0::<i8>(&evs),
+                ScalarType::I16 => events_dim0::<i16>(&evs),
+                ScalarType::I32 => events_dim0::<i32>(&evs),
+                ScalarType::I64 => events_dim0::<i64>(&evs),
+                ScalarType::F32 => events_dim0::<f32>(&evs),
+                ScalarType::F64 => events_dim0::<f64>(&evs),
+                ScalarType::BOOL => events_dim0::<bool>(&evs),
+                ScalarType::STRING => events_dim0::<String>(&evs),
+            },
+            Shape::Wave(_) => match &ch.scalar_type {
+                ScalarType::U8 => events_dim1::<u8>(&evs),
+                ScalarType::U16 => events_dim1::<u16>(&evs),
+                ScalarType::U32 => events_dim1::<u32>(&evs),
+                ScalarType::U64 => events_dim1::<u64>(&evs),
+                ScalarType::I8 => events_dim1::<i8>(&evs),
+                ScalarType::I16 => events_dim1::<i16>(&evs),
+                ScalarType::I32 => events_dim1::<i32>(&evs),
+                ScalarType::I64 => events_dim1::<i64>(&evs),
+                ScalarType::F32 => events_dim1::<f32>(&evs),
+                ScalarType::F64 => events_dim1::<f64>(&evs),
+                ScalarType::BOOL => events_dim1::<bool>(&evs),
+                ScalarType::STRING => events_dim1::<String>(&evs),
+            },
+            Shape::Image(..) => Err(Error::with_public_msg_no_trace(format!(
+                "json backend does not support image channel {name}"
+            ))),
+        }
+    }
+}
+
+fn events_dim0<T>(evs: &[&MemoryEvent]) -> Result<Box<dyn Events>, Error>
+where
+    T: ScalarOps + DeserializeOwned,
+{
+    let mut ret = EventsDim0::<T>::empty();
+    for ev in evs {
+        let value: T = serde_json::from_value(ev.value.clone())?;
+        ret.push(ev.ts, ev.pulse, value);
+    }
+    Ok(Box::new(ret))
+}
+
+fn events_dim1<T>(evs: &[&MemoryEvent]) -> Result<Box<dyn Events>, Error>
+where
+    T: ScalarOps + DeserializeOwned,
+{
+    let mut ret = EventsDim1::<T>::empty();
+    for ev in evs {
+        let value: Vec<T> = serde_json::from_value(ev.value.clone())?;
+        ret.push(ev.ts, ev.pulse, value);
+    }
+    Ok(Box::new(ret))
+}
+
+impl EventSource for MemoryEventSource {
+    /// The channels of a JSON backend are described like the channels of a Scylla backend, by the series
+    /// which `new` assigns. The events are looked up by the backend name through `event_source`,
+    /// never by the config variant. The places which read Scylla directly for a config, the cost estimate
+    /// and the status endpoints, skip the JSON backends.
+    fn channel_config<'a>(
+        &'a self,
+        _range: NanoRange,
+        channel: SfDbChannel,
+        _ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, Option<ChannelTypeConfigGen>> {
+        let ret = self.ch_conf(channel.name()).map(ChannelTypeConfigGen::Scylla);
+        Box::pin(futures_util::future::ready(Ok(ret)))
+    }
+
+    fn events<'a>(
+        &'a self,
+        subq: EventsSubQuery,
+        _reqctx: ReqCtxArc,
+        ncc: &'a NodeConfigCached,
+    ) -> EventSourceFut<'a, EventsStreamBox> {
+        let fut = async move {
+            let mut items = Vec::new();
+            if ncc.ix == 0 {
+                let one_before = subq.need_one_before_range();
+                let evs = self.events_in_range(subq.name(), subq.range(), one_before)?;
+                items.push(sitem_data(ChannelEvents::Events(evs)));
+            }
+            items.push(Ok(StreamItem::DataItem(RangeCompletableItem::RangeComplete)));
+            Ok(Box::pin(futures_util::stream::iter(items)) as EventsStreamBox)
+        };
+        Box::pin(fut)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use netpod::range::evrange::PulseRange;
+
+    fn channels() -> MemoryChannels {
+        let js = serde_json::json!({
+            "channels": [
+                {
+                    "name": "a", "scalarType": "f64", "shape": [],
+                    "events": [
+                        {"ts": 30, "pulse": 3, "value": 3.5},
+                        {"ts": 10, "pulse": 1, "value": 1.5},
+                        {"ts": 20, "pulse": 2, "value": 2.5},
+                    ],
+                },
+                {
+                    "name": "b", "scalarType": "i16", "shape": [2],
+                    "events": [{"ts": 10, "value": [4, 5]}],
+                },
+            ]
+        });
+        serde_json::from_value(js).unwrap()
+    }
+
+    #[test]
+    fn memory_events_in_range() {
+        let src = MemoryEventSource::new("json-00", channels());
+        let range = SeriesRange::TimeRange(NanoRange { beg: 15, end: 30 });
+        let evs = src.events_in_range("a", &range, false).unwrap();
+        assert_eq!(evs.tss(), &[20]);
+        let evs = src.events_in_range("a", &range, true).unwrap();
+        assert_eq!(evs.tss(), &[10, 20]);
+        let range = SeriesRange::PulseRange(PulseRange { beg: 2, end: 4 });
+        let evs = src.events_in_range("a", &range, false).unwrap();
+        assert_eq!(evs.pulses(), &[2, 3]);
+        let range = SeriesRange::TimeRange(NanoRange { beg: 0, end: 100 });
+        let evs = src.events_in_range("b", &range, false).unwrap();
+        assert_eq!(evs.len(), 1);
+        assert!(src.events_in_range("c", &range, false).is_err());
+    }
+
+    #[test]
+    fn memory_ch_conf() {
+        let src = MemoryEventSource::new("json-00", channels());
+        let conf = src.ch_conf("b").unwrap();
+        assert_eq!(conf.backend(), "json-00");
+        assert_eq!(conf.series(), 2);
+        assert_eq!(conf.shape(), &Shape::Wave(2));
+        assert!(src.ch_conf("c").is_none());
+    }
+}
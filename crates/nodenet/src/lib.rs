@@ -1,4 +1,5 @@
 pub mod channelconfig;
 pub mod configquorum;
 pub mod conn;
+pub mod eventsource;
 pub mod scylla;
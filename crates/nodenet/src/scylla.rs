@@ -1,11 +1,16 @@
 use err::Error;
 use futures_util::Stream;
 use futures_util::StreamExt;
+use items_0::streamitem::sitem_data;
 use items_0::streamitem::RangeCompletableItem;
 use items_0::streamitem::Sitemty;
 use items_0::streamitem::StreamItem;
 use items_2::channelevents::ChannelEvents;
+use items_2::channelevents::ChannelStatus;
+use items_2::channelevents::ConnStatus;
+use items_2::channelevents::ConnStatusEvent;
 use netpod::log::*;
+use netpod::range::evrange::NanoRange;
 use netpod::ChConf;
 use netpod::NodeConfigCached;
 use netpod::ScyllaConfig;
@@ -19,11 +24,9 @@ pub async fn scylla_channel_event_stream(
     scyco: &ScyllaConfig,
     _ncc: &NodeConfigCached,
 ) -> Result<Pin<Box<dyn Stream<Item = Sitemty<ChannelEvents>> + Send>>, Error> {
-    // TODO depends in general on the query
-    // TODO why both in PlainEventsQuery and as separate parameter? Check other usages.
-    let do_one_before_range = false;
+    let do_one_before_range = evq.need_one_before_range();
     // TODO use better builder pattern with shortcuts for production and dev defaults
-    let scy = scyllaconn::create_scy_session(scyco).await?;
+    let stmts = scyllaconn::shared_stmts(scyco).await?;
     let series = chconf.series();
     let scalar_type = chconf.scalar_type();
     let shape = chconf.shape();
@@ -37,12 +40,13 @@ pub async fn scylla_channel_event_stream(
         scalar_type.clone(),
         shape.clone(),
         with_values,
-        scy,
+        stmts,
         do_test_stream_error,
-    );
+    )
+    .with_partitions_concurrent(scyco.partitions_concurrent);
     let stream = stream
         .map(move |item| match &item {
-            Ok(k) => match k {
+            Ok(StreamItem::DataItem(RangeCompletableItem::Data(k))) => match k {
                 ChannelEvents::Events(k) => {
                     let n = k.len();
                     let d = evq.event_delay();
@@ -50,7 +54,7 @@ pub async fn scylla_channel_event_stream(
                 }
                 ChannelEvents::Status(_) => (item, 1, None),
             },
-            Err(_) => (item, 1, None),
+            _ => (item, 1, None),
         })
         .then(|(item, n, d)| async move {
             if let Some(d) = d {
@@ -58,24 +62,36 @@ pub async fn scylla_channel_event_stream(
                 tokio::time::sleep(d.saturating_mul(n as _)).await;
             }
             item
-        })
-        .map(|item| {
-            let item = match item {
-                Ok(item) => match item {
-                    ChannelEvents::Events(item) => {
-                        let item = ChannelEvents::Events(item);
-                        let item = Ok(StreamItem::DataItem(RangeCompletableItem::Data(item)));
-                        item
-                    }
-                    ChannelEvents::Status(item) => {
-                        let item = ChannelEvents::Status(item);
-                        let item = Ok(StreamItem::DataItem(RangeCompletableItem::Data(item)));
-                        item
-                    }
-                },
-                Err(e) => Err(e),
-            };
-            item
         });
     Ok(Box::pin(stream))
 }
+
+/// The connection status changes of the channel in the range of the query.
+pub async fn scylla_channel_status_stream(
+    evq: &EventsSubQuery,
+    chconf: &ChConf,
+    scyco: &ScyllaConfig,
+) -> Result<Pin<Box<dyn Stream<Item = Sitemty<ChannelEvents>> + Send>>, Error> {
+    let stmts = scyllaconn::shared_stmts(scyco).await?;
+    let range: NanoRange = evq.range().try_into()?;
+    let status = scyllaconn::status::StatusStreamScylla::new(
+        chconf.series(),
+        range,
+        evq.need_one_before_range(),
+        stmts.scy().clone(),
+    )
+    .map(|item| {
+        item.map(|ev| {
+            let status = match ev.status {
+                ChannelStatus::Connect => ConnStatus::Connect,
+                ChannelStatus::Disconnect => ConnStatus::Disconnect,
+            };
+            ChannelEvents::Status(Some(ConnStatusEvent::new(ev.ts, status)))
+        })
+        .and_then(sitem_data)
+    })
+    .chain(futures_util::stream::iter([Ok(StreamItem::DataItem(
+        RangeCompletableItem::RangeComplete,
+    ))]));
+    Ok(Box::pin(status))
+}
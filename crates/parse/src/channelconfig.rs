@@ -66,7 +66,7 @@ where
     Err(nom::Err::Error(e))
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum CompressionMethod {
     BitshuffleLZ4,
 }
@@ -123,6 +123,41 @@ impl ConfigEntry {
         };
         Ok(ret)
     }
+
+    pub fn basics(&self) -> Result<ConfigEntryBasics, Error> {
+        let compression = if self.is_compressed {
+            Some(
+                self.compression_method
+                    .clone()
+                    .unwrap_or(CompressionMethod::BitshuffleLZ4),
+            )
+        } else {
+            None
+        };
+        let ret = ConfigEntryBasics {
+            scalar_type: self.scalar_type.clone(),
+            shape: self.to_shape()?,
+            byte_order: self.byte_order.clone(),
+            compression,
+            keyspace: self.ks as u8,
+            bin_size: self.bs.clone(),
+        };
+        Ok(ret)
+    }
+}
+
+/// The parts of a config entry which decide how the data files of the channel are read.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ConfigEntryBasics {
+    #[serde(rename = "scalarType")]
+    pub scalar_type: ScalarType,
+    pub shape: Shape,
+    #[serde(rename = "byteOrder")]
+    pub byte_order: ByteOrder,
+    pub compression: Option<CompressionMethod>,
+    pub keyspace: u8,
+    #[serde(rename = "binSize")]
+    pub bin_size: DtNano,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -533,6 +568,16 @@ pub fn extract_matching_config_entry<'a>(
     }
 }
 
+/// The entries of the config ordered by time, each one valid from its timestamp until the next one starts.
+pub fn config_history(channel_config: &ChannelConfigs) -> Result<Vec<(TsNano, ConfigEntryBasics)>, Error> {
+    let mut ret = Vec::new();
+    for e in &channel_config.entries {
+        ret.push((e.ts.clone(), e.basics()?));
+    }
+    ret.sort_by_key(|x| x.0.ns());
+    Ok(ret)
+}
+
 #[cfg(test)]
 mod test {
     use super::parse_config;
@@ -1,2 +1,3 @@
 pub mod binned;
 pub mod events;
+pub mod latest;
@@ -1,6 +1,7 @@
 use crate::transform::TransformQuery;
 use err::Error;
 use netpod::get_url_query_pairs;
+use netpod::is_false;
 use netpod::log::*;
 use netpod::query::CacheUsage;
 use netpod::query::PulseRangeQuery;
@@ -42,6 +43,8 @@ pub struct BinnedQuery {
     pub merger_out_len_max: Option<usize>,
     #[serde(default, skip_serializing_if = "Option::is_none")]
     test_do_wasm: Option<String>,
+    #[serde(default, skip_serializing_if = "is_false", rename = "withStatus")]
+    with_status: bool,
 }
 
 impl BinnedQuery {
@@ -58,6 +61,7 @@ impl BinnedQuery {
             timeout: None,
             merger_out_len_max: None,
             test_do_wasm: None,
+            with_status: false,
         }
     }
 
@@ -150,6 +154,12 @@ impl BinnedQuery {
             None => None,
         }
     }
+
+    /// Whether connection status changes are read, so that time-weighted bins
+    /// do not carry the last value across periods where the channel was disconnected.
+    pub fn with_status(&self) -> bool {
+        self.with_status
+    }
 }
 
 impl HasBackend for BinnedQuery {
@@ -210,6 +220,7 @@ impl FromUrl for BinnedQuery {
                 .get("mergerOutLenMax")
                 .map_or(Ok(None), |k| k.parse().map(|k| Some(k)))?,
             test_do_wasm: pairs.get("testDoWasm").map(|x| String::from(x)),
+            with_status: pairs.get("withStatus").map_or("false", |x| x.as_ref()) == "true",
         };
         debug!("BinnedQuery::from_url  {:?}", ret);
         Ok(ret)
@@ -250,5 +261,8 @@ impl AppendToUrl for BinnedQuery {
         if let Some(x) = &self.test_do_wasm {
             g.append_pair("testDoWasm", &x);
         }
+        if self.with_status {
+            g.append_pair("withStatus", "true");
+        }
     }
 }
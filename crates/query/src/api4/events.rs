@@ -11,6 +11,7 @@ use netpod::AppendToUrl;
 use netpod::ByteSize;
 use netpod::ChannelTypeConfigGen;
 use netpod::DiskIoTune;
+use netpod::FrameCompression;
 use netpod::FromUrl;
 use netpod::HasBackend;
 use netpod::HasTimeout;
@@ -52,6 +53,13 @@ pub struct PlainEventsQuery {
     merger_out_len_max: Option<usize>,
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     create_errors: Vec<String>,
+    #[serde(default, skip_serializing_if = "is_false", rename = "withStatus")]
+    with_status: bool,
+    #[serde(default, skip_serializing_if = "is_false", rename = "dryRun")]
+    dry_run: bool,
+    // Local to the node which collects the result.
+    #[serde(skip)]
+    events_max_by_budget: bool,
 }
 
 impl PlainEventsQuery {
@@ -74,6 +82,9 @@ impl PlainEventsQuery {
             test_do_wasm: None,
             merger_out_len_max: None,
             create_errors: Vec::new(),
+            with_status: false,
+            dry_run: false,
+            events_max_by_budget: false,
         }
     }
 
@@ -86,7 +97,15 @@ impl PlainEventsQuery {
     }
 
     pub fn one_before_range(&self) -> bool {
-        self.transform.need_one_before_range()
+        self.one_before_range || self.transform.need_one_before_range()
+    }
+
+    pub fn set_one_before_range(&mut self, k: bool) {
+        self.one_before_range = k;
+    }
+
+    pub fn set_timeout(&mut self, k: Duration) {
+        self.timeout = Some(k);
     }
 
     pub fn transform(&self) -> &TransformQuery {
@@ -105,6 +124,16 @@ impl PlainEventsQuery {
         self.events_max.unwrap_or(1024 * 128)
     }
 
+    /// Limits the events to the query budget: the result is cut at `events_max` and tells where to continue.
+    pub fn set_events_max_by_budget(&mut self, k: u64) {
+        self.events_max = Some(k);
+        self.events_max_by_budget = true;
+    }
+
+    pub fn events_max_by_budget(&self) -> bool {
+        self.events_max_by_budget
+    }
+
     // A rough indication on how many bytes this request is allowed to return. Otherwise, the result should
     // be a partial result.
     pub fn bytes_max(&self) -> u64 {
@@ -172,6 +201,20 @@ impl PlainEventsQuery {
     pub fn create_errors_contains(&self, x: &str) -> bool {
         self.create_errors.contains(&String::from(x))
     }
+
+    /// Whether connection status changes are merged into the events.
+    pub fn with_status(&self) -> bool {
+        self.with_status
+    }
+
+    pub fn set_with_status(&mut self, k: bool) {
+        self.with_status = k;
+    }
+
+    /// Whether only the estimated cost of the query is asked for.
+    pub fn dry_run(&self) -> bool {
+        self.dry_run
+    }
 }
 
 impl HasBackend for PlainEventsQuery {
@@ -244,6 +287,9 @@ impl FromUrl for PlainEventsQuery {
                 .get("create_errors")
                 .map(|x| x.split(",").map(|x| x.to_string()).collect())
                 .unwrap_or(Vec::new()),
+            with_status: pairs.get("withStatus").map_or("false", |x| x.as_ref()) == "true",
+            dry_run: pairs.get("dryRun").map_or("false", |x| x.as_ref()) == "true",
+            events_max_by_budget: false,
         };
         Ok(ret)
     }
@@ -294,6 +340,12 @@ impl AppendToUrl for PlainEventsQuery {
         if self.create_errors.len() != 0 {
             g.append_pair("create_errors", &self.create_errors.join(","));
         }
+        if self.with_status {
+            g.append_pair("withStatus", "true");
+        }
+        if self.dry_run {
+            g.append_pair("dryRun", "true");
+        }
     }
 }
 
@@ -315,6 +367,10 @@ impl EventsSubQuerySelect {
         }
     }
 
+    pub fn range(&self) -> &SeriesRange {
+        &self.range
+    }
+
     pub fn wasm1(&self) -> Option<&str> {
         match &self.wasm1 {
             Some(x) => Some(&x),
@@ -325,6 +381,15 @@ impl EventsSubQuerySelect {
     pub fn set_wasm1(&mut self, x: String) {
         self.wasm1 = Some(x);
     }
+
+    /// The same selection for another channel config and range, used for each channel behind an alias.
+    pub fn for_segment(&self, ch_conf: ChannelTypeConfigGen, range: SeriesRange) -> Self {
+        Self {
+            ch_conf,
+            range,
+            ..self.clone()
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -336,6 +401,14 @@ pub struct EventsSubQuerySettings {
     buf_len_disk_io: Option<usize>,
     queue_len_disk_io: Option<usize>,
     create_errors: Vec<String>,
+    #[serde(default)]
+    with_status: bool,
+    #[serde(default)]
+    one_before_range: bool,
+    #[serde(default)]
+    frame_compression: Option<FrameCompression>,
+    #[serde(default)]
+    merger_out_len_max: Option<usize>,
 }
 
 impl Default for EventsSubQuerySettings {
@@ -348,6 +421,10 @@ impl Default for EventsSubQuerySettings {
             buf_len_disk_io: None,
             queue_len_disk_io: None,
             create_errors: Vec::new(),
+            with_status: false,
+            one_before_range: false,
+            frame_compression: None,
+            merger_out_len_max: None,
         }
     }
 }
@@ -363,6 +440,10 @@ impl From<&PlainEventsQuery> for EventsSubQuerySettings {
             // TODO add to query
             queue_len_disk_io: None,
             create_errors: value.create_errors.clone(),
+            with_status: value.with_status,
+            one_before_range: value.one_before_range,
+            frame_compression: None,
+            merger_out_len_max: value.merger_out_len_max,
         }
     }
 }
@@ -379,6 +460,10 @@ impl From<&BinnedQuery> for EventsSubQuerySettings {
             // TODO add to query
             queue_len_disk_io: None,
             create_errors: Vec::new(),
+            with_status: value.with_status(),
+            one_before_range: false,
+            frame_compression: None,
+            merger_out_len_max: value.merger_out_len_max,
         }
     }
 }
@@ -395,6 +480,10 @@ impl From<&Api1Query> for EventsSubQuerySettings {
             buf_len_disk_io: Some(disk_io_tune.read_buffer_len),
             queue_len_disk_io: Some(disk_io_tune.read_queue_len),
             create_errors: Vec::new(),
+            with_status: false,
+            one_before_range: false,
+            frame_compression: None,
+            merger_out_len_max: None,
         }
     }
 }
@@ -433,6 +522,11 @@ impl EventsSubQuery {
         &self.select.transform
     }
 
+    /// Whether the last event before the range is needed, either by the transform or by the query.
+    pub fn need_one_before_range(&self) -> bool {
+        self.settings.one_before_range || self.select.transform.need_one_before_range()
+    }
+
     pub fn ch_conf(&self) -> &ChannelTypeConfigGen {
         &self.select.ch_conf
     }
@@ -449,6 +543,11 @@ impl EventsSubQuery {
         &self.settings.event_delay
     }
 
+    /// The length of the batches which the node merges out of several streams.
+    pub fn merger_out_len_max(&self) -> usize {
+        self.settings.merger_out_len_max.unwrap_or(1024)
+    }
+
     pub fn disk_io_tune(&self) -> DiskIoTune {
         let mut tune = DiskIoTune::default();
         if let Some(x) = self.settings.buf_len_disk_io {
@@ -480,6 +579,18 @@ impl EventsSubQuery {
         self.settings.create_errors.contains(&String::from(x))
     }
 
+    pub fn with_status(&self) -> bool {
+        self.settings.with_status
+    }
+
+    pub fn frame_compression(&self) -> Option<&FrameCompression> {
+        self.settings.frame_compression.as_ref()
+    }
+
+    pub fn set_frame_compression(&mut self, x: Option<FrameCompression>) {
+        self.settings.frame_compression = x;
+    }
+
     pub fn reqid(&self) -> &str {
         &self.reqid
     }
@@ -492,11 +603,26 @@ impl EventsSubQuery {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Frame1Parts {
     query: EventsSubQuery,
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "nodeToken")]
+    node_token: Option<String>,
 }
 
 impl Frame1Parts {
     pub fn new(query: EventsSubQuery) -> Self {
-        Self { query }
+        Self {
+            query,
+            node_token: None,
+        }
+    }
+
+    /// The token by which the requesting node authenticates to the others of its cluster.
+    pub fn with_node_token(mut self, token: Option<String>) -> Self {
+        self.node_token = token;
+        self
+    }
+
+    pub fn node_token(&self) -> Option<&str> {
+        self.node_token.as_deref()
     }
 
     pub fn parts(self) -> (EventsSubQuery,) {
@@ -0,0 +1,151 @@
+use super::events::PlainEventsQuery;
+use chrono::DateTime;
+use chrono::TimeZone;
+use chrono::Utc;
+use err::Error;
+use netpod::get_url_query_pairs;
+use netpod::range::evrange::NanoRange;
+use netpod::AppendToUrl;
+use netpod::FromUrl;
+use netpod::HasBackend;
+use netpod::HasTimeout;
+use netpod::SfDbChannel;
+use netpod::ToNanos;
+use netpod::DATETIME_FMT_6MS;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use std::time::SystemTime;
+use url::Url;
+
+/// Asks for the most recent event at or before `at` for each of the channels.
+/// Without `at` the current time is used.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LatestQuery {
+    backend: String,
+    #[serde(rename = "channelNames")]
+    channels: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none", rename = "atNs")]
+    at: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "humantime_serde")]
+    timeout: Option<Duration>,
+}
+
+impl LatestQuery {
+    pub fn new(backend: String, channels: Vec<String>) -> Self {
+        Self {
+            backend,
+            channels,
+            at: None,
+            timeout: None,
+        }
+    }
+
+    pub fn channels(&self) -> &[String] {
+        &self.channels
+    }
+
+    pub fn at(&self) -> Option<u64> {
+        self.at
+    }
+
+    pub fn set_at(&mut self, at: u64) {
+        self.at = Some(at);
+    }
+
+    /// The requested time, or the current time if none was given.
+    pub fn at_or_now(&self) -> u64 {
+        match self.at {
+            Some(x) => x,
+            None => SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .map_or(0, |x| x.as_nanos() as u64),
+        }
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout.unwrap_or(Duration::from_millis(10000))
+    }
+
+    /// The events query for one of the channels: the range starts at `at` and only the event
+    /// before the range is needed, which lets each backend search backwards from `at`.
+    pub fn events_query(&self, name: &str, at: u64) -> PlainEventsQuery {
+        let channel = SfDbChannel::from_name(&self.backend, name);
+        let range = NanoRange {
+            beg: at,
+            end: at.saturating_add(1),
+        };
+        let mut ret = PlainEventsQuery::new(channel, range);
+        ret.set_one_before_range(true);
+        ret.set_timeout(self.timeout());
+        ret
+    }
+}
+
+impl HasBackend for LatestQuery {
+    fn backend(&self) -> &str {
+        &self.backend
+    }
+}
+
+impl HasTimeout for LatestQuery {
+    fn timeout(&self) -> Duration {
+        self.timeout()
+    }
+}
+
+impl FromUrl for LatestQuery {
+    fn from_url(url: &Url) -> Result<Self, Error> {
+        let pairs = get_url_query_pairs(url);
+        Self::from_pairs(&pairs)
+    }
+
+    fn from_pairs(pairs: &BTreeMap<String, String>) -> Result<Self, Error> {
+        let backend = pairs
+            .get("backend")
+            .ok_or_else(|| Error::with_public_msg_no_trace("missing backend"))?
+            .into();
+        let channels = pairs
+            .get("channelNames")
+            .ok_or_else(|| Error::with_public_msg_no_trace("missing channelNames"))?
+            .split(",")
+            .filter(|x| x.len() != 0)
+            .map(String::from)
+            .collect();
+        let at = if let Some(x) = pairs.get("atDate") {
+            Some(x.parse::<DateTime<Utc>>()?.to_nanos())
+        } else if let Some(x) = pairs.get("atNs") {
+            Some(x.parse()?)
+        } else {
+            None
+        };
+        let ret = Self {
+            backend,
+            channels,
+            at,
+            timeout: pairs
+                .get("timeout")
+                .map(|x| x.parse::<u64>().map(Duration::from_millis).ok())
+                .unwrap_or(None),
+        };
+        Ok(ret)
+    }
+}
+
+impl AppendToUrl for LatestQuery {
+    fn append_to_url(&self, url: &mut Url) {
+        let mut g = url.query_pairs_mut();
+        g.append_pair("backend", &self.backend);
+        g.append_pair("channelNames", &self.channels.join(","));
+        if let Some(x) = self.at {
+            g.append_pair(
+                "atDate",
+                &Utc.timestamp_nanos(x as i64).format(DATETIME_FMT_6MS).to_string(),
+            );
+        }
+        if let Some(x) = &self.timeout {
+            g.append_pair("timeout", &format!("{}", x.as_millis()));
+        }
+    }
+}
@@ -89,6 +89,14 @@ impl TransformQuery {
         }
     }
 
+    /// Event blobs which the nodes already decompressed.
+    pub fn for_event_blobs_uncompressed() -> Self {
+        Self {
+            event: EventTransformQuery::EventBlobsUncompressed,
+            time_binning: TimeBinningTransformQuery::None,
+        }
+    }
+
     pub fn for_time_weighted_scalar() -> Self {
         Self {
             event: EventTransformQuery::MinMaxAvgDev,
@@ -107,10 +115,14 @@ impl TransformQuery {
     pub fn is_event_blobs(&self) -> bool {
         match &self.event {
             EventTransformQuery::EventBlobsVerbatim => true,
-            EventTransformQuery::EventBlobsUncompressed => {
-                error!("TODO decide on uncompressed event blobs");
-                panic!()
-            }
+            EventTransformQuery::EventBlobsUncompressed => true,
+            _ => false,
+        }
+    }
+
+    pub fn is_event_blobs_uncompressed(&self) -> bool {
+        match &self.event {
+            EventTransformQuery::EventBlobsUncompressed => true,
             _ => false,
         }
     }
@@ -154,6 +166,11 @@ impl FromUrl for TransformQuery {
                     event: EventTransformQuery::EventBlobsVerbatim,
                     time_binning: TimeBinningTransformQuery::None,
                 }
+            } else if s == "eventBlobsUncompressed" {
+                TransformQuery {
+                    event: EventTransformQuery::EventBlobsUncompressed,
+                    time_binning: TimeBinningTransformQuery::None,
+                }
             } else if s == "fullValue" {
                 TransformQuery {
                     event: EventTransformQuery::ValueFull,
@@ -218,8 +235,7 @@ impl AppendToUrl for TransformQuery {
                 g.append_pair(key, &format!("{}", "eventBlobs"));
             }
             EventTransformQuery::EventBlobsUncompressed => {
-                // TODO
-                g.append_pair(key, &format!("{}", "eventBlobs"));
+                g.append_pair(key, &format!("{}", "eventBlobsUncompressed"));
             }
             EventTransformQuery::ValueFull => {
                 g.append_pair(key, &format!("{}", "fullValue"));
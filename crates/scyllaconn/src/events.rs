@@ -1,10 +1,18 @@
 use crate::errconv::ErrConv;
+use crate::prepared::StmtsCache;
 use crate::ScyllaSeriesRange;
+use bytes::Bytes;
 use err::Error;
+use futures_util::stream::FuturesOrdered;
 use futures_util::Future;
 use futures_util::FutureExt;
 use futures_util::Stream;
+use futures_util::StreamExt;
 use items_0::scalar_ops::ScalarOps;
+use items_0::streamitem::RangeCompletableItem;
+use items_0::streamitem::Sitemty;
+use items_0::streamitem::StatsItem;
+use items_0::streamitem::StreamItem;
 use items_0::Appendable;
 use items_0::Empty;
 use items_0::Events;
@@ -14,49 +22,95 @@ use items_2::eventsdim0::EventsDim0;
 use items_2::eventsdim1::EventsDim1;
 use netpod::log::*;
 use netpod::ScalarType;
+use netpod::ScyllaPartitionReadStats;
 use netpod::Shape;
-use scylla::Session as ScySession;
 use std::collections::VecDeque;
 use std::mem;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::Context;
 use std::task::Poll;
+use std::time::Instant;
+
+const PARTITIONS_CONCURRENT_DEFAULT: usize = 4;
 
 async fn find_ts_msp(
     series: u64,
     range: ScyllaSeriesRange,
-    scy: Arc<ScySession>,
+    stmts: Arc<StmtsCache>,
 ) -> Result<(VecDeque<u64>, VecDeque<u64>), Error> {
     trace!("find_ts_msp  series {}  {:?}", series, range);
     let mut ret1 = VecDeque::new();
     let mut ret2 = VecDeque::new();
-    // TODO use prepared statements
     let cql = "select ts_msp from ts_msp where series = ? and ts_msp < ? order by ts_msp desc limit 2";
-    let res = scy.query(cql, (series as i64, range.beg as i64)).await.err_conv()?;
-    for row in res.rows_typed_or_empty::<(i64,)>() {
-        let row = row.err_conv()?;
+    let (rows, _) = stmts.execute_page(cql, (series as i64, range.beg as i64), None).await?;
+    for row in rows {
+        let row: (i64,) = row.into_typed().err_conv()?;
         ret1.push_front(row.0 as u64);
     }
     let cql = "select ts_msp from ts_msp where series = ? and ts_msp >= ? and ts_msp < ?";
-    let res = scy
-        .query(cql, (series as i64, range.beg as i64, range.end as i64))
-        .await
-        .err_conv()?;
-    for row in res.rows_typed_or_empty::<(i64,)>() {
-        let row = row.err_conv()?;
-        ret2.push_back(row.0 as u64);
+    let mut paging_state = None;
+    loop {
+        let values = (series as i64, range.beg as i64, range.end as i64);
+        let (rows, next) = stmts.execute_page(cql, values, paging_state).await?;
+        for row in rows {
+            let row: (i64,) = row.into_typed().err_conv()?;
+            ret2.push_back(row.0 as u64);
+        }
+        match next {
+            Some(x) => paging_state = Some(x),
+            None => break,
+        }
     }
     let cql = "select ts_msp from ts_msp where series = ? and ts_msp >= ? limit 1";
-    let res = scy.query(cql, (series as i64, range.end as i64)).await.err_conv()?;
-    for row in res.rows_typed_or_empty::<(i64,)>() {
-        let row = row.err_conv()?;
+    let (rows, _) = stmts.execute_page(cql, (series as i64, range.end as i64), None).await?;
+    for row in rows {
+        let row: (i64,) = row.into_typed().err_conv()?;
         ret2.push_back(row.0 as u64);
     }
     trace!("find_ts_msp  n1 {}  n2 {}", ret1.len(), ret2.len());
     Ok((ret1, ret2))
 }
 
+/// The number of `ts_msp` partitions which a query of the range has to read,
+/// including the partition before the range which may hold the event before the range.
+pub async fn ts_msp_partition_count(
+    series: u64,
+    range: ScyllaSeriesRange,
+    stmts: Arc<StmtsCache>,
+) -> Result<u64, Error> {
+    let end = range.end;
+    let (before, within) = find_ts_msp(series, range, stmts).await?;
+    let n = within.iter().filter(|&&x| x < end).count() + before.len().min(1);
+    Ok(n as u64)
+}
+
+/// Splits the `ts_msp` partitions found for a range into those which are searched backwards for the
+/// event before the range and those which are read forward. The partition before the range is also
+/// read forward because its events may reach into the range. Without `one_before` nothing is searched
+/// backwards.
+fn plan_msp_reads(
+    msps_bck: VecDeque<u64>,
+    mut msps_fwd: VecDeque<u64>,
+    range_end: u64,
+    one_before: bool,
+) -> (VecDeque<u64>, VecDeque<u64>, bool) {
+    let mut found_one_after = false;
+    let n = if one_before {
+        msps_bck.len()
+    } else {
+        msps_bck.len().min(1)
+    };
+    for &x in msps_bck.iter().rev().take(n) {
+        if x >= range_end {
+            found_one_after = true;
+        }
+        msps_fwd.push_front(x);
+    }
+    let bck = if one_before { msps_bck } else { VecDeque::new() };
+    (bck, msps_fwd, found_one_after)
+}
+
 trait ValTy: Sized {
     type ScaTy: ScalarOps + std::default::Default;
     type ScyTy: scylla::cql_to_rust::FromCqlVal<scylla::frame::response::result::CqlValue>;
@@ -128,24 +182,68 @@ impl_scaty_array!(Vec<f32>, f32, Vec<f32>, "events_array_f32");
 impl_scaty_array!(Vec<f64>, f64, Vec<f64>, "events_array_f64");
 impl_scaty_array!(Vec<bool>, bool, Vec<bool>, "events_array_bool");
 
+impl ValTy for String {
+    type ScaTy = String;
+    type ScyTy = String;
+    type Container = EventsDim0<Self::ScaTy>;
+    fn from_scyty(inp: Self::ScyTy) -> Self {
+        inp
+    }
+    fn table_name() -> &'static str {
+        "events_scalar_string"
+    }
+    fn default() -> Self {
+        String::new()
+    }
+}
+
+impl ValTy for Vec<String> {
+    type ScaTy = String;
+    type ScyTy = Vec<String>;
+    type Container = EventsDim1<Self::ScaTy>;
+    fn from_scyty(inp: Self::ScyTy) -> Self {
+        inp
+    }
+    fn table_name() -> &'static str {
+        "events_array_string"
+    }
+    fn default() -> Self {
+        Vec::new()
+    }
+}
+
 struct ReadNextValuesOpts {
     series: u64,
     ts_msp: u64,
     range: ScyllaSeriesRange,
     fwd: bool,
     with_values: bool,
-    scy: Arc<ScySession>,
+    stmts: Arc<StmtsCache>,
+    paging_state: Option<Bytes>,
+    stats: ScyllaPartitionReadStats,
+}
+
+/// The events of one page of a partition. Either the partition continues with `next`,
+/// or it is done and `stats` cover all of its pages.
+struct PageRead {
+    events: Box<dyn Events>,
+    stats: Option<ScyllaPartitionReadStats>,
+    next: Option<ReadNextValuesOpts>,
 }
 
-async fn read_next_values<ST>(opts: ReadNextValuesOpts) -> Result<Box<dyn Events>, Error>
+type ReadValuesFut = Pin<Box<dyn Future<Output = Result<PageRead, Error>> + Send>>;
+
+async fn read_next_values<ST>(opts: ReadNextValuesOpts) -> Result<PageRead, Error>
 where
     ST: ValTy,
 {
+    let ts1 = Instant::now();
     let series = opts.series;
     let ts_msp = opts.ts_msp;
-    let range = opts.range;
+    let range = opts.range.clone();
     let fwd = opts.fwd;
-    let scy = opts.scy;
+    let stmts = opts.stmts.clone();
+    let mut stats = opts.stats.clone();
     let table_name = ST::table_name();
     if range.end > i64::MAX as u64 {
         return Err(Error::with_msg_no_trace(format!("range.end overflows i64")));
@@ -167,7 +265,6 @@ where
             range.end,
             table_name,
         );
-        // TODO use prepared!
         let cql = format!(
             concat!(
                 "select {} from {}",
@@ -175,16 +272,18 @@ where
             ),
             cql_fields, table_name,
         );
-        let res = scy
-            .query(
-                cql,
+        let (rows, next) = stmts
+            .execute_page(
+                &cql,
                 (series as i64, ts_msp as i64, ts_lsp_min as i64, ts_lsp_max as i64),
+                opts.paging_state.clone(),
             )
-            .await
-            .err_conv()?;
+            .await?;
+        stats.rows += rows.len() as u64;
+        stats.pages += 1;
         let mut last_before = None;
         let mut ret = ST::Container::empty();
-        for row in res.rows().err_conv()? {
+        for row in rows {
             let (ts, pulse, value) = if opts.with_values {
                 let row: (i64, i64, ST::ScyTy) = row.into_typed().err_conv()?;
                 let ts = ts_msp + row.0 as u64;
@@ -212,7 +311,7 @@ where
                 last_before = Some((ts, pulse, value));
             }
         }
-        ret
+        (ret, next)
     } else {
         let ts_lsp_max = if ts_msp < range.beg { range.beg - ts_msp } else { 0 };
         trace!(
@@ -223,7 +322,6 @@ where
             range.end,
             table_name,
         );
-        // TODO use prepared!
         let cql = format!(
             concat!(
                 "select {} from {}",
@@ -231,13 +329,18 @@ where
             ),
             cql_fields, table_name,
         );
-        let res = scy
-            .query(cql, (series as i64, ts_msp as i64, ts_lsp_max as i64))
-            .await
-            .err_conv()?;
+        let (rows, next) = stmts
+            .execute_page(
+                &cql,
+                (series as i64, ts_msp as i64, ts_lsp_max as i64),
+                opts.paging_state.clone(),
+            )
+            .await?;
+        stats.rows += rows.len() as u64;
+        stats.pages += 1;
         let mut seen_before = false;
         let mut ret = ST::Container::empty();
-        for row in res.rows().err_conv()? {
+        for row in rows {
             let (ts, pulse, value) = if opts.with_values {
                 let row: (i64, i64, ST::ScyTy) = row.into_typed().err_conv()?;
                 let ts = ts_msp + row.0 as u64;
@@ -266,10 +369,20 @@ where
         if ret.len() > 1 {
             error!("multiple events in backwards search {}", ret.len());
         }
-        ret
+        (ret, next)
+    };
+    let (ret, next) = ret;
+    stats.duration += ts1.elapsed();
+    trace!("read  ts_msp {}  len {}  {:?}", ts_msp, ret.len(), stats);
+    let ret = PageRead {
+        events: Box::new(ret),
+        stats: if next.is_none() { Some(stats.clone()) } else { None },
+        next: next.map(|paging_state| ReadNextValuesOpts {
+            paging_state: Some(paging_state),
+            stats,
+            ..opts
+        }),
     };
-    trace!("read  ts_msp {}  len {}", ts_msp, ret.len());
-    let ret = Box::new(ret);
     Ok(ret)
 }
 
@@ -281,9 +394,11 @@ struct ReadValues {
     ts_msps: VecDeque<u64>,
     fwd: bool,
     with_values: bool,
-    fut: Pin<Box<dyn Future<Output = Result<Box<dyn Events>, Error>> + Send>>,
-    fut_done: bool,
-    scy: Arc<ScySession>,
+    concurrent: usize,
+    futs: FuturesOrdered<ReadValuesFut>,
+    // The next page of the partition which is being yielded, it goes before all others.
+    cont: Option<ReadValuesFut>,
+    stmts: Arc<StmtsCache>,
 }
 
 impl ReadValues {
@@ -295,7 +410,8 @@ impl ReadValues {
         ts_msps: VecDeque<u64>,
         fwd: bool,
         with_values: bool,
-        scy: Arc<ScySession>,
+        concurrent: usize,
+        stmts: Arc<StmtsCache>,
     ) -> Self {
         let mut ret = Self {
             series,
@@ -305,35 +421,39 @@ impl ReadValues {
             ts_msps,
             fwd,
             with_values,
-            fut: Box::pin(futures_util::future::ready(Err(Error::with_msg_no_trace(
-                "future not initialized",
-            )))),
-            fut_done: false,
-            scy,
+            concurrent,
+            futs: FuturesOrdered::new(),
+            cont: None,
+            stmts,
         };
-        ret.next();
+        ret.fill();
         ret
     }
 
-    fn next(&mut self) -> bool {
-        if let Some(ts_msp) = self.ts_msps.pop_front() {
-            self.fut = self.make_fut(ts_msp);
-            self.fut_done = false;
-            true
-        } else {
-            false
+    /// Starts reads of the first page of further partitions up to the concurrency limit.
+    /// The results are still yielded in the order of `ts_msps`.
+    fn fill(&mut self) {
+        while self.futs.len() < self.concurrent {
+            if let Some(ts_msp) = self.ts_msps.pop_front() {
+                let opts = ReadNextValuesOpts {
+                    series: self.series.clone(),
+                    ts_msp,
+                    range: self.range.clone(),
+                    fwd: self.fwd,
+                    with_values: self.with_values,
+                    stmts: self.stmts.clone(),
+                    paging_state: None,
+                    stats: ScyllaPartitionReadStats::new(ts_msp),
+                };
+                let fut = self.make_fut(opts);
+                self.futs.push_back(fut);
+            } else {
+                break;
+            }
         }
     }
 
-    fn make_fut(&mut self, ts_msp: u64) -> Pin<Box<dyn Future<Output = Result<Box<dyn Events>, Error>> + Send>> {
-        let opts = ReadNextValuesOpts {
-            series: self.series.clone(),
-            ts_msp,
-            range: self.range.clone(),
-            fwd: self.fwd,
-            with_values: self.with_values,
-            scy: self.scy.clone(),
-        };
+    fn make_fut(&self, opts: ReadNextValuesOpts) -> ReadValuesFut {
         let scalar_type = self.scalar_type.clone();
         let shape = self.shape.clone();
         let fut = async move {
@@ -350,10 +470,7 @@ impl ReadValues {
                     ScalarType::F32 => read_next_values::<f32>(opts).await,
                     ScalarType::F64 => read_next_values::<f64>(opts).await,
                     ScalarType::BOOL => read_next_values::<bool>(opts).await,
-                    _ => {
-                        error!("TODO ReadValues add more types");
-                        err::todoval()
-                    }
+                    ScalarType::STRING => read_next_values::<String>(opts).await,
                 },
                 Shape::Wave(_) => match &scalar_type {
                     ScalarType::U8 => read_next_values::<Vec<u8>>(opts).await,
@@ -367,10 +484,7 @@ impl ReadValues {
                     ScalarType::F32 => read_next_values::<Vec<f32>>(opts).await,
                     ScalarType::F64 => read_next_values::<Vec<f64>>(opts).await,
                     ScalarType::BOOL => read_next_values::<Vec<bool>>(opts).await,
-                    _ => {
-                        error!("TODO ReadValues add more types");
-                        err::todoval()
-                    }
+                    ScalarType::STRING => read_next_values::<Vec<String>>(opts).await,
                 },
                 _ => {
                     error!("TODO ReadValues add more types");
@@ -382,6 +496,42 @@ impl ReadValues {
     }
 }
 
+impl Stream for ReadValues {
+    type Item = Result<(Box<dyn Events>, Option<ScyllaPartitionReadStats>), Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        use Poll::*;
+        let res = if let Some(fut) = self.cont.as_mut() {
+            match fut.poll_unpin(cx) {
+                Ready(x) => {
+                    self.cont = None;
+                    x
+                }
+                Pending => return Pending,
+            }
+        } else {
+            match self.futs.poll_next_unpin(cx) {
+                Ready(Some(x)) => x,
+                Ready(None) => return Ready(None),
+                Pending => return Pending,
+            }
+        };
+        match res {
+            Ok(page) => {
+                match page.next {
+                    Some(opts) => {
+                        let fut = self.make_fut(opts);
+                        self.cont = Some(fut);
+                    }
+                    None => self.fill(),
+                }
+                Ready(Some(Ok((page.events, page.stats))))
+            }
+            Err(e) => Ready(Some(Err(e))),
+        }
+    }
+}
+
 enum FrState {
     New,
     FindMsp(Pin<Box<dyn Future<Output = Result<(VecDeque<u64>, VecDeque<u64>), Error>> + Send>>),
@@ -401,11 +551,13 @@ pub struct EventsStreamScylla {
     do_one_before_range: bool,
     ts_msp_bck: VecDeque<u64>,
     ts_msp_fwd: VecDeque<u64>,
-    scy: Arc<ScySession>,
+    stmts: Arc<StmtsCache>,
+    partitions_concurrent: usize,
     do_test_stream_error: bool,
     found_one_after: bool,
     with_values: bool,
     outqueue: VecDeque<Box<dyn Events>>,
+    stats_queue: VecDeque<ScyllaPartitionReadStats>,
 }
 
 impl EventsStreamScylla {
@@ -416,7 +568,7 @@ impl EventsStreamScylla {
         scalar_type: ScalarType,
         shape: Shape,
         with_values: bool,
-        scy: Arc<ScySession>,
+        stmts: Arc<StmtsCache>,
         do_test_stream_error: bool,
     ) -> Self {
         Self {
@@ -428,25 +580,30 @@ impl EventsStreamScylla {
             do_one_before_range,
             ts_msp_bck: VecDeque::new(),
             ts_msp_fwd: VecDeque::new(),
-            scy,
+            stmts,
+            partitions_concurrent: PARTITIONS_CONCURRENT_DEFAULT,
             do_test_stream_error,
             found_one_after: false,
             with_values,
             outqueue: VecDeque::new(),
+            stats_queue: VecDeque::new(),
         }
     }
 
+    /// Sets how many `ts_msp` partitions are read at the same time, unset keeps the default.
+    pub fn with_partitions_concurrent(mut self, partitions_concurrent: Option<u32>) -> Self {
+        self.partitions_concurrent = partitions_concurrent.map_or(PARTITIONS_CONCURRENT_DEFAULT, |x| x.max(1) as usize);
+        self
+    }
+
     fn ts_msps_found(&mut self, msps1: VecDeque<u64>, msps2: VecDeque<u64>) {
         trace!("ts_msps_found  msps1 {msps1:?}  msps2 {msps2:?}");
-        self.ts_msp_bck = msps1;
-        self.ts_msp_fwd = msps2;
-        for x in self.ts_msp_bck.iter().rev() {
-            let x = x.clone();
-            if x >= self.range.end {
-                info!("FOUND one-after because of MSP");
-                self.found_one_after = true;
-            }
-            self.ts_msp_fwd.push_front(x);
+        let (bck, fwd, found_one_after) = plan_msp_reads(msps1, msps2, self.range.end, self.do_one_before_range);
+        self.ts_msp_bck = bck;
+        self.ts_msp_fwd = fwd;
+        if found_one_after {
+            info!("FOUND one-after because of MSP");
+            self.found_one_after = true;
         }
         trace!("ts_msp_bck {:?}", self.ts_msp_bck);
         trace!("ts_msp_fwd {:?}", self.ts_msp_fwd);
@@ -460,7 +617,8 @@ impl EventsStreamScylla {
                 [msp].into(),
                 false,
                 self.with_values,
-                self.scy.clone(),
+                self.partitions_concurrent,
+                self.stmts.clone(),
             );
             self.state = FrState::ReadBack1(st);
         } else if self.ts_msp_fwd.len() > 0 {
@@ -473,7 +631,8 @@ impl EventsStreamScylla {
                 mem::replace(&mut self.ts_msp_fwd, VecDeque::new()),
                 true,
                 self.with_values,
-                self.scy.clone(),
+                self.partitions_concurrent,
+                self.stmts.clone(),
             );
             self.state = FrState::ReadValues(st);
         } else {
@@ -494,7 +653,8 @@ impl EventsStreamScylla {
                     mem::replace(&mut self.ts_msp_fwd, VecDeque::new()),
                     true,
                     self.with_values,
-                    self.scy.clone(),
+                    self.partitions_concurrent,
+                    self.stmts.clone(),
                 );
                 self.state = FrState::ReadValues(st);
             } else {
@@ -511,7 +671,8 @@ impl EventsStreamScylla {
                     [msp].into(),
                     false,
                     self.with_values,
-                    self.scy.clone(),
+                    self.partitions_concurrent,
+                    self.stmts.clone(),
                 );
                 self.state = FrState::ReadBack2(st);
             } else if self.ts_msp_fwd.len() > 0 {
@@ -524,7 +685,8 @@ impl EventsStreamScylla {
                     mem::replace(&mut self.ts_msp_fwd, VecDeque::new()),
                     true,
                     self.with_values,
-                    self.scy.clone(),
+                    self.partitions_concurrent,
+                    self.stmts.clone(),
                 );
                 self.state = FrState::ReadValues(st);
             } else {
@@ -548,7 +710,8 @@ impl EventsStreamScylla {
                 mem::replace(&mut self.ts_msp_fwd, VecDeque::new()),
                 true,
                 self.with_values,
-                self.scy.clone(),
+                self.partitions_concurrent,
+                self.stmts.clone(),
             );
             self.state = FrState::ReadValues(st);
         } else {
@@ -558,7 +721,7 @@ impl EventsStreamScylla {
 }
 
 impl Stream for EventsStreamScylla {
-    type Item = Result<ChannelEvents, Error>;
+    type Item = Sitemty<ChannelEvents>;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
         use Poll::*;
@@ -571,11 +734,16 @@ impl Stream for EventsStreamScylla {
             if let Some(item) = self.outqueue.pop_front() {
                 item.verify();
                 item.output_info();
-                break Ready(Some(Ok(ChannelEvents::Events(item))));
+                let item = ChannelEvents::Events(item);
+                break Ready(Some(Ok(StreamItem::DataItem(RangeCompletableItem::Data(item)))));
+            }
+            if let Some(item) = self.stats_queue.pop_front() {
+                let item = StatsItem::ScyllaPartitionReadStats(item);
+                break Ready(Some(Ok(StreamItem::Stats(item))));
             }
             break match self.state {
                 FrState::New => {
-                    let fut = find_ts_msp(self.series, self.range.clone(), self.scy.clone());
+                    let fut = find_ts_msp(self.series, self.range.clone(), self.stmts.clone());
                     let fut = Box::pin(fut);
                     self.state = FrState::FindMsp(fut);
                     continue;
@@ -591,48 +759,67 @@ impl Stream for EventsStreamScylla {
                     }
                     Pending => Pending,
                 },
-                FrState::ReadBack1(ref mut st) => match st.fut.poll_unpin(cx) {
-                    Ready(Ok(item)) => {
-                        st.fut_done = true;
+                FrState::ReadBack1(ref mut st) => match st.poll_next_unpin(cx) {
+                    Ready(Some(Ok((item, stats)))) => {
+                        if let Some(stats) = stats {
+                            self.stats_queue.push_back(stats);
+                        } else if item.len() == 0 {
+                            // An empty page, the partition continues.
+                            continue;
+                        }
                         self.back_1_done(item);
                         continue;
                     }
-                    Ready(Err(e)) => {
-                        st.fut_done = true;
+                    Ready(Some(Err(e))) => {
                         self.state = FrState::DataDone;
                         Ready(Some(Err(e)))
                     }
+                    Ready(None) => {
+                        self.state = FrState::DataDone;
+                        Ready(Some(Err(Error::with_msg_no_trace("no partition to read back"))))
+                    }
                     Pending => Pending,
                 },
-                FrState::ReadBack2(ref mut st) => match st.fut.poll_unpin(cx) {
-                    Ready(Ok(item)) => {
-                        st.fut_done = true;
+                FrState::ReadBack2(ref mut st) => match st.poll_next_unpin(cx) {
+                    Ready(Some(Ok((item, stats)))) => {
+                        if let Some(stats) = stats {
+                            self.stats_queue.push_back(stats);
+                        } else if item.len() == 0 {
+                            // An empty page, the partition continues.
+                            continue;
+                        }
                         self.back_2_done(item);
                         continue;
                     }
-                    Ready(Err(e)) => {
-                        st.fut_done = true;
+                    Ready(Some(Err(e))) => {
                         self.state = FrState::DataDone;
                         Ready(Some(Err(e)))
                     }
+                    Ready(None) => {
+                        self.state = FrState::DataDone;
+                        Ready(Some(Err(Error::with_msg_no_trace("no partition to read back"))))
+                    }
                     Pending => Pending,
                 },
-                FrState::ReadValues(ref mut st) => match st.fut.poll_unpin(cx) {
-                    Ready(Ok(item)) => {
-                        st.fut_done = true;
-                        if !st.next() {
-                            trace!("ReadValues exhausted");
-                            self.state = FrState::DataDone;
-                        }
+                FrState::ReadValues(ref mut st) => match st.poll_next_unpin(cx) {
+                    Ready(Some(Ok((item, stats)))) => {
                         if item.len() > 0 {
                             self.outqueue.push_back(item);
                         }
+                        if let Some(stats) = stats {
+                            self.stats_queue.push_back(stats);
+                        }
                         continue;
                     }
-                    Ready(Err(e)) => {
-                        st.fut_done = true;
+                    Ready(Some(Err(e))) => {
+                        self.state = FrState::DataDone;
                         Ready(Some(Err(e)))
                     }
+                    Ready(None) => {
+                        trace!("ReadValues exhausted");
+                        self.state = FrState::DataDone;
+                        continue;
+                    }
                     Pending => Pending,
                 },
                 FrState::DataDone => {
@@ -647,3 +834,26 @@ impl Stream for EventsStreamScylla {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::plan_msp_reads;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn msp_reads_one_before() {
+        // Latest at 250: the event before is searched backwards from the newest partition before 250.
+        let (bck, fwd, one_after) = plan_msp_reads([100, 200].into(), [300].into(), 251, true);
+        assert_eq!(bck, VecDeque::from([100, 200]));
+        assert_eq!(fwd, VecDeque::from([100, 200, 300]));
+        assert_eq!(one_after, false);
+    }
+
+    #[test]
+    fn msp_reads_without_one_before() {
+        let (bck, fwd, one_after) = plan_msp_reads([100, 200].into(), [300].into(), 400, false);
+        assert_eq!(bck, VecDeque::new());
+        assert_eq!(fwd, VecDeque::from([200, 300]));
+        assert_eq!(one_after, false);
+    }
+}
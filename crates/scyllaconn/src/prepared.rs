@@ -0,0 +1,63 @@
+use crate::errconv::ErrConv;
+use bytes::Bytes;
+use err::Error;
+use scylla::frame::response::result::Row;
+use scylla::frame::value::ValueList;
+use scylla::prepared_statement::PreparedStatement;
+use scylla::Session as ScySession;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+pub const PAGE_SIZE_DEFAULT: u32 = 1000;
+
+/// Holds the session together with the statements prepared on it.
+/// Statements are keyed by their CQL text, which includes the table name,
+/// so each table and query variant is prepared only once.
+pub struct StmtsCache {
+    scy: Arc<ScySession>,
+    page_size: i32,
+    stmts: Mutex<BTreeMap<String, PreparedStatement>>,
+}
+
+impl StmtsCache {
+    pub fn new(scy: Arc<ScySession>, page_size: u32) -> Self {
+        Self {
+            scy,
+            page_size: page_size.min(i32::MAX as u32) as i32,
+            stmts: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn scy(&self) -> &Arc<ScySession> {
+        &self.scy
+    }
+
+    pub async fn get(&self, cql: &str) -> Result<PreparedStatement, Error> {
+        let cached = self.stmts.lock().unwrap().get(cql).cloned();
+        if let Some(stmt) = cached {
+            return Ok(stmt);
+        }
+        let mut stmt = self.scy.prepare(cql).await.err_conv()?;
+        stmt.set_page_size(self.page_size);
+        self.stmts.lock().unwrap().insert(cql.into(), stmt.clone());
+        Ok(stmt)
+    }
+
+    /// Executes the prepared statement for `cql` and fetches the page at `paging_state`.
+    /// Returns the rows and the paging state of the next page, if there is one.
+    pub async fn execute_page<V>(
+        &self,
+        cql: &str,
+        values: V,
+        paging_state: Option<Bytes>,
+    ) -> Result<(Vec<Row>, Option<Bytes>), Error>
+    where
+        V: ValueList,
+    {
+        let stmt = self.get(cql).await?;
+        let res = self.scy.execute_paged(&stmt, values, paging_state).await.err_conv()?;
+        let next = res.paging_state.clone();
+        Ok((res.rows_or_empty(), next))
+    }
+}
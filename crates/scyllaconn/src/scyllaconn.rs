@@ -1,6 +1,7 @@
 pub mod bincache;
 pub mod errconv;
 pub mod events;
+pub mod prepared;
 pub mod status;
 
 pub use scylla;
@@ -9,10 +10,14 @@ use err::Error;
 use errconv::ErrConv;
 use netpod::range::evrange::SeriesRange;
 use netpod::ScyllaConfig;
+use prepared::StmtsCache;
+use prepared::PAGE_SIZE_DEFAULT;
 use scylla::execution_profile::ExecutionProfileBuilder;
 use scylla::statement::Consistency;
 use scylla::Session as ScySession;
+use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::sync::Mutex;
 
 #[derive(Debug, Clone)]
 pub struct ScyllaSeriesRange {
@@ -45,3 +50,20 @@ pub async fn create_scy_session(scyconf: &ScyllaConfig) -> Result<Arc<ScySession
     let ret = Arc::new(scy);
     Ok(ret)
 }
+
+static SHARED_STMTS: Mutex<BTreeMap<(Vec<String>, String), Arc<StmtsCache>>> = Mutex::new(BTreeMap::new());
+
+/// The session of this process for the given cluster, together with its prepared statements.
+/// Created on first use and shared by all queries.
+pub async fn shared_stmts(scyconf: &ScyllaConfig) -> Result<Arc<StmtsCache>, Error> {
+    let key = (scyconf.hosts.clone(), scyconf.keyspace.clone());
+    if let Some(x) = SHARED_STMTS.lock().unwrap().get(&key) {
+        return Ok(x.clone());
+    }
+    let scy = create_scy_session(scyconf).await?;
+    let page_size = scyconf.page_size.unwrap_or(PAGE_SIZE_DEFAULT).max(1);
+    let stmts = Arc::new(StmtsCache::new(scy, page_size));
+    // Another query may have connected meanwhile, keep the first session.
+    let ret = SHARED_STMTS.lock().unwrap().entry(key).or_insert(stmts).clone();
+    Ok(ret)
+}
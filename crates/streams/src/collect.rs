@@ -18,6 +18,7 @@ use netpod::log::*;
 use netpod::range::evrange::SeriesRange;
 use netpod::BinnedRangeEnum;
 use netpod::DiskStats;
+use netpod::FrameCompressionStats;
 use std::fmt;
 use std::pin::Pin;
 use std::task::Context;
@@ -47,6 +48,8 @@ macro_rules! trace4 {
 pub struct Collect {
     inp: Pin<Box<dyn Stream<Item = Sitemty<Box<dyn Collectable>>> + Send>>,
     events_max: u64,
+    cut_at_events_max: bool,
+    reached_events_max: bool,
     range: Option<SeriesRange>,
     binrange: Option<BinnedRangeEnum>,
     collector: Option<Box<dyn Collector>>,
@@ -54,6 +57,7 @@ pub struct Collect {
     timeout: bool,
     timer: Pin<Box<dyn Future<Output = ()> + Send>>,
     done_input: bool,
+    comp_stats: FrameCompressionStats,
 }
 
 impl Collect {
@@ -68,6 +72,8 @@ impl Collect {
         Self {
             inp,
             events_max,
+            cut_at_events_max: false,
+            reached_events_max: false,
             range,
             binrange,
             collector: None,
@@ -75,9 +81,16 @@ impl Collect {
             timeout: false,
             timer: Box::pin(timer),
             done_input: false,
+            comp_stats: FrameCompressionStats::default(),
         }
     }
 
+    /// Stop at `events_max` and let the result tell where to continue, instead of only warning.
+    pub fn cut_at_events_max(mut self) -> Self {
+        self.cut_at_events_max = true;
+        self
+    }
+
     fn handle_item(&mut self, item: Sitemty<Box<dyn Collectable>>) -> Result<(), Error> {
         match item {
             Ok(item) => match item {
@@ -96,10 +109,16 @@ impl Collect {
                         let coll = self.collector.get_or_insert_with(|| item.new_collector());
                         coll.ingest(&mut item);
                         if coll.len() as u64 >= self.events_max {
-                            warn!(
-                                "TODO  compute continue-at  reached events_max {} abort",
-                                self.events_max
-                            );
+                            if self.cut_at_events_max {
+                                debug!("reached events_max {}", self.events_max);
+                                self.reached_events_max = true;
+                                self.done_input = true;
+                            } else {
+                                warn!(
+                                    "TODO  compute continue-at  reached events_max {} abort",
+                                    self.events_max
+                                );
+                            }
                         }
                         Ok(())
                     }
@@ -124,6 +143,9 @@ impl Collect {
                         // TODO factor and simplify the stats collection:
                         StatsItem::EventDataReadStats(_) => {}
                         StatsItem::RangeFilterStats(_) => {}
+                        StatsItem::FrameCompressionStats(k) => {
+                            self.comp_stats.add(&k);
+                        }
                         StatsItem::DiskStats(item) => match item {
                             DiskStats::OpenStats(k) => {
                                 //total_duration += k.duration;
@@ -167,11 +189,23 @@ impl Future for Collect {
                         warn!("collect timeout but no collector yet");
                     }
                 }
+                if self.reached_events_max {
+                    if let Some(coll) = self.collector.as_mut() {
+                        coll.set_continue_at_here();
+                    }
+                }
                 // TODO use range_final and timeout in result.
                 match self.collector.take() {
                     Some(mut coll) => match coll.result(self.range.clone(), self.binrange.clone()) {
                         Ok(res) => {
                             //info!("collect stats total duration: {:?}", total_duration);
+                            if self.comp_stats.frames != 0 {
+                                info!(
+                                    "collect stats frame compression ratio {:.2}  {:?}",
+                                    self.comp_stats.ratio(),
+                                    self.comp_stats
+                                );
+                            }
                             Ready(Ok(res))
                         }
                         Err(e) => Ready(Err(e)),
@@ -229,6 +263,7 @@ where
     let mut range_complete = false;
     let mut timed_out = false;
     let mut total_duration = Duration::ZERO;
+    let mut comp_stats = FrameCompressionStats::default();
     loop {
         let item = match tokio::time::timeout_at(deadline, stream.next()).await {
             Ok(Some(k)) => k,
@@ -278,6 +313,9 @@ where
                         // TODO factor and simplify the stats collection:
                         StatsItem::EventDataReadStats(_) => {}
                         StatsItem::RangeFilterStats(_) => {}
+                        StatsItem::FrameCompressionStats(k) => {
+                            comp_stats.add(&k);
+                        }
                         StatsItem::DiskStats(item) => match item {
                             DiskStats::OpenStats(k) => {
                                 total_duration += k.duration;
@@ -307,7 +345,12 @@ where
     let res = collector
         .ok_or_else(|| Error::with_msg_no_trace(format!("no result because no collector was created")))?
         .result(range, binrange)?;
-    info!("collect stats total duration: {:?}", total_duration);
+    info!(
+        "collect stats total duration: {:?}  frame compression ratio {:.2}  {:?}",
+        total_duration,
+        comp_stats.ratio(),
+        comp_stats
+    );
     Ok(res)
 }
 
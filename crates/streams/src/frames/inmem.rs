@@ -3,14 +3,18 @@ use bytes::Bytes;
 use err::Error;
 use futures_util::pin_mut;
 use futures_util::Stream;
+use items_0::streamitem::StatsItem;
 use items_0::streamitem::StreamItem;
 use items_0::streamitem::TERM_FRAME_TYPE_ID;
 use items_2::framable::INMEM_FRAME_FOOT;
 use items_2::framable::INMEM_FRAME_HEAD;
 use items_2::framable::INMEM_FRAME_MAGIC;
+use items_2::frame::decompress_frame;
+use items_2::frame::is_compressed_encid;
 use items_2::inmem::InMemoryFrame;
 use netpod::log::*;
 use netpod::ByteSize;
+use netpod::FrameCompressionStats;
 use std::pin::Pin;
 use std::task::Context;
 use std::task::Poll;
@@ -69,6 +73,7 @@ where
 /// Interprets a byte stream as length-delimited frames.
 ///
 /// Emits each frame as a single item. Therefore, each item must fit easily into memory.
+/// Compressed frames are emitted decompressed, with their statistics at the end of the stream.
 pub struct InMemoryFrameStream<T, E>
 where
     T: Stream<Item = Result<Bytes, E>> + Unpin,
@@ -81,6 +86,8 @@ where
     done: bool,
     complete: bool,
     inp_bytes_consumed: u64,
+    comp_stats: FrameCompressionStats,
+    comp_stats_emitted: bool,
 }
 
 impl<T, E> InMemoryFrameStream<T, E>
@@ -99,6 +106,8 @@ where
             done: false,
             complete: false,
             inp_bytes_consumed: 0,
+            comp_stats: FrameCompressionStats::default(),
+            comp_stats_emitted: false,
         }
     }
 
@@ -207,6 +216,15 @@ where
         };
         self.buf.adv(lentot)?;
         self.need_min = INMEM_FRAME_HEAD;
+        self.comp_stats.frames += 1;
+        self.comp_stats.bytes_wire += lentot as u64;
+        let ret = if is_compressed_encid(encid) {
+            self.comp_stats.frames_compressed += 1;
+            decompress_frame(ret)?
+        } else {
+            ret
+        };
+        self.comp_stats.bytes_raw += (INMEM_FRAME_HEAD + INMEM_FRAME_FOOT) as u64 + ret.len() as u64;
         Ok(Some(ret))
     }
 }
@@ -225,8 +243,14 @@ where
             break if self.complete {
                 panic!("{} poll_next on complete", Self::type_name())
             } else if self.done {
-                self.complete = true;
-                Ready(None)
+                if self.comp_stats.frames_compressed != 0 && !self.comp_stats_emitted {
+                    self.comp_stats_emitted = true;
+                    let item = StatsItem::FrameCompressionStats(self.comp_stats.clone());
+                    Ready(Some(Ok(StreamItem::Stats(item))))
+                } else {
+                    self.complete = true;
+                    Ready(None)
+                }
             } else if self.buf.len() >= self.need_min {
                 match self.parse() {
                     Ok(None) => {
@@ -0,0 +1,70 @@
+use crate::rangefilter2::RangeFilter2;
+use crate::tcprawclient::open_event_data_streams_segments;
+use crate::tcprawclient::segments_ch_conf;
+use err::Error;
+use futures_util::StreamExt;
+use items_0::streamitem::RangeCompletableItem;
+use items_0::streamitem::StreamItem;
+use items_0::Events;
+use items_2::channelevents::ChannelEvents;
+use items_2::merger::Merger;
+use netpod::log::*;
+use netpod::ChannelConfigSegment;
+use netpod::Cluster;
+use query::api4::events::EventsSubQuerySelect;
+use query::api4::events::EventsSubQuerySettings;
+use query::api4::events::PlainEventsQuery;
+use serde_json::Value as JsonValue;
+use std::time::Instant;
+
+/// Returns the last event of the channel at or before the begin of the query range,
+/// in the same json format as the events endpoint, or `Null` if the channel has no such event.
+/// The query is expected to ask for the event before the range.
+pub async fn latest_event_json(
+    evq: &PlainEventsQuery,
+    segments: Vec<ChannelConfigSegment>,
+    reqid: String,
+    cluster: &Cluster,
+) -> Result<JsonValue, Error> {
+    let deadline = Instant::now() + evq.timeout();
+    let ch_conf = segments_ch_conf(&segments)?;
+    let select = EventsSubQuerySelect::new(ch_conf, evq.range().clone(), evq.transform().clone());
+    let settings = EventsSubQuerySettings::from(evq);
+    let one_before = evq.one_before_range();
+    let inps = open_event_data_streams_segments(&segments, &select, &settings, one_before, &reqid, cluster).await?;
+    let stream = Merger::new(inps, evq.merger_out_len_max());
+    let mut stream = RangeFilter2::new(stream, evq.range().try_into()?, evq.one_before_range());
+    let beg = evq.range().beg_u64();
+    let mut last: Option<Box<dyn Events>> = None;
+    loop {
+        let item = match tokio::time::timeout_at(deadline.into(), stream.next()).await {
+            Ok(Some(item)) => item?,
+            Ok(None) => break,
+            Err(_) => {
+                warn!("latest_event_json  timeout  {:?}", evq.channel());
+                return Err(Error::with_public_msg_no_trace(format!(
+                    "timeout while searching the latest event of {}",
+                    evq.channel().name()
+                )));
+            }
+        };
+        if let StreamItem::DataItem(RangeCompletableItem::Data(ChannelEvents::Events(mut evs))) = item {
+            // Events are time ordered, the last one at or before the begin of the range is the latest.
+            if let Some(n) = evs.find_highest_index_lt_evs(beg.saturating_add(1)) {
+                let mut dst = evs.new_empty_evs();
+                evs.drain_into_evs(&mut dst, (n, n + 1))?;
+                last = Some(dst);
+            }
+        }
+    }
+    match last {
+        Some(mut evs) => {
+            let mut coll = evs.as_collectable_with_default_ref().new_collector();
+            coll.ingest(evs.as_collectable_with_default_mut());
+            coll.set_range_complete();
+            let res = coll.result(Some(evq.range().clone()), None)?;
+            Ok(serde_json::to_value(&res)?)
+        }
+        None => Ok(JsonValue::Null),
+    }
+}
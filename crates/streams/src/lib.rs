@@ -5,6 +5,8 @@ pub mod filechunkread;
 pub mod frames;
 pub mod generators;
 pub mod itemclone;
+pub mod latest;
+pub mod mux;
 pub mod needminbuffer;
 pub mod plaineventsjson;
 pub mod rangefilter2;
@@ -0,0 +1,387 @@
+//! Multiplexed event subqueries over persistent tcp connections between nodes.
+//!
+//! A connection starts with `MUX_MAGIC` from the requesting node. After that, both sides send
+//! messages with a header of kind, substream id and payload length, each a `u32` little endian like
+//! the in-memory frames, followed by the payload:
+//!
+//! - `Open` carries the query frame and a term frame, as they are sent on a plain connection.
+//! - `Credit` carries a `u32` number of bytes which the node may send for the substream in addition.
+//! - `Cancel` asks the node to stop the substream, because the consumer went away.
+//! - `Data` carries one frame of the response.
+//! - `End` tells that no more messages follow for the substream.
+//!
+//! A node sends data of a substream only while it has credit left, so that a slow consumer of one
+//! substream does not hold back the other substreams on the same connection.
+
+use bytes::Bytes;
+use err::Error;
+use futures_util::Stream;
+use netpod::log::*;
+use std::collections::BTreeMap;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufWriter;
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+
+pub const MUX_MAGIC: u32 = 0x5844554d;
+pub const MUX_HEAD: usize = 12;
+/// The query frames of an `Open`, the same limit as for the query frames on a plain connection.
+pub const MUX_OPEN_MAX: u32 = 1024 * 8;
+const MUX_PAYLOAD_MAX: u32 = 1024 * 1024 * 1024;
+// The payload buffer grows as the bytes arrive, so that a bad length does not allocate all at once.
+const MUX_READ_PREALLOC: usize = 1024 * 64;
+
+static MUX_CONNS: Mutex<BTreeMap<String, Arc<MuxConn>>> = Mutex::new(BTreeMap::new());
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MuxKind {
+    Open,
+    Credit,
+    Cancel,
+    Data,
+    End,
+}
+
+impl MuxKind {
+    fn to_u32(self) -> u32 {
+        match self {
+            MuxKind::Open => 1,
+            MuxKind::Credit => 2,
+            MuxKind::Cancel => 3,
+            MuxKind::Data => 4,
+            MuxKind::End => 5,
+        }
+    }
+
+    fn from_u32(x: u32) -> Option<Self> {
+        match x {
+            1 => Some(MuxKind::Open),
+            2 => Some(MuxKind::Credit),
+            3 => Some(MuxKind::Cancel),
+            4 => Some(MuxKind::Data),
+            5 => Some(MuxKind::End),
+            _ => None,
+        }
+    }
+
+    fn payload_max(self) -> u32 {
+        match self {
+            MuxKind::Open => MUX_OPEN_MAX,
+            MuxKind::Credit => 4,
+            MuxKind::Cancel => 0,
+            MuxKind::Data => MUX_PAYLOAD_MAX,
+            MuxKind::End => 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct MuxMsg {
+    pub kind: MuxKind,
+    pub sid: u32,
+    pub payload: Bytes,
+}
+
+impl MuxMsg {
+    pub fn new(kind: MuxKind, sid: u32, payload: Bytes) -> Self {
+        Self { kind, sid, payload }
+    }
+
+    pub fn credit(sid: u32, n: u32) -> Self {
+        Self::new(MuxKind::Credit, sid, Bytes::copy_from_slice(&n.to_le_bytes()))
+    }
+
+    /// The bytes granted by a `Credit` message.
+    pub fn credit_bytes(&self) -> Result<u32, Error> {
+        let b: [u8; 4] = self.payload[..]
+            .try_into()
+            .map_err(|_| Error::with_msg_no_trace(format!("bad mux credit len {}", self.payload.len())))?;
+        Ok(u32::from_le_bytes(b))
+    }
+
+    fn head(&self) -> [u8; MUX_HEAD] {
+        let mut ret = [0; MUX_HEAD];
+        ret[0..4].copy_from_slice(&self.kind.to_u32().to_le_bytes());
+        ret[4..8].copy_from_slice(&self.sid.to_le_bytes());
+        ret[8..12].copy_from_slice(&(self.payload.len() as u32).to_le_bytes());
+        ret
+    }
+}
+
+/// Reads the next message, or `None` if the connection was closed between messages.
+pub async fn read_mux_msg<R>(inp: &mut R) -> Result<Option<MuxMsg>, Error>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut head = [0; MUX_HEAD];
+    match inp.read_exact(&mut head).await {
+        Ok(_) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let kind = u32::from_le_bytes(head[0..4].try_into().unwrap());
+    let sid = u32::from_le_bytes(head[4..8].try_into().unwrap());
+    let len = u32::from_le_bytes(head[8..12].try_into().unwrap());
+    let kind = MuxKind::from_u32(kind).ok_or_else(|| Error::with_msg_no_trace(format!("bad mux kind {kind}")))?;
+    if len > kind.payload_max() {
+        return Err(Error::with_msg_no_trace(format!(
+            "mux payload too large {len} for {kind:?}"
+        )));
+    }
+    let mut payload = Vec::with_capacity(MUX_READ_PREALLOC.min(len as usize));
+    let n = inp.take(len as u64).read_to_end(&mut payload).await?;
+    if n != len as usize {
+        return Err(Error::with_msg_no_trace(format!("mux payload incomplete {n} of {len}")));
+    }
+    Ok(Some(MuxMsg::new(kind, sid, payload.into())))
+}
+
+pub async fn write_mux_msg<W>(out: &mut W, msg: &MuxMsg) -> Result<(), Error>
+where
+    W: AsyncWrite + Unpin,
+{
+    out.write_all(&msg.head()).await?;
+    out.write_all(&msg.payload).await?;
+    Ok(())
+}
+
+struct MuxSubs {
+    txs: BTreeMap<u32, mpsc::UnboundedSender<Result<Bytes, Error>>>,
+    closed: Option<String>,
+}
+
+impl MuxSubs {
+    fn close(&mut self, msg: String) {
+        for (_, tx) in std::mem::take(&mut self.txs) {
+            let _ = tx.send(Err(Error::with_msg_no_trace(msg.clone())));
+        }
+        self.closed = Some(msg);
+    }
+}
+
+/// Persistent connection to the events service of another node.
+pub struct MuxConn {
+    addr: String,
+    tx: mpsc::UnboundedSender<MuxMsg>,
+    subs: Arc<Mutex<MuxSubs>>,
+    next_sid: AtomicU32,
+    credit_window: u32,
+}
+
+impl MuxConn {
+    async fn connect(addr: String, credit_window: u32) -> Result<Arc<Self>, Error> {
+        debug!("mux connect to {addr}");
+        let net = TcpStream::connect(&addr).await?;
+        net.set_nodelay(true)?;
+        let (mut netin, mut netout) = net.into_split();
+        netout.write_all(&MUX_MAGIC.to_le_bytes()).await?;
+        let subs = Arc::new(Mutex::new(MuxSubs {
+            txs: BTreeMap::new(),
+            closed: None,
+        }));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        {
+            let subs = subs.clone();
+            let addr = addr.clone();
+            tokio::spawn(async move {
+                let mut out = BufWriter::new(netout);
+                while let Some(msg) = rx.recv().await {
+                    let mut res = write_mux_msg(&mut out, &msg).await;
+                    // Write all which is queued before the flush.
+                    while res.is_ok() {
+                        match rx.try_recv() {
+                            Ok(msg) => res = write_mux_msg(&mut out, &msg).await,
+                            Err(_) => break,
+                        }
+                    }
+                    if res.is_ok() {
+                        res = out.flush().await.map_err(Error::from);
+                    }
+                    if let Err(e) = res {
+                        warn!("mux write to {addr}: {e}");
+                        subs.lock().unwrap().close(format!("mux write to {addr}: {e}"));
+                        break;
+                    }
+                }
+            });
+        }
+        {
+            let subs = subs.clone();
+            let addr = addr.clone();
+            tokio::spawn(async move {
+                let msg = loop {
+                    match read_mux_msg(&mut netin).await {
+                        Ok(Some(msg)) => match msg.kind {
+                            MuxKind::Data => {
+                                // Data of a cancelled substream may still arrive and is dropped.
+                                if let Some(tx) = subs.lock().unwrap().txs.get(&msg.sid) {
+                                    let _ = tx.send(Ok(msg.payload));
+                                }
+                            }
+                            MuxKind::End => {
+                                subs.lock().unwrap().txs.remove(&msg.sid);
+                            }
+                            k => break format!("unexpected mux message {k:?} from {addr}"),
+                        },
+                        Ok(None) => break format!("mux connection closed by {addr}"),
+                        Err(e) => break format!("mux read from {addr}: {e}"),
+                    }
+                };
+                debug!("{msg}");
+                subs.lock().unwrap().close(msg);
+            });
+        }
+        let ret = Self {
+            addr,
+            tx,
+            subs,
+            next_sid: AtomicU32::new(1),
+            credit_window,
+        };
+        Ok(Arc::new(ret))
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.subs.lock().unwrap().closed.is_some()
+    }
+
+    fn send(&self, msg: MuxMsg) -> Result<(), Error> {
+        self.tx
+            .send(msg)
+            .map_err(|_| Error::with_msg_no_trace(format!("mux connection to {} closed", self.addr)))
+    }
+
+    /// Starts a subquery. `query` holds the frames which would be sent on a plain connection.
+    pub fn open(self: &Arc<Self>, query: Bytes) -> Result<MuxSubStream, Error> {
+        if query.len() > MUX_OPEN_MAX as usize {
+            return Err(Error::with_msg_no_trace(format!("mux query too large {}", query.len())));
+        }
+        let sid = self.next_sid.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = mpsc::unbounded_channel();
+        {
+            let mut subs = self.subs.lock().unwrap();
+            if let Some(msg) = &subs.closed {
+                return Err(Error::with_msg_no_trace(msg.clone()));
+            }
+            subs.txs.insert(sid, tx);
+        }
+        self.send(MuxMsg::new(MuxKind::Open, sid, query))?;
+        self.send(MuxMsg::credit(sid, self.credit_window))?;
+        let ret = MuxSubStream {
+            conn: self.clone(),
+            sid,
+            rx,
+            consumed: 0,
+            done: false,
+        };
+        Ok(ret)
+    }
+}
+
+/// The connection to the node at `addr`, which is opened if there is none or the last one was closed.
+pub async fn mux_conn(addr: &str, credit_window: u32) -> Result<Arc<MuxConn>, Error> {
+    if let Some(x) = MUX_CONNS.lock().unwrap().get(addr) {
+        if !x.is_closed() {
+            return Ok(x.clone());
+        }
+    }
+    let ret = MuxConn::connect(addr.into(), credit_window).await?;
+    MUX_CONNS.lock().unwrap().insert(addr.into(), ret.clone());
+    Ok(ret)
+}
+
+/// The response bytes of one subquery.
+///
+/// Returns credit to the node as the bytes are taken, and cancels the subquery when dropped before
+/// the end.
+pub struct MuxSubStream {
+    conn: Arc<MuxConn>,
+    sid: u32,
+    rx: mpsc::UnboundedReceiver<Result<Bytes, Error>>,
+    consumed: u32,
+    done: bool,
+}
+
+impl Stream for MuxSubStream {
+    type Item = Result<Bytes, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        use Poll::*;
+        if self.done {
+            return Ready(None);
+        }
+        match self.rx.poll_recv(cx) {
+            Ready(Some(Ok(x))) => {
+                self.consumed = self.consumed.saturating_add(x.len() as u32);
+                if self.consumed >= self.conn.credit_window / 2 {
+                    let n = std::mem::replace(&mut self.consumed, 0);
+                    // If the connection is gone, the receiver learns about it from the reader.
+                    let _ = self.conn.send(MuxMsg::credit(self.sid, n));
+                }
+                Ready(Some(Ok(x)))
+            }
+            Ready(Some(Err(e))) => {
+                self.done = true;
+                Ready(Some(Err(e)))
+            }
+            Ready(None) => {
+                self.done = true;
+                Ready(None)
+            }
+            Pending => Pending,
+        }
+    }
+}
+
+impl Drop for MuxSubStream {
+    fn drop(&mut self) {
+        if !self.done {
+            let present = self.conn.subs.lock().unwrap().txs.remove(&self.sid).is_some();
+            if present {
+                debug!("mux cancel {} on {}", self.sid, self.conn.addr);
+                let _ = self.conn.send(MuxMsg::new(MuxKind::Cancel, self.sid, Bytes::new()));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn msg_roundtrip() -> Result<(), Error> {
+        let fut = async {
+            let mut buf = Vec::new();
+            write_mux_msg(&mut buf, &MuxMsg::credit(7, 4096)).await?;
+            write_mux_msg(&mut buf, &MuxMsg::new(MuxKind::Data, 8, Bytes::from_static(b"frame"))).await?;
+            let mut inp = &buf[..];
+            let msg = read_mux_msg(&mut inp).await?.unwrap();
+            assert_eq!((msg.kind, msg.sid, msg.credit_bytes()?), (MuxKind::Credit, 7, 4096));
+            let msg = read_mux_msg(&mut inp).await?.unwrap();
+            assert_eq!((msg.kind, msg.sid, &msg.payload[..]), (MuxKind::Data, 8, &b"frame"[..]));
+            assert!(read_mux_msg(&mut inp).await?.is_none());
+            let mut buf = Vec::new();
+            let msg = MuxMsg::new(MuxKind::Open, 9, vec![0; MUX_OPEN_MAX as usize + 1].into());
+            write_mux_msg(&mut buf, &msg).await?;
+            assert!(read_mux_msg(&mut &buf[..]).await.is_err());
+            Ok(())
+        };
+        taskrun::run(fut)
+    }
+}
@@ -1,5 +1,6 @@
 use crate::collect::Collect;
-use crate::tcprawclient::open_event_data_streams;
+use crate::tcprawclient::open_event_data_streams_segments;
+use crate::tcprawclient::segments_ch_conf;
 use crate::transform::build_merged_event_transform;
 use crate::transform::EventsToTimeBinnable;
 use crate::transform::TimeBinnableToCollectable;
@@ -12,9 +13,8 @@ use items_2::channelevents::ChannelEvents;
 use items_2::merger::Merger;
 use items_2::streams::PlainEventStream;
 use netpod::log::*;
-use netpod::ChannelTypeConfigGen;
+use netpod::ChannelConfigSegment;
 use netpod::Cluster;
-use query::api4::events::EventsSubQuery;
 use query::api4::events::EventsSubQuerySelect;
 use query::api4::events::EventsSubQuerySettings;
 use query::api4::events::PlainEventsQuery;
@@ -23,22 +23,23 @@ use std::time::Instant;
 
 pub async fn plain_events_json(
     evq: &PlainEventsQuery,
-    ch_conf: ChannelTypeConfigGen,
+    segments: Vec<ChannelConfigSegment>,
     reqid: String,
     cluster: &Cluster,
 ) -> Result<JsonValue, Error> {
     info!("plain_events_json  evquery {:?}", evq);
+    let ch_conf = segments_ch_conf(&segments)?;
     let mut select = EventsSubQuerySelect::new(ch_conf, evq.range().clone(), evq.transform().clone());
     if let Some(x) = evq.test_do_wasm() {
         select.set_wasm1(x.into());
     }
     let settings = EventsSubQuerySettings::from(evq);
-    let subq = EventsSubQuery::from_parts(select, settings, reqid);
     // TODO remove magic constant
     let deadline = Instant::now() + evq.timeout();
     let mut tr = build_merged_event_transform(evq.transform())?;
     // TODO make sure the empty container arrives over the network.
-    let inps = open_event_data_streams::<ChannelEvents>(subq, cluster).await?;
+    let one_before = evq.one_before_range();
+    let inps = open_event_data_streams_segments(&segments, &select, &settings, one_before, &reqid, cluster).await?;
     // TODO propagate also the max-buf-len for the first stage event reader.
     // TODO use a mixture of count and byte-size as threshold.
     let stream = Merger::new(inps, evq.merger_out_len_max());
@@ -200,7 +201,13 @@ pub async fn plain_events_json(
     //let stream = EventsToTimeBinnable::new(stream);
     //let stream = TimeBinnableToCollectable::new(stream);
     let stream = Box::pin(stream);
-    let collected = Collect::new(stream, deadline, evq.events_max(), Some(evq.range().clone()), None).await?;
+    let collected = Collect::new(stream, deadline, evq.events_max(), Some(evq.range().clone()), None);
+    let collected = if evq.events_max_by_budget() {
+        collected.cut_at_events_max()
+    } else {
+        collected
+    };
+    let collected = collected.await?;
     let jsval = serde_json::to_value(&collected)?;
     Ok(jsval)
 }
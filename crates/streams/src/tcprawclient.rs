@@ -6,19 +6,28 @@
 use crate::frames::eventsfromframes::EventsFromFrames;
 use crate::frames::inmem::InMemoryFrameStream;
 use crate::frames::inmem::TcpReadAsBytes;
+use crate::mux::mux_conn;
+use crate::rangefilter2::RangeFilter2;
 use err::Error;
 use futures_util::Stream;
 use items_0::framable::FrameTypeInnerStatic;
 use items_0::streamitem::sitem_data;
 use items_0::streamitem::Sitemty;
+use items_2::channelevents::ChannelEvents;
 use items_2::eventfull::EventFull;
 use items_2::framable::EventQueryJsonStringFrame;
 use items_2::framable::Framable;
 use items_2::frame::make_term_frame;
 use netpod::log::*;
+use netpod::range::evrange::NanoRange;
+use netpod::ChannelConfigSegment;
+use netpod::ChannelTypeConfigGen;
 use netpod::Cluster;
 use netpod::Node;
+use netpod::NodeNetConfig;
 use query::api4::events::EventsSubQuery;
+use query::api4::events::EventsSubQuerySelect;
+use query::api4::events::EventsSubQuerySettings;
 use query::api4::events::Frame1Parts;
 use serde::de::DeserializeOwned;
 use std::fmt;
@@ -27,7 +36,7 @@ use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
 
 pub fn make_node_command_frame(query: EventsSubQuery) -> Result<EventQueryJsonStringFrame, Error> {
-    let obj = Frame1Parts::new(query);
+    let obj = Frame1Parts::new(query).with_node_token(httpclient::node_token().map(String::from));
     let ret = serde_json::to_string(&obj)?;
     Ok(EventQueryJsonStringFrame(ret))
 }
@@ -72,7 +81,7 @@ pub async fn x_processed_event_blobs_stream_from_node_http(
 
     let url = node.baseurl().join("/api/4/private/eventdata/frames").unwrap();
     debug!("open_event_data_streams_http  post  {url}");
-    let req = Request::builder()
+    let req = httpclient::with_authorization(Request::builder())
         .method(Method::POST)
         .uri(url.to_string())
         .header(header::ACCEPT, "application/octet-stream")
@@ -173,7 +182,7 @@ where
 
         let url = node.baseurl().join("/api/4/private/eventdata/frames").unwrap();
         debug!("open_event_data_streams_http  post  {url}");
-        let req = Request::builder()
+        let req = httpclient::with_authorization(Request::builder())
             .method(Method::POST)
             .uri(url.to_string())
             .header(header::ACCEPT, "application/octet-stream")
@@ -211,14 +220,91 @@ where
     Ok(streams)
 }
 
-pub async fn open_event_data_streams<T>(subq: EventsSubQuery, cluster: &Cluster) -> Result<Vec<BoxedStream<T>>, Error>
+/// Opens a substream on the persistent connection to each node.
+/// Dropping the returned streams cancels the subquery on the nodes.
+async fn open_event_data_streams_mux<T>(
+    subq: EventsSubQuery,
+    cluster: &Cluster,
+    conf: &NodeNetConfig,
+) -> Result<Vec<BoxedStream<T>>, Error>
 where
     // TODO group bounds in new trait
     T: FrameTypeInnerStatic + DeserializeOwned + Send + Unpin + fmt::Debug + 'static,
 {
-    if true {
+    let frame1 = make_node_command_frame(subq.clone())?;
+    let mut buf = sitem_data(frame1).make_frame()?;
+    buf.extend_from_slice(&make_term_frame()?);
+    let query = buf.freeze();
+    let mut streams = Vec::new();
+    for node in &cluster.nodes {
+        let addr = format!("{}:{}", node.host, node.port_raw);
+        debug!("open_event_data_streams_mux  to: {addr}");
+        let conn = mux_conn(&addr, conf.credit_window()).await?;
+        let sub = conn.open(query.clone())?;
+        let frames = InMemoryFrameStream::new(sub, subq.inmem_bufcap());
+        let frames = Box::pin(frames);
+        let stream = EventsFromFrames::<T>::new(frames, addr);
+        streams.push(Box::pin(stream) as _);
+    }
+    Ok(streams)
+}
+
+pub async fn open_event_data_streams<T>(
+    mut subq: EventsSubQuery,
+    cluster: &Cluster,
+) -> Result<Vec<BoxedStream<T>>, Error>
+where
+    // TODO group bounds in new trait
+    T: FrameTypeInnerStatic + DeserializeOwned + Send + Unpin + fmt::Debug + 'static,
+{
+    let conf = cluster.node_net.clone().unwrap_or_default();
+    // Nodes which do not know the setting send their frames uncompressed.
+    subq.set_frame_compression(conf.compression.clone());
+    if conf.mux {
+        open_event_data_streams_mux(subq, cluster, &conf).await
+    } else if true {
         open_event_data_streams_http(subq, cluster).await
     } else {
         open_event_data_streams_tcp(subq, cluster).await
     }
 }
+
+/// The channel config to build the sub query with, before it is set per segment.
+pub fn segments_ch_conf(segments: &[ChannelConfigSegment]) -> Result<ChannelTypeConfigGen, Error> {
+    segments
+        .first()
+        .map(|x| x.ch_conf.clone())
+        .ok_or_else(|| Error::with_msg_no_trace("no channel config segments"))
+}
+
+/// Opens the event streams for the channel config of each segment.
+/// With several segments, as for an alias, each stream only yields the events within the range of its
+/// segment, so that the merged result continues from one underlying channel to the next.
+/// The event before the range can only come from the segment at the begin of the range.
+pub async fn open_event_data_streams_segments(
+    segments: &[ChannelConfigSegment],
+    select: &EventsSubQuerySelect,
+    settings: &EventsSubQuerySettings,
+    one_before_range: bool,
+    reqid: &str,
+    cluster: &Cluster,
+) -> Result<Vec<BoxedStream<ChannelEvents>>, Error> {
+    if let [seg] = segments {
+        let select = select.for_segment(seg.ch_conf.clone(), seg.range.clone());
+        let subq = EventsSubQuery::from_parts(select, settings.clone(), reqid.into());
+        return open_event_data_streams::<ChannelEvents>(subq, cluster).await;
+    }
+    let beg = select.range().beg_u64();
+    let mut ret = Vec::new();
+    for seg in segments {
+        let range: NanoRange = (&seg.range).try_into()?;
+        let one_before = one_before_range && range.beg <= beg;
+        let select = select.for_segment(seg.ch_conf.clone(), seg.range.clone());
+        let subq = EventsSubQuery::from_parts(select, settings.clone(), reqid.into());
+        for inp in open_event_data_streams::<ChannelEvents>(subq, cluster).await? {
+            let inp = RangeFilter2::new(inp, range.clone(), one_before);
+            ret.push(Box::pin(inp) as BoxedStream<ChannelEvents>);
+        }
+    }
+    Ok(ret)
+}
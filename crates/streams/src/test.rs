@@ -1,6 +1,8 @@
 #[cfg(test)]
 mod collect;
 #[cfg(test)]
+mod inmem;
+#[cfg(test)]
 mod timebin;
 
 use err::Error;
@@ -80,6 +80,38 @@ fn collect_channel_events_01() -> Result<(), Error> {
     runfut(fut)
 }
 
+#[test]
+fn collect_channel_events_cut_at_events_max() -> Result<(), Error> {
+    let fut = async {
+        let evs0 = make_some_boxed_d0_f32(20, SEC * 10, SEC * 1, 0, 28736487);
+        let evs1 = make_some_boxed_d0_f32(20, SEC * 30, SEC * 1, 0, 882716583);
+        let stream = stream::iter(vec![
+            sitem_data(evs0),
+            sitem_data(evs1),
+            Ok(StreamItem::DataItem(RangeCompletableItem::RangeComplete)),
+        ]);
+        let deadline = Instant::now() + Duration::from_millis(4000);
+        let events_max = 10;
+        let stream = PlainEventStream::new(stream);
+        let stream = EventsToTimeBinnable::new(stream);
+        let stream = TimeBinnableToCollectable::new(stream);
+        let stream = Box::pin(stream);
+        let res = Collect::new(stream, deadline, events_max, None, None)
+            .cut_at_events_max()
+            .await?;
+        if let Some(res) = res.as_any_ref().downcast_ref::<EventsDim0CollectorOutput<f32>>() {
+            assert_eq!(res.len(), 20);
+            assert_eq!(res.timed_out(), false);
+            assert_eq!(res.range_final(), false);
+            assert!(res.continue_at().is_some());
+        } else {
+            return Err(Error::with_msg(format!("bad type of collected result")));
+        }
+        Ok(())
+    };
+    runfut(fut)
+}
+
 #[test]
 fn collect_channel_events_pulse_id_diff() -> Result<(), Error> {
     let fut = async {
@@ -0,0 +1,112 @@
+use crate::frames::inmem::InMemoryFrameStream;
+use crate::test::runfut;
+use bytes::Bytes;
+use err::Error;
+use futures_util::stream;
+use futures_util::StreamExt;
+use items_0::streamitem::sitem_data;
+use items_0::streamitem::RangeCompletableItem;
+use items_0::streamitem::Sitemty;
+use items_0::streamitem::StatsItem;
+use items_0::streamitem::StreamItem;
+use items_0::Appendable;
+use items_0::Empty;
+use items_0::WithLen;
+use items_2::channelevents::ChannelEvents;
+use items_2::eventsdim0::EventsDim0;
+use items_2::framable::Framable;
+use items_2::framable::INMEM_FRAME_ENCID;
+use items_2::frame::compress_frame;
+use items_2::frame::decode_frame;
+use items_2::frame::is_compressed_encid;
+use items_2::frame::make_term_frame;
+use netpod::timeunits::SEC;
+use netpod::ByteSize;
+use netpod::FrameCompression;
+use netpod::FrameCompressionAlgo;
+use netpod::FrameCompressionStats;
+
+fn decompress_in_stream(algo: FrameCompressionAlgo) -> Result<(), Error> {
+    let fut = async move {
+        let mut evs = EventsDim0::<i32>::empty();
+        for i in 0..400 {
+            evs.push(SEC * i, i, 42);
+        }
+        let item: Sitemty<ChannelEvents> = sitem_data(ChannelEvents::Events(Box::new(evs)));
+        let frame = item.make_frame()?;
+        let comp = FrameCompression {
+            algo,
+            threshold: 64,
+            level: None,
+        };
+        let frame_comp = compress_frame(frame.clone(), &comp)?;
+        assert!(is_compressed_encid(u32::from_le_bytes(frame_comp[4..8].try_into()?)));
+        assert!(frame_comp.len() < frame.len());
+        let term = make_term_frame()?;
+        let mut wire = frame_comp.to_vec();
+        wire.extend_from_slice(&term);
+        // Deliver in small chunks so that the frame is parsed across several reads.
+        let chunks: Vec<Result<Bytes, Error>> = wire.chunks(100).map(|x| Ok(Bytes::copy_from_slice(x))).collect();
+        let mut inp = InMemoryFrameStream::new(stream::iter(chunks), ByteSize::from_kb(64));
+        let mut frames = Vec::new();
+        let mut stats = Vec::new();
+        while let Some(item) = inp.next().await {
+            match item? {
+                StreamItem::DataItem(frame) => frames.push(frame),
+                StreamItem::Stats(StatsItem::FrameCompressionStats(k)) => stats.push(k),
+                item => return Err(Error::with_msg_no_trace(format!("unexpected item {item:?}"))),
+            }
+        }
+        assert_eq!(frames.len(), 1);
+        let frame_out = &frames[0];
+        assert_eq!(frame_out.encid(), INMEM_FRAME_ENCID);
+        assert_eq!(frame_out.buf().as_ref(), &frame[16..frame.len() - 4]);
+        match decode_frame::<Sitemty<ChannelEvents>>(frame_out)? {
+            Ok(StreamItem::DataItem(RangeCompletableItem::Data(ChannelEvents::Events(evs)))) => {
+                assert_eq!(evs.len(), 400);
+            }
+            item => return Err(Error::with_msg_no_trace(format!("unexpected decoded item {item:?}"))),
+        }
+        let exp = FrameCompressionStats {
+            frames: 2,
+            frames_compressed: 1,
+            bytes_wire: wire.len() as u64,
+            bytes_raw: (frame.len() + term.len()) as u64,
+        };
+        assert_eq!(stats, vec![exp]);
+        assert!(stats[0].ratio() > 1.);
+        Ok(())
+    };
+    runfut(fut)
+}
+
+#[test]
+fn inmem_decompress_lz4() -> Result<(), Error> {
+    decompress_in_stream(FrameCompressionAlgo::Lz4)
+}
+
+#[test]
+fn inmem_decompress_zstd() -> Result<(), Error> {
+    decompress_in_stream(FrameCompressionAlgo::Zstd)
+}
+
+#[test]
+fn inmem_uncompressed_no_stats() -> Result<(), Error> {
+    let fut = async {
+        let mut evs = EventsDim0::<i32>::empty();
+        evs.push(SEC, 1, 42);
+        let item: Sitemty<ChannelEvents> = sitem_data(ChannelEvents::Events(Box::new(evs)));
+        let mut wire = item.make_frame()?.to_vec();
+        wire.extend_from_slice(&make_term_frame()?);
+        let chunks: Vec<Result<Bytes, Error>> = vec![Ok(Bytes::from(wire))];
+        let inp = InMemoryFrameStream::new(stream::iter(chunks), ByteSize::from_kb(8));
+        let items: Vec<_> = inp.collect().await;
+        assert_eq!(items.len(), 1);
+        match &items[0] {
+            Ok(StreamItem::DataItem(frame)) => assert_eq!(frame.encid(), INMEM_FRAME_ENCID),
+            item => return Err(Error::with_msg_no_trace(format!("unexpected item {item:?}"))),
+        }
+        Ok(())
+    };
+    runfut(fut)
+}
@@ -1,6 +1,7 @@
 use crate::collect::Collect;
 use crate::rangefilter2::RangeFilter2;
-use crate::tcprawclient::open_event_data_streams;
+use crate::tcprawclient::open_event_data_streams_segments;
+use crate::tcprawclient::segments_ch_conf;
 use crate::timebin::TimeBinnedStream;
 use crate::transform::build_merged_event_transform;
 use crate::transform::EventsToTimeBinnable;
@@ -21,10 +22,9 @@ use items_2::streams::PlainEventStream;
 use netpod::log::*;
 use netpod::range::evrange::NanoRange;
 use netpod::BinnedRangeEnum;
-use netpod::ChannelTypeConfigGen;
+use netpod::ChannelConfigSegment;
 use netpod::Cluster;
 use query::api4::binned::BinnedQuery;
-use query::api4::events::EventsSubQuery;
 use query::api4::events::EventsSubQuerySelect;
 use query::api4::events::EventsSubQuerySettings;
 use serde_json::Value as JsonValue;
@@ -40,18 +40,19 @@ async fn timebinnable_stream(
     query: BinnedQuery,
     range: NanoRange,
     one_before_range: bool,
-    ch_conf: ChannelTypeConfigGen,
+    segments: Vec<ChannelConfigSegment>,
     reqid: String,
     cluster: Cluster,
 ) -> Result<TimeBinnableStreamBox, Error> {
+    let ch_conf = segments_ch_conf(&segments)?;
     let mut select = EventsSubQuerySelect::new(ch_conf, range.clone().into(), query.transform().clone());
     if let Some(wasm1) = query.test_do_wasm() {
         select.set_wasm1(wasm1.into());
     }
     let settings = EventsSubQuerySettings::from(&query);
-    let subq = EventsSubQuery::from_parts(select.clone(), settings, reqid);
-    let mut tr = build_merged_event_transform(subq.transform())?;
-    let inps = open_event_data_streams::<ChannelEvents>(subq, &cluster).await?;
+    let mut tr = build_merged_event_transform(query.transform())?;
+    let inps =
+        open_event_data_streams_segments(&segments, &select, &settings, one_before_range, &reqid, &cluster).await?;
     // TODO propagate also the max-buf-len for the first stage event reader.
     // TODO use a mixture of count and byte-size as threshold.
     let stream = Merger::new(inps, query.merger_out_len_max());
@@ -208,7 +209,7 @@ async fn timebinnable_stream(
 async fn timebinned_stream(
     query: BinnedQuery,
     binned_range: BinnedRangeEnum,
-    ch_conf: ChannelTypeConfigGen,
+    segments: Vec<ChannelConfigSegment>,
     reqid: String,
     cluster: Cluster,
 ) -> Result<Pin<Box<dyn Stream<Item = Sitemty<Box<dyn TimeBinned>>> + Send>>, Error> {
@@ -217,7 +218,7 @@ async fn timebinned_stream(
     let do_time_weight = true;
     let one_before_range = true;
 
-    let stream = timebinnable_stream(query.clone(), range, one_before_range, ch_conf, reqid, cluster).await?;
+    let stream = timebinnable_stream(query.clone(), range, one_before_range, segments, reqid, cluster).await?;
     let stream: Pin<Box<dyn TimeBinnableStreamTrait>> = stream.0;
     let stream = Box::pin(stream);
     // TODO rename TimeBinnedStream to make it more clear that it is the component which initiates the time binning.
@@ -242,14 +243,14 @@ fn timebinned_to_collectable(
 
 pub async fn timebinned_json(
     query: BinnedQuery,
-    ch_conf: ChannelTypeConfigGen,
+    segments: Vec<ChannelConfigSegment>,
     reqid: String,
     cluster: Cluster,
 ) -> Result<JsonValue, Error> {
     let deadline = Instant::now().checked_add(query.timeout_value()).unwrap();
     let binned_range = BinnedRangeEnum::covering_range(query.range().clone(), query.bin_count())?;
     let collect_max = 10000;
-    let stream = timebinned_stream(query.clone(), binned_range.clone(), ch_conf, reqid, cluster).await?;
+    let stream = timebinned_stream(query.clone(), binned_range.clone(), segments, reqid, cluster).await?;
     let stream = timebinned_to_collectable(stream);
     let collected = Collect::new(stream, deadline, collect_max, None, Some(binned_range));
     let collected: BoxFuture<_> = Box::pin(collected);